@@ -0,0 +1,31 @@
+-- Everything that has no table of its own: audit log, usage counters and removed birthdays
+CREATE TABLE bot_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    -- Bumped by every write, instances only write on top of the revision they loaded
+    revision BIGINT NOT NULL,
+    data JSONB NOT NULL
+);
+
+INSERT INTO bot_state (id, revision, data) VALUES (1, 0, '{"entries": [], "server_channels": {}}');
+
+CREATE TABLE birthday_entries (
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    entry JSONB NOT NULL,
+    PRIMARY KEY (guild_id, user_id)
+);
+
+CREATE TABLE guild_configs (
+    guild_id BIGINT PRIMARY KEY,
+    announcement_channel BIGINT,
+    config JSONB
+);
+
+-- Claimed before a birthday is announced, so two instances never announce the same one
+CREATE TABLE announcements (
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    year INTEGER NOT NULL,
+    announced_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (guild_id, user_id, year)
+);
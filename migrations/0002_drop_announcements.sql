@@ -0,0 +1,3 @@
+-- Announced birthdays are recorded together with the rest of the data now, which is kept
+-- consistent between instances by the revision in `bot_state`
+DROP TABLE announcements;
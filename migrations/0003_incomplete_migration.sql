@@ -0,0 +1,2 @@
+-- Set while `migrate-storage` copies data into the database, the bot refuses to use it meanwhile
+ALTER TABLE bot_state ADD COLUMN incomplete_migration TEXT;
@@ -0,0 +1,22 @@
+-- Everything that has no table of its own: audit log, usage counters and removed birthdays
+CREATE TABLE bot_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    -- Bumped by every write, so changes made by other processes are noticed
+    revision INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+
+INSERT INTO bot_state (id, revision, data) VALUES (1, 0, '{"entries": [], "server_channels": {}}');
+
+CREATE TABLE birthday_entries (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    entry TEXT NOT NULL,
+    PRIMARY KEY (guild_id, user_id)
+);
+
+CREATE TABLE guild_configs (
+    guild_id INTEGER PRIMARY KEY,
+    announcement_channel INTEGER,
+    config TEXT
+);
@@ -0,0 +1,110 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use poise::serenity_prelude::{self as serenity, UserId};
+use tracing::{error, warn};
+
+use crate::quiet_message;
+
+static CHECK_TIME: Duration = Duration::from_secs(30);
+// The owner gets at most one DM within this time, alerts raised meanwhile are collected
+static ALERT_INTERVAL: Duration = Duration::from_secs(30 * 60);
+// Keeps the DM below Discord's message length limit
+static MAX_LENGTH: usize = 1800;
+
+/// Alerts that weren't sent to the owner yet
+static PENDING: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Reports a fatal error to the bot owner, can be called from anywhere and before the bot
+/// connected to Discord
+pub fn raise(summary: impl Into<String>) {
+    let summary = summary.into();
+    error!(alert = %summary, "Raised an alert");
+    PENDING.lock().unwrap().push(summary);
+}
+
+/// One line per distinct alert with how often it was raised, oldest first
+fn summarize(alerts: &[String]) -> String {
+    let mut counted: Vec<(&String, usize)> = Vec::new();
+    for alert in alerts {
+        match counted.iter_mut().find(|(counted, _)| *counted == alert) {
+            Some((_, count)) => *count += 1,
+            None => counted.push((alert, 1)),
+        }
+    }
+    let mut summary = String::from("🚨 BirthdayBot ran into trouble:");
+    for (alert, count) in counted {
+        let line = if count == 1 {
+            format!("\n- {}", alert)
+        } else {
+            format!("\n- {} ({} times)", alert, count)
+        };
+        if summary.len() + line.len() > MAX_LENGTH {
+            summary.push_str("\n- …and more, see the logs");
+            break;
+        }
+        summary.push_str(&line);
+    }
+    summary
+}
+
+/// Sends the pending alerts to the owners, at most once per ALERT_INTERVAL
+pub async fn deliver_periodically(http: Arc<serenity::Http>, owners: HashSet<UserId>) {
+    if owners.is_empty() {
+        warn!("No bot owner is known, alerts are only logged");
+        return;
+    }
+    let mut last_sent: Option<Instant> = None;
+    loop {
+        tokio::time::sleep(CHECK_TIME).await;
+        if last_sent.is_some_and(|sent| sent.elapsed() < ALERT_INTERVAL) {
+            continue;
+        }
+        let alerts = std::mem::take(&mut *PENDING.lock().unwrap());
+        if alerts.is_empty() {
+            continue;
+        }
+        let summary = summarize(&alerts);
+        for owner in &owners {
+            let sent = match owner.create_dm_channel(&http).await {
+                Ok(channel) => channel
+                    .send_message(&http, quiet_message(summary.as_str()))
+                    .await
+                    .map(|_| ()),
+                Err(error) => Err(error),
+            };
+            if let Err(error) = sent {
+                error!(%owner, %error, "Failed to alert the owner");
+            }
+        }
+        last_sent = Some(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_alerts_are_counted_and_the_summary_stays_short() {
+        let alerts = vec![
+            "Saving failed".to_string(),
+            "The check panicked".to_string(),
+            "Saving failed".to_string(),
+        ];
+        assert_eq!(
+            summarize(&alerts),
+            "🚨 BirthdayBot ran into trouble:\n- Saving failed (2 times)\n- The check panicked"
+        );
+
+        let flood: Vec<String> = (0..100)
+            .map(|i| format!("{} {}", i, "x".repeat(50)))
+            .collect();
+        let summary = summarize(&flood);
+        assert!(summary.len() <= MAX_LENGTH + 40);
+        assert!(summary.ends_with("see the logs"));
+    }
+}
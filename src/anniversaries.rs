@@ -0,0 +1,183 @@
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Datelike, NaiveDate};
+use poise::serenity_prelude::{self as serenity, GuildId, UserId};
+
+use crate::{
+    audit, dates, format, quiet_message, Announcement, BirthdayEntry, BirthdayList, Context, Error,
+    Toggle,
+};
+
+/// The day the Discord account was created, it is part of every user ID
+fn account_created(user_id: UserId) -> NaiveDate {
+    DateTime::from_timestamp(user_id.created_at().unix_timestamp(), 0)
+        .unwrap_or_default()
+        .date_naive()
+}
+
+/// Entries whose account turns a year older today in guilds that celebrate it, with the
+/// number of years. Members who opted out and the ones already announced are left out.
+pub fn due(
+    birthdays: &BirthdayList,
+    today: NaiveDate,
+    in_scope: impl Fn(GuildId) -> bool,
+) -> Vec<(&BirthdayEntry, i32)> {
+    birthdays
+        .entries
+        .iter()
+        .filter(|entry| in_scope(entry.guild_id))
+        .filter(|entry| {
+            birthdays
+                .guild_configs
+                .get(&entry.guild_id)
+                .is_some_and(|config| config.account_anniversaries && !config.is_quiet(today))
+        })
+        .filter(|entry| !birthdays.anniversary_opt_outs.contains(&entry.user_id))
+        .filter(|entry| {
+            !birthdays
+                .announced_anniversaries
+                .contains(&Announcement::of(entry, today))
+        })
+        .filter_map(|entry| {
+            let created = account_created(entry.user_id);
+            let years = today.year() - created.year();
+            let leap_day = birthdays.leap_day(entry.guild_id);
+            (years > 0 && dates::is_birthday_on(created, today, leap_day)).then_some((entry, years))
+        })
+        .collect()
+}
+
+/// Posts a low-key note in the announcement channel, returns false if there is none
+pub async fn announce(
+    http: &serenity::Http,
+    birthdays: &BirthdayList,
+    entry: &BirthdayEntry,
+    years: i32,
+) -> Result<bool, serenity::Error> {
+    let Some(channel) = birthdays.server_channels.get(&entry.guild_id) else {
+        return Ok(false);
+    };
+    let message = format!(
+        "🗓️ {} joined Discord {} year{} ago today!",
+        format::escape(&entry.name),
+        years,
+        if years == 1 { "" } else { "s" }
+    );
+    channel.send_message(http, quiet_message(message)).await?;
+    Ok(true)
+}
+
+/// Prunes the anniversaries announced before the previous year
+pub fn prune(announced: &mut BTreeSet<Announcement>, today: NaiveDate) {
+    announced.retain(|announcement| announcement.year >= today.year() - 1);
+}
+
+/// Announces the anniversaries of members' Discord accounts in this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_account_anniversaries(
+    ctx: Context<'_>,
+    #[description = "Whether to announce account anniversaries"] state: Toggle,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let enabled = matches!(state, Toggle::On);
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .account_anniversaries = enabled;
+    let state = if enabled { "on" } else { "off" };
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("turned {} account anniversaries", state),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = if enabled {
+        "🗓️🎈 Members with a birthday set now also get a note when their Discord account turns a year older!"
+    } else {
+        "🗓️ Account anniversaries are no longer announced!"
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Whether the anniversary of your Discord account may be announced, in all servers
+#[poise::command(slash_command, prefix_command)]
+pub async fn account_anniversary(
+    ctx: Context<'_>,
+    #[description = "Whether your account anniversary may be announced"] state: Toggle,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let message = match state {
+        Toggle::On => {
+            birthdays.anniversary_opt_outs.remove(&user_id);
+            "🗓️🎈 Servers that celebrate account anniversaries may announce yours!"
+        }
+        Toggle::Off => {
+            birthdays.anniversary_opt_outs.insert(user_id);
+            "🗓️ Your account anniversary won't be announced anywhere!"
+        }
+    };
+    ctx.data().storage.write(&birthdays).await?;
+    ctx.say(message).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GuildConfig;
+
+    // Created on 2016-04-30 according to its snowflake
+    static USER: u64 = 175928847299117063;
+
+    fn birthdays() -> BirthdayList {
+        BirthdayList {
+            entries: vec![BirthdayEntry {
+                name: "user".to_string(),
+                ..BirthdayEntry::example(USER, 1)
+            }],
+            guild_configs: [(
+                GuildId::new(1),
+                GuildConfig {
+                    account_anniversaries: true,
+                    ..Default::default()
+                },
+            )]
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn anniversaries_are_due_once_unless_opted_out() {
+        let today = NaiveDate::from_ymd_opt(2024, 4, 30).unwrap();
+        assert_eq!(
+            account_created(UserId::new(USER)),
+            today.with_year(2016).unwrap()
+        );
+
+        let mut birthdays = birthdays();
+        let due_today = due(&birthdays, today, |_| true);
+        assert_eq!(due_today.len(), 1);
+        assert_eq!(due_today[0].1, 8);
+        assert!(due(&birthdays, today.succ_opt().unwrap(), |_| true).is_empty());
+
+        let announcement = Announcement::of(&birthdays.entries[0], today);
+        birthdays.announced_anniversaries.insert(announcement);
+        assert!(due(&birthdays, today, |_| true).is_empty());
+
+        birthdays.announced_anniversaries.clear();
+        birthdays.anniversary_opt_outs.insert(UserId::new(USER));
+        assert!(due(&birthdays, today, |_| true).is_empty());
+    }
+}
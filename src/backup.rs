@@ -0,0 +1,134 @@
+use std::sync::Arc;
+
+use poise::serenity_prelude::{self as serenity, GuildId};
+
+use crate::{is_moderator, quiet_message, storage::StorageFormat, BirthdayList, Context, Error};
+
+// Discord refuses larger attachments for bots anyway
+static MAX_FILE_SIZE: u32 = 25 * 1024 * 1024;
+
+/// The birthdays, anniversaries, announcement channel and settings of one guild, everything
+/// else is left out
+fn guild_data(birthdays: &BirthdayList, guild_id: GuildId) -> BirthdayList {
+    BirthdayList {
+        entries: birthdays
+            .entries
+            .iter()
+            .filter(|entry| entry.guild_id == guild_id)
+            .cloned()
+            .collect(),
+        server_channels: birthdays
+            .server_channels
+            .iter()
+            .filter(|(id, _)| **id == guild_id)
+            .map(|(id, channel)| (*id, *channel))
+            .collect(),
+        events: birthdays
+            .events
+            .iter()
+            .filter(|event| event.guild_id == guild_id)
+            .cloned()
+            .collect(),
+        guild_configs: birthdays
+            .guild_configs
+            .iter()
+            .filter(|(id, _)| **id == guild_id)
+            .map(|(id, config)| (*id, config.clone()))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Loads an uploaded backup, the format is picked by the file extension like `merge_data` does
+fn parse(filename: &str, data: Vec<u8>) -> Result<BirthdayList, String> {
+    let format = filename
+        .rsplit_once('.')
+        .and_then(|(_, extension)| StorageFormat::from_extension(extension))
+        .ok_or_else(|| format!("`{}` is neither a json nor a toml file", filename))?;
+    let text = String::from_utf8(data).map_err(|_| "The file isn't UTF-8 encoded".to_string())?;
+    let mut birthdays = format
+        .deserialize(&text)
+        .map_err(|error| format!("The file isn't a valid data file: {}", error))?;
+    birthdays.migrate_announcements();
+    Ok(birthdays)
+}
+
+/// Downloads and checks a backup uploaded to `restore`, nothing is changed if it is refused
+pub async fn read_attachment(file: &serenity::Attachment) -> Result<BirthdayList, String> {
+    if file.size > MAX_FILE_SIZE {
+        return Err("The file is too large for a data file".to_string());
+    }
+    let data = file
+        .download()
+        .await
+        .map_err(|error| format!("Couldn't download the file: {}", error))?;
+    parse(&file.filename, data)
+}
+
+/// Sends you the data file, owners get all data and moderators the data of their server
+#[poise::command(slash_command, prefix_command)]
+pub async fn backup(ctx: Context<'_>) -> Result<(), Error> {
+    let birthdays = ctx.data().storage.read().await;
+    let (birthdays, filename) = if ctx.framework().options().owners.contains(&ctx.author().id) {
+        (birthdays, "birthdays.json".to_string())
+    } else if is_moderator(ctx).await {
+        let guild_id = ctx.guild_id().unwrap();
+        (
+            Arc::new(guild_data(&birthdays, guild_id)),
+            format!("birthdays-{}.json", guild_id),
+        )
+    } else {
+        ctx.say("🐺🎩❌ Only moderators can back up the birthdays!")
+            .await?;
+        return Ok(());
+    };
+
+    let data = StorageFormat::Json.serialize(&birthdays)?;
+    // Sent privately, the file holds every birthday including private ones
+    ctx.author()
+        .direct_message(
+            ctx,
+            quiet_message(format!(
+                "💾 Backup of {} birthday(s), `restore` takes it back",
+                birthdays.entries.len()
+            ))
+            .add_file(serenity::CreateAttachment::bytes(data, filename)),
+        )
+        .await?;
+    ctx.say("💾 Sent you the backup!").await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::ChannelId;
+
+    use super::*;
+    use crate::GuildConfig;
+
+    #[test]
+    fn backups_are_filtered_and_checked() {
+        let birthdays = BirthdayList {
+            server_channels: [
+                (GuildId::new(1), ChannelId::new(10)),
+                (GuildId::new(2), ChannelId::new(20)),
+            ]
+            .into(),
+            guild_configs: [(GuildId::new(2), GuildConfig::default())].into(),
+            ..Default::default()
+        };
+        let guild = guild_data(&birthdays, GuildId::new(1));
+        assert_eq!(
+            guild.server_channels,
+            [(GuildId::new(1), ChannelId::new(10))].into()
+        );
+        assert!(guild.guild_configs.is_empty());
+
+        let data = StorageFormat::Json.serialize(&guild).unwrap();
+        let parsed = parse("birthdays-1.JSON", data.clone().into_bytes()).unwrap();
+        assert_eq!(parsed.server_channels, guild.server_channels);
+        assert!(parse("birthdays.csv", data.into_bytes()).is_err());
+        assert!(parse("birthdays.json", b"{\"entries\": 5}".to_vec()).is_err());
+        assert!(parse("birthdays.json", vec![0xff, 0xfe]).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+use chrono::NaiveDate;
+use poise::serenity_prelude::{self as serenity, GuildId, RoleId, UserId};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    audit, retry::FailureKind, storage::Storage, BirthdayEntry, BirthdayList, Context, Error,
+};
+
+static AUDIT_LOG_REASON: &str = "Birthday role";
+// Removals that keep failing are given up after this many days, the admins must have
+// taken the bot's permissions away for good
+static REMOVAL_DAYS: i64 = 7;
+
+/// A birthday role given to a member, kept until it was taken away again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppliedRole {
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    pub role_id: RoleId,
+    // Day on which the role was given, it is taken away on the next day
+    pub day: NaiveDate,
+}
+
+/// Gives the celebrant the guild's birthday role, failures like missing permissions or a role
+/// above the bot's own are only logged. Returns the role to remember if it was given.
+pub async fn grant(
+    http: &serenity::Http,
+    birthdays: &BirthdayList,
+    entry: &BirthdayEntry,
+    today: NaiveDate,
+) -> Option<AppliedRole> {
+    let role_id = birthdays
+        .guild_configs
+        .get(&entry.guild_id)
+        .and_then(|config| config.birthday_role)?;
+    let applied = birthdays.birthday_roles.iter().any(|applied| {
+        applied.guild_id == entry.guild_id
+            && applied.user_id == entry.user_id
+            && applied.role_id == role_id
+    });
+    if applied {
+        return None;
+    }
+    if let Err(error) = http
+        .add_member_role(
+            entry.guild_id,
+            entry.user_id,
+            role_id,
+            Some(AUDIT_LOG_REASON),
+        )
+        .await
+    {
+        warn!(
+            role = %role_id,
+            user = %entry.user_id,
+            guild = %entry.guild_id,
+            kind = FailureKind::of(&error).describe(),
+            %error,
+            "Failed to give the birthday role"
+        );
+        return None;
+    }
+    Some(AppliedRole {
+        guild_id: entry.guild_id,
+        user_id: entry.user_id,
+        role_id,
+        day: today,
+    })
+}
+
+/// Whether the role should be taken away today
+fn expired(applied: &AppliedRole, today: NaiveDate) -> bool {
+    applied.day < today
+}
+
+/// Takes the birthday roles of past days away again. Roles that can't be taken away are
+/// retried on the next check, unless the member or role is gone or it kept failing for days.
+pub async fn remove_expired(
+    http: &serenity::Http,
+    storage: &Storage,
+    today: NaiveDate,
+    in_scope: impl Fn(GuildId) -> bool,
+) -> Result<(), Error> {
+    let birthdays = storage.read().await;
+    let mut done = Vec::new();
+    for applied in birthdays
+        .birthday_roles
+        .iter()
+        .filter(|applied| in_scope(applied.guild_id) && expired(applied, today))
+    {
+        let result = http
+            .remove_member_role(
+                applied.guild_id,
+                applied.user_id,
+                applied.role_id,
+                Some(AUDIT_LOG_REASON),
+            )
+            .await;
+        let Err(error) = result else {
+            done.push(applied.clone());
+            continue;
+        };
+        let kind = FailureKind::of(&error);
+        warn!(
+            role = %applied.role_id,
+            user = %applied.user_id,
+            guild = %applied.guild_id,
+            kind = kind.describe(),
+            %error,
+            "Failed to take the birthday role away"
+        );
+        if kind == FailureKind::MissingChannel
+            || today - applied.day > chrono::Duration::days(REMOVAL_DAYS)
+        {
+            done.push(applied.clone());
+        }
+    }
+    if done.is_empty() {
+        return Ok(());
+    }
+    storage
+        .update(|birthdays| {
+            birthdays
+                .birthday_roles
+                .retain(|applied| !done.contains(applied));
+        })
+        .await
+}
+
+/// Sets the role celebrants get for the day of their birthday, leave it empty to remove it
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_birthday_role(
+    ctx: Context<'_>,
+    #[description = "Role given for the birthday (removes it if empty)"] role: Option<RoleId>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .birthday_role = role;
+    let action = match role {
+        Some(role) => format!("set the birthday role to <@&{}>", role),
+        None => "removed the birthday role".to_string(),
+    };
+    audit(&mut birthdays, guild_id, ctx.author().id, action);
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = match role {
+        Some(role) => format!(
+            "🥳🎈 Celebrants now get <@&{}> for the day, make sure my role is above it!",
+            role
+        ),
+        None => "🥳 Celebrants no longer get a role for the day!".to_string(),
+    };
+    ctx.send(
+        poise::CreateReply::default()
+            .content(message)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_expire_the_day_after_they_were_given() {
+        let applied = AppliedRole {
+            guild_id: GuildId::new(1),
+            user_id: UserId::new(2),
+            role_id: RoleId::new(3),
+            day: NaiveDate::from_ymd_opt(2024, 12, 31).unwrap(),
+        };
+        assert!(!expired(
+            &applied,
+            NaiveDate::from_ymd_opt(2024, 12, 31).unwrap()
+        ));
+        assert!(expired(
+            &applied,
+            NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()
+        ));
+    }
+}
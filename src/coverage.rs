@@ -0,0 +1,186 @@
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{Datelike, NaiveDate, Utc};
+use poise::serenity_prelude::{self as serenity, GuildId, UserId};
+use tracing::warn;
+
+use crate::{BirthdayList, Context, Error};
+
+// Guilds with more members only get the approximate member count, enumerating them is too slow
+static ENUMERATE_LIMIT: u64 = 5000;
+static PAGE_SIZE: u64 = 1000;
+// Months of history kept per guild
+static HISTORY_MONTHS: usize = 12;
+
+/// How many members have a birthday registered
+struct Coverage {
+    registered: usize,
+    members: u64,
+    // Bots and members who left can't be told apart without enumerating the members
+    approximate: bool,
+}
+
+impl Coverage {
+    fn percent(&self) -> u32 {
+        if self.members == 0 {
+            return 0;
+        }
+        ((self.registered as f64 / self.members as f64) * 100.0).round() as u32
+    }
+}
+
+/// All members of the guild that aren't bots, None if they can't be listed
+async fn humans(http: &serenity::Http, guild_id: GuildId) -> Option<HashSet<UserId>> {
+    let mut humans = HashSet::new();
+    let mut after = None;
+    loop {
+        let page = match guild_id.members(http, Some(PAGE_SIZE), after).await {
+            Ok(page) => page,
+            Err(error) => {
+                warn!(guild = %guild_id, %error, "Failed to list the members");
+                return None;
+            }
+        };
+        after = page.last().map(|member| member.user.id);
+        let full = page.len() as u64 == PAGE_SIZE;
+        humans.extend(
+            page.into_iter()
+                .filter(|member| !member.user.bot)
+                .map(|member| member.user.id),
+        );
+        if !full {
+            return Some(humans);
+        }
+    }
+}
+
+async fn coverage(
+    http: &serenity::Http,
+    birthdays: &BirthdayList,
+    guild_id: GuildId,
+) -> Result<Coverage, Error> {
+    let entries = birthdays
+        .entries
+        .iter()
+        .filter(|entry| entry.guild_id == guild_id);
+    let approximate_members = guild_id
+        .to_partial_guild_with_counts(http)
+        .await?
+        .approximate_member_count
+        .unwrap_or_default();
+
+    if approximate_members <= ENUMERATE_LIMIT {
+        if let Some(humans) = humans(http, guild_id).await {
+            let registered: HashSet<UserId> = entries
+                .map(|entry| entry.user_id)
+                .filter(|user_id| humans.contains(user_id))
+                .collect();
+            return Ok(Coverage {
+                registered: registered.len(),
+                members: humans.len() as u64,
+                approximate: false,
+            });
+        }
+    }
+    // Entries of members who are known to have left don't count
+    Ok(Coverage {
+        registered: entries
+            .filter(|entry| entry.missing_since.is_none())
+            .count(),
+        members: approximate_members,
+        approximate: true,
+    })
+}
+
+/// Stores the coverage of the month, only the latest value of a month and the last
+/// HISTORY_MONTHS months are kept
+fn record(history: &mut BTreeMap<NaiveDate, u32>, today: NaiveDate, percent: u32) {
+    history.insert(today.with_day(1).unwrap(), percent);
+    while history.len() > HISTORY_MONTHS {
+        history.pop_first();
+    }
+}
+
+/// Shows how many members of this server have set their birthday
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn birthday_coverage(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    ctx.defer().await?;
+    let birthdays = ctx.data().storage.read().await;
+    let coverage = coverage(ctx.http(), &birthdays, guild_id).await?;
+    let percent = coverage.percent();
+    let today = Utc::now().date_naive();
+    let history = ctx
+        .data()
+        .storage
+        .update(|birthdays| {
+            let history = birthdays.coverage_history.entry(guild_id).or_default();
+            record(history, today, percent);
+            history.clone()
+        })
+        .await?;
+
+    let mut message = format!(
+        "📊🎈 {}% of members have set a birthday ({} of {})",
+        percent, coverage.registered, coverage.members
+    );
+    if coverage.approximate {
+        message.push_str(
+            "\n⚠️ The members couldn't be listed, so this is based on Discord's approximate member count which includes bots",
+        );
+    }
+    if history.len() > 1 {
+        let trend: Vec<String> = history
+            .iter()
+            .map(|(month, percent)| format!("- {}: {}%", month.format("%Y-%m"), percent))
+            .collect();
+        message.push_str(&format!("\n📈 Trend:\n{}", trend.join("\n")));
+    }
+    ctx.say(message).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_keeps_one_value_per_month_for_a_year() {
+        let mut history = BTreeMap::new();
+        record(
+            &mut history,
+            NaiveDate::from_ymd_opt(2023, 1, 5).unwrap(),
+            10,
+        );
+        record(
+            &mut history,
+            NaiveDate::from_ymd_opt(2023, 1, 20).unwrap(),
+            12,
+        );
+        assert_eq!(history.len(), 1);
+        assert_eq!(history.values().next(), Some(&12));
+
+        for month in 2..=12 {
+            record(
+                &mut history,
+                NaiveDate::from_ymd_opt(2023, month, 1).unwrap(),
+                month,
+            );
+        }
+        record(
+            &mut history,
+            NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            40,
+        );
+        assert_eq!(history.len(), HISTORY_MONTHS);
+        assert_eq!(
+            history.keys().next(),
+            NaiveDate::from_ymd_opt(2023, 2, 1).as_ref()
+        );
+    }
+}
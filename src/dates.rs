@@ -0,0 +1,208 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+
+use crate::{schedule, BirthdayEntry, LeapDay};
+
+/// The day the birthday falls on in the given year, everything working with occurrences must
+/// go through this so February 29th is celebrated on the same day everywhere
+pub fn birthday_in_year(date: NaiveDate, year: i32, leap_day: LeapDay) -> NaiveDate {
+    // Only February 29th is missing in some years
+    date.with_year(year).unwrap_or_else(|| match leap_day {
+        LeapDay::February28 => NaiveDate::from_ymd_opt(year, 2, 28).unwrap(),
+        LeapDay::March1 => NaiveDate::from_ymd_opt(year, 3, 1).unwrap(),
+    })
+}
+
+/// Returns the next date on which the birthday falls, today included
+pub fn next_occurrence(date: NaiveDate, today: NaiveDate, leap_day: LeapDay) -> NaiveDate {
+    let this_year = birthday_in_year(date, today.year(), leap_day);
+    if today > this_year {
+        birthday_in_year(date, today.year() + 1, leap_day)
+    } else {
+        this_year
+    }
+}
+
+/// Returns the most recent date on which the birthday fell, today included
+pub fn last_occurrence(date: NaiveDate, today: NaiveDate, leap_day: LeapDay) -> NaiveDate {
+    let this_year = birthday_in_year(date, today.year(), leap_day);
+    if today < this_year {
+        birthday_in_year(date, today.year() - 1, leap_day)
+    } else {
+        this_year
+    }
+}
+
+/// Whether the birthday falls on `day`
+pub fn is_birthday_on(date: NaiveDate, day: NaiveDate, leap_day: LeapDay) -> bool {
+    birthday_in_year(date, day.year(), leap_day) == day
+}
+
+/// The date it is in the entry's time zone at `now`
+pub fn local_date(entry: &BirthdayEntry, now: DateTime<Utc>) -> NaiveDate {
+    (now + entry.utc_offset.duration()).date_naive()
+}
+
+/// Whether it is the entry's birthday at `now` in its own time zone
+pub fn is_birthday_now(entry: &BirthdayEntry, now: DateTime<Utc>, leap_day: LeapDay) -> bool {
+    is_birthday_on(entry.date, local_date(entry, now), leap_day)
+}
+
+/// The entry's next birthday in its time zone, today's while it is still going on there. Lists
+/// and countdowns must use this instead of the date in UTC, or members are told their birthday
+/// is a year away while they celebrate it.
+pub fn next_birthday(entry: &BirthdayEntry, now: DateTime<Utc>, leap_day: LeapDay) -> NaiveDate {
+    next_occurrence(entry.date, local_date(entry, now), leap_day)
+}
+
+/// The next birthday of the entry in its time zone and the moment it starts there, today's
+/// birthday while it is still going on
+pub fn next_birthday_start(
+    entry: &BirthdayEntry,
+    now: DateTime<Utc>,
+    leap_day: LeapDay,
+) -> (NaiveDate, DateTime<Utc>) {
+    let date = next_birthday(entry, now, leap_day);
+    (date, schedule::start_of(date, entry.utc_offset.duration()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offset::UtcOffset;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    fn entry(born: NaiveDate, utc_offset: UtcOffset) -> BirthdayEntry {
+        BirthdayEntry {
+            date: born,
+            year: Some(born.year()),
+            utc_offset,
+            ..BirthdayEntry::example(1, 1)
+        }
+    }
+
+    #[test]
+    fn todays_birthday_is_the_next_and_the_last() {
+        let born = date(1995, 6, 14);
+        let today = date(2025, 6, 14);
+        let leap_day = LeapDay::default();
+        assert_eq!(next_occurrence(born, today, leap_day), today);
+        assert_eq!(last_occurrence(born, today, leap_day), today);
+        assert!(is_birthday_on(born, today, leap_day));
+
+        // Yesterday's birthday is a year away and no longer going on
+        let tomorrow = date(2025, 6, 15);
+        assert_eq!(next_occurrence(born, tomorrow, leap_day), date(2026, 6, 14));
+        assert_eq!(last_occurrence(born, tomorrow, leap_day), today);
+        assert!(!is_birthday_on(born, tomorrow, leap_day));
+    }
+
+    #[test]
+    fn occurrences_wrap_around_new_year() {
+        let leap_day = LeapDay::default();
+        assert_eq!(
+            next_occurrence(date(1990, 1, 2), date(2024, 12, 31), leap_day),
+            date(2025, 1, 2)
+        );
+        assert_eq!(
+            last_occurrence(date(1990, 12, 31), date(2025, 1, 2), leap_day),
+            date(2024, 12, 31)
+        );
+        assert_eq!(
+            next_occurrence(date(1990, 12, 31), date(2024, 12, 31), leap_day),
+            date(2024, 12, 31)
+        );
+    }
+
+    #[test]
+    fn leap_day_birthdays_move_in_other_years() {
+        let born = date(2000, 2, 29);
+        for leap_day in [LeapDay::February28, LeapDay::March1] {
+            assert!(is_birthday_on(born, date(2024, 2, 29), leap_day));
+            assert_eq!(
+                next_occurrence(born, date(2024, 2, 1), leap_day),
+                date(2024, 2, 29)
+            );
+        }
+        assert!(is_birthday_on(born, date(2025, 2, 28), LeapDay::February28));
+        assert!(!is_birthday_on(born, date(2025, 3, 1), LeapDay::February28));
+        assert!(is_birthday_on(born, date(2025, 3, 1), LeapDay::March1));
+        assert!(!is_birthday_on(born, date(2025, 2, 28), LeapDay::March1));
+        // February 28th of a leap year is somebody else's birthday
+        assert!(!is_birthday_on(
+            born,
+            date(2024, 2, 28),
+            LeapDay::February28
+        ));
+        assert_eq!(
+            next_occurrence(born, date(2025, 3, 1), LeapDay::February28),
+            date(2026, 2, 28)
+        );
+    }
+
+    #[test]
+    fn far_offsets_move_the_birthday_across_midnight() {
+        let born = date(1995, 6, 14);
+        let leap_day = LeapDay::default();
+
+        // June 14th has begun in UTC+14 at 10:00 UTC the day before, not in UTC-12
+        let east = entry(born, UtcOffset::from_hours(14));
+        let west = entry(born, UtcOffset::from_hours(-12));
+        let now = at("2025-06-13T10:00:00Z");
+        assert!(is_birthday_now(&east, now, leap_day));
+        assert!(!is_birthday_now(&west, now, leap_day));
+        assert_eq!(next_birthday(&east, now, leap_day), date(2025, 6, 14));
+
+        // It is still June 14th in UTC-12 until noon UTC the day after
+        let now = at("2025-06-15T11:59:00Z");
+        assert!(is_birthday_now(&west, now, leap_day));
+        assert!(!is_birthday_now(&east, now, leap_day));
+        assert_eq!(next_birthday(&west, now, leap_day), date(2025, 6, 14));
+        assert_eq!(next_birthday(&east, now, leap_day), date(2026, 6, 14));
+        let now = at("2025-06-15T12:00:00Z");
+        assert!(!is_birthday_now(&west, now, leap_day));
+        assert_eq!(next_birthday(&west, now, leap_day), date(2026, 6, 14));
+
+        // Half-hour offsets too
+        let india = entry(born, UtcOffset::from_minutes(5 * 60 + 30));
+        assert!(!is_birthday_now(
+            &india,
+            at("2025-06-13T18:29:00Z"),
+            leap_day
+        ));
+        assert!(is_birthday_now(
+            &india,
+            at("2025-06-13T18:30:00Z"),
+            leap_day
+        ));
+    }
+
+    #[test]
+    fn countdowns_run_to_local_midnight() {
+        let mut entry = entry(date(1995, 6, 14), UtcOffset::from_hours(2));
+        let leap_day = LeapDay::default();
+
+        // June 14th starts at 22:00 UTC the day before and lasts until 22:00 UTC
+        let (next, start) = next_birthday_start(&entry, at("2025-06-10T12:00:00Z"), leap_day);
+        assert_eq!(next, date(2025, 6, 14));
+        assert_eq!(start, at("2025-06-13T22:00:00Z"));
+        let now = at("2025-06-14T21:00:00Z");
+        assert!(next_birthday_start(&entry, now, leap_day).1 <= now);
+        let (next, _) = next_birthday_start(&entry, at("2025-06-14T22:00:00Z"), leap_day);
+        assert_eq!(next, date(2026, 6, 14));
+
+        // February 29th wraps into the next year and follows the guild's leap day setting
+        entry.date = date(2024, 2, 29);
+        let now = at("2025-03-05T00:00:00Z");
+        let (next, _) = next_birthday_start(&entry, now, LeapDay::March1);
+        assert_eq!(next, date(2026, 3, 1));
+        let (next, _) = next_birthday_start(&entry, now, LeapDay::February28);
+        assert_eq!(next, date(2026, 2, 28));
+    }
+}
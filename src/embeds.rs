@@ -0,0 +1,192 @@
+use poise::serenity_prelude as serenity;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{announcement_mentions, audit, BirthdayEntry, Context, Error, Toggle};
+
+// Discord cuts embed titles off at this many characters
+static TITLE_LENGTH: usize = 256;
+
+/// Announcing birthdays as an embed, None in the config for plain messages
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbedConfig {
+    // Accent color as 0xRRGGBB, None for Discord's default
+    pub color: Option<u32>,
+    pub image_url: Option<String>,
+}
+
+impl EmbedConfig {
+    pub fn describe(&self) -> String {
+        let color = match self.color {
+            Some(color) => format!("#{:06x}", color),
+            None => "default color".to_string(),
+        };
+        match &self.image_url {
+            Some(url) => format!("{}, image {}", color, url),
+            None => color,
+        }
+    }
+}
+
+/// Parses a hex color like `#ff66cc`, the `#` is optional
+fn parse_color(text: &str) -> Result<u32, String> {
+    let hex = text.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("`{}` is no hex color like #ff66cc", text));
+    }
+    Ok(u32::from_str_radix(hex, 16).unwrap())
+}
+
+/// Checks that the image is a web address Discord can load
+fn parse_image_url(text: &str) -> Result<String, String> {
+    match Url::parse(text.trim()) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => {
+            Ok(url.to_string())
+        }
+        _ => Err(format!("`{}` is no http(s) link to an image", text)),
+    }
+}
+
+/// The announcement as an embed with the avatar of a lone celebrant, None if the member can't be
+/// fetched so the plain message is sent instead
+pub async fn announcement(
+    http: &serenity::Http,
+    entries: &[&BirthdayEntry],
+    config: &EmbedConfig,
+    title: String,
+    description: String,
+    mention: bool,
+    ping: Option<serenity::RoleId>,
+) -> Option<serenity::CreateMessage> {
+    let title: String = title.chars().take(TITLE_LENGTH).collect();
+    let mut embed = serenity::CreateEmbed::new()
+        .title(title)
+        .description(description);
+    // Several celebrants share the announcement, none of them gets the thumbnail
+    if let [entry] = entries {
+        match entry.guild_id.member(http, entry.user_id).await {
+            Ok(member) => embed = embed.thumbnail(member.face()),
+            Err(error) => {
+                warn!(
+                    user = %entry.user_id,
+                    guild = %entry.guild_id,
+                    %error,
+                    "Failed to fetch the member for the announcement embed, sending plain text"
+                );
+                return None;
+            }
+        }
+    }
+    if let Some(color) = config.color {
+        embed = embed.color(color);
+    }
+    if let Some(url) = &config.image_url {
+        embed = embed.image(url);
+    }
+    // Mentions in embeds don't ping, so they go above it
+    let mut mentions = Vec::new();
+    if let Some(role) = ping {
+        mentions.push(format!("<@&{}>", role));
+    }
+    if mention {
+        mentions.extend(entries.iter().map(|entry| format!("<@{}>", entry.user_id)));
+    }
+    let mut message = serenity::CreateMessage::new()
+        .embed(embed)
+        .allowed_mentions(announcement_mentions(entries, ping));
+    if !mentions.is_empty() {
+        message = message.content(mentions.join(" "));
+    }
+    Some(message)
+}
+
+/// Announces birthdays as an embed with the member's avatar instead of a plain message
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_announcement_embed(
+    ctx: Context<'_>,
+    #[description = "Whether to announce birthdays as an embed"] state: Toggle,
+    #[description = "Accent color as hex, like #ff66cc"] color: Option<String>,
+    #[description = "Link to an image shown in the embed"] image_url: Option<String>,
+) -> Result<(), Error> {
+    let color = color.as_deref().map(parse_color).transpose();
+    let image_url = image_url.as_deref().map(parse_image_url).transpose();
+    let (color, image_url) = match (color, image_url) {
+        (Ok(color), Ok(image_url)) => (color, image_url),
+        (Err(error), _) | (_, Err(error)) => {
+            ctx.say(format!("🐺🎩❌ {}!", error)).await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let config = birthdays.guild_configs.entry(guild_id).or_default();
+    config.announcement_embed = match state {
+        Toggle::On => {
+            let mut embed = config.announcement_embed.take().unwrap_or_default();
+            if color.is_some() {
+                embed.color = color;
+            }
+            if image_url.is_some() {
+                embed.image_url = image_url;
+            }
+            Some(embed)
+        }
+        Toggle::Off => None,
+    };
+    let message = match &config.announcement_embed {
+        Some(embed) => format!(
+            "🖼️🎈 Birthdays are announced as an embed now ({})!",
+            embed.describe()
+        ),
+        None => "🖼️ Birthdays are announced as a plain message again!".to_string(),
+    };
+    let state = if config.announcement_embed.is_some() {
+        "on"
+    } else {
+        "off"
+    };
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("turned {} announcement embeds", state),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(message)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_and_images_are_checked() {
+        assert_eq!(parse_color("#ff66cc"), Ok(0xff66cc));
+        assert_eq!(parse_color("FF66CC"), Ok(0xff66cc));
+        assert!(parse_color("#f6c").is_err());
+        assert!(parse_color("pink").is_err());
+        assert!(parse_color("#ff66cg").is_err());
+
+        assert_eq!(
+            parse_image_url("https://example.com/cake.png"),
+            Ok("https://example.com/cake.png".to_string())
+        );
+        assert!(parse_image_url("example.com/cake.png").is_err());
+        assert!(parse_image_url("javascript:alert(1)").is_err());
+        assert!(parse_image_url("file:///etc/passwd").is_err());
+    }
+}
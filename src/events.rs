@@ -0,0 +1,423 @@
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use poise::serenity_prelude::{self as serenity, GuildId};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    args_to_date, audit, checked_birth_year, due_occurrence, format, i18n::Language, is_moderator,
+    offset::UtcOffset, parse_date, quiet_message, BirthdayEntry, BirthdayList, Context, Error,
+    Visibility,
+};
+
+// Longer labels would crowd the announcement and the lists
+static LABEL_LENGTH: usize = 100;
+
+/// What an entry celebrates. Custom anniversaries are kept in `BirthdayList::events`, apart
+/// from the birthdays, so looking up a member's birthday never finds one of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventKind {
+    #[default]
+    Birthday,
+    // Server-wide ones like the founding day belong to no member, their `user_id` is whoever
+    // set them
+    Custom {
+        label: String,
+        server_wide: bool,
+    },
+}
+
+impl EventKind {
+    pub fn is_birthday(&self) -> bool {
+        matches!(self, EventKind::Birthday)
+    }
+
+    pub fn is_server_wide(&self) -> bool {
+        matches!(
+            self,
+            EventKind::Custom {
+                server_wide: true,
+                ..
+            }
+        )
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        match self {
+            EventKind::Birthday => None,
+            EventKind::Custom { label, .. } => Some(label),
+        }
+    }
+}
+
+/// A custom anniversary that was announced in the year of its occurrence
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EventAnnouncement {
+    pub guild_id: GuildId,
+    pub label: String,
+    pub year: i32,
+}
+
+impl EventAnnouncement {
+    pub fn of(entry: &BirthdayEntry, occurrence: NaiveDate) -> EventAnnouncement {
+        EventAnnouncement {
+            guild_id: entry.guild_id,
+            label: entry.kind.label().unwrap_or_default().to_string(),
+            year: occurrence.year(),
+        }
+    }
+}
+
+/// Position of the guild's anniversary with the label, labels are unique per guild regardless
+/// of case
+fn position(birthdays: &BirthdayList, guild_id: GuildId, label: &str) -> Option<usize> {
+    birthdays.events.iter().position(|entry| {
+        entry.guild_id == guild_id
+            && entry
+                .kind
+                .label()
+                .is_some_and(|found| found.eq_ignore_ascii_case(label))
+    })
+}
+
+/// The custom anniversaries of the guild
+pub fn of_guild(birthdays: &BirthdayList, guild_id: GuildId) -> Vec<&BirthdayEntry> {
+    birthdays
+        .events
+        .iter()
+        .filter(|entry| entry.guild_id == guild_id)
+        .collect()
+}
+
+/// The anniversaries that are due at `now` and weren't announced yet, with their occurrence.
+/// They follow the same announcement time, quiet days and leap day setting as birthdays.
+pub fn due(
+    birthdays: &BirthdayList,
+    now: DateTime<Utc>,
+    in_scope: impl Fn(GuildId) -> bool,
+) -> Vec<(&BirthdayEntry, NaiveDate)> {
+    birthdays
+        .events
+        .iter()
+        .filter(|entry| {
+            in_scope(entry.guild_id)
+                && entry.missing_since.is_none()
+                && !birthdays.left_guilds.contains_key(&entry.guild_id)
+        })
+        .filter_map(|entry| {
+            let config = birthdays.guild_configs.get(&entry.guild_id);
+            let occurrence = due_occurrence(entry, now, config, &BTreeSet::new())?;
+            let announced = birthdays
+                .announced_events
+                .contains(&EventAnnouncement::of(entry, occurrence));
+            (!announced).then_some((entry, occurrence))
+        })
+        .collect()
+}
+
+/// Posts the anniversary in the announcement channel, returns false if there is none
+pub async fn announce(
+    http: &serenity::Http,
+    birthdays: &BirthdayList,
+    entry: &BirthdayEntry,
+    occurrence: NaiveDate,
+) -> Result<bool, serenity::Error> {
+    let Some(channel) = birthdays.server_channels.get(&entry.guild_id) else {
+        return Ok(false);
+    };
+    let label = format::escape(entry.kind.label().unwrap_or_default());
+    let years = entry
+        .year
+        .map(|year| occurrence.year() - year)
+        .filter(|years| *years > 0);
+    let message = match years {
+        Some(years) => format!(
+            "🎊 Today is {} ({} anniversary)!",
+            label,
+            format::ordinal(years)
+        ),
+        None => format!("🎊 Today is {}!", label),
+    };
+    channel.send_message(http, quiet_message(message)).await?;
+    Ok(true)
+}
+
+/// Prunes the anniversaries announced before the previous year
+pub fn prune(announced: &mut BTreeSet<EventAnnouncement>, today: NaiveDate) {
+    announced.retain(|announcement| announcement.year >= today.year() - 1);
+}
+
+/// Whether the invoking user may change the anniversary, replies why not otherwise. Only
+/// moderators may touch server-wide anniversaries and those of other members.
+async fn may_change(
+    ctx: Context<'_>,
+    server_wide: bool,
+    user_id: serenity::UserId,
+) -> Result<bool, Error> {
+    if (server_wide || user_id != ctx.author().id) && !is_moderator(ctx).await {
+        ctx.say("🐺🎩❌ Only moderators can change server-wide anniversaries and those of others!")
+            .await?;
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Announces a recurring anniversary like the server's founding day, replaces one with the
+/// same label
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn set_anniversary(
+    ctx: Context<'_>,
+    #[description = "What is celebrated, like \"the server's founding day\""] label: String,
+    #[description = "Date as DD.MM.YYYY, or DD.MM. without a year"] date: String,
+    #[description = "Member it belongs to, server-wide if empty"] user: Option<serenity::User>,
+    #[description = "UTC offset like +2, -5 or +5:30 (defaults to +0)"] utc_offset: Option<String>,
+) -> Result<(), Error> {
+    let label = label.trim().to_string();
+    let date = parse_date(&date, Language::English).and_then(|date| {
+        if label.is_empty() || label.chars().count() > LABEL_LENGTH {
+            return Err(format!(
+                "The label must be 1 to {} characters long",
+                LABEL_LENGTH
+            ));
+        }
+        let utc_offset = match &utc_offset {
+            Some(utc_offset) => UtcOffset::parse(utc_offset, Language::English)?,
+            None => UtcOffset::default(),
+        };
+        checked_birth_year(date.2, utc_offset, Utc::now(), Language::English)?;
+        Ok((date, utc_offset))
+    });
+    let ((day, month, year), utc_offset) = match date {
+        Ok(date) => date,
+        Err(error) => {
+            ctx.say(format!("🐺🎩❌ {}!", format::escape(&error)))
+                .await?;
+            return Ok(());
+        }
+    };
+    let date = args_to_date(day, month, year)?;
+
+    let guild_id = ctx.guild_id().unwrap();
+    let server_wide = user.is_none();
+    let user_id = user.map_or(ctx.author().id, |user| user.id);
+    // Replacing an anniversary needs the permission to change it as well
+    let existing = ctx
+        .data()
+        .storage
+        .read_with(|birthdays| {
+            position(birthdays, guild_id, &label).map(|index| {
+                let existing = &birthdays.events[index];
+                (existing.kind.is_server_wide(), existing.user_id)
+            })
+        })
+        .await;
+    if let Some((server_wide, user_id)) = existing {
+        if !may_change(ctx, server_wide, user_id).await? {
+            return Ok(());
+        }
+    }
+    if !may_change(ctx, server_wide, user_id).await? {
+        return Ok(());
+    }
+
+    let mut entry = BirthdayEntry {
+        user_id,
+        guild_id,
+        name: label.clone(),
+        date,
+        year: year.map(|year| year as i32),
+        last_announcement: None,
+        utc_offset,
+        snoozed: None,
+        visibility: Visibility::Public,
+        private_year: false,
+        missing_since: None,
+        created_at: None,
+        updated_at: None,
+        set_by: None,
+        gift_note: None,
+        note: None,
+        kind: EventKind::Custom {
+            label: label.clone(),
+            server_wide,
+        },
+    };
+    entry.touch(ctx.author().id, Utc::now());
+    // The permission checks take a while, the change is made on the data as it is by now
+    let date_format = ctx
+        .data()
+        .storage
+        .update(|birthdays| {
+            if let Some(index) = position(birthdays, guild_id, &label) {
+                birthdays.events.remove(index);
+            }
+            birthdays.events.push(entry.clone());
+            let date_format = birthdays.date_format(guild_id);
+            audit(
+                birthdays,
+                guild_id,
+                ctx.author().id,
+                format!(
+                    "set the anniversary {} to {}",
+                    format::escape(&label),
+                    date_format.format(date.day(), date.month(), None)
+                ),
+            );
+            date_format
+        })
+        .await?;
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!(
+                "🎊 {} is announced every year on {} now!",
+                format::escape(&label),
+                date_format.format(date.day(), date.month(), None)
+            ))
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn autocomplete_label<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    let labels: Vec<String> = match ctx.guild_id() {
+        Some(guild_id) => {
+            ctx.data()
+                .storage
+                .read_with(|birthdays| {
+                    of_guild(birthdays, guild_id)
+                        .into_iter()
+                        .filter_map(|entry| entry.kind.label().map(str::to_string))
+                        .collect()
+                })
+                .await
+        }
+        None => Vec::new(),
+    };
+    labels
+        .into_iter()
+        .filter(move |label| label.to_lowercase().contains(&partial.to_lowercase()))
+        .take(25)
+}
+
+/// Stops announcing an anniversary
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn remove_anniversary(
+    ctx: Context<'_>,
+    #[description = "Label of the anniversary"]
+    #[autocomplete = "autocomplete_label"]
+    label: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let missing = format!(
+        "☹️🎊 There is no anniversary called `{}` in this server!",
+        format::escape(&label)
+    );
+    let found = ctx
+        .data()
+        .storage
+        .read_with(|birthdays| {
+            position(birthdays, guild_id, label.trim()).map(|index| {
+                let entry = &birthdays.events[index];
+                (entry.kind.is_server_wide(), entry.user_id)
+            })
+        })
+        .await;
+    let Some((server_wide, user_id)) = found else {
+        ctx.say(missing).await?;
+        return Ok(());
+    };
+    if !may_change(ctx, server_wide, user_id).await? {
+        return Ok(());
+    }
+
+    // It may have been removed while the permissions were checked
+    let removed = ctx
+        .data()
+        .storage
+        .update(|birthdays| {
+            let index = position(birthdays, guild_id, label.trim())?;
+            let entry = birthdays.events.remove(index);
+            let label = entry.kind.label().unwrap_or_default().to_string();
+            audit(
+                birthdays,
+                guild_id,
+                ctx.author().id,
+                format!("removed the anniversary {}", format::escape(&label)),
+            );
+            Some(label)
+        })
+        .await?;
+    let Some(label) = removed else {
+        ctx.say(missing).await?;
+        return Ok(());
+    };
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!(
+                "🗑️🎊 {} is no longer announced!",
+                format::escape(&label)
+            ))
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(label: &str, month: u32, day: u32, year: Option<i32>) -> BirthdayEntry {
+        BirthdayEntry {
+            name: label.to_string(),
+            date: NaiveDate::from_ymd_opt(year.unwrap_or(2024), month, day).unwrap(),
+            year,
+            kind: EventKind::Custom {
+                label: label.to_string(),
+                server_wide: true,
+            },
+            ..BirthdayEntry::example(1, 1)
+        }
+    }
+
+    #[test]
+    fn anniversaries_are_due_once_a_year() {
+        let now = DateTime::parse_from_rfc3339("2025-03-14T12:00:00Z")
+            .unwrap()
+            .to_utc();
+        let mut birthdays = BirthdayList {
+            events: vec![
+                event("the founding day", 3, 14, Some(2019)),
+                event("pi day", 3, 15, None),
+            ],
+            ..Default::default()
+        };
+        let found = due(&birthdays, now, |_| true);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0.kind.label(), Some("the founding day"));
+        assert!(due(&birthdays, now, |guild_id| guild_id != GuildId::new(1)).is_empty());
+
+        let announcement = EventAnnouncement::of(found[0].0, found[0].1);
+        birthdays.announced_events.insert(announcement);
+        assert!(due(&birthdays, now, |_| true).is_empty());
+        assert_eq!(position(&birthdays, GuildId::new(1), "PI DAY"), Some(1));
+        assert_eq!(position(&birthdays, GuildId::new(2), "pi day"), None);
+    }
+
+    #[test]
+    fn entries_without_a_kind_are_birthdays() {
+        let mut value = serde_json::to_value(event("pi day", 3, 15, None)).unwrap();
+        assert!(value.get("kind").is_some());
+        value.as_object_mut().unwrap().remove("kind");
+        let entry: BirthdayEntry = serde_json::from_value(value).unwrap();
+        assert_eq!(entry.kind, EventKind::Birthday);
+        // Birthdays are stored without it, like before anniversaries existed
+        let value = serde_json::to_value(entry).unwrap();
+        assert!(value.get("kind").is_none());
+    }
+}
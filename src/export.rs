@@ -0,0 +1,307 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Datelike, Utc};
+use poise::serenity_prelude::{
+    self as serenity, ChannelId, CreateAttachment, GuildId, PermissionOverwriteType, Permissions,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::{
+    quiet_message, storage::Storage, BirthdayEntry, BirthdayList, Context, Error, Visibility,
+};
+
+static EXPORT_CHECK_TIME: u64 = 60 * 60; // 1 hour
+static EXPORT_INTERVAL_DAYS: i64 = 7;
+// A failed export is retried after this long instead of waiting for the next week
+static RETRY_HOURS: i64 = 24;
+
+/// Weekly export of a guild's birthdays into a private channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportConfig {
+    pub channel: ChannelId,
+    pub last_export: Option<DateTime<Utc>>,
+    // Set while exports fail, admins are only alerted about the first failure
+    pub failed_at: Option<DateTime<Utc>>,
+}
+
+impl ExportConfig {
+    fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.last_export
+            .is_none_or(|last| now - last >= chrono::Duration::days(EXPORT_INTERVAL_DAYS))
+            && self
+                .failed_at
+                .is_none_or(|failed| now - failed >= chrono::Duration::hours(RETRY_HOURS))
+    }
+}
+
+/// Entries of a guild that may leave the bot, private birthdays are only known to the bot
+pub fn exported_entries(birthdays: &BirthdayList, guild_id: GuildId) -> Vec<&BirthdayEntry> {
+    birthdays
+        .entries
+        .iter()
+        .filter(|entry| entry.guild_id == guild_id && entry.visibility != Visibility::Private)
+        .collect()
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// CSV with one row per birthday: user_id, name, day, month, year, utc_offset. The year is
+/// empty for birthdays without one, `import_birthdays` reads the file back the same way.
+pub fn guild_csv(birthdays: &BirthdayList, guild_id: GuildId) -> String {
+    let mut csv = String::from("user_id,name,day,month,year,utc_offset\n");
+    for entry in exported_entries(birthdays, guild_id) {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            entry.user_id,
+            csv_field(&entry.name),
+            entry.date.day(),
+            entry.date.month(),
+            entry.year.map(|year| year.to_string()).unwrap_or_default(),
+            entry.utc_offset
+        ));
+    }
+    csv
+}
+
+/// The birthdays of a guild together with its settings
+pub fn guild_json(birthdays: &BirthdayList, guild_id: GuildId) -> Result<String, Error> {
+    // Gift notes are only for the organizers
+    let entries: Vec<BirthdayEntry> = exported_entries(birthdays, guild_id)
+        .into_iter()
+        .map(|entry| BirthdayEntry {
+            gift_note: None,
+            note: None,
+            ..entry.clone()
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&serde_json::json!({
+        "guild_id": guild_id,
+        "entries": entries,
+        "announcement_channel": birthdays.server_channels.get(&guild_id),
+        "config": birthdays.guild_configs.get(&guild_id),
+    }))?)
+}
+
+/// Whether @everyone can see the channel, exports shouldn't end up in such a channel
+pub async fn visible_to_everyone(
+    http: &serenity::Http,
+    guild_id: GuildId,
+    channel: ChannelId,
+) -> Result<bool, Error> {
+    let guild = guild_id.to_partial_guild(http).await?;
+    let everyone = guild_id.everyone_role();
+    let mut permissions = guild
+        .roles
+        .get(&everyone)
+        .map_or(Permissions::empty(), |role| role.permissions);
+
+    let Some(channel) = channel.to_channel(http).await?.guild() else {
+        return Ok(false);
+    };
+    for overwrite in &channel.permission_overwrites {
+        if overwrite.kind == PermissionOverwriteType::Role(everyone) {
+            permissions = (permissions & !overwrite.deny) | overwrite.allow;
+        }
+    }
+    Ok(permissions.view_channel())
+}
+
+async fn send_export(
+    http: &serenity::Http,
+    birthdays: &BirthdayList,
+    guild_id: GuildId,
+    channel: ChannelId,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    let name = format!("birthdays-{}", now.format("%Y-%m-%d"));
+    let message = quiet_message("🗄️🎈 Weekly export of this server's birthdays")
+        .add_file(CreateAttachment::bytes(
+            guild_csv(birthdays, guild_id),
+            format!("{}.csv", name),
+        ))
+        .add_file(CreateAttachment::bytes(
+            guild_json(birthdays, guild_id)?,
+            format!("{}.json", name),
+        ));
+    channel.send_message(http, message).await?;
+    Ok(())
+}
+
+async fn alert_owner(
+    http: &serenity::Http,
+    guild_id: GuildId,
+    channel: ChannelId,
+    error: &Error,
+) -> Result<(), Error> {
+    let guild = guild_id.to_partial_guild(http).await?;
+    let message = format!(
+        "🐺🎩❌ The weekly birthday export of {} to <#{}> failed: {}\n\
+        Please check that I can send files in that channel, I'll keep trying once a day.",
+        guild.name, channel, error
+    );
+    guild
+        .owner_id
+        .create_dm_channel(http)
+        .await?
+        .send_message(http, quiet_message(message))
+        .await?;
+    Ok(())
+}
+
+/// Posts the exports of all guilds that are due
+pub async fn export_periodically(http: Arc<serenity::Http>, storage: Storage) {
+    loop {
+        if let Err(error) = export_once(&http, &storage).await {
+            error!(%error, "Failed to export birthdays");
+        }
+        tokio::time::sleep(tokio::time::Duration::from_secs(EXPORT_CHECK_TIME)).await;
+    }
+}
+
+async fn export_once(http: &serenity::Http, storage: &Storage) -> Result<(), Error> {
+    let birthdays = storage.read().await;
+    let now = Utc::now();
+
+    let mut results = Vec::new();
+    for (guild_id, config) in &birthdays.guild_configs {
+        let Some(export) = config.export.as_ref().filter(|export| export.is_due(now)) else {
+            continue;
+        };
+        let result = send_export(http, &birthdays, *guild_id, export.channel, now).await;
+        if let Err(error) = &result {
+            warn!(guild = %guild_id, %error, "Failed to export the birthdays");
+            if export.failed_at.is_none() {
+                if let Err(error) = alert_owner(http, *guild_id, export.channel, error).await {
+                    warn!(guild = %guild_id, %error, "Failed to alert the owner");
+                }
+            }
+        }
+        results.push((*guild_id, export.channel, result.is_ok()));
+    }
+    if results.is_empty() {
+        return Ok(());
+    }
+
+    storage
+        .update(|birthdays| {
+            for (guild_id, channel, success) in &results {
+                let Some(export) = birthdays
+                    .guild_configs
+                    .get_mut(guild_id)
+                    .and_then(|config| config.export.as_mut())
+                    .filter(|export| export.channel == *channel)
+                else {
+                    continue;
+                };
+                if *success {
+                    export.last_export = Some(now);
+                    export.failed_at = None;
+                } else {
+                    export.failed_at = Some(now);
+                }
+            }
+        })
+        .await
+}
+
+/// Sends you the birthdays of this server as a CSV file, private birthdays are left out
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn export_birthdays(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    let count = exported_entries(&birthdays, guild_id).len();
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!("🗄️🎈 Exported {} birthday(s)!", count))
+            .attachment(CreateAttachment::bytes(
+                guild_csv(&birthdays, guild_id),
+                format!("birthdays-{}.csv", Utc::now().format("%Y-%m-%d")),
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::offset::UtcOffset;
+
+    fn entry(user_id: u64, name: &str, visibility: Visibility) -> BirthdayEntry {
+        BirthdayEntry {
+            name: name.to_string(),
+            utc_offset: UtcOffset::from_hours(2),
+            visibility,
+            ..BirthdayEntry::example(user_id, 1)
+        }
+    }
+
+    #[test]
+    fn csv_leaves_out_private_entries_and_quotes_names() {
+        let birthdays = BirthdayList {
+            entries: vec![
+                entry(1, "Anna, \"the\" tester", Visibility::Public),
+                entry(2, "Private", Visibility::Private),
+                entry(3, "Mods", Visibility::ModsOnly),
+                BirthdayEntry {
+                    date: NaiveDate::from_ymd_opt(2024, 6, 14).unwrap(),
+                    year: None,
+                    utc_offset: UtcOffset::from_minutes(5 * 60 + 30),
+                    ..entry(4, "No year", Visibility::Public)
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            guild_csv(&birthdays, GuildId::new(1)),
+            "user_id,name,day,month,year,utc_offset\n\
+            1,\"Anna, \"\"the\"\" tester\",14,6,1995,+2\n\
+            3,Mods,14,6,1995,+2\n\
+            4,No year,14,6,,+5:30\n"
+        );
+    }
+
+    #[test]
+    fn json_leaves_out_gift_notes() {
+        let mut noted = entry(1, "Anna", Visibility::Public);
+        noted.gift_note = Some("fountain pens".to_string());
+        let birthdays = BirthdayList {
+            entries: vec![noted],
+            ..Default::default()
+        };
+        let json = guild_json(&birthdays, GuildId::new(1)).unwrap();
+        assert!(json.contains("Anna"));
+        assert!(!json.contains("fountain pens"));
+    }
+
+    #[test]
+    fn failed_exports_are_retried_daily() {
+        let now = Utc::now();
+        let mut export = ExportConfig {
+            channel: ChannelId::new(1),
+            last_export: Some(now - chrono::Duration::days(8)),
+            failed_at: Some(now - chrono::Duration::hours(2)),
+        };
+        assert!(!export.is_due(now));
+        export.failed_at = Some(now - chrono::Duration::hours(25));
+        assert!(export.is_due(now));
+        export.last_export = Some(now - chrono::Duration::days(1));
+        export.failed_at = None;
+        assert!(!export.is_due(now));
+    }
+}
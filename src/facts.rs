@@ -0,0 +1,216 @@
+use std::{collections::HashMap, future::Future, time::Duration};
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::Error;
+
+// Announcements wait for the fact at most this long
+static FACT_TIMEOUT: Duration = Duration::from_secs(3);
+static USER_AGENT: &str = "BirthdayBot (https://github.com/AnnsAnns/BirthdayBot)";
+
+/// What kind of "on this day" fact is added to announcements
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, poise::ChoiceParameter,
+)]
+pub enum FactKind {
+    #[name = "events"]
+    Events,
+    #[name = "famous birthdays"]
+    Births,
+}
+
+impl FactKind {
+    pub fn describe(self) -> &'static str {
+        match self {
+            FactKind::Events => "events",
+            FactKind::Births => "famous birthdays",
+        }
+    }
+}
+
+/// Where facts come from, all facts for a date in the format "<year>: <text>"
+pub trait FactSource {
+    fn fetch(
+        &self,
+        kind: FactKind,
+        date: NaiveDate,
+    ) -> impl Future<Output = Result<Vec<String>, Error>> + Send;
+}
+
+/// Wikipedia's "On this day" feed
+pub struct Wikipedia {
+    client: reqwest::Client,
+}
+
+impl Wikipedia {
+    pub fn new() -> Wikipedia {
+        Wikipedia {
+            client: reqwest::Client::builder()
+                .user_agent(USER_AGENT)
+                .timeout(FACT_TIMEOUT)
+                .build()
+                .unwrap(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct OnThisDay {
+    #[serde(default)]
+    events: Vec<WikipediaFact>,
+    #[serde(default)]
+    births: Vec<WikipediaFact>,
+}
+
+#[derive(Deserialize)]
+struct WikipediaFact {
+    text: String,
+    year: Option<i32>,
+}
+
+impl FactSource for Wikipedia {
+    async fn fetch(&self, kind: FactKind, date: NaiveDate) -> Result<Vec<String>, Error> {
+        let endpoint = match kind {
+            FactKind::Events => "events",
+            FactKind::Births => "births",
+        };
+        let url = format!(
+            "https://api.wikimedia.org/feed/v1/wikipedia/en/onthisday/{}/{:02}/{:02}",
+            endpoint,
+            date.month(),
+            date.day()
+        );
+        let response: OnThisDay = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let facts = match kind {
+            FactKind::Events => response.events,
+            FactKind::Births => response.births,
+        };
+        Ok(facts
+            .into_iter()
+            .filter_map(|fact| Some(format!("{}: {}", fact.year?, fact.text)))
+            .collect())
+    }
+}
+
+/// Fetches facts at most once per date and kind, failed fetches count as no facts for the day
+pub struct Facts<S> {
+    source: S,
+    timeout: Duration,
+    cache: Mutex<HashMap<(FactKind, NaiveDate), Vec<String>>>,
+}
+
+impl<S: FactSource> Facts<S> {
+    pub fn new(source: S) -> Facts<S> {
+        Facts::with_timeout(source, FACT_TIMEOUT)
+    }
+
+    fn with_timeout(source: S, timeout: Duration) -> Facts<S> {
+        Facts {
+            source,
+            timeout,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// A fact for the date, None if there is none or it couldn't be fetched in time
+    pub async fn fact(&self, kind: FactKind, date: NaiveDate) -> Option<String> {
+        let mut cache = self.cache.lock().await;
+        if !cache.contains_key(&(kind, date)) {
+            let facts =
+                match tokio::time::timeout(self.timeout, self.source.fetch(kind, date)).await {
+                    Ok(Ok(facts)) => facts,
+                    Ok(Err(error)) => {
+                        warn!(%date, %error, "Failed to fetch the facts");
+                        Vec::new()
+                    }
+                    Err(_) => {
+                        warn!(%date, "Fetching the facts timed out");
+                        Vec::new()
+                    }
+                };
+            // Only the current day is ever needed again
+            cache.retain(|(_, cached), _| *cached == date);
+            cache.insert((kind, date), facts);
+        }
+
+        let facts = &cache[&(kind, date)];
+        // Vary the fact from year to year
+        let index = date.year().rem_euclid(facts.len().max(1) as i32) as usize;
+        facts.get(index).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    struct Mock {
+        calls: AtomicUsize,
+        delay: Duration,
+        fail: bool,
+    }
+
+    impl Mock {
+        fn new(delay: Duration, fail: bool) -> Mock {
+            Mock {
+                calls: AtomicUsize::new(0),
+                delay,
+                fail,
+            }
+        }
+    }
+
+    impl FactSource for Mock {
+        async fn fetch(&self, _: FactKind, date: NaiveDate) -> Result<Vec<String>, Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            if self.fail {
+                return Err("unavailable".into());
+            }
+            Ok(vec![format!("1969: {}", date)])
+        }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 7, day).unwrap()
+    }
+
+    #[tokio::test]
+    async fn facts_are_cached_per_date() {
+        let facts = Facts::new(Mock::new(Duration::ZERO, false));
+        let fact = facts.fact(FactKind::Events, date(20)).await;
+        assert_eq!(fact.as_deref(), Some("1969: 2024-07-20"));
+        facts.fact(FactKind::Events, date(20)).await;
+        assert_eq!(facts.source.calls.load(Ordering::SeqCst), 1);
+
+        facts.fact(FactKind::Events, date(21)).await;
+        assert_eq!(facts.source.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn failures_and_timeouts_leave_out_the_fact() {
+        let facts = Facts::new(Mock::new(Duration::ZERO, true));
+        assert_eq!(facts.fact(FactKind::Births, date(20)).await, None);
+
+        let facts = Facts::with_timeout(
+            Mock::new(Duration::from_secs(10), false),
+            Duration::from_millis(10),
+        );
+        assert_eq!(facts.fact(FactKind::Births, date(20)).await, None);
+        // The failure is cached so the next announcement doesn't wait again
+        assert_eq!(facts.fact(FactKind::Births, date(20)).await, None);
+        assert_eq!(facts.source.calls.load(Ordering::SeqCst), 1);
+    }
+}
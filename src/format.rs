@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+
+/// Order in which the parts of a date are displayed
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, poise::ChoiceParameter,
+)]
+pub enum DateOrder {
+    #[default]
+    #[name = "Day Month Year"]
+    Dmy,
+    #[name = "Month Day Year"]
+    Mdy,
+    #[name = "Year Month Day"]
+    Ymd,
+}
+
+/// How dates are displayed in a guild, defaults to the `14.6` style
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DateFormat {
+    pub order: DateOrder,
+    pub separator: char,
+}
+
+impl Default for DateFormat {
+    fn default() -> Self {
+        Self {
+            order: DateOrder::Dmy,
+            separator: '.',
+        }
+    }
+}
+
+impl DateFormat {
+    /// Formats a day and month, plus the year if one is given
+    pub fn format(&self, day: u32, month: u32, year: Option<i32>) -> String {
+        let parts = match (self.order, year) {
+            (DateOrder::Dmy, Some(year)) => {
+                vec![day.to_string(), month.to_string(), year.to_string()]
+            }
+            (DateOrder::Mdy, Some(year)) => {
+                vec![month.to_string(), day.to_string(), year.to_string()]
+            }
+            (DateOrder::Ymd, Some(year)) => {
+                vec![year.to_string(), month.to_string(), day.to_string()]
+            }
+            (DateOrder::Dmy, None) => vec![day.to_string(), month.to_string()],
+            (DateOrder::Mdy | DateOrder::Ymd, None) => vec![month.to_string(), day.to_string()],
+        };
+        parts.join(&self.separator.to_string())
+    }
+}
+
+/// Makes user-provided text safe to put into a message: Discord markdown is escaped and
+/// mentions can't be formed, so names show up exactly as they were typed
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '\\' | '*' | '_' | '~' | '`' | '|' | '>' | '<' | '[' | ']' | '(' | ')' | '#' | '-' => {
+                escaped.push('\\');
+                escaped.push(character);
+            }
+            // A zero width space keeps @everyone and @here from pinging
+            '@' => escaped.push_str("@\u{200B}"),
+            // Names are always shown on one line
+            '\n' | '\r' => escaped.push(' '),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// The number with its English ordinal suffix, like 1st, 12th or 23rd
+pub fn ordinal(number: i32) -> String {
+    let suffix = match (number % 10, number % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", number, suffix)
+}
+
+fn count(number: i64, unit: &str) -> String {
+    if number == 1 {
+        format!("1 {}", unit)
+    } else {
+        format!("{} {}s", number, unit)
+    }
+}
+
+/// How long the duration is in days and hours, or hours and minutes when it is less than a day
+pub fn countdown(duration: chrono::Duration) -> String {
+    let (days, hours, minutes) = (
+        duration.num_days(),
+        duration.num_hours() % 24,
+        duration.num_minutes() % 60,
+    );
+    let (large, small) = match (days, hours, minutes) {
+        (0, 0, 0) => return "less than a minute".to_string(),
+        (0, 0, _) => return count(minutes, "minute"),
+        (0, _, _) => ((hours, "hour"), (minutes, "minute")),
+        _ => ((days, "day"), (hours, "hour")),
+    };
+    if small.0 == 0 {
+        count(large.0, large.1)
+    } else {
+        format!(
+            "{} and {}",
+            count(large.0, large.1),
+            count(small.0, small.1)
+        )
+    }
+}
+
+// Discord rejects messages longer than this
+static MESSAGE_LIMIT: usize = 2000;
+
+/// Joins the lines into as few messages as possible that each stay within Discord's length
+/// limit, the header starts the first one
+pub fn split_message(header: &str, lines: &[String]) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut current = header.to_string();
+    for line in lines {
+        if !current.is_empty() && current.len() + 1 + line.len() > MESSAGE_LIMIT {
+            messages.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(line);
+    }
+    if !current.is_empty() {
+        messages.push(current);
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(order: DateOrder, separator: char) -> DateFormat {
+        DateFormat { order, separator }
+    }
+
+    #[test]
+    fn default_matches_the_original_style() {
+        assert_eq!(DateFormat::default().format(14, 6, None), "14.6");
+        assert_eq!(DateFormat::default().format(14, 6, Some(1995)), "14.6.1995");
+    }
+
+    #[test]
+    fn orders() {
+        assert_eq!(format(DateOrder::Mdy, '/').format(14, 6, None), "6/14");
+        assert_eq!(
+            format(DateOrder::Mdy, '/').format(14, 6, Some(1995)),
+            "6/14/1995"
+        );
+        assert_eq!(format(DateOrder::Ymd, '-').format(14, 6, None), "6-14");
+        assert_eq!(
+            format(DateOrder::Ymd, '-').format(14, 6, Some(1995)),
+            "1995-6-14"
+        );
+        assert_eq!(
+            format(DateOrder::Dmy, ' ').format(1, 12, Some(2001)),
+            "1 12 2001"
+        );
+    }
+
+    #[test]
+    fn missing_fields_deserialize_to_defaults() {
+        let format: DateFormat = serde_json::from_str(r#"{"order": "Mdy"}"#).unwrap();
+        assert_eq!(format, self::format(DateOrder::Mdy, '.'));
+    }
+
+    #[test]
+    fn escape_neutralizes_markdown_and_mentions() {
+        assert_eq!(
+            escape("**][(https://evil)"),
+            "\\*\\*\\]\\[\\(https://evil\\)"
+        );
+        assert_eq!(escape("@everyone"), "@\u{200B}everyone");
+        assert_eq!(escape("<@&123>"), "\\<@\u{200B}&123\\>");
+        assert_eq!(escape("<#123>"), "\\<\\#123\\>");
+        assert_eq!(escape("a\nb"), "a b");
+    }
+
+    #[test]
+    fn escape_keeps_code_blocks_from_opening() {
+        let name = "```\n`inner` ```rust\n```";
+        let escaped = escape(name);
+        assert!(!escaped.contains('\n'));
+        // Every backtick is escaped, so no code block can start or end
+        assert_eq!(escaped.matches("\\`").count(), name.matches('`').count());
+        assert_eq!(escaped.matches('`').count(), name.matches('`').count());
+    }
+
+    #[test]
+    fn escape_keeps_unicode_intact() {
+        assert_eq!(escape("Zoë 🎂 さくら"), "Zoë 🎂 さくら");
+    }
+
+    #[test]
+    fn long_lists_are_split_below_the_limit() {
+        let lines: Vec<String> = (0..100)
+            .map(|i| format!("{:03} {}", i, "x".repeat(40)))
+            .collect();
+        let messages = split_message("Header", &lines);
+        assert!(messages.len() > 1);
+        assert!(messages
+            .iter()
+            .all(|message| message.len() <= MESSAGE_LIMIT));
+        assert!(messages[0].starts_with("Header\n000 "));
+        assert_eq!(messages.join("\n"), format!("Header\n{}", lines.join("\n")));
+        assert_eq!(split_message("Header", &[]), vec!["Header".to_string()]);
+    }
+
+    #[test]
+    fn ordinals_get_the_right_suffix() {
+        let ordinals: Vec<String> = [1, 2, 3, 4, 11, 12, 13, 21, 22, 23, 101, 111, 112]
+            .into_iter()
+            .map(ordinal)
+            .collect();
+        assert_eq!(
+            ordinals,
+            [
+                "1st", "2nd", "3rd", "4th", "11th", "12th", "13th", "21st", "22nd", "23rd",
+                "101st", "111th", "112th"
+            ]
+        );
+    }
+
+    #[test]
+    fn countdowns_get_coarser_with_distance() {
+        let countdown = |minutes| countdown(chrono::Duration::minutes(minutes));
+        assert_eq!(
+            countdown(23 * 24 * 60 + 14 * 60 + 59),
+            "23 days and 14 hours"
+        );
+        assert_eq!(countdown(24 * 60 + 30), "1 day");
+        assert_eq!(countdown(2 * 24 * 60 + 60), "2 days and 1 hour");
+        assert_eq!(countdown(14 * 60 + 5), "14 hours and 5 minutes");
+        assert_eq!(countdown(60), "1 hour");
+        assert_eq!(countdown(42), "42 minutes");
+        assert_eq!(countdown(0), "less than a minute");
+    }
+}
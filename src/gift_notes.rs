@@ -0,0 +1,169 @@
+use poise::serenity_prelude as serenity;
+use poise::CreateReply;
+
+use crate::{audit, format, BirthdayEntry, Context, Error};
+
+static NOTE_LIMIT: usize = 200; // characters
+
+/// Whether the invoking member has the organizer role of the guild
+async fn is_organizer(ctx: Context<'_>) -> Result<bool, Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let role = ctx
+        .data()
+        .storage
+        .read()
+        .await
+        .guild_configs
+        .get(&guild_id)
+        .and_then(|config| config.organizer_role);
+    let Some(role) = role else {
+        return Ok(false);
+    };
+    Ok(ctx
+        .author_member()
+        .await
+        .is_some_and(|member| member.roles.contains(&role)))
+}
+
+/// Replies only to the organizer, notes must not show up for anyone else. That's why gift notes
+/// are slash commands only, replies to prefix commands can't be ephemeral.
+async fn reply(ctx: Context<'_>, content: String) -> Result<(), Error> {
+    ctx.send(
+        CreateReply::default()
+            .content(content)
+            .ephemeral(true)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Runs `change` on the entry of the user, replies and returns false if the user has no entry
+async fn change_note(
+    ctx: Context<'_>,
+    user: &serenity::User,
+    action: &str,
+    change: impl FnOnce(&mut BirthdayEntry),
+) -> Result<bool, Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let Some(entry) = birthdays
+        .entries
+        .iter_mut()
+        .find(|entry| entry.user_id == user.id && entry.guild_id == guild_id)
+    else {
+        reply(
+            ctx,
+            "☹️🎈 No birthday set for this user for this guild!".to_string(),
+        )
+        .await?;
+        return Ok(false);
+    };
+    change(entry);
+    // The note itself stays out of the audit log
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!(
+            "{} the gift note of {} ({})",
+            action,
+            format::escape(&user.name),
+            user.id
+        ),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+    Ok(true)
+}
+
+/// Gift ideas for members, only visible to the organizer role
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("set", "show", "clear"),
+    subcommand_required
+)]
+pub async fn gift_note(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+async fn check_organizer(ctx: Context<'_>) -> Result<bool, Error> {
+    if is_organizer(ctx).await? {
+        return Ok(true);
+    }
+    reply(
+        ctx,
+        "🐺🎩❌ Only the gift organizers can use gift notes, moderators can pick the organizer role with `set_organizer_role`!"
+            .to_string(),
+    )
+    .await?;
+    Ok(false)
+}
+
+/// Stores a gift idea for a member
+#[poise::command(slash_command, guild_only, check = "check_organizer")]
+async fn set(
+    ctx: Context<'_>,
+    #[description = "Member the idea is for"] user: serenity::User,
+    #[description = "The gift idea"]
+    #[rest]
+    text: String,
+) -> Result<(), Error> {
+    let text = text.trim().to_string();
+    if text.is_empty() || text.chars().count() > NOTE_LIMIT {
+        reply(
+            ctx,
+            format!(
+                "🐺🎩❌ Gift notes must be 1 to {} characters long!",
+                NOTE_LIMIT
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+    if change_note(ctx, &user, "set", |entry| entry.gift_note = Some(text)).await? {
+        reply(ctx, format!("🎁🎈 Saved the gift note for <@{}>!", user.id)).await?;
+    }
+    Ok(())
+}
+
+/// Shows the gift idea stored for a member
+#[poise::command(slash_command, guild_only, check = "check_organizer")]
+async fn show(
+    ctx: Context<'_>,
+    #[description = "Member to show the idea for"] user: serenity::User,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let note = ctx
+        .data()
+        .storage
+        .read_with(|birthdays| {
+            birthdays
+                .entries
+                .iter()
+                .find(|entry| entry.user_id == user.id && entry.guild_id == guild_id)
+                .and_then(|entry| entry.gift_note.clone())
+        })
+        .await;
+    let message = match note {
+        Some(note) => format!("🎁🎈 Gift note for <@{}>: {}", user.id, note),
+        None => format!("☹️🎈 There is no gift note for <@{}>!", user.id),
+    };
+    reply(ctx, message).await
+}
+
+/// Removes the gift idea stored for a member
+#[poise::command(slash_command, guild_only, check = "check_organizer")]
+async fn clear(
+    ctx: Context<'_>,
+    #[description = "Member to remove the idea for"] user: serenity::User,
+) -> Result<(), Error> {
+    if change_note(ctx, &user, "cleared", |entry| entry.gift_note = None).await? {
+        reply(
+            ctx,
+            format!("🗑️🎈 Removed the gift note for <@{}>!", user.id),
+        )
+        .await?;
+    }
+    Ok(())
+}
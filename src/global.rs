@@ -0,0 +1,310 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{self as serenity, GuildId, UserId};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    args_to_date, audit, checked_birth_year, date_to_discord_timestamp, due_occurrence,
+    events::EventKind, format, i18n::Language, offset::UtcOffset, parse_date, retry::FailureKind,
+    BirthdayEntry, BirthdayList, Context, Error, Toggle, Visibility,
+};
+
+/// A birthday a user set once for every guild that turned global birthdays on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GlobalBirthday {
+    pub name: String,
+    // In the birth year if it is known, in NO_YEAR otherwise, like `BirthdayEntry::date`
+    pub date: chrono::NaiveDate,
+    pub year: Option<i32>,
+    pub utc_offset: UtcOffset,
+    pub private_year: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl GlobalBirthday {
+    /// The entry it stands in for in the guild, it is never stored
+    pub fn entry(&self, user_id: UserId, guild_id: GuildId) -> BirthdayEntry {
+        BirthdayEntry {
+            user_id,
+            guild_id,
+            name: self.name.clone(),
+            date: self.date,
+            year: self.year,
+            last_announcement: None,
+            utc_offset: self.utc_offset,
+            snoozed: None,
+            visibility: Visibility::Public,
+            private_year: self.private_year,
+            missing_since: None,
+            created_at: None,
+            updated_at: Some(self.updated_at),
+            set_by: Some(user_id),
+            gift_note: None,
+            note: None,
+            kind: EventKind::Birthday,
+        }
+    }
+}
+
+fn enabled(birthdays: &BirthdayList, guild_id: GuildId) -> bool {
+    birthdays
+        .guild_configs
+        .get(&guild_id)
+        .is_some_and(|config| config.global_birthdays)
+        && !birthdays.left_guilds.contains_key(&guild_id)
+}
+
+/// The global birthday of the user in the guild, if the guild turned them on and the user has
+/// neither a birthday of their own there nor opted out of it. Whether the user is a member of
+/// the guild is up to the caller.
+pub fn fallback(
+    birthdays: &BirthdayList,
+    user_id: UserId,
+    guild_id: GuildId,
+) -> Option<BirthdayEntry> {
+    let global = birthdays.global_birthdays.get(&user_id)?;
+    let own = birthdays
+        .entries
+        .iter()
+        .any(|entry| entry.user_id == user_id && entry.guild_id == guild_id);
+    (enabled(birthdays, guild_id)
+        && !own
+        && !birthdays.birthday_opt_outs.contains(&(guild_id, user_id)))
+    .then(|| global.entry(user_id, guild_id))
+}
+
+/// Every global birthday that stands in for a missing entry in a guild that turned them on,
+/// regardless of membership
+pub fn candidates(birthdays: &BirthdayList) -> Vec<BirthdayEntry> {
+    let guilds: Vec<GuildId> = birthdays
+        .guild_configs
+        .keys()
+        .copied()
+        .filter(|guild_id| enabled(birthdays, *guild_id))
+        .collect();
+    guilds
+        .iter()
+        .flat_map(|guild_id| {
+            birthdays
+                .global_birthdays
+                .keys()
+                .filter_map(|user_id| fallback(birthdays, *user_id, *guild_id))
+        })
+        .collect()
+}
+
+/// Adds the global birthdays that are due at `now` or waiting for a retry to the entries, for
+/// the guilds the users are members of. The entries are only added to check them, they must
+/// never be saved. The data is only copied if there are any. Returns the guilds and users that
+/// were added.
+pub async fn add_due(
+    http: &serenity::Http,
+    birthdays: &mut Arc<BirthdayList>,
+    now: DateTime<Utc>,
+    in_scope: impl Fn(GuildId) -> bool,
+) -> Vec<(GuildId, UserId)> {
+    let mut added = Vec::new();
+    let mut entries = Vec::new();
+    for entry in candidates(birthdays) {
+        if !in_scope(entry.guild_id) {
+            continue;
+        }
+        let config = birthdays.guild_configs.get(&entry.guild_id);
+        let due = due_occurrence(&entry, now, config, &birthdays.announced).is_some();
+        let retried = birthdays.failed_announcements.iter().any(|failed| {
+            failed.guild_id == entry.guild_id
+                && failed.user_id == entry.user_id
+                && !failed.exhausted()
+        });
+        if !due && !retried {
+            continue;
+        }
+        // Membership is only looked up on the birthday, it takes an API call
+        if let Err(error) = entry.guild_id.member(http, entry.user_id).await {
+            // Users who aren't members are a 404 like a missing channel
+            if FailureKind::of(&error) != FailureKind::MissingChannel {
+                warn!(
+                    user = %entry.user_id,
+                    guild = %entry.guild_id,
+                    %error,
+                    "Failed to look up the member for their global birthday"
+                );
+            }
+            continue;
+        }
+        added.push((entry.guild_id, entry.user_id));
+        entries.push(entry);
+    }
+    if !entries.is_empty() {
+        Arc::make_mut(birthdays).entries.extend(entries);
+    }
+    added
+}
+
+/// Sets your birthday for every server that turned on global birthdays
+#[poise::command(slash_command, prefix_command)]
+pub async fn set_global_birthday(
+    ctx: Context<'_>,
+    #[description = "Date as DD.MM.YYYY, or DD.MM. without a year"] date: String,
+    #[description = "UTC offset like +2, -5 or +5:30"] utc_offset: String,
+    #[description = "Hide the year and age from everyone"] private_year: Option<bool>,
+) -> Result<(), Error> {
+    let date = parse_date(&date, Language::English).and_then(|date| {
+        let utc_offset = UtcOffset::parse(&utc_offset, Language::English)?;
+        checked_birth_year(date.2, utc_offset, Utc::now(), Language::English)?;
+        Ok((date, utc_offset))
+    });
+    let ((day, month, year), utc_offset) = match date {
+        Ok(date) => date,
+        Err(error) => {
+            ctx.say(format!("🐺🎩❌ {}!", format::escape(&error)))
+                .await?;
+            return Ok(());
+        }
+    };
+    let date = args_to_date(day, month, year)?;
+
+    let user = ctx.author();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let private_year = private_year.unwrap_or_else(|| {
+        birthdays
+            .global_birthdays
+            .get(&user.id)
+            .is_some_and(|global| global.private_year)
+    });
+    birthdays.global_birthdays.insert(
+        user.id,
+        GlobalBirthday {
+            name: user.name.clone(),
+            date,
+            year: year.map(|year| year as i32),
+            utc_offset,
+            private_year,
+            updated_at: Utc::now(),
+        },
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!(
+                "🌍📅🎈 Set your global birthday to {} (UTC{}), servers that turned on global birthdays use it unless you set one there!",
+                date_to_discord_timestamp(date, utc_offset, false),
+                utc_offset
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Removes your global birthday, it is no longer announced anywhere it was used
+#[poise::command(slash_command, prefix_command)]
+pub async fn remove_global_birthday(ctx: Context<'_>) -> Result<(), Error> {
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    if birthdays
+        .global_birthdays
+        .remove(&ctx.author().id)
+        .is_none()
+    {
+        ctx.say("☹️🎈 You have no global birthday!").await?;
+        return Ok(());
+    }
+    ctx.data().storage.write(&birthdays).await?;
+    ctx.send(
+        poise::CreateReply::default()
+            .content("🌍🗑️ Removed your global birthday, birthdays you set in servers stay!")
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Uses the global birthday of members who didn't set one in this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn enable_global_birthdays(
+    ctx: Context<'_>,
+    #[description = "Whether to use members' global birthdays"] state: Toggle,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let enabled = matches!(state, Toggle::On);
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .global_birthdays = enabled;
+    let state = if enabled { "on" } else { "off" };
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("turned {} global birthdays", state),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = if enabled {
+        "🌍🎈 Members without a birthday in this server are announced with their global birthday now!"
+    } else {
+        "🌍 Only birthdays set in this server are announced now!"
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::GuildConfig;
+
+    #[test]
+    fn global_birthdays_only_fill_in_missing_entries() {
+        let global = GlobalBirthday {
+            name: "global".to_string(),
+            date: NaiveDate::from_ymd_opt(1995, 6, 14).unwrap(),
+            year: Some(1995),
+            utc_offset: UtcOffset::default(),
+            private_year: false,
+            updated_at: Utc::now(),
+        };
+        let enabled = GuildConfig {
+            global_birthdays: true,
+            ..Default::default()
+        };
+        let mut birthdays = BirthdayList {
+            entries: vec![global.entry(UserId::new(1), GuildId::new(1))],
+            global_birthdays: BTreeMap::from([(UserId::new(1), global.clone())]),
+            guild_configs: [
+                (GuildId::new(1), enabled.clone()),
+                (GuildId::new(2), enabled),
+                (GuildId::new(3), GuildConfig::default()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        birthdays.entries[0].name = "own".to_string();
+
+        // The guild's own entry wins and guilds that didn't turn it on are left alone
+        let found = candidates(&birthdays);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].guild_id, GuildId::new(2));
+        assert_eq!(found[0].name, "global");
+
+        birthdays
+            .birthday_opt_outs
+            .insert((GuildId::new(2), UserId::new(1)));
+        assert!(candidates(&birthdays).is_empty());
+        assert!(fallback(&birthdays, UserId::new(1), GuildId::new(3)).is_none());
+    }
+}
@@ -0,0 +1,708 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use chrono::{Datelike, NaiveDate, Utc};
+use poise::serenity_prelude::{GuildId, UserId};
+use ring::{rand::SystemRandom, signature};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{error, warn};
+
+use crate::{audit, export::exported_entries, storage::Storage, BirthdayList, Context, Error};
+
+static SCOPE: &str = "https://www.googleapis.com/auth/calendar";
+static API: &str = "https://www.googleapis.com/calendar/v3/calendars";
+static REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+static SYNC_INTERVAL: Duration = Duration::from_secs(10 * 60);
+// Failed syncs are retried after 1, 2, 4, ... minutes, up to MAX_BACKOFF
+static FIRST_BACKOFF: Duration = Duration::from_secs(60);
+static MAX_BACKOFF: Duration = Duration::from_secs(6 * 60 * 60);
+// Private property on every event the bot created, so they can be found again
+static GUILD_PROPERTY: &str = "birthdaybot_guild";
+static USER_PROPERTY: &str = "birthdaybot_user";
+// Events are recurring from this year on, it is a leap year so February 29th exists
+static EVENT_YEAR: i32 = 2000;
+
+/// Event the bot created for a birthday, kept to update or delete it later
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalendarEvent {
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    pub calendar_id: String,
+    pub event_id: String,
+    // What the event was last set to
+    pub summary: String,
+    pub date: NaiveDate,
+}
+
+/// The event a birthday should have
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Wanted {
+    guild_id: GuildId,
+    user_id: UserId,
+    calendar_id: String,
+    summary: String,
+    date: NaiveDate,
+}
+
+impl Wanted {
+    fn body(&self) -> serde_json::Value {
+        // Yearly on February 29th would skip three out of four years
+        let rule = if self.date.month() == 2 && self.date.day() == 29 {
+            "RRULE:FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1"
+        } else {
+            "RRULE:FREQ=YEARLY"
+        };
+        serde_json::json!({
+            "summary": self.summary,
+            "start": { "date": self.date },
+            "end": { "date": self.date.succ_opt() },
+            "recurrence": [rule],
+            "transparency": "transparent",
+            "extendedProperties": {
+                "private": {
+                    GUILD_PROPERTY: self.guild_id.to_string(),
+                    USER_PROPERTY: self.user_id.to_string(),
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Change<'a> {
+    Create(Wanted),
+    Update(&'a CalendarEvent, Wanted),
+    Delete(&'a CalendarEvent),
+}
+
+/// Events of the birthdays that guilds sync, private birthdays stay out of the calendar
+fn wanted(birthdays: &BirthdayList) -> Vec<Wanted> {
+    birthdays
+        .guild_configs
+        .iter()
+        .filter_map(|(guild_id, config)| Some((guild_id, config.google_calendar.as_ref()?)))
+        .flat_map(|(guild_id, calendar_id)| {
+            exported_entries(birthdays, *guild_id)
+                .into_iter()
+                .map(|entry| Wanted {
+                    guild_id: entry.guild_id,
+                    user_id: entry.user_id,
+                    calendar_id: calendar_id.clone(),
+                    summary: format!("🎂 {}'s birthday", entry.name),
+                    date: entry.date.with_year(EVENT_YEAR).unwrap(),
+                })
+        })
+        .collect()
+}
+
+/// What has to change for the calendars to match the birthdays. Events in a calendar the
+/// guild no longer syncs to are deleted and created in the new one.
+fn plan(birthdays: &BirthdayList) -> Vec<Change<'_>> {
+    let mut wanted: HashMap<(GuildId, UserId), Wanted> = wanted(birthdays)
+        .into_iter()
+        .map(|wanted| ((wanted.guild_id, wanted.user_id), wanted))
+        .collect();
+    let mut changes = Vec::new();
+    for event in &birthdays.calendar_events {
+        match wanted.remove(&(event.guild_id, event.user_id)) {
+            Some(wanted) if wanted.calendar_id == event.calendar_id => {
+                if wanted.summary != event.summary || wanted.date != event.date {
+                    changes.push(Change::Update(event, wanted));
+                }
+            }
+            Some(wanted) => {
+                changes.push(Change::Delete(event));
+                changes.push(Change::Create(wanted));
+            }
+            None => changes.push(Change::Delete(event)),
+        }
+    }
+    let mut created: Vec<Wanted> = wanted.into_values().collect();
+    created.sort_by_key(|wanted| (wanted.guild_id, wanted.user_id));
+    changes.extend(created.into_iter().map(Change::Create));
+    changes
+}
+
+/// How long to wait after `failures` failed syncs in a row
+fn backoff(failures: u32) -> Duration {
+    if failures == 0 {
+        return SYNC_INTERVAL;
+    }
+    FIRST_BACKOFF
+        .saturating_mul(2u32.saturating_pow(failures - 1))
+        .min(MAX_BACKOFF)
+}
+
+/// The parts of a service account key file the bot needs
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+#[derive(Deserialize)]
+struct Token {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct EventList {
+    #[serde(default)]
+    items: Vec<RemoteEvent>,
+    #[serde(rename = "nextPageToken")]
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RemoteEvent {
+    id: String,
+    #[serde(default)]
+    summary: String,
+    start: Option<EventDate>,
+}
+
+#[derive(Deserialize)]
+struct EventDate {
+    date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct SyncReport {
+    created: usize,
+    updated: usize,
+    deleted: usize,
+}
+
+/// Differences between a calendar and what the bot believes is in it
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Drift {
+    // Deleted from the calendar by someone else
+    missing: usize,
+    // Edited in the calendar by someone else
+    edited: usize,
+    // Created by the bot but no longer known to it
+    untracked: usize,
+}
+
+/// Access to Google Calendar through a service account, configured by the bot owner
+pub struct GoogleCalendar {
+    client: reqwest::Client,
+    email: String,
+    key_pair: signature::RsaKeyPair,
+    token_uri: String,
+    token: Mutex<Option<(String, Instant)>>,
+    // Only one sync at a time, or events could be created twice
+    syncing: Mutex<()>,
+}
+
+impl GoogleCalendar {
+    /// Reads a service account key file as downloaded from the Google Cloud console
+    pub fn from_key_file(path: &str) -> Result<GoogleCalendar, Error> {
+        let key: ServiceAccountKey = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let der: String = key
+            .private_key
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        let key_pair = signature::RsaKeyPair::from_pkcs8(&STANDARD.decode(der)?)
+            .map_err(|error| format!("Invalid private key: {}", error))?;
+        Ok(GoogleCalendar {
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap(),
+            email: key.client_email,
+            key_pair,
+            token_uri: key.token_uri,
+            token: Mutex::new(None),
+            syncing: Mutex::new(()),
+        })
+    }
+
+    /// A signed JWT asking for access to calendars
+    fn assertion(&self) -> Result<String, Error> {
+        let now = Utc::now().timestamp();
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256","typ":"JWT"}"#);
+        let claims = URL_SAFE_NO_PAD.encode(serde_json::to_string(&serde_json::json!({
+            "iss": self.email,
+            "scope": SCOPE,
+            "aud": self.token_uri,
+            "iat": now,
+            "exp": now + 3600,
+        }))?);
+        let message = format!("{}.{}", header, claims);
+        let mut signed = vec![0; self.key_pair.public().modulus_len()];
+        self.key_pair
+            .sign(
+                &signature::RSA_PKCS1_SHA256,
+                &SystemRandom::new(),
+                message.as_bytes(),
+                &mut signed,
+            )
+            .map_err(|_| "Failed to sign the token request")?;
+        Ok(format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signed)))
+    }
+
+    async fn access_token(&self) -> Result<String, Error> {
+        let mut token = self.token.lock().await;
+        if let Some((token, expires)) = token.as_ref() {
+            if Instant::now() < *expires {
+                return Ok(token.clone());
+            }
+        }
+        let response: Token = self
+            .client
+            .post(&self.token_uri)
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", &self.assertion()?),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        // Renewed a minute early so it can't expire in the middle of a request
+        let lifetime = Duration::from_secs(response.expires_in.saturating_sub(60));
+        *token = Some((response.access_token.clone(), Instant::now() + lifetime));
+        Ok(response.access_token)
+    }
+
+    fn events_url(calendar_id: &str, event_id: Option<&str>) -> reqwest::Url {
+        let mut url = reqwest::Url::parse(API).unwrap();
+        {
+            let mut segments = url.path_segments_mut().unwrap();
+            segments.push(calendar_id).push("events");
+            if let Some(event_id) = event_id {
+                segments.push(event_id);
+            }
+        }
+        url
+    }
+
+    async fn create(&self, wanted: &Wanted) -> Result<String, Error> {
+        let event: RemoteEvent = self
+            .client
+            .post(Self::events_url(&wanted.calendar_id, None))
+            .bearer_auth(self.access_token().await?)
+            .json(&wanted.body())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(event.id)
+    }
+
+    async fn update(&self, event: &CalendarEvent, wanted: &Wanted) -> Result<(), Error> {
+        self.client
+            .put(Self::events_url(&event.calendar_id, Some(&event.event_id)))
+            .bearer_auth(self.access_token().await?)
+            .json(&wanted.body())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn delete(&self, calendar_id: &str, event_id: &str) -> Result<(), Error> {
+        let response = self
+            .client
+            .delete(Self::events_url(calendar_id, Some(event_id)))
+            .bearer_auth(self.access_token().await?)
+            .send()
+            .await?;
+        // Events deleted by someone else are gone already
+        if matches!(response.status().as_u16(), 404 | 410) {
+            return Ok(());
+        }
+        response.error_for_status()?;
+        Ok(())
+    }
+
+    /// The events the bot created for the guild in the calendar
+    async fn list(&self, calendar_id: &str, guild_id: GuildId) -> Result<Vec<RemoteEvent>, Error> {
+        let mut events = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut request = self
+                .client
+                .get(Self::events_url(calendar_id, None))
+                .bearer_auth(self.access_token().await?)
+                .query(&[
+                    (
+                        "privateExtendedProperty",
+                        format!("{}={}", GUILD_PROPERTY, guild_id),
+                    ),
+                    ("maxResults", "2500".to_string()),
+                ]);
+            if let Some(page_token) = &page_token {
+                request = request.query(&[("pageToken", page_token)]);
+            }
+            let page: EventList = request.send().await?.error_for_status()?.json().await?;
+            events.extend(page.items);
+            match page.next_page_token {
+                Some(next) => page_token = Some(next),
+                None => return Ok(events),
+            }
+        }
+    }
+
+    /// Applies the changes, only `guild_id` if given. Stops at the first error, every change
+    /// made until then is kept in the data.
+    async fn sync(
+        &self,
+        storage: &Storage,
+        guild_id: Option<GuildId>,
+    ) -> Result<SyncReport, Error> {
+        let _syncing = self.syncing.lock().await;
+        let birthdays = storage.read().await;
+        let mut report = SyncReport::default();
+        let mut created = Vec::new();
+        let mut updated = Vec::new();
+        let mut deleted = HashSet::new();
+        let mut result = Ok(());
+        for change in plan(&birthdays) {
+            let change_guild = match &change {
+                Change::Create(wanted) | Change::Update(_, wanted) => wanted.guild_id,
+                Change::Delete(event) => event.guild_id,
+            };
+            if guild_id.is_some_and(|guild_id| guild_id != change_guild) {
+                continue;
+            }
+            let applied = match change {
+                Change::Create(wanted) => self.create(&wanted).await.map(|event_id| {
+                    report.created += 1;
+                    created.push(CalendarEvent {
+                        guild_id: wanted.guild_id,
+                        user_id: wanted.user_id,
+                        calendar_id: wanted.calendar_id,
+                        event_id,
+                        summary: wanted.summary,
+                        date: wanted.date,
+                    });
+                }),
+                Change::Update(event, wanted) => self.update(event, &wanted).await.map(|_| {
+                    report.updated += 1;
+                    updated.push((event.event_id.clone(), wanted));
+                }),
+                Change::Delete(event) => self
+                    .delete(&event.calendar_id, &event.event_id)
+                    .await
+                    .map(|_| {
+                        report.deleted += 1;
+                        deleted.insert(event.event_id.clone());
+                    }),
+            };
+            if let Err(error) = applied {
+                result = Err(error);
+                break;
+            }
+        }
+
+        storage
+            .update(|birthdays| {
+                let events = &mut birthdays.calendar_events;
+                events.retain(|event| !deleted.contains(&event.event_id));
+                for (event_id, wanted) in &updated {
+                    if let Some(event) = events.iter_mut().find(|event| event.event_id == *event_id)
+                    {
+                        event.summary = wanted.summary.clone();
+                        event.date = wanted.date;
+                    }
+                }
+                events.extend(created.iter().cloned());
+            })
+            .await?;
+        result.map(|_| report)
+    }
+
+    /// Compares the guild's calendar with what the bot believes is in it. Changes made by
+    /// others are forgotten, so the next sync puts the events back, and events the bot lost
+    /// track of are deleted.
+    async fn find_drift(
+        &self,
+        storage: &Storage,
+        guild_id: GuildId,
+        calendar_id: &str,
+    ) -> Result<Drift, Error> {
+        let _syncing = self.syncing.lock().await;
+        let remote = self.list(calendar_id, guild_id).await?;
+        let birthdays = storage.read().await;
+        let tracked: HashMap<&str, &CalendarEvent> = birthdays
+            .calendar_events
+            .iter()
+            .filter(|event| event.guild_id == guild_id && event.calendar_id == calendar_id)
+            .map(|event| (event.event_id.as_str(), event))
+            .collect();
+
+        let mut drift = Drift::default();
+        let mut edited = HashSet::new();
+        for event in &remote {
+            match tracked.get(event.id.as_str()) {
+                Some(tracked) => {
+                    let date = event.start.as_ref().and_then(|start| start.date);
+                    if event.summary != tracked.summary || date != Some(tracked.date) {
+                        drift.edited += 1;
+                        edited.insert(event.id.clone());
+                    }
+                }
+                None => {
+                    drift.untracked += 1;
+                    self.delete(calendar_id, &event.id).await?;
+                }
+            }
+        }
+        let present: HashSet<&str> = remote.iter().map(|event| event.id.as_str()).collect();
+        let missing: HashSet<String> = tracked
+            .keys()
+            .filter(|event_id| !present.contains(*event_id))
+            .map(|event_id| event_id.to_string())
+            .collect();
+        drift.missing = missing.len();
+
+        storage
+            .update(|birthdays| {
+                birthdays
+                    .calendar_events
+                    .retain(|event| !missing.contains(&event.event_id));
+                for event in &mut birthdays.calendar_events {
+                    if edited.contains(&event.event_id) {
+                        // Makes the next sync overwrite the edit
+                        event.summary.clear();
+                    }
+                }
+            })
+            .await?;
+        Ok(drift)
+    }
+}
+
+/// Keeps the calendars of all guilds in sync, backing off while the API fails
+pub async fn sync_periodically(calendar: Arc<GoogleCalendar>, storage: Storage) {
+    let mut failures = 0;
+    loop {
+        match calendar.sync(&storage, None).await {
+            Ok(_) => failures = 0,
+            Err(error) => {
+                failures += 1;
+                error!(%error, failures, "Failed to sync the Google calendars");
+            }
+        }
+        tokio::time::sleep(backoff(failures)).await;
+    }
+}
+
+/// Syncs the birthdays of this server to a Google Calendar, leave it empty to stop syncing
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_google_calendar(
+    ctx: Context<'_>,
+    #[description = "ID of the calendar, found in its settings"] calendar_id: Option<String>,
+) -> Result<(), Error> {
+    let Some(calendar) = ctx.data().google_calendar.as_ref() else {
+        ctx.say("🐺🎩❌ The bot owner hasn't set up access to Google Calendar!")
+            .await?;
+        return Ok(());
+    };
+    let guild_id = ctx.guild_id().unwrap();
+    let calendar_id = calendar_id
+        .map(|calendar_id| calendar_id.trim().to_string())
+        .filter(|calendar_id| !calendar_id.is_empty());
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .google_calendar = calendar_id.clone();
+    let message = match &calendar_id {
+        Some(calendar_id) => {
+            audit(
+                &mut birthdays,
+                guild_id,
+                ctx.author().id,
+                format!("set the Google Calendar to {}", calendar_id),
+            );
+            format!(
+                "📅🎈 Birthdays will be synced to `{}` within a few minutes! Share the calendar with `{}` and let it make changes to events.",
+                calendar_id, calendar.email
+            )
+        }
+        None => {
+            audit(
+                &mut birthdays,
+                guild_id,
+                ctx.author().id,
+                "stopped syncing to Google Calendar".to_string(),
+            );
+            "📅 Birthdays are no longer synced, the bot removes its events from the calendar!"
+                .to_string()
+        }
+    };
+    ctx.data().storage.write(&birthdays).await?;
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Checks the Google Calendar for changes made outside the bot and syncs all birthdays again
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn resync_google_calendar(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let calendar_id = ctx
+        .data()
+        .storage
+        .read()
+        .await
+        .guild_configs
+        .get(&guild_id)
+        .and_then(|config| config.google_calendar.clone());
+    let (Some(calendar), Some(calendar_id)) = (ctx.data().google_calendar.as_ref(), calendar_id)
+    else {
+        ctx.say("🐺🎩❌ This server doesn't sync to Google Calendar!")
+            .await?;
+        return Ok(());
+    };
+    ctx.defer().await?;
+    let storage = &ctx.data().storage;
+    let result = match calendar.find_drift(storage, guild_id, &calendar_id).await {
+        Ok(drift) => calendar
+            .sync(storage, Some(guild_id))
+            .await
+            .map(|report| (drift, report)),
+        Err(error) => Err(error),
+    };
+    let (drift, report) = match result {
+        Ok(result) => result,
+        Err(error) => {
+            warn!(guild = %guild_id, %error, "Failed to resync the Google calendar");
+            ctx.say(format!(
+                "🐺🎩❌ Google Calendar refused the sync, is the calendar shared with `{}`? ({})",
+                calendar.email, error
+            ))
+            .await?;
+            return Ok(());
+        }
+    };
+    ctx.say(format!(
+        "📅🎈 Resynced the calendar!\n\
+        - Deleted from the calendar: {}\n\
+        - Edited in the calendar: {}\n\
+        - Unknown to the bot: {} (deleted)\n\
+        - Created {}, updated {} and deleted {} events",
+        drift.missing,
+        drift.edited,
+        drift.untracked,
+        report.created,
+        report.updated,
+        report.deleted
+    ))
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BirthdayEntry, GuildConfig, Visibility};
+
+    fn entry(user_id: u64, name: &str, visibility: Visibility) -> BirthdayEntry {
+        BirthdayEntry {
+            name: name.to_string(),
+            date: NaiveDate::from_ymd_opt(1996, 2, 29).unwrap(),
+            year: Some(1996),
+            visibility,
+            ..BirthdayEntry::example(user_id, 1)
+        }
+    }
+
+    fn event(user_id: u64, calendar_id: &str, summary: &str) -> CalendarEvent {
+        CalendarEvent {
+            guild_id: GuildId::new(1),
+            user_id: UserId::new(user_id),
+            calendar_id: calendar_id.to_string(),
+            event_id: format!("event{}", user_id),
+            summary: summary.to_string(),
+            date: NaiveDate::from_ymd_opt(EVENT_YEAR, 2, 29).unwrap(),
+        }
+    }
+
+    #[test]
+    fn plan_creates_updates_and_deletes() {
+        let birthdays = BirthdayList {
+            entries: vec![
+                entry(1, "Anna", Visibility::Public),
+                entry(2, "Renamed", Visibility::Public),
+                entry(3, "Private", Visibility::Private),
+                entry(5, "Moved", Visibility::ModsOnly),
+            ],
+            guild_configs: [(
+                GuildId::new(1),
+                GuildConfig {
+                    google_calendar: Some("staff".to_string()),
+                    ..Default::default()
+                },
+            )]
+            .into(),
+            calendar_events: vec![
+                event(2, "staff", "🎂 Old name's birthday"),
+                event(3, "staff", "🎂 Private's birthday"),
+                event(4, "staff", "🎂 Removed's birthday"),
+                event(5, "old", "🎂 Moved's birthday"),
+            ],
+            ..Default::default()
+        };
+        let wanted = |user_id: u64, name: &str| Wanted {
+            guild_id: GuildId::new(1),
+            user_id: UserId::new(user_id),
+            calendar_id: "staff".to_string(),
+            summary: format!("🎂 {}'s birthday", name),
+            date: NaiveDate::from_ymd_opt(EVENT_YEAR, 2, 29).unwrap(),
+        };
+        let events = &birthdays.calendar_events;
+        assert_eq!(
+            plan(&birthdays),
+            vec![
+                Change::Update(&events[0], wanted(2, "Renamed")),
+                Change::Delete(&events[1]),
+                Change::Delete(&events[2]),
+                Change::Delete(&events[3]),
+                Change::Create(wanted(5, "Moved")),
+                Change::Create(wanted(1, "Anna")),
+            ]
+        );
+        let body = wanted(1, "Anna").body();
+        assert_eq!(
+            body["recurrence"][0],
+            "RRULE:FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1"
+        );
+        assert_eq!(body["end"]["date"], "2000-03-01");
+        assert_eq!(body["extendedProperties"]["private"][USER_PROPERTY], "1");
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        assert_eq!(backoff(0), SYNC_INTERVAL);
+        assert_eq!(backoff(1), FIRST_BACKOFF);
+        assert_eq!(backoff(3), FIRST_BACKOFF * 4);
+        assert_eq!(backoff(40), MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,501 @@
+use std::fmt::Display;
+
+use poise::serenity_prelude as serenity;
+
+use crate::{audit, format, Context, Error};
+
+/// Languages the bot answers in, guilds without a known language get English
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    English,
+    German,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::English, Language::German];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::German => "Deutsch",
+        }
+    }
+
+    /// The language of a code like `de` or `de-AT`, regardless of case
+    pub fn from_code(code: &str) -> Option<Language> {
+        let code = code.trim().split(['-', '_']).next().unwrap_or_default();
+        Language::ALL
+            .into_iter()
+            .find(|language| language.code().eq_ignore_ascii_case(code))
+    }
+
+    fn texts(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Language::English => ENGLISH,
+            Language::German => GERMAN,
+        }
+    }
+}
+
+// Placeholders like `{name}` are filled in by `text`, every language has to use the same ones
+static ENGLISH: &[(&str, &str)] = &[
+    (
+        "no_birthday",
+        "☹️🎈 No birthday set for this user for this guild!",
+    ),
+    (
+        "birthday_set",
+        "✍️📅🎈 Added birthday for {name} on {date} (UTC{offset}) which is {time} for you!",
+    ),
+    (
+        "birthday_next",
+        "📅🎈 {name}'s birthday is on {date} (UTC{offset}), so {relative} which is {time} for you!",
+    ),
+    (
+        "birthday_next_age",
+        "📅🎈 {name}'s birthday is on {date} (UTC{offset}), they turn {age} {relative} which is {time} for you!",
+    ),
+    (
+        "date_unparsable",
+        "Couldn't parse '{date}', use DD.MM.YYYY or DD.MM. without a year",
+    ),
+    ("date_no_month", "{month} is not a month"),
+    ("date_no_day", "0 is not a day"),
+    ("date_month_days", "{month} only has {days} days"),
+    ("date_february_days", "February {year} only has {days} days"),
+    ("year_in_future", "{year} is in the future"),
+    ("year_too_old", "{year} is more than {max} years ago"),
+    (
+        "offset_unparsable",
+        "`{offset}` is no UTC offset like +2 or +5:30",
+    ),
+    (
+        "offset_out_of_range",
+        "UTC offsets go from -12 to +14, UTC{offset} doesn't exist",
+    ),
+    (
+        "set_birthday_not_allowed",
+        "🐺🎩❌ Only moderators and birthday managers can set other people's birthdays!",
+    ),
+    (
+        "set_birthday_opted_out",
+        "🐺🎩❌ You opted out, opt back in with `birthday_optout` first!",
+    ),
+    (
+        "set_birthday_opted_out_other",
+        "🐺🎩❌ This user opted out, nobody can set a birthday for them in this guild!",
+    ),
+    (
+        "missing_channel",
+        "\n⚠️ Note: this server hasn't configured an announcement channel yet, ask a moderator to run `set_announcement_channel`!",
+    ),
+    (
+        "life_expectancy_range",
+        "🐺🎩❌ The life expectancy must be between {min} and {max}!",
+    ),
+    (
+        "time_left",
+        "💀 {name} is expected to skibidi out of this world {time} ({source} life expectancy of {expectancy})",
+    ),
+    (
+        "time_left_beaten",
+        "💪 {name} already beat the odds of the {source} life expectancy of {expectancy}!",
+    ),
+    ("expectancy_given", "given"),
+    ("expectancy_server", "this server's"),
+    ("expectancy_default", "default"),
+    (
+        "channel_set",
+        "📢🎈 Birthday channel set to <#{channel}>!",
+    ),
+    (
+        "channel_tags_need_forum",
+        "🐺🎩❌ Tags only work for forum channels!",
+    ),
+    (
+        "channel_unknown_tag",
+        "🐺🎩❌ The forum has no tag called `{tag}`! Available tags: {tags}",
+    ),
+    (
+        "announcement",
+        "🎉🎈 Happy{age} Birthday {name}! 🎈🎉{belated}",
+    ),
+    (
+        "announcement_title",
+        "🎉 Happy{age} Birthday {name}! 🎉{belated}",
+    ),
+    ("announcement_belated", " (belated)"),
+    (
+        "announcement_caught_up",
+        "🎉 Belated Happy{age} Birthday {name}! (we were asleep) 🎈",
+    ),
+    (
+        "announcement_caught_up_title",
+        "🎉 Belated Happy{age} Birthday {name}! (we were asleep)",
+    ),
+    ("list_and", " and "),
+    ("announcement_next_up", "\n⏭️ Next up: {name} {time} 🎂"),
+    (
+        "announcement_fallback",
+        "\n-# Posted in the system channel as no announcement channel is set, admins can pick one with `set_announcement_channel`",
+    ),
+];
+
+static GERMAN: &[(&str, &str)] = &[
+    (
+        "no_birthday",
+        "☹️🎈 Für diese Person ist auf diesem Server kein Geburtstag eingetragen!",
+    ),
+    (
+        "birthday_set",
+        "✍️📅🎈 Geburtstag von {name} am {date} (UTC{offset}) eingetragen, bei dir ist das {time}!",
+    ),
+    (
+        "birthday_next",
+        "📅🎈 {name} hat am {date} (UTC{offset}) Geburtstag, also {relative}, bei dir ist das {time}!",
+    ),
+    (
+        "birthday_next_age",
+        "📅🎈 {name} hat am {date} (UTC{offset}) Geburtstag und wird {relative} {age}, bei dir ist das {time}!",
+    ),
+    (
+        "date_unparsable",
+        "'{date}' ist kein Datum, schreib TT.MM.JJJJ oder TT.MM. ohne Jahr",
+    ),
+    ("date_no_month", "{month} ist kein Monat"),
+    ("date_no_day", "0 ist kein Tag"),
+    ("date_month_days", "Der {month} hat nur {days} Tage"),
+    ("date_february_days", "Der Februar {year} hat nur {days} Tage"),
+    ("year_in_future", "{year} liegt in der Zukunft"),
+    ("year_too_old", "{year} ist mehr als {max} Jahre her"),
+    (
+        "offset_unparsable",
+        "`{offset}` ist kein UTC-Versatz wie +2 oder +5:30",
+    ),
+    (
+        "offset_out_of_range",
+        "UTC-Versätze gehen von -12 bis +14, UTC{offset} gibt es nicht",
+    ),
+    (
+        "set_birthday_not_allowed",
+        "🐺🎩❌ Nur Moderation und Geburtstagsverwaltung können die Geburtstage anderer eintragen!",
+    ),
+    (
+        "set_birthday_opted_out",
+        "🐺🎩❌ Du hast widersprochen, nimm das erst mit `birthday_optout` zurück!",
+    ),
+    (
+        "set_birthday_opted_out_other",
+        "🐺🎩❌ Diese Person hat widersprochen, auf diesem Server kann niemand ihren Geburtstag eintragen!",
+    ),
+    (
+        "missing_channel",
+        "\n⚠️ Hinweis: Auf diesem Server ist noch kein Kanal für Ankündigungen eingestellt, bitte eine Moderation, `set_announcement_channel` auszuführen!",
+    ),
+    (
+        "life_expectancy_range",
+        "🐺🎩❌ Die Lebenserwartung muss zwischen {min} und {max} liegen!",
+    ),
+    (
+        "time_left",
+        "💀 {name} wird voraussichtlich {time} aus dieser Welt skibidien (bei der {source} Lebenserwartung von {expectancy})",
+    ),
+    (
+        "time_left_beaten",
+        "💪 {name} hat der {source} Lebenserwartung von {expectancy} schon ein Schnippchen geschlagen!",
+    ),
+    ("expectancy_given", "angegebenen"),
+    ("expectancy_server", "auf diesem Server eingestellten"),
+    ("expectancy_default", "voreingestellten"),
+    (
+        "channel_set",
+        "📢🎈 Geburtstage werden jetzt in <#{channel}> angekündigt!",
+    ),
+    (
+        "channel_tags_need_forum",
+        "🐺🎩❌ Tags gibt es nur in Forenkanälen!",
+    ),
+    (
+        "channel_unknown_tag",
+        "🐺🎩❌ Das Forum hat keinen Tag namens `{tag}`! Verfügbare Tags: {tags}",
+    ),
+    (
+        "announcement",
+        "🎉🎈 Alles Gute zum{age} Geburtstag, {name}! 🎈🎉{belated}",
+    ),
+    (
+        "announcement_title",
+        "🎉 Alles Gute zum{age} Geburtstag, {name}! 🎉{belated}",
+    ),
+    ("announcement_belated", " (nachträglich)"),
+    (
+        "announcement_caught_up",
+        "🎉 Nachträglich alles Gute zum{age} Geburtstag, {name}! (wir haben geschlafen) 🎈",
+    ),
+    (
+        "announcement_caught_up_title",
+        "🎉 Nachträglich alles Gute zum{age} Geburtstag, {name}! (wir haben geschlafen)",
+    ),
+    ("list_and", " und "),
+    ("announcement_next_up", "\n⏭️ Als Nächstes: {name} {time} 🎂"),
+    (
+        "announcement_fallback",
+        "\n-# Im Systemkanal gepostet, da kein Kanal für Ankündigungen eingestellt ist, Admins können mit `set_announcement_channel` einen wählen",
+    ),
+];
+
+/// The text for `key` in the language with the placeholders filled in, the English text if
+/// the language lacks it
+pub fn text(language: Language, key: &str, args: &[(&str, &(dyn Display + Sync))]) -> String {
+    let lookup = |language: Language| {
+        language
+            .texts()
+            .iter()
+            .find(|(found, _)| *found == key)
+            .map(|(_, text)| *text)
+    };
+    let text = lookup(language)
+        .or_else(|| lookup(Language::English))
+        .unwrap_or(key);
+    fill(text, args)
+}
+
+/// Fills in the placeholders in a single pass, so values like names can't smuggle in
+/// placeholders of their own. A placeholder filled in with nothing takes a space next to it
+/// along, so `Happy {age} birthday` doesn't end up with two spaces.
+pub fn fill(template: &str, args: &[(&str, &(dyn Display + Sync))]) -> String {
+    let mut filled = String::new();
+    let mut rest = template;
+    'next: while let Some(c) = rest.chars().next() {
+        for (name, value) in args {
+            let Some(mut after) = rest
+                .strip_prefix('{')
+                .and_then(|rest| rest.strip_prefix(name))
+                .and_then(|rest| rest.strip_prefix('}'))
+            else {
+                continue;
+            };
+            let value = value.to_string();
+            if value.is_empty() && (filled.is_empty() || filled.ends_with(' ')) {
+                match after.strip_prefix(' ') {
+                    Some(stripped) => after = stripped,
+                    None => {
+                        filled.pop();
+                    }
+                }
+            }
+            filled.push_str(&value);
+            rest = after;
+            continue 'next;
+        }
+        filled.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    filled
+}
+
+/// An ordinal number the way the language writes it, like 21st or 21.
+pub fn ordinal(language: Language, number: i32) -> String {
+    match language {
+        Language::English => format::ordinal(number),
+        Language::German => format!("{}.", number),
+    }
+}
+
+static GERMAN_MONTHS: [&str; 12] = [
+    "Januar",
+    "Februar",
+    "März",
+    "April",
+    "Mai",
+    "Juni",
+    "Juli",
+    "August",
+    "September",
+    "Oktober",
+    "November",
+    "Dezember",
+];
+
+/// The name of the month, 1 being January
+pub fn month(language: Language, month: u32) -> &'static str {
+    match language {
+        Language::English => chrono::Month::try_from(month as u8).unwrap().name(),
+        Language::German => GERMAN_MONTHS[month as usize - 1],
+    }
+}
+
+/// Joins names the way the language lists them, like `Anna, Ben and Chris`
+pub fn list(language: Language, items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [item] => item.clone(),
+        [rest @ .., last] => format!(
+            "{}{}{}",
+            rest.join(", "),
+            text(language, "list_and", &[]),
+            last
+        ),
+    }
+}
+
+/// Sets the language the bot answers and announces birthdays in on this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_language(
+    ctx: Context<'_>,
+    #[description = "Language code like en or de"] language: String,
+) -> Result<(), Error> {
+    let Some(language) = Language::from_code(&language) else {
+        let known: Vec<String> = Language::ALL
+            .iter()
+            .map(|language| format!("`{}` ({})", language.code(), language.name()))
+            .collect();
+        ctx.say(format!(
+            "🐺🎩❌ `{}` is no language I speak! Pick one of {}",
+            format::escape(&language),
+            known.join(", ")
+        ))
+        .await?;
+        return Ok(());
+    };
+
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .language = Some(language.code().to_string());
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("set the language to {}", language.name()),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!("🗣️🎈 Language set to {}!", language.name()))
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use poise::serenity_prelude::GuildId;
+
+    use super::*;
+    use crate::{BirthdayList, GuildConfig};
+
+    fn placeholders(text: &str) -> BTreeSet<&str> {
+        text.split('{')
+            .skip(1)
+            .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+            .collect()
+    }
+
+    #[test]
+    fn every_language_has_every_text() {
+        for language in Language::ALL {
+            assert_eq!(language.texts().len(), ENGLISH.len(), "{:?}", language);
+            for (key, english) in ENGLISH {
+                let found = language.texts().iter().find(|(found, _)| found == key);
+                let Some((_, text)) = found else {
+                    panic!("{:?} has no text for {}", language, key);
+                };
+                assert_eq!(
+                    placeholders(text),
+                    placeholders(english),
+                    "{:?} {}",
+                    language,
+                    key
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_languages_fall_back_to_english() {
+        assert_eq!(Language::from_code("DE-at"), Some(Language::German));
+        assert_eq!(Language::from_code("xx"), None);
+        let config = |code: &str| GuildConfig {
+            language: Some(code.to_string()),
+            ..Default::default()
+        };
+        let birthdays = BirthdayList {
+            guild_configs: [
+                (GuildId::new(1), config("de")),
+                (GuildId::new(2), config("xx")),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        assert_eq!(birthdays.language(GuildId::new(1)), Language::German);
+        assert_eq!(birthdays.language(GuildId::new(2)), Language::English);
+        assert_eq!(birthdays.language(GuildId::new(3)), Language::English);
+
+        assert_eq!(
+            text(
+                Language::German,
+                "channel_set",
+                &[("channel", &serenity::ChannelId::new(5))]
+            ),
+            "📢🎈 Geburtstage werden jetzt in <#5> angekündigt!"
+        );
+        assert_eq!(
+            text(Language::English, "announcement_belated", &[]),
+            " (belated)"
+        );
+        assert_eq!(
+            text(
+                Language::English,
+                "announcement",
+                &[
+                    ("age", &""),
+                    ("name", &"{belated}"),
+                    ("belated", &" (belated)")
+                ]
+            ),
+            "🎉🎈 Happy Birthday {belated}! 🎈🎉 (belated)"
+        );
+        assert_eq!(ordinal(Language::German, 21), "21.");
+        assert_eq!(ordinal(Language::English, 21), "21st");
+    }
+
+    #[test]
+    fn names_are_listed_with_and() {
+        let names =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|name| name.to_string()).collect() };
+        assert_eq!(list(Language::English, &names(&["Anna"])), "Anna");
+        assert_eq!(
+            list(Language::English, &names(&["Anna", "Ben"])),
+            "Anna and Ben"
+        );
+        assert_eq!(
+            list(Language::English, &names(&["Anna", "Ben", "Chris"])),
+            "Anna, Ben and Chris"
+        );
+        assert_eq!(
+            list(Language::German, &names(&["Anna", "Ben", "Chris"])),
+            "Anna, Ben und Chris"
+        );
+        assert_eq!(list(Language::English, &[]), "");
+    }
+}
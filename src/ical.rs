@@ -0,0 +1,194 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use poise::serenity_prelude::{CreateAttachment, GuildId};
+
+use crate::{export::exported_entries, BirthdayList, Context, Error, LeapDay, Visibility};
+
+// Events recur from this year on, it is a leap year so February 29th exists
+static EVENT_YEAR: i32 = 2000;
+// Lines longer than this many bytes are folded (RFC 5545 3.1)
+static MAX_LINE_LENGTH: usize = 75;
+
+/// Escapes a text value (RFC 5545 3.3.11)
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Ends the content line with CRLF, splitting it into lines of at most 75 bytes that
+/// continue with a space. Characters are never split.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE_LENGTH {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+/// Yearly recurrence of a birthday. February 29th falls on the day the guild celebrates it on
+/// in other years, a plain yearly rule would skip three out of four years.
+fn recurrence(date: NaiveDate, leap_day: LeapDay) -> &'static str {
+    if date.month() != 2 || date.day() != 29 {
+        return "FREQ=YEARLY";
+    }
+    match leap_day {
+        LeapDay::February28 => "FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1",
+        // The 60th day of the year is February 29th in leap years and March 1st otherwise
+        LeapDay::March1 => "FREQ=YEARLY;BYYEARDAY=60",
+    }
+}
+
+/// A calendar with an all-day event every year for the guild's birthdays that everyone may
+/// look up. The birth year is in the description if it may be shown.
+pub fn guild_calendar(birthdays: &BirthdayList, guild_id: GuildId, now: DateTime<Utc>) -> String {
+    let mut entries: Vec<_> = exported_entries(birthdays, guild_id)
+        .into_iter()
+        .filter(|entry| entry.visibility == Visibility::Public)
+        .collect();
+    entries.sort_by_key(|entry| (entry.date.month(), entry.date.day(), entry.user_id));
+    let leap_day = birthdays.leap_day(guild_id);
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//BirthdayBot//Birthdays//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "X-WR-CALNAME:Birthdays".to_string(),
+    ];
+    for entry in entries {
+        let date = entry.date.with_year(EVENT_YEAR).unwrap();
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}-{}@birthdaybot", guild_id, entry.user_id));
+        lines.push(format!("DTSTAMP:{}", now.format("%Y%m%dT%H%M%SZ")));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")));
+        lines.push("DURATION:P1D".to_string());
+        lines.push(format!("RRULE:{}", recurrence(date, leap_day)));
+        lines.push(format!("SUMMARY:🎂 {}'s birthday", escape(&entry.name)));
+        if let Some(year) = birthdays.birth_year(entry) {
+            lines.push(format!("DESCRIPTION:Born in {}", year));
+        }
+        lines.push("TRANSP:TRANSPARENT".to_string());
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+/// Sends you this server's birthdays as a calendar file, to import into any calendar app
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn export_ical(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    ctx.send(
+        poise::CreateReply::default()
+            .content("📅🎈 Here are this server's birthdays, import the file into your calendar!")
+            .attachment(CreateAttachment::bytes(
+                guild_calendar(&birthdays, guild_id, Utc::now()),
+                "birthdays.ics",
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BirthdayEntry, GuildConfig};
+
+    fn entry(user_id: u64, name: &str, date: NaiveDate, year: Option<i32>) -> BirthdayEntry {
+        BirthdayEntry {
+            name: name.to_string(),
+            date,
+            year,
+            ..BirthdayEntry::example(user_id, 1)
+        }
+    }
+
+    #[test]
+    fn calendar_matches_the_fixture() {
+        let date = |year, month, day| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        let mut private = entry(4, "Hidden", date(1990, 1, 1), Some(1990));
+        private.visibility = Visibility::ModsOnly;
+        let birthdays = BirthdayList {
+            entries: vec![
+                entry(
+                    1,
+                    "Anna; \"the\" tester, Jr.",
+                    date(1995, 6, 14),
+                    Some(1995),
+                ),
+                entry(2, "Leap", date(2024, 2, 29), None),
+                entry(3, &"long name ".repeat(8), date(1980, 12, 31), Some(1980)),
+                private,
+            ],
+            guild_configs: [(
+                GuildId::new(1),
+                GuildConfig {
+                    leap_day: LeapDay::March1,
+                    ..Default::default()
+                },
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let now = DateTime::parse_from_rfc3339("2025-01-02T03:04:05Z")
+            .unwrap()
+            .to_utc();
+
+        let expected = "BEGIN:VCALENDAR\r\n\
+            VERSION:2.0\r\n\
+            PRODID:-//BirthdayBot//Birthdays//EN\r\n\
+            CALSCALE:GREGORIAN\r\n\
+            X-WR-CALNAME:Birthdays\r\n\
+            BEGIN:VEVENT\r\n\
+            UID:1-2@birthdaybot\r\n\
+            DTSTAMP:20250102T030405Z\r\n\
+            DTSTART;VALUE=DATE:20000229\r\n\
+            DURATION:P1D\r\n\
+            RRULE:FREQ=YEARLY;BYYEARDAY=60\r\n\
+            SUMMARY:🎂 Leap's birthday\r\n\
+            TRANSP:TRANSPARENT\r\n\
+            END:VEVENT\r\n\
+            BEGIN:VEVENT\r\n\
+            UID:1-1@birthdaybot\r\n\
+            DTSTAMP:20250102T030405Z\r\n\
+            DTSTART;VALUE=DATE:20000614\r\n\
+            DURATION:P1D\r\n\
+            RRULE:FREQ=YEARLY\r\n\
+            SUMMARY:🎂 Anna\\; \"the\" tester\\, Jr.'s birthday\r\n\
+            DESCRIPTION:Born in 1995\r\n\
+            TRANSP:TRANSPARENT\r\n\
+            END:VEVENT\r\n\
+            BEGIN:VEVENT\r\n\
+            UID:1-3@birthdaybot\r\n\
+            DTSTAMP:20250102T030405Z\r\n\
+            DTSTART;VALUE=DATE:20001231\r\n\
+            DURATION:P1D\r\n\
+            RRULE:FREQ=YEARLY\r\n\
+            SUMMARY:🎂 long name long name long name long name long name long name lo\r\n \
+            ng name long name 's birthday\r\n\
+            DESCRIPTION:Born in 1980\r\n\
+            TRANSP:TRANSPARENT\r\n\
+            END:VEVENT\r\n\
+            END:VCALENDAR\r\n";
+        assert_eq!(guild_calendar(&birthdays, GuildId::new(1), now), expected);
+    }
+}
@@ -0,0 +1,269 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use poise::serenity_prelude::{self as serenity, UserId};
+
+use crate::{
+    args_to_date, audit, checked_birth_year, format, i18n::Language, offset::UtcOffset,
+    put_birthday, Context, Error,
+};
+
+// Larger files are most likely not a list of birthdays
+static MAX_FILE_SIZE: u32 = 1024 * 1024;
+static MAX_ROWS: usize = 5000;
+
+/// A valid row of an imported CSV file
+#[derive(Debug, PartialEq)]
+struct Row {
+    user_id: UserId,
+    name: String,
+    date: NaiveDate,
+    year: Option<i32>,
+    utc_offset: UtcOffset,
+}
+
+/// Splits CSV into records with the line each starts on. Fields may be quoted the way
+/// `export::guild_csv` writes them, empty lines are skipped.
+fn records(text: &str) -> Vec<(usize, Vec<String>)> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                fields.push(std::mem::take(&mut field));
+                if fields.iter().any(|field| !field.trim().is_empty()) {
+                    records.push((start, std::mem::take(&mut fields)));
+                }
+                fields.clear();
+                line += 1;
+                start = line;
+            }
+            c => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+    fields.push(field);
+    if fields.iter().any(|field| !field.trim().is_empty()) {
+        records.push((start, fields));
+    }
+    records
+}
+
+/// Checks a record with the columns user_id, name, day, month, year and utc_offset, the year
+/// may be empty or left out entirely
+fn parse_row(fields: &[String], now: DateTime<Utc>) -> Result<Row, String> {
+    let fields: Vec<&str> = fields.iter().map(|field| field.trim()).collect();
+    let (user_id, name, day, month, year, utc_offset) = match fields[..] {
+        [user_id, name, day, month, year, utc_offset] => {
+            (user_id, name, day, month, year, utc_offset)
+        }
+        [user_id, name, day, month, utc_offset] => (user_id, name, day, month, "", utc_offset),
+        _ => return Err(format!("expected 6 columns, found {}", fields.len())),
+    };
+
+    let user_id = user_id
+        .parse::<u64>()
+        .ok()
+        .filter(|id| *id != 0)
+        .map(UserId::new)
+        .ok_or_else(|| format!("`{}` is no user id", user_id))?;
+    if name.is_empty() {
+        return Err("the name is empty".to_string());
+    }
+    let number = |value: &str, what: &str| {
+        value
+            .parse::<usize>()
+            .map_err(|_| format!("`{}` is no {}", value, what))
+    };
+    let year = match year {
+        "" => None,
+        year => Some(number(year, "year")?),
+    };
+    // The same checks `set_birthday` does
+    let date = args_to_date(number(day, "day")?, number(month, "month")?, year)
+        .map_err(|_| "invalid date".to_string())?;
+    let utc_offset = UtcOffset::parse(utc_offset, Language::English)?;
+    checked_birth_year(year, utc_offset, now, Language::English)?;
+
+    Ok(Row {
+        user_id,
+        name: name.to_string(),
+        date,
+        year: year.map(|year| year as i32),
+        utc_offset,
+    })
+}
+
+/// Valid rows in file order and the rejected rows with their line and reason
+fn parse(text: &str, now: DateTime<Utc>) -> (Vec<Row>, Vec<(usize, String)>) {
+    let mut rows = Vec::new();
+    let mut rejected = Vec::new();
+    for (index, (line, fields)) in records(text).into_iter().enumerate() {
+        let header = fields
+            .first()
+            .is_some_and(|field| field.trim().eq_ignore_ascii_case("user_id"));
+        if index == 0 && header {
+            continue;
+        }
+        match parse_row(&fields, now) {
+            Ok(row) => rows.push(row),
+            Err(reason) => rejected.push((line, reason)),
+        }
+    }
+    (rows, rejected)
+}
+
+/// Imports birthdays from a CSV file, existing birthdays of the same users are replaced
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn import_birthdays(
+    ctx: Context<'_>,
+    #[description = "CSV with the columns user_id, name, day, month, year (may be empty), utc_offset"]
+    file: serenity::Attachment,
+) -> Result<(), Error> {
+    if file.size > MAX_FILE_SIZE {
+        ctx.say("🐺🎩❌ The file is too large for a list of birthdays!")
+            .await?;
+        return Ok(());
+    }
+    let Ok(text) = String::from_utf8(file.download().await?) else {
+        ctx.say("🐺🎩❌ The file isn't a UTF-8 encoded CSV file!")
+            .await?;
+        return Ok(());
+    };
+    let (rows, rejected) = parse(text.trim_start_matches('\u{feff}'), Utc::now());
+    if rows.len() + rejected.len() > MAX_ROWS {
+        ctx.say(format!(
+            "🐺🎩❌ At most {} birthdays can be imported at once!",
+            MAX_ROWS
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    // Members who opted out are left out like everywhere else birthdays are set
+    let (rows, opted_out): (Vec<Row>, Vec<Row>) = rows.into_iter().partition(|row| {
+        !birthdays
+            .birthday_opt_outs
+            .contains(&(guild_id, row.user_id))
+    });
+    if !rows.is_empty() {
+        let now = Utc::now();
+        let imported = rows.len();
+        // Later rows of the same user replace earlier ones, like setting the birthday again
+        for row in rows.iter() {
+            put_birthday(
+                &mut birthdays,
+                row.user_id,
+                guild_id,
+                row.name.clone(),
+                row.date,
+                row.year,
+                row.utc_offset,
+                ctx.author().id,
+                now,
+            );
+        }
+        audit(
+            &mut birthdays,
+            guild_id,
+            ctx.author().id,
+            format!("imported {} birthdays from {}", imported, file.filename),
+        );
+        ctx.data().storage.write(&birthdays).await?;
+        // Only reported as imported once it is on disk
+        ctx.data().storage.flush().await?;
+    }
+
+    let skipped = if opted_out.is_empty() {
+        String::new()
+    } else {
+        format!(", skipped {} member(s) who opted out", opted_out.len())
+    };
+    let header = format!(
+        "📥🎈 Imported {} birthday(s){}, rejected {} row(s){}",
+        rows.len(),
+        skipped,
+        rejected.len(),
+        if rejected.is_empty() { "!" } else { ":" }
+    );
+    let lines: Vec<String> = rejected
+        .iter()
+        .map(|(line, reason)| format!("- Line {}: {}", line, format::escape(reason)))
+        .collect();
+    for message in format::split_message(&header, &lines) {
+        ctx.send(
+            poise::CreateReply::default()
+                .content(message)
+                .allowed_mentions(serenity::CreateAllowedMentions::new()),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_checked_like_set_birthday() {
+        let csv = "user_id,name,day,month,year,utc_offset\r\n\
+            1,\"Anna, \"\"the\"\" tester\",14,6,1995,2\r\n\
+            2,Bob,30,2,,0\n\
+            \n\
+            3,Carl,1,1,-5\n\
+            0,Nobody,1,1,,0\n\
+            4,Dora,1,13,,0\n\
+            5,Emil,1,1,,UTC\n\
+            6,Finn,1,1,,+15\n\
+            7,Gina,1,1,2030,0\n";
+        let now = DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        let (rows, rejected) = parse(csv, now);
+
+        assert_eq!(
+            rows,
+            vec![
+                Row {
+                    user_id: UserId::new(1),
+                    name: "Anna, \"the\" tester".to_string(),
+                    date: NaiveDate::from_ymd_opt(1995, 6, 14).unwrap(),
+                    year: Some(1995),
+                    utc_offset: UtcOffset::from_hours(2),
+                },
+                Row {
+                    user_id: UserId::new(3),
+                    name: "Carl".to_string(),
+                    date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                    year: None,
+                    utc_offset: UtcOffset::from_hours(-5),
+                },
+            ]
+        );
+        let lines: Vec<usize> = rejected.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, vec![3, 6, 7, 8, 9, 10]);
+        assert_eq!(rejected[0].1, "invalid date");
+    }
+}
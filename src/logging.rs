@@ -0,0 +1,99 @@
+use poise::serenity_prelude as serenity;
+use tracing::{field, warn, Instrument, Span};
+use tracing_subscriber::EnvFilter;
+
+// Used unless RUST_LOG says otherwise, the libraries are only heard from when something is off
+static DEFAULT_FILTER: &str = "warn,birthdaybot=info";
+
+/// How log lines are written, picked with BIRTHDAYBOT_LOG_FORMAT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    // One readable line per event
+    Pretty,
+    // One JSON object per event, for journald or docker log collection
+    Json,
+}
+
+impl LogFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "pretty" => Some(Self::Pretty),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Sets up logging, must run before anything is logged. The levels come from RUST_LOG.
+pub fn init() {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let name = std::env::var("BIRTHDAYBOT_LOG_FORMAT").ok();
+    let format = name.as_deref().map(LogFormat::from_name);
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    match format.flatten().unwrap_or(LogFormat::Pretty) {
+        LogFormat::Pretty => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+    if let (Some(name), Some(None)) = (name, format) {
+        warn!(%name, "Unknown BIRTHDAYBOT_LOG_FORMAT, logging in the pretty format");
+    }
+}
+
+/// The span a command invoked by the event runs in, None for events that can't invoke one. The
+/// command itself is only known once it was parsed, see `record_command`.
+fn command_span(event: &serenity::FullEvent) -> Option<Span> {
+    let (guild, user) = match event {
+        serenity::FullEvent::InteractionCreate {
+            interaction: serenity::Interaction::Command(interaction),
+        }
+        | serenity::FullEvent::InteractionCreate {
+            interaction: serenity::Interaction::Autocomplete(interaction),
+        } => (interaction.guild_id, interaction.user.id),
+        serenity::FullEvent::Message { new_message } if !new_message.author.bot => {
+            (new_message.guild_id, new_message.author.id)
+        }
+        _ => return None,
+    };
+    Some(tracing::info_span!(
+        "command",
+        command = field::Empty,
+        guild = guild.map(|guild| guild.get()),
+        user = user.get(),
+    ))
+}
+
+/// Fills in the command of the span it runs in
+pub fn record_command(name: &str) {
+    Span::current().record("command", name);
+}
+
+/// Runs the framework's handling of every event that can invoke a command in a span for it, so
+/// everything logged while running the command tells which command, guild and user it was
+pub struct Traced<F>(pub F);
+
+#[serenity::async_trait]
+impl<F: serenity::Framework> serenity::Framework for Traced<F> {
+    async fn init(&mut self, client: &serenity::Client) {
+        self.0.init(client).await;
+    }
+
+    async fn dispatch(&self, ctx: serenity::Context, event: serenity::FullEvent) {
+        match command_span(&event) {
+            Some(span) => self.0.dispatch(ctx, event).instrument(span).await,
+            None => self.0.dispatch(ctx, event).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_formats_are_picked_by_name() {
+        assert_eq!(LogFormat::from_name("pretty"), Some(LogFormat::Pretty));
+        assert_eq!(LogFormat::from_name(" JSON "), Some(LogFormat::Json));
+        assert_eq!(LogFormat::from_name("xml"), None);
+    }
+}
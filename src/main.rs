@@ -1,352 +1,6584 @@
-use core::task;
-use std::{collections::HashMap, sync::Arc};
+mod alerts;
+mod anniversaries;
+mod backup;
+mod birthday_role;
+mod coverage;
+mod dates;
+mod embeds;
+mod events;
+mod export;
+mod facts;
+mod format;
+mod gift_notes;
+mod global;
+mod google_calendar;
+mod i18n;
+mod ical;
+mod import;
+mod logging;
+mod merge;
+mod metrics;
+mod missed;
+mod month_roles;
+mod notes;
+mod offset;
+mod pages;
+mod picker;
+mod prune;
+mod quiz;
+mod reactions;
+mod reminders;
+mod retirement;
+mod retry;
+mod schedule;
+mod snapshot;
+mod stats;
+mod storage;
+mod templates;
+mod themes;
+mod usage;
+mod wishes;
+mod year_review;
 
-use chrono::{Datelike, FixedOffset, NaiveDate, TimeZone, Utc};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use events::EventKind;
+use format::{DateFormat, DateOrder};
+use i18n::{text, Language};
+use offset::UtcOffset;
 use poise::serenity_prelude::{self as serenity, ChannelId, GuildId};
 use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
+use storage::Storage;
+use tokio::sync::{mpsc, oneshot, watch, Mutex, Notify};
+use tracing::{debug, error, info, warn, Instrument};
+use usage::UsageStats;
 
-static FILE_LOCK: Mutex<()> = Mutex::const_new(());
-static FILE_PATH: &str = "birthdays.json";
+// Age `time_left` counts down to unless the guild or the user picked another one
 static LIFE_EXPECTANCY: i32 = 83;
+static LIFE_EXPECTANCY_RANGE: std::ops::RangeInclusive<i32> = 1..=150;
+// The date of birthdays without a year is in this year, it has to be a leap year so February
+// 29th can be set without one
+static NO_YEAR: i32 = 2024;
+// Ages above this are treated as a typo in the year, such years aren't accepted either
+static MAX_AGE: i32 = 120;
+static DEFAULT_UPCOMING_DAYS: u32 = 30;
+static MAX_UPCOMING_DAYS: u32 = 366;
+// Birthdays that passed this many days ago without an announcement, e.g. while the bot was
+// down, are still announced unless the guild says otherwise
+static DEFAULT_CATCH_UP_DAYS: u32 = 1;
+static MAX_CATCH_UP_DAYS: u32 = 7;
+// Upcoming birthdays shown in the configuration
+static CONFIG_UPCOMING: usize = 3;
+// The announcement task wakes up at least this often, so the metrics can tell it is alive
 static CHECK_TIME: u64 = 60 * 60; // 1 hour
 
-struct Data {} // User data, which is stored and accessible in all command invocations
+// Requested checks that may wait for the running one before `force_check` is turned away
+static CHECK_QUEUE: usize = 4;
+static AUDIT_LOG_LIMIT: usize = 1000;
+static USAGE_FLUSH_TIME: u64 = 5 * 60; // 5 minutes
+static RESTORE_DAYS: i64 = 30;
+// The owner of a guild without an announcement channel is reminded at most this often
+static CHANNEL_NUDGE_DAYS: i64 = 7;
+// Quiet replies to prefix commands are deleted after this long, they can't be ephemeral
+static QUIET_REPLY_TIME: u64 = 30; // seconds
+
+// How long shutting down waits for the running check and for the gateway to close
+static SHUTDOWN_TIME: u64 = 10; // seconds
+
+// Discord's limit for the title of forum posts
+static FORUM_TITLE_LENGTH: usize = 100;
+// Commands that can't be disabled per guild so nobody gets locked out of their data
+static CORE_COMMANDS: &[&str] = &[
+    "set_birthday",
+    "get_birthday",
+    "toggle_command",
+    "config",
+    "remove_birthday",
+    "restore_birthday",
+    "delete_my_data",
+    "birthday_optout",
+];
+
+static DEFAULT_PREFIX: &str = "!";
+
+// User data, which is stored and accessible in all command invocations
+struct Data {
+    default_prefix: String,
+    storage: Storage,
+    // Command invocations that haven't been written to the file yet
+    pending_usage: Arc<Mutex<UsageStats>>,
+    // Requests for `check_for_announcements` to check right away
+    check_requests: mpsc::Sender<CheckRequest>,
+    // When `check_for_announcements` runs its next regular check
+    next_check: Arc<Mutex<DateTime<Utc>>>,
+    // Tells `check_for_announcements` to work out when to check again, after the data changed
+    reschedule: Arc<Notify>,
+    // Whether the message content intent was requested, see `reactions`
+    message_content: bool,
+    reactions: Mutex<reactions::RateLimiter>,
+    // None unless the owner provided a service account key, see `google_calendar`
+    google_calendar: Option<Arc<google_calendar::GoogleCalendar>>,
+    metrics: Arc<metrics::Metrics>,
+}
 type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, Data, Error>;
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 struct BirthdayList {
     entries: Vec<BirthdayEntry>,
     server_channels: HashMap<GuildId, ChannelId>,
+    #[serde(default)]
+    audit_log: Vec<AuditLogEntry>,
+    #[serde(default)]
+    guild_configs: HashMap<GuildId, GuildConfig>,
+    #[serde(default)]
+    usage: UsageStats,
+    // Removed entries which can still be restored for RESTORE_DAYS
+    #[serde(default)]
+    deleted: Vec<DeletedEntry>,
+    #[serde(default)]
+    announced: BTreeSet<Announcement>,
+    // Announcements that couldn't be sent, see `retry`
+    #[serde(default)]
+    failed_announcements: Vec<retry::FailedAnnouncement>,
+    // Announcements skipped per guild because the member held the opt-out role
+    #[serde(default)]
+    opt_out_skips: HashMap<GuildId, u64>,
+    // Monthly share of members with a birthday in percent, see `coverage`
+    #[serde(default)]
+    coverage_history: HashMap<GuildId, BTreeMap<NaiveDate, u32>>,
+    #[serde(default)]
+    announced_anniversaries: BTreeSet<Announcement>,
+    // Users who don't want their account anniversary announced anywhere
+    #[serde(default)]
+    anniversary_opt_outs: BTreeSet<serenity::UserId>,
+    // Guilds that removed the bot and when, their data is purged after a while, see `prune`
+    #[serde(default)]
+    left_guilds: HashMap<GuildId, DateTime<Utc>>,
+    // Members nobody may set a birthday for in the guild, see `birthday_optout`
+    #[serde(default)]
+    birthday_opt_outs: BTreeSet<(GuildId, serenity::UserId)>,
+    // Birthdays users set for every guild that turned them on, see `global`
+    #[serde(default)]
+    global_birthdays: BTreeMap<serenity::UserId, global::GlobalBirthday>,
+    // Events created in the guilds' Google calendars, see `google_calendar`
+    #[serde(default)]
+    calendar_events: Vec<google_calendar::CalendarEvent>,
+    // Birthdays that passed without an announcement, see `missed`
+    #[serde(default)]
+    missed: Vec<missed::Missed>,
+    // Birthday wishes of the last year, see `wishes`
+    #[serde(default)]
+    wishes: Vec<wishes::Wish>,
+    // Birthday roles that still have to be taken away, see `birthday_role`
+    #[serde(default)]
+    birthday_roles: Vec<birthday_role::AppliedRole>,
+    // DMs members asked for ahead of other members' birthdays, see `reminders`
+    #[serde(default)]
+    reminders: Vec<reminders::Reminder>,
+    // Custom anniversaries like the server's founding day, see `events`
+    #[serde(default)]
+    events: Vec<BirthdayEntry>,
+    #[serde(default)]
+    announced_events: BTreeSet<events::EventAnnouncement>,
+    // Last journal record that was compacted into the file, see `Storage::compact`
+    #[serde(default)]
+    journal_seq: u64,
+    // Bumped by every write, see `Storage::write`
+    #[serde(skip)]
+    version: u64,
+}
+
+/// Per-guild settings, guilds that never configured anything simply have no entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct GuildConfig {
+    quiet_dates: Vec<QuietDate>,
+    disabled_commands: Vec<String>,
+    prefix: Option<String>,
+    date_format: DateFormat,
+    export: Option<export::ExportConfig>,
+    // Kind of "on this day" fact added to announcements, None if turned off
+    fun_facts: Option<facts::FactKind>,
+    // Role given to members born in the month
+    #[serde(with = "month_roles::string_keys")]
+    month_roles: HashMap<u32, serenity::RoleId>,
+    // Whether announcements tease whose birthday comes next
+    next_up_footer: bool,
+    // Off hides every age and birth year in the guild, no matter what the members entered
+    show_ages: bool,
+    // When the owner was last told that no announcement channel is set
+    channel_nudged_at: Option<DateTime<Utc>>,
+    // Members with this role can use the gift notes
+    organizer_role: Option<serenity::RoleId>,
+    // Members with this role are never announced
+    opt_out_role: Option<serenity::RoleId>,
+    // Members with this role can set other members' birthdays, like moderators
+    birthday_manager_role: Option<serenity::RoleId>,
+    reactions: Option<reactions::ReactionConfig>,
+    // Whether members also get a note on the anniversary of their Discord account
+    account_anniversaries: bool,
+    // Age `retirement` counts down to, None for retirement::DEFAULT_AGE
+    retirement_age: Option<i32>,
+    // Age `time_left` counts down to, None for LIFE_EXPECTANCY
+    life_expectancy: Option<i32>,
+    // Seasonal decorations of the announcements
+    themes: themes::ThemeConfig,
+    // ID of the Google calendar the birthdays are synced to
+    google_calendar: Option<String>,
+    // Whether birthdays go to the system channel while no announcement channel is set
+    system_channel_fallback: bool,
+    // When the guild was last told about birthdays that passed without an announcement
+    missed_notified_at: Option<DateTime<Utc>>,
+    // Tags of the posts if the announcement channel is a forum
+    forum_tags: Vec<serenity::ForumTagId>,
+    // Whether a review of the year is posted at the end of December
+    year_in_review: bool,
+    // Last year the review was posted for
+    year_reviewed: Option<i32>,
+    // Role celebrants get for the day of their birthday
+    birthday_role: Option<serenity::RoleId>,
+    // When birthdays are announced, None for midnight in each member's own time zone
+    announcement_time: Option<AnnouncementTime>,
+    // Day February 29th birthdays are celebrated on in other years
+    leap_day: LeapDay,
+    // Whether members are mentioned instead of named, which also pings them in announcements
+    mention_celebrants: bool,
+    // What happens to the birthday of a member who leaves the guild
+    on_member_leave: prune::LeaveAction,
+    // Announcing birthdays as an embed, None for plain messages
+    announcement_embed: Option<embeds::EmbedConfig>,
+    // Role pinged by every announcement
+    ping_role: Option<serenity::RoleId>,
+    // Whether members without an entry are announced with their global birthday
+    global_birthdays: bool,
+    // Checks in a row on which announcements failed because of the channel, none are sent
+    // there anymore once it reaches retry::MAX_CHANNEL_FAILURES
+    channel_failures: u32,
+    // Code of the language of the replies and announcements, None for English
+    language: Option<String>,
+    // Whether replies to the birthday commands are only shown to whoever ran them
+    quiet_replies: bool,
+    // Whether announcements show the notes the celebrants attached to their birthdays
+    announcement_notes: bool,
+    // How many days back birthdays that were never announced are still announced, 0 for none
+    catch_up_days: u32,
+    // Announcements pick one of these at random, see `templates`, empty for the built-in one
+    announcement_messages: Vec<String>,
+}
+
+impl Default for GuildConfig {
+    fn default() -> Self {
+        Self {
+            quiet_dates: Vec::new(),
+            disabled_commands: Vec::new(),
+            prefix: None,
+            date_format: DateFormat::default(),
+            export: None,
+            fun_facts: None,
+            month_roles: HashMap::new(),
+            next_up_footer: false,
+            show_ages: true,
+            channel_nudged_at: None,
+            organizer_role: None,
+            opt_out_role: None,
+            birthday_manager_role: None,
+            reactions: None,
+            account_anniversaries: false,
+            retirement_age: None,
+            life_expectancy: None,
+            themes: themes::ThemeConfig::default(),
+            google_calendar: None,
+            system_channel_fallback: true,
+            missed_notified_at: None,
+            forum_tags: Vec::new(),
+            year_in_review: false,
+            year_reviewed: None,
+            birthday_role: None,
+            announcement_time: None,
+            leap_day: LeapDay::default(),
+            mention_celebrants: false,
+            on_member_leave: prune::LeaveAction::default(),
+            announcement_embed: None,
+            ping_role: None,
+            global_birthdays: false,
+            channel_failures: 0,
+            language: None,
+            quiet_replies: false,
+            announcement_notes: false,
+            catch_up_days: DEFAULT_CATCH_UP_DAYS,
+            announcement_messages: Vec::new(),
+        }
+    }
+}
+
+impl BirthdayList {
+    fn date_format(&self, guild_id: GuildId) -> DateFormat {
+        self.guild_configs
+            .get(&guild_id)
+            .map(|config| config.date_format)
+            .unwrap_or_default()
+    }
+
+    /// Whether a reply goes only to whoever ran the command, which they can ask for and the
+    /// guild can make the rule
+    fn replies_quietly(&self, guild_id: GuildId, asked: Option<bool>) -> bool {
+        asked.unwrap_or(false)
+            || self
+                .guild_configs
+                .get(&guild_id)
+                .is_some_and(|config| config.quiet_replies)
+    }
+
+    /// The guild's language, English if it picked none or one that isn't known
+    fn language(&self, guild_id: GuildId) -> Language {
+        self.guild_configs
+            .get(&guild_id)
+            .and_then(|config| config.language.as_deref())
+            .and_then(Language::from_code)
+            .unwrap_or_default()
+    }
+
+    /// How an entry is referred to in messages: a mention if the guild turned them on, so the
+    /// name is always current, or the stored name for members who left the guild
+    fn display_name(&self, entry: &BirthdayEntry) -> String {
+        let mention = self
+            .guild_configs
+            .get(&entry.guild_id)
+            .is_some_and(|config| config.mention_celebrants);
+        if mention && entry.missing_since.is_none() {
+            format!("<@{}>", entry.user_id)
+        } else {
+            format::escape(&entry.name)
+        }
+    }
+
+    /// Whether announcements stopped going to the guild's channel until an admin sets it again
+    fn channel_broken(&self, guild_id: GuildId) -> bool {
+        self.server_channels.contains_key(&guild_id)
+            && self
+                .guild_configs
+                .get(&guild_id)
+                .is_some_and(|config| config.channel_failures >= retry::MAX_CHANNEL_FAILURES)
+    }
+
+    fn leap_day(&self, guild_id: GuildId) -> LeapDay {
+        self.guild_configs
+            .get(&guild_id)
+            .map(|config| config.leap_day)
+            .unwrap_or_default()
+    }
+
+    /// Moves the announcement dates of files written before `announced` existed into it
+    fn migrate_announcements(&mut self) {
+        let entries = self
+            .entries
+            .iter_mut()
+            .chain(self.deleted.iter_mut().map(|deleted| &mut deleted.entry));
+        for entry in entries {
+            if let Some(date) = entry.last_announcement.take() {
+                self.announced.insert(Announcement::of(entry, date));
+            }
+        }
+    }
+
+    fn shows_ages(&self, guild_id: GuildId) -> bool {
+        self.guild_configs
+            .get(&guild_id)
+            .is_none_or(|config| config.show_ages)
+    }
+
+    /// The birth year of an entry as far as it may be displayed, everything showing ages or
+    /// years must go through this. None if the year wasn't set, is private or the guild hides
+    /// ages.
+    fn birth_year(&self, entry: &BirthdayEntry) -> Option<i32> {
+        entry
+            .year
+            .filter(|_| !entry.private_year && self.shows_ages(entry.guild_id))
+    }
+
+    /// The age the entry turns on the given occurrence of the birthday, None if it can't be
+    /// shown or makes no sense (a birth year in the future or far too long ago)
+    fn age_on(&self, entry: &BirthdayEntry, occurrence: NaiveDate) -> Option<i32> {
+        let age = occurrence.year() - self.birth_year(entry)?;
+        (1..=MAX_AGE).contains(&age).then_some(age)
+    }
+}
+
+impl GuildConfig {
+    fn is_quiet(&self, date: NaiveDate) -> bool {
+        self.quiet_dates.iter().any(|quiet| quiet.matches(date))
+    }
+}
+
+/// Day February 29th birthdays are celebrated on in years without one
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, poise::ChoiceParameter,
+)]
+enum LeapDay {
+    #[default]
+    #[name = "February 28th"]
+    February28,
+    #[name = "March 1st"]
+    March1,
+}
+
+impl LeapDay {
+    fn describe(&self) -> &'static str {
+        match self {
+            LeapDay::February28 => "February 28th",
+            LeapDay::March1 => "March 1st",
+        }
+    }
+}
+
+/// Month picked from a list, prefix commands also take its number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+enum MonthChoice {
+    #[name = "January"]
+    #[name = "1"]
+    January,
+    #[name = "February"]
+    #[name = "2"]
+    February,
+    #[name = "March"]
+    #[name = "3"]
+    March,
+    #[name = "April"]
+    #[name = "4"]
+    April,
+    #[name = "May"]
+    #[name = "5"]
+    May,
+    #[name = "June"]
+    #[name = "6"]
+    June,
+    #[name = "July"]
+    #[name = "7"]
+    July,
+    #[name = "August"]
+    #[name = "8"]
+    August,
+    #[name = "September"]
+    #[name = "9"]
+    September,
+    #[name = "October"]
+    #[name = "10"]
+    October,
+    #[name = "November"]
+    #[name = "11"]
+    November,
+    #[name = "December"]
+    #[name = "12"]
+    December,
+}
+
+impl MonthChoice {
+    fn number(self) -> usize {
+        self as usize + 1
+    }
+}
+
+/// Hour of the day in the guild's time zone at which birthdays are announced
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+struct AnnouncementTime {
+    hour: u32,
+    utc_offset: UtcOffset,
+}
+
+impl AnnouncementTime {
+    /// The day whose birthdays are due at `now`, it starts at the hour instead of midnight
+    fn date(&self, now: DateTime<Utc>) -> NaiveDate {
+        (now + self.utc_offset.duration() - chrono::Duration::hours(self.hour as i64)).date_naive()
+    }
+
+    fn describe(&self) -> String {
+        format!("{}:00 (UTC{})", self.hour, self.utc_offset)
+    }
+}
+
+/// A day on which no announcements are posted, either every year or in a single `year`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+struct QuietDate {
+    day: u32,
+    month: u32,
+    year: Option<i32>,
+}
+
+impl QuietDate {
+    fn matches(&self, date: NaiveDate) -> bool {
+        self.day == date.day()
+            && self.month == date.month()
+            && self.year.is_none_or(|year| year == date.year())
+    }
+
+    fn describe(&self, format: DateFormat) -> String {
+        let date = format.format(self.day, self.month, self.year);
+        match self.year {
+            Some(_) => date,
+            None => format!("{} (every year)", date),
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "StoredEntry")]
 struct BirthdayEntry {
     user_id: serenity::UserId,
     guild_id: GuildId,
     name: String,
+    // In the birth year if it is known, in NO_YEAR otherwise
     date: NaiveDate,
+    year: Option<i32>,
+    // Only read from old files, announcements are recorded in `BirthdayList::announced`
+    #[serde(default, skip_serializing)]
     last_announcement: Option<NaiveDate>,
-    utc_offset: i32,
+    utc_offset: UtcOffset,
+    #[serde(default)]
+    snoozed: Option<Snooze>,
+    #[serde(default)]
+    visibility: Visibility,
+    // Only day and month are shown to anyone, the year is kept for the user
+    #[serde(default)]
+    private_year: bool,
+    // Set while the user isn't a member of the guild anymore, see `prune`
+    #[serde(default)]
+    missing_since: Option<DateTime<Utc>>,
+    // Who changed the entry when, unknown for entries from before this was tracked
+    #[serde(default)]
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    updated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    set_by: Option<serenity::UserId>,
+    // Gift idea of the organizers, never shown to anyone else, see `gift_notes`
+    #[serde(default)]
+    gift_note: Option<String>,
+    // Shown to everyone who can see the entry, see `notes`
+    #[serde(default)]
+    note: Option<String>,
+    // Left out for birthdays, so files stay readable for older versions
+    #[serde(default, skip_serializing_if = "EventKind::is_birthday")]
+    kind: EventKind,
 }
 
-async fn read_from_file() -> Result<BirthdayList, Error> {
-    let _lock = FILE_LOCK.lock().await;
-    let data = std::fs::read_to_string(FILE_PATH);
-    // Make a backup of the file if it's corrupted and return an empty list
-    let data = match data {
-        Ok(data) => data,
-        Err(_) => {
-            let backup_path = format!("{}.bak", FILE_PATH);
-            let _ = std::fs::copy(FILE_PATH, &backup_path);
-            panic!("Corrupted file, backed up to {}", backup_path);
+#[cfg(test)]
+impl BirthdayEntry {
+    /// A public birthday on June 14th 1995 without any history, for tests to adjust with
+    /// struct update syntax
+    fn example(user_id: u64, guild_id: u64) -> Self {
+        BirthdayEntry {
+            user_id: serenity::UserId::new(user_id),
+            guild_id: GuildId::new(guild_id),
+            name: format!("user {}", user_id),
+            date: NaiveDate::from_ymd_opt(1995, 6, 14).unwrap(),
+            year: Some(1995),
+            last_announcement: None,
+            utc_offset: UtcOffset::default(),
+            snoozed: None,
+            visibility: Visibility::Public,
+            private_year: false,
+            missing_since: None,
+            created_at: None,
+            updated_at: None,
+            set_by: None,
+            gift_note: None,
+            note: None,
+            kind: EventKind::Birthday,
         }
-    };
-    Ok(serde_json::from_str(&data).unwrap_or_default())
+    }
 }
 
-async fn write_to_file(birthdays: &BirthdayList) -> Result<(), Error> {
-    let _lock = FILE_LOCK.lock().await;
-    let data = serde_json::to_string_pretty(birthdays)?;
-    std::fs::write(FILE_PATH, data)?;
-    Ok(())
+/// An entry as it is stored. Entries from before `year` existed have no such field, their date
+/// is in NO_YEAR if they were set without a year.
+#[derive(Deserialize)]
+struct StoredEntry {
+    user_id: serenity::UserId,
+    guild_id: GuildId,
+    name: String,
+    date: NaiveDate,
+    #[serde(default, deserialize_with = "present")]
+    year: Option<Option<i32>>,
+    #[serde(default)]
+    last_announcement: Option<NaiveDate>,
+    utc_offset: UtcOffset,
+    #[serde(default)]
+    snoozed: Option<Snooze>,
+    #[serde(default)]
+    visibility: Visibility,
+    #[serde(default)]
+    private_year: bool,
+    #[serde(default)]
+    missing_since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    updated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    set_by: Option<serenity::UserId>,
+    #[serde(default)]
+    gift_note: Option<String>,
+    #[serde(default)]
+    note: Option<String>,
+    #[serde(default)]
+    kind: EventKind,
+}
+
+/// Tells a field that is null apart from a missing one, which is None
+fn present<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Option<i32>>, D::Error> {
+    Option::deserialize(deserializer).map(Some)
+}
+
+impl From<StoredEntry> for BirthdayEntry {
+    fn from(stored: StoredEntry) -> Self {
+        let year = stored
+            .year
+            .unwrap_or_else(|| Some(stored.date.year()).filter(|year| *year != NO_YEAR));
+        BirthdayEntry {
+            user_id: stored.user_id,
+            guild_id: stored.guild_id,
+            name: stored.name,
+            date: stored.date,
+            year,
+            last_announcement: stored.last_announcement,
+            utc_offset: stored.utc_offset,
+            snoozed: stored.snoozed,
+            visibility: stored.visibility,
+            private_year: stored.private_year,
+            missing_since: stored.missing_since,
+            created_at: stored.created_at,
+            updated_at: stored.updated_at,
+            set_by: stored.set_by,
+            gift_note: stored.gift_note,
+            note: stored.note,
+            kind: stored.kind,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DeletedEntry {
+    entry: BirthdayEntry,
+    deleted_at: DateTime<Utc>,
+}
+
+/// Who may look up an entry, announcements are not affected by this
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, poise::ChoiceParameter,
+)]
+enum Visibility {
+    #[default]
+    #[name = "Everyone"]
+    Public,
+    #[name = "Only moderators"]
+    ModsOnly,
+    #[name = "Only me"]
+    Private,
+}
+
+/// Remembers which announcements a moderator marked as done by snoozing the entry, so the
+/// snooze can be undone on the same day
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Snooze {
+    date: NaiveDate,
+    #[serde(default)]
+    years: Vec<i32>,
+}
+
+/// A birthday that was announced (or snoozed) in the year of its occurrence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct Announcement {
+    guild_id: GuildId,
+    user_id: serenity::UserId,
+    year: i32,
+}
+
+impl Announcement {
+    fn of(entry: &BirthdayEntry, occurrence: NaiveDate) -> Announcement {
+        Announcement {
+            guild_id: entry.guild_id,
+            user_id: entry.user_id,
+            year: occurrence.year(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AuditLogEntry {
+    timestamp: DateTime<Utc>,
+    guild_id: GuildId,
+    actor: serenity::UserId,
+    action: String,
 }
 
 fn args_to_date(day: usize, month: usize, year: Option<usize>) -> Result<NaiveDate, Error> {
-    match NaiveDate::from_ymd_opt(year.unwrap_or(2024) as i32, month as u32, day as u32) {
+    let year = year.map_or(NO_YEAR, |year| year as i32);
+    match NaiveDate::from_ymd_opt(year, month as u32, day as u32) {
         Some(date) => Ok(date),
         None => Err("Invalid date!".into()),
     }
 }
 
+/// Checks that someone born in `year` could be alive, it may neither be after the current year
+/// in the time zone at `utc_offset` nor more than MAX_AGE years before it
+fn checked_birth_year(
+    year: Option<usize>,
+    utc_offset: UtcOffset,
+    now: DateTime<Utc>,
+    language: Language,
+) -> Result<(), String> {
+    let Some(year) = year else {
+        return Ok(());
+    };
+    let this_year = (now + utc_offset.duration()).year();
+    if year as i32 > this_year {
+        Err(text(language, "year_in_future", &[("year", &year)]))
+    } else if (year as i32) < this_year - MAX_AGE {
+        Err(text(
+            language,
+            "year_too_old",
+            &[("year", &year), ("max", &MAX_AGE)],
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks the date like `args_to_date`, the error says what is wrong with it
+fn checked_date(
+    day: usize,
+    month: usize,
+    year: Option<usize>,
+    language: Language,
+) -> Result<NaiveDate, String> {
+    if !(1..=12).contains(&month) {
+        return Err(text(language, "date_no_month", &[("month", &month)]));
+    }
+    if day == 0 {
+        return Err(text(language, "date_no_day", &[]));
+    }
+    args_to_date(day, month, year).map_err(|_| {
+        let days = (28..=31)
+            .rev()
+            .find(|day| args_to_date(*day, month, year).is_ok())
+            .unwrap();
+        match year {
+            // Only February differs between years
+            Some(year) if month == 2 => text(
+                language,
+                "date_february_days",
+                &[("year", &year), ("days", &days)],
+            ),
+            _ => text(
+                language,
+                "date_month_days",
+                &[
+                    ("month", &i18n::month(language, month as u32)),
+                    ("days", &days),
+                ],
+            ),
+        }
+    })
+}
+
+/// Reads a date typed as DD.MM., DD.MM.YYYY, YYYY-MM-DD or DD/MM/YYYY into day, month and
+/// year, the date has to exist like in `checked_date`
+fn parse_date(input: &str, language: Language) -> Result<(usize, usize, Option<usize>), String> {
+    let input = input.trim();
+    let unparsable = || text(language, "date_unparsable", &[("date", &input)]);
+    let (day, month, year) = if input.contains('-') {
+        match input.split('-').collect::<Vec<_>>()[..] {
+            [year, month, day] => (day, month, Some(year)),
+            _ => return Err(unparsable()),
+        }
+    } else if input.contains('/') {
+        match input.split('/').collect::<Vec<_>>()[..] {
+            [day, month, year] => (day, month, Some(year)),
+            _ => return Err(unparsable()),
+        }
+    } else {
+        match input
+            .strip_suffix('.')
+            .unwrap_or(input)
+            .split('.')
+            .collect::<Vec<_>>()[..]
+        {
+            [day, month] => (day, month, None),
+            [day, month, year] => (day, month, Some(year)),
+            _ => return Err(unparsable()),
+        }
+    };
+
+    let number = |part: &str, digits: std::ops::RangeInclusive<usize>| {
+        if digits.contains(&part.len()) && part.bytes().all(|byte| byte.is_ascii_digit()) {
+            part.parse::<usize>().ok()
+        } else {
+            None
+        }
+    };
+    let day = number(day, 1..=2).ok_or_else(unparsable)?;
+    let month = number(month, 1..=2).ok_or_else(unparsable)?;
+    let year = match year {
+        Some(year) => Some(number(year, 4..=4).ok_or_else(unparsable)?),
+        None => None,
+    };
+    checked_date(day, month, year, language)?;
+    Ok((day, month, year))
+}
+
+impl BirthdayEntry {
+    /// Records a change to the entry
+    fn touch(&mut self, set_by: serenity::UserId, now: DateTime<Utc>) {
+        self.created_at.get_or_insert(now);
+        self.updated_at = Some(now);
+        self.set_by = Some(set_by);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn append_birthday(
+    storage: &Storage,
     user_id: serenity::UserId,
     guild_id: GuildId,
     name: String,
     day: usize,
     month: usize,
     year: Option<usize>,
-    utc_offset: i32,
+    utc_offset: UtcOffset,
+    private_year: Option<bool>,
+    set_by: serenity::UserId,
 ) -> Result<(), Error> {
-    let mut birthdays = read_from_file().await?;
+    let mut birthdays = storage.read_owned().await;
+    let date = args_to_date(day, month, year)?;
+    audit(
+        &mut birthdays,
+        guild_id,
+        set_by,
+        format!(
+            "set the birthday of {} ({})",
+            format::escape(&name),
+            user_id
+        ),
+    );
+    put_birthday(
+        &mut birthdays,
+        user_id,
+        guild_id,
+        name,
+        date,
+        year.map(|year| year as i32),
+        utc_offset,
+        set_by,
+        Utc::now(),
+    );
+    if let Some(private_year) = private_year {
+        let entry = birthdays.entries.last_mut().unwrap();
+        entry.private_year = private_year;
+    }
+    storage.write(&birthdays).await?;
+    Ok(())
+}
+
+/// Adds the entry, replacing any existing entry of the user in the guild
+#[allow(clippy::too_many_arguments)]
+fn put_birthday(
+    birthdays: &mut BirthdayList,
+    user_id: serenity::UserId,
+    guild_id: GuildId,
+    name: String,
+    date: NaiveDate,
+    year: Option<i32>,
+    utc_offset: UtcOffset,
+    set_by: serenity::UserId,
+    now: DateTime<Utc>,
+) {
+    let existing = birthdays
+        .entries
+        .iter()
+        .find(|entry| entry.user_id == user_id && entry.guild_id == guild_id);
+    // Keep the privacy choices, the creation time and the notes of an existing entry
+    let visibility = existing.map(|entry| entry.visibility).unwrap_or_default();
+    let private_year = existing.is_some_and(|entry| entry.private_year);
+    let created_at = existing.and_then(|entry| entry.created_at);
+    let gift_note = existing.and_then(|entry| entry.gift_note.clone());
+    let note = existing.and_then(|entry| entry.note.clone());
     // Remove any existing entry for this user and this specific guild
     birthdays
         .entries
         .retain(|entry| entry.user_id != user_id || entry.guild_id != guild_id);
 
     // Add the new entry
-    birthdays.entries.push(BirthdayEntry {
+    let mut entry = BirthdayEntry {
         user_id,
         guild_id,
         name,
-        date: args_to_date(day, month, year)?,
+        date,
+        year,
         last_announcement: None,
         utc_offset,
+        snoozed: None,
+        visibility,
+        private_year,
+        missing_since: None,
+        created_at,
+        updated_at: None,
+        set_by: None,
+        gift_note,
+        note,
+        kind: EventKind::Birthday,
+    };
+    entry.touch(set_by, now);
+    birthdays.entries.push(entry);
+}
+
+/// Returns a notice for the confirmation of a new birthday if the guild has no announcement
+/// channel, the owner is also told about it at most once a week
+async fn missing_channel_notice(
+    http: &serenity::Http,
+    storage: &Storage,
+    guild_id: GuildId,
+    language: Language,
+) -> String {
+    let now = Utc::now();
+    let nudge = storage
+        .update(|birthdays| {
+            if birthdays.server_channels.contains_key(&guild_id) {
+                return None;
+            }
+            let config = birthdays.guild_configs.entry(guild_id).or_default();
+            let due = config
+                .channel_nudged_at
+                .is_none_or(|nudged| now - nudged >= chrono::Duration::days(CHANNEL_NUDGE_DAYS));
+            if due {
+                config.channel_nudged_at = Some(now);
+            }
+            Some(due)
+        })
+        .await;
+
+    match nudge {
+        Ok(None) => String::new(),
+        Ok(Some(due)) => {
+            if due {
+                if let Err(error) = nudge_owner(http, guild_id).await {
+                    warn!(
+                        guild = %guild_id,
+                        %error,
+                        "Failed to tell the owner about the channel"
+                    );
+                }
+            }
+            text(language, "missing_channel", &[])
+        }
+        Err(error) => {
+            error!(
+                guild = %guild_id,
+                %error,
+                "Failed to check the announcement channel"
+            );
+            String::new()
+        }
+    }
+}
+
+/// Sends the reply, only to whoever ran the command if `quiet`. Prefix commands have no
+/// ephemeral replies, their quiet replies are deleted after QUIET_REPLY_TIME instead.
+async fn send_reply(ctx: Context<'_>, quiet: bool, reply: poise::CreateReply) -> Result<(), Error> {
+    let handle = ctx.send(reply.ephemeral(quiet)).await?;
+    if !quiet || !matches!(ctx, poise::Context::Prefix(_)) {
+        return Ok(());
+    }
+    let message = handle.into_message().await?;
+    let http = ctx.serenity_context().http.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_secs(QUIET_REPLY_TIME)).await;
+        if let Err(error) = message.delete(&http).await {
+            warn!(message = %message.id, %error, "Failed to delete a quiet reply");
+        }
     });
-    write_to_file(&birthdays).await?;
     Ok(())
 }
 
-fn date_to_discord_timestamp(date: NaiveDate, offset: i32, relative: bool) -> String {
-    let flag = if relative { "R" } else { "f" };
+/// A message that pings nobody, whatever names or user text end up in it
+fn quiet_message(content: impl Into<String>) -> serenity::CreateMessage {
+    serenity::CreateMessage::new()
+        .content(content)
+        .allowed_mentions(serenity::CreateAllowedMentions::new())
+}
 
-    // Calculate time with offset
-    let offset = if offset == 0 { 0 } else { offset - 1 };
-    let date = date.and_hms_opt(0, 0, 0).unwrap() - chrono::Duration::hours(offset as i64);
-    let timestamp = date.and_utc().timestamp();
+async fn nudge_owner(http: &serenity::Http, guild_id: GuildId) -> Result<(), Error> {
+    let guild = guild_id.to_partial_guild(http).await?;
+    let message = format!(
+        "📢🎈 Members of {} are setting their birthdays, but no announcement channel is set, so \
+        nobody will be congratulated. Run `set_announcement_channel` in the server to pick one!",
+        guild.name
+    );
+    guild
+        .owner_id
+        .create_dm_channel(http)
+        .await?
+        .send_message(http, quiet_message(message))
+        .await?;
+    Ok(())
+}
 
+/// Discord timestamp of the moment `date` begins in the time zone at `offset`
+fn date_to_discord_timestamp(date: NaiveDate, offset: UtcOffset, relative: bool) -> String {
+    let flag = if relative { "R" } else { "f" };
+    let timestamp = schedule::start_of(date, offset.duration()).timestamp();
     format!("<t:{}:{}>", timestamp, flag)
 }
 
 async fn get_birthday_from_file(
+    storage: &Storage,
     user_id: serenity::UserId,
     guild_id: GuildId,
-) -> Result<Option<BirthdayEntry>, Error> {
-    let birthdays = read_from_file().await?;
-    Ok(birthdays
-        .entries
-        .into_iter()
-        .find(|entry| entry.user_id == user_id && entry.guild_id == guild_id))
+) -> Option<BirthdayEntry> {
+    storage
+        .read_with(|birthdays| {
+            birthdays
+                .entries
+                .iter()
+                .find(|entry| entry.user_id == user_id && entry.guild_id == guild_id)
+                .cloned()
+        })
+        .await
 }
 
-fn offset_to_string(offset: i32) -> String {
-    if offset >= 0 {
-        format!("+{}", offset)
-    } else {
-        format!("{}", offset)
-    }
+/// Whether the invoking user has the Manage Server permission in the invocation channel
+async fn is_moderator(ctx: Context<'_>) -> bool {
+    let Some(guild_id) = ctx.guild_id() else {
+        return false;
+    };
+    let Ok(guild) = guild_id.to_partial_guild(ctx).await else {
+        return false;
+    };
+    let Ok(serenity::Channel::Guild(channel)) = ctx.channel_id().to_channel(ctx).await else {
+        return false;
+    };
+    let Ok(member) = guild.member(ctx, ctx.author().id).await else {
+        return false;
+    };
+    guild.user_permissions_in(&channel, &member).manage_guild()
 }
 
-/// Sets your or another user's birthday
-#[poise::command(slash_command, prefix_command)]
-async fn set_birthday(
+/// Whether the invoking user may set the birthday of `user_id`, replies why not otherwise, only
+/// to them if `quiet`. Setting your own is open to everyone, others need to be moderators or
+/// birthday managers and nobody may set the birthday of a member who opted out.
+async fn may_set_birthday(
     ctx: Context<'_>,
-    #[description = "Day"] day: usize,
-    #[description = "Month"] month: usize,
-    #[description = "Year"] year: Option<usize>,
-    #[description = "UTC offset from UTC+00"] utc_offset: i32,
-    #[description = "User to set the birthday for (defaults to yourself)"] user: Option<
-        serenity::User,
-    >,
-) -> Result<(), Error> {
-    // Turn the month and day into a date and check if it's valid
-    if chrono::NaiveDate::from_ymd_opt(year.unwrap_or(2024) as i32, month as u32, day as u32)
-        .is_none()
-    {
-        ctx.say("🐺🎩❌ Invalid date!").await?;
-        return Ok(());
+    user_id: serenity::UserId,
+    quiet: bool,
+) -> Result<bool, Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    let language = birthdays.language(guild_id);
+    let for_other = user_id != ctx.author().id;
+    if for_other && !is_moderator(ctx).await {
+        let manager_role = birthdays
+            .guild_configs
+            .get(&guild_id)
+            .and_then(|config| config.birthday_manager_role);
+        let is_manager = match manager_role {
+            Some(role) => ctx
+                .author_member()
+                .await
+                .is_some_and(|member| member.roles.contains(&role)),
+            None => false,
+        };
+        if !is_manager {
+            let message = text(language, "set_birthday_not_allowed", &[]);
+            send_reply(ctx, quiet, poise::CreateReply::default().content(message)).await?;
+            return Ok(false);
+        }
     }
+    if birthdays.birthday_opt_outs.contains(&(guild_id, user_id)) {
+        let key = if for_other {
+            "set_birthday_opted_out_other"
+        } else {
+            "set_birthday_opted_out"
+        };
+        let message = text(language, key, &[]);
+        send_reply(ctx, quiet, poise::CreateReply::default().content(message)).await?;
+        return Ok(false);
+    }
+    Ok(true)
+}
 
-    let user = user.unwrap_or_else(|| ctx.author().clone());
-    append_birthday(
-        user.id,
-        ctx.guild_id().unwrap(),
-        user.name.clone(),
-        day,
-        month,
-        year,
-        utc_offset,
-    )
-    .await?;
-
-    ctx.say(format!(
-        "✍️📅🎈 Added birthday for {} on {}.{} (UTC{}) which is {} for you!",
-        user.name,
-        day,
-        month,
-        offset_to_string(utc_offset),
-        date_to_discord_timestamp(args_to_date(day, month, year)?, utc_offset, false)
-    ))
-    .await?;
-    Ok(())
+/// Whether the invoking user may look up the given entry
+async fn can_view(ctx: Context<'_>, entry: &BirthdayEntry) -> bool {
+    match entry.visibility {
+        Visibility::Public => true,
+        _ if entry.user_id == ctx.author().id => true,
+        Visibility::ModsOnly => is_moderator(ctx).await,
+        Visibility::Private => false,
+    }
 }
 
-/// Gets your or another user's birthday
-#[poise::command(slash_command, prefix_command)]
-async fn get_birthday(
+/// Looks up a birthday in the invoking guild, entries hidden from the invoking user
+/// are treated as if they didn't exist
+async fn get_visible_birthday(
     ctx: Context<'_>,
-    #[description = "User to get the birthday for (defaults to yourself)"] user: Option<
-        serenity::User,
-    >,
-) -> Result<(), Error> {
-    let user = user.unwrap_or_else(|| ctx.author().clone());
-    let entry = get_birthday_from_file(user.id, ctx.guild_id().unwrap()).await?;
-    let entry = match entry {
-        Some(entry) => entry,
-        None => {
-            ctx.say("☹️🎈 No birthday set for this user for this guild!")
-                .await?;
-            return Ok(());
-        }
+    user_id: serenity::UserId,
+) -> Result<Option<BirthdayEntry>, Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let storage = &ctx.data().storage;
+    let entry = match get_birthday_from_file(storage, user_id, guild_id).await {
+        Some(entry) => Some(entry),
+        // The global birthday of members who didn't set one in the guild
+        None => match storage
+            .read_with(|birthdays| global::fallback(birthdays, user_id, guild_id))
+            .await
+        {
+            Some(entry) if guild_id.member(ctx, user_id).await.is_ok() => Some(entry),
+            _ => None,
+        },
     };
+    match entry {
+        Some(entry) if can_view(ctx, &entry).await => Ok(Some(entry)),
+        _ => Ok(None),
+    }
+}
 
-    // Check whether the birthday already happened this year
-    let today = Utc::now().naive_utc().date();
+/// The day whose birthdays are announced at `now`, it starts at midnight in the entry's time
+/// zone unless the guild set an announcement time
+fn announcement_date(
+    entry: &BirthdayEntry,
+    config: Option<&GuildConfig>,
+    now: DateTime<Utc>,
+) -> NaiveDate {
+    match config.and_then(|config| config.announcement_time) {
+        Some(time) => time.date(now),
+        None => dates::local_date(entry, now),
+    }
+}
 
-    // Set entry year to this year
-    let entry = BirthdayEntry {
-        date: NaiveDate::from_ymd_opt(today.year(), entry.date.month(), entry.date.day()).unwrap(),
-        ..entry
-    };
+/// Returns the birthday's occurrence that should be announced now, if any. A birthday is due
+/// once it is midnight in the entry's time zone, or the guild's announcement time.
+/// Occurrences falling on quiet dates are deferred to the next non-quiet day, so the
+/// returned date can lie in the past (even in the previous year).
+fn due_occurrence(
+    entry: &BirthdayEntry,
+    now: DateTime<Utc>,
+    config: Option<&GuildConfig>,
+    announced: &BTreeSet<Announcement>,
+) -> Option<NaiveDate> {
+    let today = announcement_date(entry, config, now);
+    let is_quiet = |date: NaiveDate| config.is_some_and(|config| config.is_quiet(date));
+    if is_quiet(today) {
+        return None;
+    }
 
-    // If the birthday already happened this year, set the year to next year
-    let year = if today > entry.date {
-        today.year() + 1
-    } else {
-        today.year()
-    };
+    let leap_day = config.map(|config| config.leap_day).unwrap_or_default();
+    let occurrence = dates::last_occurrence(entry.date, today, leap_day);
 
-    // Get next birthday
-    let next_birthday =
-        NaiveDate::from_ymd_opt(year, entry.date.month(), entry.date.day()).unwrap();
+    // Every day between the occurrence and today must have been quiet, otherwise the
+    // announcement was already due on an earlier day
+    if occurrence
+        .iter_days()
+        .take_while(|date| *date < today)
+        .any(|date| !is_quiet(date))
+    {
+        return None;
+    }
 
-    ctx.say(format!(
-        "📅🎈 {}'s birthday is on {}.{} (UTC{}) so {} which is {} for you!",
-        entry.name,
-        entry.date.day(),
-        entry.date.month(),
-        offset_to_string(entry.utc_offset),
-        date_to_discord_timestamp(next_birthday, entry.utc_offset, true),
-        date_to_discord_timestamp(next_birthday, entry.utc_offset, false),
-    ))
-    .await?;
-    Ok(())
+    if announced.contains(&Announcement::of(entry, occurrence)) {
+        return None;
+    }
+
+    Some(occurrence)
 }
 
-#[poise::command(slash_command, prefix_command, required_permissions = "MANAGE_GUILD")]
-async fn set_announcement_channel(
-    ctx: Context<'_>,
-    #[description = "Channel to set as the birthday announcement channel"] channel: ChannelId,
-) -> Result<(), Error> {
-    let mut birthdays = read_from_file().await?;
-    birthdays
-        .server_channels
-        .insert(ctx.guild_id().unwrap(), channel);
-    write_to_file(&birthdays).await?;
-    ctx.say(format!("📢🎈 Birthday channel set to <#{}>!", channel))
-        .await?;
-    Ok(())
+/// Returns the occurrence `snooze_all_today` skips: the one due now, or today's birthday that
+/// isn't due yet because it is still earlier in the day, or still yesterday in the member's
+/// time zone
+fn todays_occurrence(
+    entry: &BirthdayEntry,
+    now: DateTime<Utc>,
+    config: Option<&GuildConfig>,
+    announced: &BTreeSet<Announcement>,
+) -> Option<NaiveDate> {
+    if let Some(occurrence) = due_occurrence(entry, now, config, announced) {
+        return Some(occurrence);
+    }
+
+    let today = match config.and_then(|config| config.announcement_time) {
+        Some(_) => announcement_date(entry, config, now),
+        None => announcement_date(entry, config, now).max(now.date_naive()),
+    };
+    let leap_day = config.map(|config| config.leap_day).unwrap_or_default();
+    let is_quiet = config.is_some_and(|config| config.is_quiet(today));
+    (dates::is_birthday_on(entry.date, today, leap_day)
+        && !is_quiet
+        && !announced.contains(&Announcement::of(entry, today)))
+    .then_some(today)
 }
 
-/// Gets your or another user's birthday
-#[poise::command(slash_command, prefix_command)]
+/// Returns an occurrence from the guild's catch-up window that was never announced, e.g.
+/// because the bot was down on the day. Birthdays that were set or changed after the occurrence
+/// began weren't missed and aren't caught up, neither is anything on quiet dates.
+fn missed_occurrence(
+    entry: &BirthdayEntry,
+    now: DateTime<Utc>,
+    config: Option<&GuildConfig>,
+    announced: &BTreeSet<Announcement>,
+) -> Option<NaiveDate> {
+    let days = config.map_or(DEFAULT_CATCH_UP_DAYS, |config| config.catch_up_days);
+    let today = announcement_date(entry, config, now);
+    if days == 0 || config.is_some_and(|config| config.is_quiet(today)) {
+        return None;
+    }
+
+    // Today's occurrence is up to `due_occurrence`
+    let leap_day = config.map(|config| config.leap_day).unwrap_or_default();
+    let occurrence = dates::last_occurrence(entry.date, today.pred_opt()?, leap_day);
+    if (today - occurrence).num_days() > days as i64 {
+        return None;
+    }
+    let began = schedule::start_of(occurrence, entry.utc_offset.duration());
+    if entry
+        .updated_at
+        .or(entry.created_at)
+        .is_some_and(|changed| changed >= began)
+    {
+        return None;
+    }
+
+    if announced.contains(&Announcement::of(entry, occurrence)) {
+        return None;
+    }
+
+    Some(occurrence)
+}
+
+/// The public birthday of the guild that comes next after today, leaving out the members
+/// celebrated today. None if nobody else has one, a lone celebrant isn't teased as next up.
+fn next_up<'a>(
+    birthdays: &'a BirthdayList,
+    guild_id: GuildId,
+    today: NaiveDate,
+    celebrating: &[(GuildId, serenity::UserId)],
+) -> Option<(&'a BirthdayEntry, NaiveDate)> {
+    let tomorrow = today.succ_opt()?;
+    birthdays
+        .entries
+        .iter()
+        // Members who left aren't announced, so they aren't next up either
+        .filter(|entry| {
+            entry.guild_id == guild_id
+                && entry.visibility == Visibility::Public
+                && entry.missing_since.is_none()
+                && !birthdays.left_guilds.contains_key(&entry.guild_id)
+                && !celebrating.contains(&(entry.guild_id, entry.user_id))
+        })
+        .map(|entry| {
+            let next = dates::next_occurrence(entry.date, tomorrow, birthdays.leap_day(guild_id));
+            (entry, next)
+        })
+        .min_by_key(|(_, date)| *date)
+}
+
+/// Marks the given occurrence as already announced, remembering what was marked so
+/// `unsnooze` can revert it on the same day
+fn snooze_entry(
+    entry: &mut BirthdayEntry,
+    announced: &mut BTreeSet<Announcement>,
+    occurrence: NaiveDate,
+    today: NaiveDate,
+) {
+    // Snoozing twice on the same day must not lose the original state
+    if !matches!(&entry.snoozed, Some(snooze) if snooze.date == today) {
+        entry.snoozed = Some(Snooze {
+            date: today,
+            years: Vec::new(),
+        });
+    }
+    if announced.insert(Announcement::of(entry, occurrence)) {
+        if let Some(snooze) = &mut entry.snoozed {
+            snooze.years.push(occurrence.year());
+        }
+    }
+}
+
+/// Reverts a snooze issued today, returns false if there was nothing to revert
+fn unsnooze_entry(
+    entry: &mut BirthdayEntry,
+    announced: &mut BTreeSet<Announcement>,
+    today: NaiveDate,
+) -> bool {
+    match entry.snoozed.take() {
+        Some(snooze) if snooze.date == today => {
+            for year in snooze.years {
+                announced.remove(&Announcement {
+                    guild_id: entry.guild_id,
+                    user_id: entry.user_id,
+                    year,
+                });
+            }
+            true
+        }
+        snooze => {
+            entry.snoozed = snooze;
+            false
+        }
+    }
+}
+
+/// Moves the user's entry in the guild to the deleted entries, returns false if there was none
+fn soft_delete(
+    birthdays: &mut BirthdayList,
+    user_id: serenity::UserId,
+    guild_id: GuildId,
+    now: DateTime<Utc>,
+) -> bool {
+    let Some(index) = birthdays
+        .entries
+        .iter()
+        .position(|entry| entry.user_id == user_id && entry.guild_id == guild_id)
+    else {
+        return false;
+    };
+
+    let entry = birthdays.entries.remove(index);
+    birthdays.deleted.push(DeletedEntry {
+        entry,
+        deleted_at: now,
+    });
+    true
+}
+
+/// Removes everything about the member's birthday in the guild for good and keeps anyone from
+/// setting it again, returns whether there was an entry
+fn opt_out(birthdays: &mut BirthdayList, user_id: serenity::UserId, guild_id: GuildId) -> bool {
+    let count = birthdays.entries.len();
+    birthdays
+        .entries
+        .retain(|entry| entry.user_id != user_id || entry.guild_id != guild_id);
+    birthdays
+        .deleted
+        .retain(|deleted| deleted.entry.user_id != user_id || deleted.entry.guild_id != guild_id);
+    birthdays
+        .reminders
+        .retain(|reminder| reminder.target != user_id || reminder.guild_id != guild_id);
+    birthdays.birthday_opt_outs.insert((guild_id, user_id));
+    birthdays.entries.len() != count
+}
+
+/// Permanently removes deleted entries whose restore window has passed, returns how many
+fn purge_deleted(birthdays: &mut BirthdayList, now: DateTime<Utc>) -> usize {
+    let count = birthdays.deleted.len();
+    birthdays
+        .deleted
+        .retain(|deleted| now - deleted.deleted_at < chrono::Duration::days(RESTORE_DAYS));
+    count - birthdays.deleted.len()
+}
+
+fn audit(birthdays: &mut BirthdayList, guild_id: GuildId, actor: serenity::UserId, action: String) {
+    info!(guild = %guild_id, user = %actor, %action, "Audit");
+    birthdays.audit_log.push(AuditLogEntry {
+        timestamp: Utc::now(),
+        guild_id,
+        actor,
+        action,
+    });
+
+    // Only keep the most recent entries around
+    if birthdays.audit_log.len() > AUDIT_LOG_LIMIT {
+        let excess = birthdays.audit_log.len() - AUDIT_LOG_LIMIT;
+        birthdays.audit_log.drain(..excess);
+    }
+}
+
+/// Sets your or another user's birthday
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn set_birthday(
+    ctx: Context<'_>,
+    #[description = "Date as DD.MM.YYYY, or DD.MM. without a year"] date: String,
+    #[description = "UTC offset like +2, -5 or +5:30"] utc_offset: String,
+    #[description = "User to set the birthday for (defaults to yourself)"] user: Option<
+        serenity::User,
+    >,
+    #[description = "Hide the year and age from everyone (keeps the current choice if empty)"]
+    private_year: Option<bool>,
+    #[description = "Only show the reply to you"] quiet: Option<bool>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let (quiet, language) = ctx
+        .data()
+        .storage
+        .read_with(|birthdays| {
+            (
+                birthdays.replies_quietly(guild_id, quiet),
+                birthdays.language(guild_id),
+            )
+        })
+        .await;
+    let date = parse_date(&date, language).and_then(|date| {
+        let utc_offset = UtcOffset::parse(&utc_offset, language)?;
+        checked_birth_year(date.2, utc_offset, Utc::now(), language)?;
+        Ok((date, utc_offset))
+    });
+    let ((day, month, year), utc_offset) = match date {
+        Ok(date) => date,
+        Err(error) => {
+            send_reply(
+                ctx,
+                quiet,
+                poise::CreateReply::default()
+                    .content(format!("🐺🎩❌ {}!", format::escape(&error))),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let user = user.unwrap_or_else(|| ctx.author().clone());
+    if !may_set_birthday(ctx, user.id, quiet).await? {
+        return Ok(());
+    }
+    append_birthday(
+        &ctx.data().storage,
+        user.id,
+        guild_id,
+        user.name.clone(),
+        day,
+        month,
+        year,
+        utc_offset,
+        private_year,
+        ctx.author().id,
+    )
+    .await?;
+
+    let birthdays = ctx.data().storage.read().await;
+    month_roles::apply(
+        ctx.http(),
+        &birthdays,
+        guild_id,
+        user.id,
+        Some(month as u32),
+    )
+    .await;
+    let format = birthdays.date_format(guild_id);
+    let notice = missing_channel_notice(ctx.http(), &ctx.data().storage, guild_id, language).await;
+    let message = text(
+        language,
+        "birthday_set",
+        &[
+            ("name", &format::escape(&user.name)),
+            ("date", &format.format(day as u32, month as u32, None)),
+            ("offset", &utc_offset),
+            (
+                "time",
+                &date_to_discord_timestamp(args_to_date(day, month, year)?, utc_offset, false),
+            ),
+        ],
+    );
+    send_reply(
+        ctx,
+        quiet,
+        poise::CreateReply::default().content(format!("{}{}", message, notice)),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Gets your or another user's birthday
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn get_birthday(
+    ctx: Context<'_>,
+    #[description = "User to get the birthday for (defaults to yourself)"] user: Option<
+        serenity::User,
+    >,
+    #[description = "Only show the reply to you"] quiet: Option<bool>,
+) -> Result<(), Error> {
+    let user = user.unwrap_or_else(|| ctx.author().clone());
+    show_birthday(ctx, &user, quiet).await
+}
+
+/// Gets the birthday of the member you right-clicked
+#[poise::command(context_menu_command = "Get Birthday", guild_only)]
+async fn get_birthday_menu(ctx: Context<'_>, user: serenity::User) -> Result<(), Error> {
+    // Nobody else in the channel asked for it
+    show_birthday(ctx, &user, Some(true)).await
+}
+
+/// Replies with the user's birthday as far as the invoking user may see it, for `get_birthday`
+/// and its context menu
+async fn show_birthday(
+    ctx: Context<'_>,
+    user: &serenity::User,
+    quiet: Option<bool>,
+) -> Result<(), Error> {
+    let entry = get_visible_birthday(ctx, user.id).await?;
+    let birthdays = ctx.data().storage.read().await;
+    let language = birthdays.language(ctx.guild_id().unwrap());
+    let quiet = birthdays.replies_quietly(ctx.guild_id().unwrap(), quiet);
+    let entry = match entry {
+        Some(entry) => entry,
+        None => {
+            let message = text(language, "no_birthday", &[]);
+            send_reply(ctx, quiet, poise::CreateReply::default().content(message)).await?;
+            return Ok(());
+        }
+    };
+
+    let (next_birthday, _) = dates::next_birthday_start(
+        &entry,
+        Utc::now(),
+        birthdays.leap_day(ctx.guild_id().unwrap()),
+    );
+    let format = birthdays.date_format(ctx.guild_id().unwrap());
+    let age = birthdays.age_on(&entry, next_birthday);
+    let mut message = text(
+        language,
+        if age.is_some() {
+            "birthday_next_age"
+        } else {
+            "birthday_next"
+        },
+        &[
+            ("name", &birthdays.display_name(&entry)),
+            (
+                "date",
+                &format.format(entry.date.day(), entry.date.month(), None),
+            ),
+            ("offset", &entry.utc_offset),
+            ("age", &age.unwrap_or_default()),
+            (
+                "relative",
+                &date_to_discord_timestamp(next_birthday, entry.utc_offset, true),
+            ),
+            (
+                "time",
+                &date_to_discord_timestamp(next_birthday, entry.utc_offset, false),
+            ),
+        ],
+    );
+    if let Some(note) = &entry.note {
+        message.push_str(&format!("\n📝 {}", notes::display(note)));
+    }
+    if is_moderator(ctx).await {
+        message.push_str(&entry_metadata(&entry));
+    }
+    send_reply(
+        ctx,
+        quiet,
+        poise::CreateReply::default()
+            .content(message)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Counts down the days and hours until your or another user's next birthday
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn countdown(
+    ctx: Context<'_>,
+    #[description = "User to count down to (defaults to yourself)"] user: Option<serenity::User>,
+    #[description = "Only show the reply to you"] quiet: Option<bool>,
+) -> Result<(), Error> {
+    let user = user.unwrap_or_else(|| ctx.author().clone());
+    let entry = get_visible_birthday(ctx, user.id).await?;
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    let quiet = birthdays.replies_quietly(guild_id, quiet);
+    let Some(entry) = entry else {
+        let message = text(birthdays.language(guild_id), "no_birthday", &[]);
+        send_reply(ctx, quiet, poise::CreateReply::default().content(message)).await?;
+        return Ok(());
+    };
+
+    let now = Utc::now();
+    let (_, start) = dates::next_birthday_start(&entry, now, birthdays.leap_day(guild_id));
+    let name = birthdays.display_name(&entry);
+    // The start of a birthday that is going on right now already lies in the past
+    let message = if start <= now {
+        format!("🎉🎈 {}'s birthday is TODAY 🎉", name)
+    } else {
+        format!(
+            "⏳🎈 {}'s birthday is in {}!",
+            name,
+            format::countdown(start - now)
+        )
+    };
+    send_reply(
+        ctx,
+        quiet,
+        poise::CreateReply::default()
+            .content(message)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// The entries of the invoking guild the invoking user may look up
+async fn visible_entries<'a>(
+    ctx: Context<'_>,
+    birthdays: &'a BirthdayList,
+) -> Vec<&'a BirthdayEntry> {
+    let guild_id = ctx.guild_id().unwrap();
+    let author = ctx.author().id;
+    // Checked once instead of per entry, it takes a few API calls
+    let moderator = is_moderator(ctx).await;
+    birthdays
+        .entries
+        .iter()
+        .filter(|entry| entry.guild_id == guild_id)
+        .filter(|entry| match entry.visibility {
+            Visibility::Public => true,
+            _ if entry.user_id == author => true,
+            Visibility::ModsOnly => moderator,
+            Visibility::Private => false,
+        })
+        .collect()
+}
+
+/// A list line with the entry's name, birthday and how long until its next occurrence
+fn upcoming_line(birthdays: &BirthdayList, entry: &BirthdayEntry, next: NaiveDate) -> String {
+    // Anniversaries stand out from the birthdays around them
+    let name = match entry.kind.label() {
+        Some(label) => format!("🎊 {}", format::escape(label)),
+        None => birthdays.display_name(entry),
+    };
+    format!(
+        "- {}: {} (UTC{}) {}",
+        name,
+        birthdays
+            .date_format(entry.guild_id)
+            .format(entry.date.day(), entry.date.month(), None),
+        entry.utc_offset,
+        date_to_discord_timestamp(next, entry.utc_offset, true),
+    )
+}
+
+/// Shows who celebrates their birthday next in this server
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn next_birthday(
+    ctx: Context<'_>,
+    #[description = "Only show the reply to you"] quiet: Option<bool>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    let quiet = birthdays.replies_quietly(guild_id, quiet);
+    let now = Utc::now();
+    let leap_day = birthdays.leap_day(guild_id);
+    let entries: Vec<(NaiveDate, &BirthdayEntry)> = visible_entries(ctx, &birthdays)
+        .await
+        .into_iter()
+        .map(|entry| (dates::next_birthday(entry, now, leap_day), entry))
+        .collect();
+    let Some(nearest) = entries.iter().map(|(next, _)| *next).min() else {
+        let message = "☹️🎈 No birthday set for anyone in this guild!";
+        send_reply(ctx, quiet, poise::CreateReply::default().content(message)).await?;
+        return Ok(());
+    };
+
+    let mut next: Vec<&BirthdayEntry> = entries
+        .iter()
+        .filter(|(next, _)| *next == nearest)
+        .map(|(_, entry)| *entry)
+        .collect();
+    next.sort_by_key(|entry| entry.name.clone());
+    let lines: Vec<String> = next
+        .iter()
+        .map(|entry| upcoming_line(&birthdays, entry, nearest))
+        .collect();
+    send_reply(
+        ctx,
+        quiet,
+        poise::CreateReply::default()
+            .content(format!("⏭️🎈 Next up:\n{}", lines.join("\n")))
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Lists the birthdays of this server, the next one first
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn list_birthdays(
+    ctx: Context<'_>,
+    #[description = "Only show the reply to you"] quiet: Option<bool>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    let quiet = birthdays.replies_quietly(guild_id, quiet);
+    let now = Utc::now();
+    let leap_day = birthdays.leap_day(guild_id);
+    let mut entries: Vec<(NaiveDate, &BirthdayEntry)> = visible_entries(ctx, &birthdays)
+        .await
+        .into_iter()
+        .chain(events::of_guild(&birthdays, guild_id))
+        .map(|entry| (dates::next_birthday(entry, now, leap_day), entry))
+        .collect();
+    if entries.is_empty() {
+        let message = "☹️🎈 No birthdays set for this guild!";
+        send_reply(ctx, quiet, poise::CreateReply::default().content(message)).await?;
+        return Ok(());
+    }
+    entries.sort_by_key(|(next, entry)| (*next, entry.name.clone()));
+
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|(next, entry)| upcoming_line(&birthdays, entry, *next))
+        .collect();
+    pages::send(ctx, quiet, "📅🎈 Upcoming birthdays", &lines).await
+}
+
+/// The entries whose birthday falls within the next `days` days in their time zone, today
+/// included, in the order they come up
+fn upcoming(
+    entries: Vec<&BirthdayEntry>,
+    now: DateTime<Utc>,
+    days: u32,
+    leap_day: LeapDay,
+) -> Vec<(NaiveDate, &BirthdayEntry)> {
+    let days = chrono::Duration::days(days as i64);
+    let mut upcoming: Vec<(NaiveDate, &BirthdayEntry)> = entries
+        .into_iter()
+        .map(|entry| (dates::next_birthday(entry, now, leap_day), entry))
+        .filter(|(next, entry)| *next <= dates::local_date(entry, now) + days)
+        .collect();
+    upcoming.sort_by_key(|(next, entry)| (*next, entry.name.clone()));
+    upcoming
+}
+
+/// Lists the birthdays of this server within the next days
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn upcoming_birthdays(
+    ctx: Context<'_>,
+    #[description = "Number of days to look ahead (defaults to 30, at most 366)"] days: Option<u32>,
+    #[description = "Only show the reply to you"] quiet: Option<bool>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let days = days.unwrap_or(DEFAULT_UPCOMING_DAYS).min(MAX_UPCOMING_DAYS);
+    let birthdays = ctx.data().storage.read().await;
+    let quiet = birthdays.replies_quietly(guild_id, quiet);
+    let mut entries = visible_entries(ctx, &birthdays).await;
+    entries.extend(events::of_guild(&birthdays, guild_id));
+    let entries = upcoming(entries, Utc::now(), days, birthdays.leap_day(guild_id));
+    if entries.is_empty() {
+        let message = format!(
+            "☹️🎈 No birthdays in the next {} days for this guild!",
+            days
+        );
+        send_reply(ctx, quiet, poise::CreateReply::default().content(message)).await?;
+        return Ok(());
+    }
+
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|(next, entry)| upcoming_line(&birthdays, entry, *next))
+        .collect();
+    let title = format!("📅🎈 Birthdays in the next {} days", days);
+    pages::send(ctx, quiet, &title, &lines).await
+}
+
+/// Who created and last changed an entry, for moderators
+fn entry_metadata(entry: &BirthdayEntry) -> String {
+    let time = |time: Option<DateTime<Utc>>| {
+        time.map_or("unknown".to_string(), |time| {
+            format!("<t:{}:f>", time.timestamp())
+        })
+    };
+    let set_by = entry
+        .set_by
+        .map_or("unknown".to_string(), |user| format!("<@{}>", user));
+    format!(
+        "\n🛠️ Created: {}, last changed: {} by {}",
+        time(entry.created_at),
+        time(entry.updated_at),
+        set_by
+    )
+}
+
+#[poise::command(slash_command, prefix_command, required_permissions = "MANAGE_GUILD")]
+async fn set_announcement_channel(
+    ctx: Context<'_>,
+    #[description = "Channel to set as the birthday announcement channel"] channel: ChannelId,
+    #[description = "Comma separated tags for the posts, if the channel is a forum"]
+    forum_tags: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let language = ctx
+        .data()
+        .storage
+        .read_with(|birthdays| birthdays.language(guild_id))
+        .await;
+    let forum = channel
+        .to_channel(ctx)
+        .await?
+        .guild()
+        .filter(|channel| channel.kind == serenity::ChannelType::Forum);
+    let tags = match (forum, forum_tags) {
+        (_, None) => Vec::new(),
+        (None, Some(_)) => {
+            ctx.say(text(language, "channel_tags_need_forum", &[]))
+                .await?;
+            return Ok(());
+        }
+        (Some(forum), Some(names)) => match forum_tag_ids(&forum, &names) {
+            Ok(tags) => tags,
+            Err(unknown) => {
+                let available: Vec<&str> = forum
+                    .available_tags
+                    .iter()
+                    .map(|tag| tag.name.as_str())
+                    .collect();
+                ctx.say(text(
+                    language,
+                    "channel_unknown_tag",
+                    &[("tag", &unknown), ("tags", &available.join(", "))],
+                ))
+                .await?;
+                return Ok(());
+            }
+        },
+    };
+    // Looking the channel up takes a while, the change is made on the data as it is by now
+    ctx.data()
+        .storage
+        .update(|birthdays| {
+            birthdays.server_channels.insert(guild_id, channel);
+            let config = birthdays.guild_configs.entry(guild_id).or_default();
+            config.forum_tags = tags.clone();
+            config.channel_failures = 0;
+            // Nothing to warn about anymore once there is a channel
+            birthdays
+                .missed
+                .retain(|missed| missed.guild_id != guild_id);
+        })
+        .await?;
+    ctx.say(text(language, "channel_set", &[("channel", &channel)]))
+        .await?;
+    Ok(())
+}
+
+/// Stops announcing birthdays in the announcement channel of this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn unset_announcement_channel(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    // Works without looking the channel up, so deleted channels can be removed too
+    let Some(channel) = birthdays.server_channels.remove(&guild_id) else {
+        ctx.say("☹️🎈 No announcement channel set for this guild!")
+            .await?;
+        return Ok(());
+    };
+    if let Some(config) = birthdays.guild_configs.get_mut(&guild_id) {
+        config.forum_tags.clear();
+        config.channel_failures = 0;
+    }
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("removed the announcement channel <#{}>", channel),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    let fallback = birthdays
+        .guild_configs
+        .get(&guild_id)
+        .is_none_or(|config| config.system_channel_fallback);
+    let message = if fallback {
+        "📢 Announcement channel removed, birthdays are posted in the system channel now! Turn that off with `set_system_channel_fallback`."
+    } else {
+        "📢 Announcement channel removed, birthdays are no longer posted anywhere!"
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Looks up the forum's tags by name, returns the first unknown name if there is one
+fn forum_tag_ids(
+    forum: &serenity::GuildChannel,
+    names: &str,
+) -> Result<Vec<serenity::ForumTagId>, String> {
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            forum
+                .available_tags
+                .iter()
+                .find(|tag| tag.name.eq_ignore_ascii_case(name))
+                .map(|tag| tag.id)
+                .ok_or_else(|| name.to_string())
+        })
+        .collect()
+}
+
+/// Skips a user's upcoming birthday announcement without removing their birthday
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn snooze_announcement(
+    ctx: Context<'_>,
+    #[description = "User whose announcement should be skipped"] user: serenity::User,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let now = Utc::now();
+    let today = now.date_naive();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+
+    let config = birthdays.guild_configs.get(&guild_id);
+    let announced = &mut birthdays.announced;
+    let entry = birthdays
+        .entries
+        .iter_mut()
+        .find(|entry| entry.user_id == user.id && entry.guild_id == guild_id);
+    let entry = match entry {
+        Some(entry) => entry,
+        None => {
+            ctx.say("☹️🎈 No birthday set for this user for this guild!")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // Prefer an announcement that is pending right now (e.g. deferred by a quiet date)
+    let leap_day = config.map(|config| config.leap_day).unwrap_or_default();
+    let occurrence = due_occurrence(entry, now, config, announced)
+        .unwrap_or_else(|| dates::next_birthday(entry, now, leap_day));
+    snooze_entry(entry, announced, occurrence, today);
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!(
+            "snoozed the announcement of {} ({}) on {}",
+            format::escape(&user.name),
+            user.id,
+            occurrence
+        ),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    let format = birthdays.date_format(guild_id);
+    ctx.say(format!(
+        "😴🎈 Snoozed the announcement for {} on {}, use `unsnooze` today to undo this!",
+        format::escape(&user.name),
+        format.format(occurrence.day(), occurrence.month(), None)
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Skips all of today's pending birthday announcements in this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn snooze_all_today(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let now = Utc::now();
+    let today = now.date_naive();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+
+    let config = birthdays.guild_configs.get(&guild_id);
+    let announced = &mut birthdays.announced;
+    let mut snoozed = Vec::new();
+    for entry in birthdays
+        .entries
+        .iter_mut()
+        .filter(|entry| entry.guild_id == guild_id)
+    {
+        if let Some(occurrence) = todays_occurrence(entry, now, config, announced) {
+            snooze_entry(entry, announced, occurrence, today);
+            snoozed.push(format!(
+                "{} ({})",
+                format::escape(&entry.name),
+                entry.user_id
+            ));
+        }
+    }
+
+    if snoozed.is_empty() {
+        ctx.say("☹️🎈 There are no pending birthday announcements for today!")
+            .await?;
+        return Ok(());
+    }
+
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("snoozed today's announcements of {}", snoozed.join(", ")),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.say(format!(
+        "😴🎈 Snoozed {} announcement(s) for today, use `unsnooze` today to undo this!",
+        snoozed.len()
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Reverts today's snoozes for a user or, if no user is given, for the whole server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn unsnooze(
+    ctx: Context<'_>,
+    #[description = "User whose snooze should be reverted (defaults to everyone)"] user: Option<
+        serenity::User,
+    >,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let today = Utc::now().naive_utc().date();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+
+    let mut reverted = Vec::new();
+    for entry in birthdays.entries.iter_mut().filter(|entry| {
+        entry.guild_id == guild_id && user.as_ref().is_none_or(|user| user.id == entry.user_id)
+    }) {
+        if unsnooze_entry(entry, &mut birthdays.announced, today) {
+            reverted.push(format!(
+                "{} ({})",
+                format::escape(&entry.name),
+                entry.user_id
+            ));
+        }
+    }
+
+    if reverted.is_empty() {
+        ctx.say("☹️🎈 Nothing was snoozed today, so there is nothing to revert!")
+            .await?;
+        return Ok(());
+    }
+
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!(
+            "reverted the snoozed announcements of {}",
+            reverted.join(", ")
+        ),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.say(format!(
+        "⏰🎈 Reverted {} snoozed announcement(s)!",
+        reverted.len()
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Removes your or another user's birthday, it can be restored for 30 days
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn remove_birthday(
+    ctx: Context<'_>,
+    #[description = "User to remove the birthday for (defaults to yourself)"] user: Option<
+        serenity::User,
+    >,
+) -> Result<(), Error> {
+    let user = user.unwrap_or_else(|| ctx.author().clone());
+    let for_other = user.id != ctx.author().id;
+    if for_other && !is_moderator(ctx).await {
+        ctx.say("🐺🎩❌ Only moderators can remove other people's birthdays!")
+            .await?;
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    if !soft_delete(&mut birthdays, user.id, guild_id, Utc::now()) {
+        ctx.say("☹️🎈 No birthday set for this user for this guild!")
+            .await?;
+        return Ok(());
+    }
+    if for_other {
+        audit(
+            &mut birthdays,
+            guild_id,
+            ctx.author().id,
+            format!(
+                "removed the birthday of {} ({})",
+                format::escape(&user.name),
+                user.id
+            ),
+        );
+    }
+    ctx.data().storage.write(&birthdays).await?;
+    month_roles::apply(ctx.http(), &birthdays, guild_id, user.id, None).await;
+
+    ctx.say(format!(
+        "🗑️🎈 Removed the birthday of {}, it can be restored with `restore_birthday` within {} days!",
+        format::escape(&user.name),
+        RESTORE_DAYS
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Restores a removed birthday of yours or another user
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn restore_birthday(
+    ctx: Context<'_>,
+    #[description = "User to restore the birthday for (defaults to yourself)"] user: Option<
+        serenity::User,
+    >,
+) -> Result<(), Error> {
+    let user = user.unwrap_or_else(|| ctx.author().clone());
+    let for_other = user.id != ctx.author().id;
+    if for_other && !is_moderator(ctx).await {
+        ctx.say("🐺🎩❌ Only moderators can restore other people's birthdays!")
+            .await?;
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    purge_deleted(&mut birthdays, Utc::now());
+    if birthdays
+        .entries
+        .iter()
+        .any(|entry| entry.user_id == user.id && entry.guild_id == guild_id)
+    {
+        ctx.say("🐺🎩❌ This user already has a birthday set for this guild!")
+            .await?;
+        return Ok(());
+    }
+
+    // Restore the most recently removed entry
+    let Some(index) = birthdays.deleted.iter().rposition(|deleted| {
+        deleted.entry.user_id == user.id && deleted.entry.guild_id == guild_id
+    }) else {
+        ctx.say("☹️🎈 There is no removed birthday to restore for this user!")
+            .await?;
+        return Ok(());
+    };
+    let entry = birthdays.deleted.remove(index).entry;
+    let month = entry.date.month();
+    birthdays.entries.push(entry);
+    if for_other {
+        audit(
+            &mut birthdays,
+            guild_id,
+            ctx.author().id,
+            format!(
+                "restored the birthday of {} ({})",
+                format::escape(&user.name),
+                user.id
+            ),
+        );
+    }
+    ctx.data().storage.write(&birthdays).await?;
+    month_roles::apply(ctx.http(), &birthdays, guild_id, user.id, Some(month)).await;
+
+    ctx.say(format!(
+        "♻️🎈 Restored the birthday of {}!",
+        format::escape(&user.name)
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Removes your birthdays from every server
+#[poise::command(slash_command, prefix_command)]
+async fn delete_my_data(
+    ctx: Context<'_>,
+    #[description = "Delete everything right away instead of allowing a restore for 30 days"]
+    permanently: Option<bool>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id;
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let now = Utc::now();
+
+    let guilds: Vec<GuildId> = birthdays
+        .entries
+        .iter()
+        .filter(|entry| entry.user_id == user_id)
+        .map(|entry| entry.guild_id)
+        .collect();
+    for guild_id in &guilds {
+        soft_delete(&mut birthdays, user_id, *guild_id, now);
+    }
+
+    if permanently.unwrap_or(false) {
+        birthdays
+            .deleted
+            .retain(|deleted| deleted.entry.user_id != user_id);
+        birthdays
+            .reminders
+            .retain(|reminder| reminder.subscriber != user_id && reminder.target != user_id);
+        birthdays
+            .events
+            .retain(|event| event.user_id != user_id || event.kind.is_server_wide());
+        ctx.data().storage.write(&birthdays).await?;
+        for guild_id in &guilds {
+            month_roles::apply(ctx.http(), &birthdays, *guild_id, user_id, None).await;
+        }
+        ctx.say("🗑️🎈 All of your birthday data has been deleted permanently!")
+            .await?;
+        return Ok(());
+    }
+
+    ctx.data().storage.write(&birthdays).await?;
+    for guild_id in &guilds {
+        month_roles::apply(ctx.http(), &birthdays, *guild_id, user_id, None).await;
+    }
+    ctx.say(format!(
+        "🗑️🎈 Removed your birthday from {} server(s), it can be restored with `restore_birthday` within {} days!",
+        guilds.len(),
+        RESTORE_DAYS
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Removes your birthday from this server for good and keeps anyone from setting it again
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn birthday_optout(
+    ctx: Context<'_>,
+    #[description = "Whether nobody may set your birthday here (off opts back in)"] state: Toggle,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let user_id = ctx.author().id;
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let message = match state {
+        Toggle::On => {
+            let removed = opt_out(&mut birthdays, user_id, guild_id);
+            ctx.data().storage.write(&birthdays).await?;
+            if removed {
+                month_roles::apply(ctx.http(), &birthdays, guild_id, user_id, None).await;
+            }
+            "🚫🎈 Your birthday data in this server is deleted and nobody can set it again until you opt back in!"
+        }
+        Toggle::Off => {
+            birthdays.birthday_opt_outs.remove(&(guild_id, user_id));
+            ctx.data().storage.write(&birthdays).await?;
+            "🎈 You opted back in, birthdays can be set for you again!"
+        }
+    };
+    ctx.send(
+        poise::CreateReply::default()
+            .content(message)
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Sets who may look up your birthday, it is announced either way
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn set_birthday_visibility(
+    ctx: Context<'_>,
+    #[description = "Who may look up your birthday"] visibility: Visibility,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let entry = birthdays
+        .entries
+        .iter_mut()
+        .find(|entry| entry.user_id == ctx.author().id && entry.guild_id == guild_id);
+    let entry = match entry {
+        Some(entry) => entry,
+        None => {
+            ctx.say("☹️🎈 You haven't set a birthday for this guild!")
+                .await?;
+            return Ok(());
+        }
+    };
+    entry.visibility = visibility;
+    entry.touch(ctx.author().id, Utc::now());
+    ctx.data().storage.write(&birthdays).await?;
+
+    let description = match visibility {
+        Visibility::Public => "everyone",
+        Visibility::ModsOnly => "you and the moderators",
+        Visibility::Private => "only you",
+    };
+    ctx.say(format!(
+        "🔒🎈 Your birthday can now be looked up by {}!",
+        description
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Hides your birth year and age from everyone, your birthday is still announced
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn set_birthday_privacy(
+    ctx: Context<'_>,
+    #[description = "Whether your birth year and age are hidden"] state: Toggle,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let private = matches!(state, Toggle::On);
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let entry = birthdays
+        .entries
+        .iter_mut()
+        .find(|entry| entry.user_id == ctx.author().id && entry.guild_id == guild_id);
+    let Some(entry) = entry else {
+        ctx.say("☹️🎈 You haven't set a birthday for this guild!")
+            .await?;
+        return Ok(());
+    };
+    entry.private_year = private;
+    entry.touch(ctx.author().id, Utc::now());
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.say(if private {
+        "🔒🎈 Only the day and month of your birthday are shown now!"
+    } else {
+        "🔓🎈 Your birth year and age are shown again!"
+    })
+    .await?;
+    Ok(())
+}
+
+/// Shows the most recent moderation actions in this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn audit_log(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+
+    let lines: Vec<String> = birthdays
+        .audit_log
+        .iter()
+        .rev()
+        .filter(|entry| entry.guild_id == guild_id)
+        .take(10)
+        .map(|entry| {
+            format!(
+                "<t:{}:f> <@{}> {}",
+                entry.timestamp.timestamp(),
+                entry.actor,
+                entry.action
+            )
+        })
+        .collect();
+
+    if lines.is_empty() {
+        ctx.say("📜 The audit log for this guild is empty!").await?;
+        return Ok(());
+    }
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!("📜 Recent actions:\n{}", lines.join("\n")))
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Adds a date on which no birthdays are announced, they are posted the next day instead
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn add_quiet_date(
+    ctx: Context<'_>,
+    #[description = "Day"]
+    #[min = 1]
+    #[max = 31]
+    day: usize,
+    #[description = "Month"] month: MonthChoice,
+    #[description = "Only in this year (defaults to every year)"] year: Option<usize>,
+) -> Result<(), Error> {
+    let month = month.number();
+    if let Err(error) = checked_date(day, month, year, Language::English) {
+        ctx.say(format!("🐺🎩❌ {}!", error)).await?;
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().unwrap();
+    let quiet_date = QuietDate {
+        day: day as u32,
+        month: month as u32,
+        year: year.map(|year| year as i32),
+    };
+
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let config = birthdays.guild_configs.entry(guild_id).or_default();
+    let description = quiet_date.describe(config.date_format);
+    if config.quiet_dates.contains(&quiet_date) {
+        ctx.say(format!("🤫🎈 {} already is a quiet date!", description))
+            .await?;
+        return Ok(());
+    }
+    config.quiet_dates.push(quiet_date);
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("added the quiet date {}", description),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.say(format!(
+        "🤫🎈 Added {} as a quiet date, birthdays on it will be announced the next day!",
+        description
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Removes a quiet date again
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn remove_quiet_date(
+    ctx: Context<'_>,
+    #[description = "Day"]
+    #[min = 1]
+    #[max = 31]
+    day: usize,
+    #[description = "Month"] month: MonthChoice,
+    #[description = "Year, if the quiet date only applies to a single year"] year: Option<usize>,
+) -> Result<(), Error> {
+    let month = month.number();
+    let guild_id = ctx.guild_id().unwrap();
+    let quiet_date = QuietDate {
+        day: day as u32,
+        month: month as u32,
+        year: year.map(|year| year as i32),
+    };
+
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let config = birthdays.guild_configs.entry(guild_id).or_default();
+    let description = quiet_date.describe(config.date_format);
+    let count = config.quiet_dates.len();
+    config.quiet_dates.retain(|date| *date != quiet_date);
+    if config.quiet_dates.len() == count {
+        ctx.say(format!("☹️🎈 {} is not a quiet date!", description))
+            .await?;
+        return Ok(());
+    }
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("removed the quiet date {}", description),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.say(format!("📢🎈 Removed the quiet date {}!", description))
+        .await?;
+    Ok(())
+}
+
+/// Lists the dates on which no birthdays are announced in this server
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn list_quiet_dates(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    let format = birthdays.date_format(guild_id);
+    let quiet_dates = birthdays
+        .guild_configs
+        .get(&guild_id)
+        .map(|config| config.quiet_dates.as_slice())
+        .unwrap_or_default();
+
+    if quiet_dates.is_empty() {
+        ctx.say("📢🎈 This server has no quiet dates!").await?;
+        return Ok(());
+    }
+
+    let lines: Vec<String> = quiet_dates
+        .iter()
+        .map(|date| format!("- {}", date.describe(format)))
+        .collect();
+    ctx.say(format!("🤫🎈 Quiet dates:\n{}", lines.join("\n")))
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+enum Toggle {
+    #[name = "on"]
+    On,
+    #[name = "off"]
+    Off,
+}
+
+/// Enables or disables a command in this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn toggle_command(
+    ctx: Context<'_>,
+    #[description = "Name of the command"] name: String,
+    #[description = "Whether the command should be usable"] state: Toggle,
+) -> Result<(), Error> {
+    let name = name.trim_start_matches('/').to_lowercase();
+    if !ctx
+        .framework()
+        .options()
+        .commands
+        .iter()
+        .any(|command| command.name == name)
+    {
+        ctx.say(format!("🐺🎩❌ There is no command called `{}`!", name))
+            .await?;
+        return Ok(());
+    }
+    if CORE_COMMANDS.contains(&name.as_str()) {
+        ctx.say(format!("🐺🎩❌ `{}` can't be disabled!", name))
+            .await?;
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let config = birthdays.guild_configs.entry(guild_id).or_default();
+    config.disabled_commands.retain(|command| *command != name);
+    if let Toggle::Off = state {
+        config.disabled_commands.push(name.clone());
+    }
+    let state = match state {
+        Toggle::On => "enabled",
+        Toggle::Off => "disabled",
+    };
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("{} the command {}", state, name),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.say(format!("🔧🎈 `{}` is now {} in this server!", name, state))
+        .await?;
+    Ok(())
+}
+
+/// Shows the birthday configuration of this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    aliases("birthday_config", "show_config")
+)]
+async fn config(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    let config = birthdays.guild_configs.get(&guild_id);
+
+    let channel = match birthdays.server_channels.get(&guild_id) {
+        Some(channel) if birthdays.channel_broken(guild_id) => format!(
+            "<#{}> (⚠️ paused after failing on {} checks in a row, set it again once it is fixed)",
+            channel,
+            retry::MAX_CHANNEL_FAILURES
+        ),
+        Some(channel) => match channel.to_channel(ctx).await {
+            Err(error) if retry::FailureKind::of(&error) == retry::FailureKind::MissingChannel => {
+                format!(
+                    "<#{}> (⚠️ the channel no longer exists, set a new one or `unset_announcement_channel`)",
+                    channel
+                )
+            }
+            Ok(serenity::Channel::Guild(channel)) if !can_send_in(ctx, &channel).await => {
+                format!(
+                    "<#{}> (⚠️ I can't send messages there, check my permissions)",
+                    channel.id
+                )
+            }
+            _ => format!("<#{}>", channel),
+        },
+        None if config.is_none_or(|config| config.system_channel_fallback) => {
+            "not set (using the system channel)".to_string()
+        }
+        None => "not set".to_string(),
+    };
+    let quiet_dates = config.map_or(0, |config| config.quiet_dates.len());
+    let disabled_commands = match config {
+        Some(config) if !config.disabled_commands.is_empty() => config
+            .disabled_commands
+            .iter()
+            .map(|command| format!("`{}`", command))
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => "none".to_string(),
+    };
+
+    let prefix = config
+        .and_then(|config| config.prefix.as_deref())
+        .unwrap_or(&ctx.data().default_prefix);
+    let date_format = birthdays.date_format(guild_id);
+    let export = match config.and_then(|config| config.export.as_ref()) {
+        Some(export) => format!("weekly to <#{}>", export.channel),
+        None => "off".to_string(),
+    };
+    let fun_facts = match config.and_then(|config| config.fun_facts) {
+        Some(kind) => kind.describe(),
+        None => "off",
+    };
+    let month_roles = config.map_or(0, |config| config.month_roles.len());
+    let next_up_footer = if config.is_some_and(|config| config.next_up_footer) {
+        "on"
+    } else {
+        "off"
+    };
+    let announcement_notes = if config.is_some_and(|config| config.announcement_notes) {
+        "on"
+    } else {
+        "off"
+    };
+    let announcement_messages = match config.map_or(0, |config| config.announcement_messages.len())
+    {
+        0 => "default".to_string(),
+        count => format!("{} picked at random", count),
+    };
+    let catch_up = match config.map_or(DEFAULT_CATCH_UP_DAYS, |config| config.catch_up_days) {
+        0 => "off".to_string(),
+        1 => "1 day".to_string(),
+        days => format!("{} days", days),
+    };
+    let birthday_role = match config.and_then(|config| config.birthday_role) {
+        Some(role) => format!("<@&{}>", role),
+        None => "not set".to_string(),
+    };
+    let organizer_role = match config.and_then(|config| config.organizer_role) {
+        Some(role) => format!("<@&{}>", role),
+        None => "not set".to_string(),
+    };
+    let global_birthdays = if config.is_some_and(|config| config.global_birthdays) {
+        "on"
+    } else {
+        "off"
+    };
+    let ping_role = match config.and_then(|config| config.ping_role) {
+        Some(role) => format!("<@&{}>", role),
+        None => "not set".to_string(),
+    };
+    let birthday_manager_role = match config.and_then(|config| config.birthday_manager_role) {
+        Some(role) => format!("<@&{}>", role),
+        None => "not set".to_string(),
+    };
+    let opt_out_role = match config.and_then(|config| config.opt_out_role) {
+        Some(role) => format!(
+            "<@&{}> ({} announcement(s) skipped)",
+            role,
+            birthdays.opt_out_skips.get(&guild_id).unwrap_or(&0)
+        ),
+        None => "not set".to_string(),
+    };
+    let reactions = match config.and_then(|config| config.reactions.as_ref()) {
+        Some(reactions) => format!("on ({})", reactions.describe()),
+        None => "off".to_string(),
+    };
+    let quiet_replies = if config.is_some_and(|config| config.quiet_replies) {
+        "on"
+    } else {
+        "off"
+    };
+    let account_anniversaries = if config.is_some_and(|config| config.account_anniversaries) {
+        "on"
+    } else {
+        "off"
+    };
+    let retirement_age = retirement::age(config);
+    let life_expectancy = config
+        .and_then(|config| config.life_expectancy)
+        .unwrap_or(LIFE_EXPECTANCY);
+    let google_calendar = match config.and_then(|config| config.google_calendar.as_ref()) {
+        Some(calendar_id) => format!("`{}`", calendar_id),
+        None => "off".to_string(),
+    };
+    let year_in_review = if config.is_some_and(|config| config.year_in_review) {
+        "on"
+    } else {
+        "off"
+    };
+    let announcement_time = match config.and_then(|config| config.announcement_time) {
+        Some(time) => time.describe(),
+        None => "midnight in each member's time zone".to_string(),
+    };
+    let themes = if config.is_some_and(|config| config.themes.enabled) {
+        "on"
+    } else {
+        "off"
+    };
+    let mention_celebrants = if config.is_some_and(|config| config.mention_celebrants) {
+        "on"
+    } else {
+        "off"
+    };
+    let announcement_embed = match config.and_then(|config| config.announcement_embed.as_ref()) {
+        Some(embed) => format!("on ({})", embed.describe()),
+        None => "off".to_string(),
+    };
+    let show_ages = if birthdays.shows_ages(guild_id) {
+        "shown"
+    } else {
+        "hidden"
+    };
+    let registered = birthdays
+        .entries
+        .iter()
+        .filter(|entry| entry.guild_id == guild_id)
+        .count();
+    let next_check = *ctx.data().next_check.lock().await;
+
+    let leap_day = birthdays.leap_day(guild_id);
+    let upcoming: Vec<String> = upcoming(
+        visible_entries(ctx, &birthdays).await,
+        Utc::now(),
+        MAX_UPCOMING_DAYS,
+        leap_day,
+    )
+    .into_iter()
+    .take(CONFIG_UPCOMING)
+    .map(|(next, entry)| upcoming_line(&birthdays, entry, next))
+    .collect();
+    let upcoming = if upcoming.is_empty() {
+        "none".to_string()
+    } else {
+        format!("\n{}", upcoming.join("\n"))
+    };
+
+    let announcements = format!(
+        "- Channel: {}\n\
+        - Time: {}\n\
+        - Next check: <t:{}:R>\n\
+        - February 29th in other years: {}\n\
+        - Quiet dates: {}\n\
+        - Catch-up of missed birthdays: {}\n\
+        - Messages: {}\n\
+        - Mention celebrants: {}\n\
+        - Embed: {}\n\
+        - Next up footer: {}\n\
+        - Birthday notes: {}\n\
+        - Seasonal themes: {}\n\
+        - On this day facts: {}\n\
+        - Reactions to wishes: {}\n\
+        - Year in review: {}\n\
+        - Account anniversaries: {}",
+        channel,
+        announcement_time,
+        next_check.timestamp(),
+        leap_day.describe(),
+        quiet_dates,
+        catch_up,
+        announcement_messages,
+        mention_celebrants,
+        announcement_embed,
+        next_up_footer,
+        announcement_notes,
+        themes,
+        fun_facts,
+        reactions,
+        year_in_review,
+        account_anniversaries,
+    );
+    let members = format!(
+        "- Registered birthdays: {}\n\
+        - Global birthdays: {}\n\
+        - Upcoming: {}\n\
+        - Ages: {}\n\
+        - Retirement age: {}\n\
+        - Life expectancy: {}\n\
+        - Birthdays of members who leave: {}",
+        registered,
+        global_birthdays,
+        upcoming,
+        show_ages,
+        retirement_age,
+        life_expectancy,
+        config
+            .map(|config| config.on_member_leave)
+            .unwrap_or_default()
+            .describe(),
+    );
+    let roles = format!(
+        "- Birthday role: {}\n\
+        - Ping role: {}\n\
+        - Month roles: {} of 12\n\
+        - Gift organizers: {}\n\
+        - Birthday managers: {}\n\
+        - Opt-out role: {}",
+        birthday_role, ping_role, month_roles, organizer_role, birthday_manager_role, opt_out_role,
+    );
+    let server = format!(
+        "- Prefix: `{}`\n\
+        - Disabled commands: {}\n\
+        - Date format: {}\n\
+        - Language: {}\n\
+        - Quiet replies: {}\n\
+        - Export: {}\n\
+        - Google Calendar: {}",
+        prefix,
+        disabled_commands,
+        date_format.format(14, 6, Some(1995)),
+        birthdays.language(guild_id).name(),
+        quiet_replies,
+        export,
+        google_calendar,
+    );
+
+    let embed = serenity::CreateEmbed::new()
+        .title("🔧🎈 Birthday configuration")
+        .field("📣 Announcements", announcements, false)
+        .field("🎂 Birthdays", members, false)
+        .field("🎭 Roles", roles, false)
+        .field("⚙️ Server", server, false);
+    ctx.send(
+        poise::CreateReply::default()
+            .embed(embed)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Whether the bot can post in the channel, assumes it can if that can't be looked up
+async fn can_send_in(ctx: Context<'_>, channel: &serenity::GuildChannel) -> bool {
+    let Ok(guild) = channel.guild_id.to_partial_guild(ctx).await else {
+        return true;
+    };
+    let Ok(member) = guild.member(ctx, ctx.framework().bot_id).await else {
+        return true;
+    };
+    let permissions = guild.user_permissions_in(channel, &member);
+    permissions.view_channel() && permissions.send_messages()
+}
+
+/// Sets the prefix for prefix commands in this server, mentioning the bot always works too
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_prefix(
+    ctx: Context<'_>,
+    #[description = "New prefix (resets to the default if empty)"] prefix: Option<String>,
+) -> Result<(), Error> {
+    if let Some(prefix) = &prefix {
+        if prefix.is_empty() || prefix.chars().count() > 10 || prefix.contains(char::is_whitespace)
+        {
+            ctx.say("🐺🎩❌ The prefix must be 1 to 10 characters long and contain no spaces!")
+                .await?;
+            return Ok(());
+        }
+    }
+
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays.guild_configs.entry(guild_id).or_default().prefix = prefix.clone();
+    let prefix = prefix.unwrap_or_else(|| ctx.data().default_prefix.clone());
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("set the prefix to {}", prefix),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.say(format!("🔧🎈 The prefix is now `{}`!", prefix))
+        .await?;
+    Ok(())
+}
+
+/// Sets how dates are displayed in this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_date_format(
+    ctx: Context<'_>,
+    #[description = "Order of day, month and year"] order: DateOrder,
+    #[description = "Separator between the parts, e.g. . / or - (defaults to .)"] separator: Option<
+        String,
+    >,
+) -> Result<(), Error> {
+    let separator = match separator.as_deref() {
+        None => DateFormat::default().separator,
+        Some(separator) => {
+            let mut chars = separator.chars();
+            match (chars.next(), chars.next()) {
+                (Some(separator), None) if !separator.is_alphanumeric() => separator,
+                _ => {
+                    ctx.say("🐺🎩❌ The separator must be a single non-alphanumeric character!")
+                        .await?;
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    let guild_id = ctx.guild_id().unwrap();
+    let date_format = DateFormat { order, separator };
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .date_format = date_format;
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!(
+            "set the date format to {}",
+            date_format.format(14, 6, Some(1995))
+        ),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.say(format!(
+        "🔧🎈 Dates now look like this: {}",
+        date_format.format(14, 6, Some(1995))
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Posts an export of this server's birthdays to a private channel every week
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_export_channel(
+    ctx: Context<'_>,
+    #[description = "Private channel for the weekly export (turns it off if empty)"]
+    channel: Option<ChannelId>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let public = match channel {
+        Some(channel) => export::visible_to_everyone(ctx.http(), guild_id, channel).await?,
+        None => false,
+    };
+
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let config = birthdays.guild_configs.entry(guild_id).or_default();
+    // Keep the schedule when only the channel changes, so it doesn't cause an extra export
+    let last_export = config.export.as_ref().and_then(|export| export.last_export);
+    config.export = channel.map(|channel| export::ExportConfig {
+        channel,
+        last_export,
+        failed_at: None,
+    });
+    let action = match channel {
+        Some(channel) => format!("set the export channel to <#{}>", channel),
+        None => "turned off the weekly export".to_string(),
+    };
+    audit(&mut birthdays, guild_id, ctx.author().id, action);
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = match channel {
+        Some(channel) if public => format!(
+            "🗄️🎈 The birthdays of this server will be exported to <#{}> every week!\n\
+            ⚠️ Everyone can see that channel, you may want to make it private.",
+            channel
+        ),
+        Some(channel) => format!(
+            "🗄️🎈 The birthdays of this server will be exported to <#{}> every week!",
+            channel
+        ),
+        None => "🗄️ The weekly export is turned off!".to_string(),
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Adds an "on this day" fact to the birthday announcements of this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_fun_facts(
+    ctx: Context<'_>,
+    #[description = "Kind of fact to add (turns them off if empty)"] kind: Option<facts::FactKind>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .fun_facts = kind;
+    let action = match kind {
+        Some(kind) => format!("turned on {} facts", kind.describe()),
+        None => "turned off the facts".to_string(),
+    };
+    audit(&mut birthdays, guild_id, ctx.author().id, action);
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = match kind {
+        Some(kind) => format!(
+            "📜🎈 Birthday announcements now come with {} from that day in history!",
+            kind.describe()
+        ),
+        None => "📜 Birthday announcements no longer come with facts!".to_string(),
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Shows whose birthday comes next below the birthday announcements of this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_next_up_footer(
+    ctx: Context<'_>,
+    #[description = "Whether announcements show who's next"] state: Toggle,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let enabled = matches!(state, Toggle::On);
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .next_up_footer = enabled;
+    let state = if enabled { "on" } else { "off" };
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("turned {} the next up footer", state),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = if enabled {
+        "⏭️🎈 Birthday announcements now show whose birthday is next!"
+    } else {
+        "⏭️ Birthday announcements no longer show whose birthday is next!"
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Shows the notes members attached to their birthdays in the birthday announcements of this
+/// server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_announcement_notes(
+    ctx: Context<'_>,
+    #[description = "Whether announcements show the birthday notes"] state: Toggle,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let enabled = matches!(state, Toggle::On);
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .announcement_notes = enabled;
+    let state = if enabled { "on" } else { "off" };
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("turned {} the notes in announcements", state),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = if enabled {
+        "📝🎈 Birthday announcements now show the notes of the celebrants!"
+    } else {
+        "📝 Birthday announcements no longer show the notes of the celebrants!"
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Sets for how many days birthdays missed while the bot was down are announced afterwards
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_catch_up_days(
+    ctx: Context<'_>,
+    #[description = "Days to catch up on (defaults to 1, 0 turns it off)"]
+    #[max = 7]
+    days: Option<u32>,
+) -> Result<(), Error> {
+    let days = days.unwrap_or(DEFAULT_CATCH_UP_DAYS);
+    if days > MAX_CATCH_UP_DAYS {
+        ctx.say(format!(
+            "🐺🎩❌ Birthdays can be caught up on for at most {} days!",
+            MAX_CATCH_UP_DAYS
+        ))
+        .await?;
+        return Ok(());
+    }
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .catch_up_days = days;
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("set the catch-up window to {} day(s)", days),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = if days == 0 {
+        "⏰ Missed birthdays are no longer announced afterwards!".to_string()
+    } else {
+        format!(
+            "⏰🎈 Birthdays missed within the last {} day(s) now get a belated announcement!",
+            days
+        )
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Sets the day February 29th birthdays are celebrated on in other years
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_leap_day(
+    ctx: Context<'_>,
+    #[description = "Day to celebrate on when there is no February 29th"] day: LeapDay,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .leap_day = day;
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("set the leap day to {}", day.describe()),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.say(format!(
+        "📅🎈 February 29th birthdays are celebrated on {} in other years!",
+        day.describe()
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Sets the hour at which birthdays are announced, leave it empty for midnight
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_announcement_time(
+    ctx: Context<'_>,
+    #[description = "Hour of the day, 0 to 23 (resets to midnight if empty)"] hour: Option<u32>,
+    #[description = "UTC offset of the server's time zone, like +2 or +5:30 (defaults to UTC+0)"]
+    utc_offset: Option<String>,
+) -> Result<(), Error> {
+    if hour.is_some_and(|hour| hour > 23) {
+        ctx.say("🐺🎩❌ The hour must be between 0 and 23!").await?;
+        return Ok(());
+    }
+    let utc_offset = utc_offset
+        .as_deref()
+        .map(|utc_offset| UtcOffset::parse(utc_offset, Language::English))
+        .transpose();
+    let utc_offset = match utc_offset {
+        Ok(utc_offset) => utc_offset.unwrap_or_default(),
+        Err(error) => {
+            ctx.say(format!("🐺🎩❌ {}!", format::escape(&error)))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let time = hour.map(|hour| AnnouncementTime { hour, utc_offset });
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .announcement_time = time;
+    let action = match time {
+        Some(time) => format!("set the announcement time to {}", time.describe()),
+        None => "reset the announcement time".to_string(),
+    };
+    audit(&mut birthdays, guild_id, ctx.author().id, action);
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = match time {
+        Some(time) => format!("⏰🎈 Birthdays are now announced at {}!", time.describe()),
+        None => {
+            "⏰ Birthdays are announced at midnight in each member's time zone again!".to_string()
+        }
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Posts birthdays in the system channel while no announcement channel is set
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_system_channel_fallback(
+    ctx: Context<'_>,
+    #[description = "Whether to use the system channel without an announcement channel"]
+    state: Toggle,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let enabled = matches!(state, Toggle::On);
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .system_channel_fallback = enabled;
+    let state = if enabled { "on" } else { "off" };
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("turned {} the system channel fallback", state),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = if enabled {
+        "📢🎈 Without an announcement channel, birthdays are posted in the system channel!"
+    } else {
+        "📢 Without an announcement channel, birthdays are no longer posted anywhere!"
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Mentions members instead of using the name they had when their birthday was set
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_mention_celebrants(
+    ctx: Context<'_>,
+    #[description = "Whether to mention members, which pings them in announcements"] state: Toggle,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let enabled = matches!(state, Toggle::On);
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .mention_celebrants = enabled;
+    let state = if enabled { "on" } else { "off" };
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("turned {} mentioning celebrants", state),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = if enabled {
+        "📣🎈 Members are mentioned now, celebrants get pinged by their announcement!"
+    } else {
+        "📣 Members are named with the name they had when their birthday was set again!"
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Only shows the replies to the birthday commands to whoever ran them
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_quiet_replies(
+    ctx: Context<'_>,
+    #[description = "Whether replies are only shown to whoever ran the command"] state: Toggle,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let enabled = matches!(state, Toggle::On);
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .quiet_replies = enabled;
+    let state = if enabled { "on" } else { "off" };
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("turned {} quiet replies", state),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = if enabled {
+        "🤫🎈 Replies to the birthday commands are only shown to whoever ran them now!"
+    } else {
+        "📣 Replies to the birthday commands are public again, unless someone asks for quiet!"
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Shows or hides ages and birth years everywhere in this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_show_ages(
+    ctx: Context<'_>,
+    #[description = "Whether ages and birth years may be shown"] state: Toggle,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let enabled = matches!(state, Toggle::On);
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .show_ages = enabled;
+    let action = if enabled {
+        "allowed showing ages"
+    } else {
+        "disabled showing ages"
+    };
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        action.to_string(),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = if enabled {
+        "🔧🎈 Ages and birth years can be shown in this server again!"
+    } else {
+        "🔧🎈 Ages and birth years are no longer shown in this server, the entered years are kept!"
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Sets the role whose members can use the gift notes, leave it empty to remove it
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_organizer_role(
+    ctx: Context<'_>,
+    #[description = "Role of the gift organizers (removes it if empty)"] role: Option<
+        serenity::RoleId,
+    >,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .organizer_role = role;
+    let action = match role {
+        Some(role) => format!("set the organizer role to <@&{}>", role),
+        None => "removed the organizer role".to_string(),
+    };
+    audit(&mut birthdays, guild_id, ctx.author().id, action);
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = match role {
+        Some(role) => format!("🎁🎈 Members with <@&{}> can now use the gift notes!", role),
+        None => "🎁 Nobody can use the gift notes anymore, the notes are kept!".to_string(),
+    };
+    ctx.send(
+        poise::CreateReply::default()
+            .content(message)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Sets a role whose members can set other members' birthdays, leave it empty to remove it
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_birthday_manager_role(
+    ctx: Context<'_>,
+    #[description = "Role of members who may set others' birthdays (removes it if empty)"]
+    role: Option<serenity::RoleId>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .birthday_manager_role = role;
+    let action = match role {
+        Some(role) => format!("set the birthday manager role to <@&{}>", role),
+        None => "removed the birthday manager role".to_string(),
+    };
+    audit(&mut birthdays, guild_id, ctx.author().id, action);
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = match role {
+        Some(role) => format!(
+            "✍️🎈 Members with <@&{}> can now set other members' birthdays!",
+            role
+        ),
+        None => "✍️ Only moderators can set other members' birthdays now!".to_string(),
+    };
+    ctx.send(
+        poise::CreateReply::default()
+            .content(message)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Sets a role that is pinged by every announcement, leave it empty to remove it
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_announcement_ping_role(
+    ctx: Context<'_>,
+    #[description = "Role to ping when announcing a birthday (removes it if empty)"] role: Option<
+        serenity::RoleId,
+    >,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .ping_role = role;
+    let action = match role {
+        Some(role) => format!("set the announcement ping role to <@&{}>", role),
+        None => "removed the announcement ping role".to_string(),
+    };
+    audit(&mut birthdays, guild_id, ctx.author().id, action);
+    ctx.data().storage.write(&birthdays).await?;
+
+    let mut message = match role {
+        Some(role) => format!("🔔🎈 Announcements now ping <@&{}>!", role),
+        None => "🔕 Announcements no longer ping a role!".to_string(),
+    };
+    // Discord drops pings of roles that aren't mentionable, unless the bot may ping everyone
+    let mentionable = role.and_then(|role| {
+        ctx.guild()
+            .and_then(|guild| guild.roles.get(&role).map(|role| role.mentionable))
+    });
+    if mentionable == Some(false) {
+        message.push_str(
+            "\n⚠️ The role isn't mentionable, allow anyone to mention it or give me the Mention @everyone permission.",
+        );
+    }
+    ctx.send(
+        poise::CreateReply::default()
+            .content(message)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Sets a role whose members are never announced, leave it empty to remove it
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_opt_out_role(
+    ctx: Context<'_>,
+    #[description = "Role of members who don't want to be announced (removes it if empty)"]
+    role: Option<serenity::RoleId>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .opt_out_role = role;
+    let action = match role {
+        Some(role) => format!("set the opt-out role to <@&{}>", role),
+        None => "removed the opt-out role".to_string(),
+    };
+    audit(&mut birthdays, guild_id, ctx.author().id, action);
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = match role {
+        Some(role) => format!("🔕🎈 Members with <@&{}> are no longer announced!", role),
+        None => "🔔🎈 Everyone is announced again!".to_string(),
+    };
+    ctx.send(
+        poise::CreateReply::default()
+            .content(message)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Sets the role members born in a month get, leave the role empty to remove it
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_month_role(
+    ctx: Context<'_>,
+    #[description = "Month"] month: u32,
+    #[description = "Role for members born in that month (removes it if empty)"] role: Option<
+        serenity::RoleId,
+    >,
+) -> Result<(), Error> {
+    if !(1..=12).contains(&month) {
+        ctx.say("🐺🎩❌ Invalid month!").await?;
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let month_roles = &mut birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .month_roles;
+    let action = match role {
+        Some(role) => {
+            month_roles.insert(month, role);
+            format!(
+                "set the role for {} to <@&{}>",
+                month_roles::month_name(month),
+                role
+            )
+        }
+        None => {
+            month_roles.remove(&month);
+            format!("removed the role for {}", month_roles::month_name(month))
+        }
+    };
+    audit(&mut birthdays, guild_id, ctx.author().id, action);
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = match role {
+        Some(role) => format!(
+            "🎭🎈 Members born in {} now get <@&{}>, run `sync_month_roles` to update everyone!",
+            month_roles::month_name(month),
+            role
+        ),
+        None => format!(
+            "🎭 Members born in {} no longer get a role!",
+            month_roles::month_name(month)
+        ),
+    };
+    ctx.send(
+        poise::CreateReply::default()
+            .content(message)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Creates a role for every month that doesn't have one yet
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn create_month_roles(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut month_roles = ctx
+        .data()
+        .storage
+        .read_owned()
+        .await
+        .guild_configs
+        .get(&guild_id)
+        .map(|config| config.month_roles.clone())
+        .unwrap_or_default();
+    let previous = month_roles.len();
+    let result = month_roles::create_roles(ctx.http(), guild_id, &mut month_roles).await;
+    let created = month_roles.len() - previous;
+
+    // Keep the roles that were created even if a later one failed
+    if created > 0 {
+        ctx.data()
+            .storage
+            .update(|birthdays| {
+                let config = birthdays.guild_configs.entry(guild_id).or_default();
+                for (month, role) in &month_roles {
+                    config.month_roles.entry(*month).or_insert(*role);
+                }
+                audit(
+                    birthdays,
+                    guild_id,
+                    ctx.author().id,
+                    format!("created {} month roles", created),
+                );
+            })
+            .await?;
+    }
+
+    match result {
+        Ok(()) => {
+            ctx.say(format!(
+                "🎭🎈 Created {} month roles, run `sync_month_roles` to hand them out!",
+                created
+            ))
+            .await?
+        }
+        Err(error) => {
+            ctx.say(format!(
+                "🐺🎩❌ Created {} month roles before failing, am I allowed to manage roles? ({})",
+                created, error
+            ))
+            .await?
+        }
+    };
+    Ok(())
+}
+
+/// Gives everyone with a birthday the role of their month and removes outdated month roles
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn sync_month_roles(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    if birthdays
+        .guild_configs
+        .get(&guild_id)
+        .is_none_or(|config| config.month_roles.is_empty())
+    {
+        ctx.say(
+            "☹️🎈 No month roles are set up, use `set_month_role` or `create_month_roles` first!",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    // Large servers take a while
+    ctx.defer().await?;
+    let (updated, failed) = month_roles::sync(ctx.http(), &birthdays, guild_id).await;
+    if failed > 0 {
+        ctx.say(format!(
+            "🎭 Updated the month roles of {} members, {} failed. Am I allowed to manage the roles and is my role above them?",
+            updated, failed
+        ))
+        .await?;
+    } else {
+        ctx.say(format!(
+            "🎭🎈 Updated the month roles of {} members!",
+            updated
+        ))
+        .await?;
+    }
+    Ok(())
+}
+
+/// Checks for birthdays to announce right away instead of waiting for the next hourly check
+#[poise::command(slash_command, prefix_command)]
+async fn force_check(ctx: Context<'_>) -> Result<(), Error> {
+    // Owners check every guild, moderators only their own
+    let guild_id = if ctx.framework().options().owners.contains(&ctx.author().id) {
+        None
+    } else if is_moderator(ctx).await {
+        ctx.guild_id()
+    } else {
+        ctx.say("🐺🎩❌ Only moderators can force a birthday check!")
+            .await?;
+        return Ok(());
+    };
+
+    ctx.defer().await?;
+    let (reply, summary) = oneshot::channel();
+    if ctx
+        .data()
+        .check_requests
+        .try_send(CheckRequest { guild_id, reply })
+        .is_err()
+    {
+        ctx.say("🐺🎩❌ Too many checks are waiting already, please try again later!")
+            .await?;
+        return Ok(());
+    }
+    let summary = summary.await?;
+
+    ctx.say(format!(
+        "🔎🎈 Checked {} birthday(s):\n\
+        - Announced: {}\n\
+        - Announced late as they were missed on the day: {}\n\
+        - Announced after failing earlier: {}\n\
+        - Failed, will be retried: {}\n\
+        - Not due: {}\n\
+        - Already announced or snoozed: {}\n\
+        - Skipped for holding the opt-out role: {}\n\
+        - Skipped as no announcement channel is set: {}\n\
+        - Account anniversaries announced: {}\n\
+        - Custom anniversaries announced: {}",
+        summary.examined,
+        summary.sent,
+        summary.caught_up,
+        summary.retried,
+        summary.failed,
+        summary.not_due,
+        summary.already_announced,
+        summary.opted_out,
+        summary.no_channel,
+        summary.anniversaries,
+        summary.events
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Shows which commands are used the most
+#[poise::command(slash_command, prefix_command, owners_only)]
+async fn usage(ctx: Context<'_>) -> Result<(), Error> {
+    flush_usage(&ctx.data().storage, &ctx.data().pending_usage).await?;
+    let birthdays = ctx.data().storage.read().await;
+    let usage = &birthdays.usage;
+
+    let today = Utc::now().naive_utc().date();
+    let last_week = usage.since(today - chrono::Duration::days(6));
+    let mut commands: Vec<(&String, &u64)> = usage.commands.iter().collect();
+    commands.sort_by(|a, b| b.1.cmp(a.1));
+    if commands.is_empty() {
+        ctx.say("📊 No commands have been used yet!").await?;
+        return Ok(());
+    }
+
+    let commands: Vec<String> = commands
+        .into_iter()
+        .map(|(command, total)| {
+            format!(
+                "- `{}`: {} total, {} in the last 7 days",
+                command,
+                total,
+                last_week.get(command).unwrap_or(&0)
+            )
+        })
+        .collect();
+    let guilds: Vec<String> = usage
+        .top_guilds(5)
+        .into_iter()
+        .map(|(guild_id, total)| {
+            let name = guild_id
+                .name(ctx.cache())
+                .unwrap_or_else(|| guild_id.to_string());
+            format!("- {}: {}", name, total)
+        })
+        .collect();
+
+    let given_up: Vec<String> = birthdays
+        .failed_announcements
+        .iter()
+        .filter(|failed| failed.exhausted())
+        .map(|failed| {
+            format!(
+                "- <@{}> in {} on {}: {}",
+                failed.user_id,
+                failed.guild_id,
+                failed.occurrence,
+                failed.kind.describe()
+            )
+        })
+        .collect();
+    let given_up = if given_up.is_empty() {
+        "none".to_string()
+    } else {
+        given_up.join("\n")
+    };
+
+    ctx.say(format!(
+        "📊 Command usage:\n{}\n\nTop guilds:\n{}\n\nAnnouncements that couldn't be sent:\n{}",
+        commands.join("\n"),
+        guilds.join("\n"),
+        given_up
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Saves a copy of all data and sends it to you
+#[poise::command(slash_command, prefix_command, owners_only)]
+async fn snapshot(ctx: Context<'_>) -> Result<(), Error> {
+    let birthdays = ctx.data().storage.read().await;
+    let path = snapshot::write_snapshot(&ctx.data().storage.snapshot_dir(), &birthdays).await?;
+
+    ctx.author()
+        .direct_message(
+            ctx,
+            quiet_message(format!("💾 Snapshot {}", path.display()))
+                .add_file(serenity::CreateAttachment::path(&path).await?),
+        )
+        .await?;
+    ctx.say(format!(
+        "💾 Saved the snapshot {} and sent it to you!",
+        path.display()
+    ))
+    .await?;
+    Ok(())
+}
+
+async fn autocomplete_snapshot<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    snapshot::list_snapshots(&ctx.data().storage.snapshot_dir())
+        .into_iter()
+        .filter(move |name| name.contains(partial))
+        .take(25)
+}
+
+/// Replaces all data with a snapshot or an uploaded backup, lists the snapshots if neither is
+/// given
+#[poise::command(slash_command, prefix_command, owners_only)]
+async fn restore(
+    ctx: Context<'_>,
+    #[description = "Snapshot to restore"]
+    #[autocomplete = "autocomplete_snapshot"]
+    snapshot: Option<String>,
+    #[description = "Backup file sent by the backup command"] file: Option<serenity::Attachment>,
+    #[description = "Merge it into the current data instead of replacing it"] merge: Option<bool>,
+) -> Result<(), Error> {
+    let restored = match (snapshot, file) {
+        (Some(_), Some(_)) => Err("Pick either a snapshot or a file".to_string()),
+        (Some(name), None) => snapshot::read_snapshot(&ctx.data().storage.snapshot_dir(), &name)
+            .map(|restored| (format!("`{}`", name), restored))
+            .map_err(|error| format!("Couldn't load the snapshot: {}", error)),
+        (None, Some(file)) => backup::read_attachment(&file)
+            .await
+            .map(|restored| (format!("`{}`", file.filename), restored)),
+        (None, None) => {
+            list_snapshots(ctx).await?;
+            return Ok(());
+        }
+    };
+    let (name, restored) = match restored {
+        Ok(restored) => restored,
+        Err(error) => {
+            ctx.say(format!("🐺🎩❌ {}!", error)).await?;
+            return Ok(());
+        }
+    };
+
+    let current = ctx.data().storage.read().await;
+    let merging = merge.unwrap_or(false);
+    let (prompt, data) = if merging {
+        let mut merged = BirthdayList::clone(&current);
+        let report = merge::merge(&mut merged, restored);
+        let prompt = format!(
+            "💾 Merging {} goes from {} to {} ({} added, {} overwritten, {} conflicts kept as they are), continue?",
+            name,
+            describe_data(&current),
+            describe_data(&merged),
+            report.added,
+            report.overwritten,
+            report.conflicts.len()
+        );
+        (prompt, merged)
+    } else {
+        let prompt = format!(
+            "💾 Restoring {} replaces the current data ({}) with {}, continue?",
+            name,
+            describe_data(&current),
+            describe_data(&restored)
+        );
+        (prompt, restored)
+    };
+    if !confirm(ctx, prompt).await? {
+        return Ok(());
+    }
+
+    let backup = snapshot::write_snapshot(&ctx.data().storage.snapshot_dir(), &current).await?;
+    // Merging builds on the current data, so it must fail if that changed in the meantime
+    if merging {
+        ctx.data().storage.write(&data).await?;
+    } else {
+        ctx.data().storage.replace(data).await?;
+    }
+    ctx.say(format!(
+        "💾 {} {}, the previous data was saved as {}!",
+        if merging { "Merged" } else { "Restored" },
+        name,
+        backup.display()
+    ))
+    .await?;
+    Ok(())
+}
+
+async fn list_snapshots(ctx: Context<'_>) -> Result<(), Error> {
+    let snapshots = snapshot::list_snapshots(&ctx.data().storage.snapshot_dir());
+    if snapshots.is_empty() {
+        ctx.say("💾 There are no snapshots yet!").await?;
+    } else {
+        let lines: Vec<String> = snapshots
+            .iter()
+            .take(20)
+            .map(|name| format!("- `{}`", name))
+            .collect();
+        ctx.say(format!("💾 Available snapshots:\n{}", lines.join("\n")))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Merges the data file of another instance into this one, after a dry run
+#[poise::command(slash_command, prefix_command, owners_only)]
+async fn merge_data(
+    ctx: Context<'_>,
+    #[description = "Path of the other data file"] path: String,
+) -> Result<(), Error> {
+    let theirs = match merge::read_data_file(std::path::Path::new(&path)) {
+        Ok(theirs) => theirs,
+        Err(error) => {
+            ctx.say(format!("🐺🎩❌ Couldn't load the data file: {}", error))
+                .await?;
+            return Ok(());
+        }
+    };
+    let current = ctx.data().storage.read().await;
+    let mut merged = BirthdayList::clone(&current);
+    let report = merge::merge(&mut merged, theirs);
+
+    // Nothing is written before every conflict was shown
+    let mut dry_run = poise::CreateReply::default().content(format!(
+        "🔀 Dry run of merging `{}`:\n\
+        - Added: {}\n\
+        - Overwritten by newer changes: {}\n\
+        - Unchanged: {}\n\
+        - Conflicts kept as they are: {}{}",
+        path,
+        report.added,
+        report.overwritten,
+        report.unchanged,
+        report.conflicts.len(),
+        if report.conflicts.is_empty() {
+            ""
+        } else {
+            ", see the attached list"
+        }
+    ));
+    if !report.conflicts.is_empty() {
+        dry_run = dry_run.attachment(serenity::CreateAttachment::bytes(
+            report.conflicts.join("\n"),
+            "conflicts.txt",
+        ));
+    }
+    ctx.send(dry_run).await?;
+
+    let prompt = format!(
+        "🔀 Merging goes from {} to {}, continue?",
+        describe_data(&current),
+        describe_data(&merged)
+    );
+    if !confirm(ctx, prompt).await? {
+        return Ok(());
+    }
+
+    let backup = snapshot::write_snapshot(&ctx.data().storage.snapshot_dir(), &current).await?;
+    ctx.data().storage.write(&merged).await?;
+    ctx.say(format!(
+        "🔀 Merged `{}`, the previous data was saved as {}!",
+        path,
+        backup.display()
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Re-reads the data file after it was edited by hand
+#[poise::command(slash_command, prefix_command, owners_only)]
+async fn reload(ctx: Context<'_>) -> Result<(), Error> {
+    match ctx.data().storage.reload().await {
+        Ok(Some((previous, current))) => {
+            ctx.say(format!(
+                "🔄 Reloaded the data file, it went from {} to {}!",
+                describe_data(&previous),
+                describe_data(&current)
+            ))
+            .await?
+        }
+        Ok(None) => ctx.say("🔄 The data file is already up to date!").await?,
+        Err(error) => {
+            ctx.say(format!(
+                "🐺🎩❌ {}, changes are refused until it is fixed!",
+                error
+            ))
+            .await?
+        }
+    };
+    Ok(())
+}
+
+/// Stores the data in another file format
+#[poise::command(slash_command, prefix_command, owners_only)]
+async fn convert_storage(
+    ctx: Context<'_>,
+    #[description = "Format to store the data in"] format: storage::StorageFormat,
+) -> Result<(), Error> {
+    match ctx.data().storage.convert(format).await {
+        Ok((previous, path)) => {
+            ctx.say(format!(
+                "💾 The data is now stored in {}, the previous file was kept as {}.bak!",
+                path.display(),
+                previous.display()
+            ))
+            .await?
+        }
+        Err(error) => ctx.say(format!("🐺🎩❌ {}", error)).await?,
+    };
+    Ok(())
+}
+
+fn describe_data(birthdays: &BirthdayList) -> String {
+    let guilds: std::collections::HashSet<GuildId> = birthdays
+        .entries
+        .iter()
+        .map(|entry| entry.guild_id)
+        .chain(birthdays.server_channels.keys().copied())
+        .chain(birthdays.guild_configs.keys().copied())
+        .collect();
+    format!(
+        "{} birthdays in {} guilds",
+        birthdays.entries.len(),
+        guilds.len()
+    )
+}
+
+/// Asks the invoking user to confirm an action with buttons, returns whether they did
+async fn confirm(ctx: Context<'_>, prompt: String) -> Result<bool, Error> {
+    let confirm_id = format!("{}-confirm", ctx.id());
+    let cancel_id = format!("{}-cancel", ctx.id());
+    let reply = ctx
+        .send(
+            poise::CreateReply::default()
+                .content(prompt.clone())
+                .components(vec![serenity::CreateActionRow::Buttons(vec![
+                    serenity::CreateButton::new(&confirm_id)
+                        .style(serenity::ButtonStyle::Danger)
+                        .label("Confirm"),
+                    serenity::CreateButton::new(&cancel_id)
+                        .style(serenity::ButtonStyle::Secondary)
+                        .label("Cancel"),
+                ])]),
+        )
+        .await?;
+
+    let ids = [confirm_id.clone(), cancel_id];
+    let interaction = serenity::ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .channel_id(ctx.channel_id())
+        .timeout(std::time::Duration::from_secs(60))
+        .filter(move |interaction| ids.contains(&interaction.data.custom_id))
+        .await;
+
+    let (confirmed, outcome) = match &interaction {
+        Some(interaction) if interaction.data.custom_id == confirm_id => (true, "✅ Confirmed"),
+        Some(_) => (false, "❌ Cancelled"),
+        None => (false, "⌛ Timed out"),
+    };
+    if let Some(interaction) = interaction {
+        interaction
+            .create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
+            .await?;
+    }
+    // Remove the buttons so the prompt can't be answered twice
+    reply
+        .edit(
+            ctx,
+            poise::CreateReply::default()
+                .content(format!("{}\n{}", prompt, outcome))
+                .components(Vec::new()),
+        )
+        .await?;
+    Ok(confirmed)
+}
+
+async fn event_handler(
+    ctx: &serenity::Context,
+    event: &serenity::FullEvent,
+    data: &Data,
+) -> Result<(), Error> {
+    match event {
+        serenity::FullEvent::Resume { .. } => data.metrics.set_connected(true),
+        serenity::FullEvent::ShardStageUpdate { event } => data
+            .metrics
+            .set_connected(event.new == serenity::ConnectionStage::Connected),
+        serenity::FullEvent::Message { new_message } => {
+            reactions::react_to_wishes(ctx, new_message, data).await?;
+        }
+        serenity::FullEvent::GuildMemberRemoval { guild_id, user, .. } => {
+            let bot_id = ctx.cache.current_user().id;
+            prune::on_member_removal(&data.storage, *guild_id, user.id, bot_id).await;
+        }
+        serenity::FullEvent::GuildMemberAddition { new_member } => {
+            prune::on_member_addition(&data.storage, new_member.guild_id, new_member.user.id).await;
+        }
+        serenity::FullEvent::GuildDelete { incomplete, .. } => {
+            prune::on_guild_delete(&data.storage, incomplete).await;
+        }
+        serenity::FullEvent::GuildCreate { guild, .. } => {
+            prune::on_guild_create(&data.storage, guild.id).await;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Names the command in the span it runs in, see `logging`
+async fn before_command(ctx: Context<'_>) {
+    logging::record_command(&ctx.command().qualified_name);
+    info!("Running the command");
+}
+
+/// Logs a failed command with its context and tells whoever ran it what went wrong
+async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
+    match error {
+        poise::FrameworkError::Command { error, ctx, .. } => {
+            error!(%error, "The command failed");
+            if let Err(error) = ctx.say(error.to_string()).await {
+                warn!(%error, "Failed to tell about the failed command");
+            }
+        }
+        poise::FrameworkError::Setup { error, .. } => error!(%error, "Failed to set up the bot"),
+        error => {
+            if let Err(error) = poise::builtins::on_error(error).await {
+                warn!(%error, "Failed to handle an error");
+            }
+        }
+    }
+}
+
+/// Commands may have added or changed birthdays, so the next check is worked out again
+async fn after_command(ctx: Context<'_>) {
+    record_usage(ctx).await;
+    ctx.data().metrics.command(&ctx.command().qualified_name);
+    ctx.data().reschedule.notify_one();
+}
+
+/// Counts a successful command invocation, it is persisted with the next flush
+async fn record_usage(ctx: Context<'_>) {
+    let today = Utc::now().naive_utc().date();
+    ctx.data().pending_usage.lock().await.record(
+        &ctx.command().qualified_name,
+        ctx.guild_id(),
+        today,
+    );
+}
+
+/// Writes the pending usage counters to the file
+async fn flush_usage(storage: &Storage, pending_usage: &Mutex<UsageStats>) -> Result<(), Error> {
+    let mut pending_usage = pending_usage.lock().await;
+    if pending_usage.is_empty() {
+        return Ok(());
+    }
+
+    let today = Utc::now().naive_utc().date();
+    let usage = std::mem::take(&mut *pending_usage);
+    storage
+        .update(|birthdays| birthdays.usage.merge(usage.clone(), today))
+        .await
+}
+
+async fn flush_usage_periodically(storage: Storage, pending_usage: Arc<Mutex<UsageStats>>) {
+    loop {
+        tokio::time::sleep(tokio::time::Duration::from_secs(USAGE_FLUSH_TIME)).await;
+        if let Err(error) = flush_usage(&storage, &pending_usage).await {
+            error!(%error, "Failed to save the command usage");
+        }
+    }
+}
+
+/// Resolves the prefix of the guild a message was sent in, falling back to the default
+async fn guild_prefix(
+    ctx: poise::PartialContext<'_, Data, Error>,
+) -> Result<Option<String>, Error> {
+    let prefix = match ctx.guild_id {
+        Some(guild_id) => {
+            ctx.data
+                .storage
+                .read_with(|birthdays| {
+                    birthdays
+                        .guild_configs
+                        .get(&guild_id)
+                        .and_then(|config| config.prefix.clone())
+                })
+                .await
+        }
+        None => None,
+    };
+    Ok(Some(
+        prefix.unwrap_or_else(|| ctx.data.default_prefix.clone()),
+    ))
+}
+
+/// Rejects commands that were disabled in the invoking guild
+async fn check_command_enabled(ctx: Context<'_>) -> Result<bool, Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(true);
+    };
+
+    let disabled = ctx
+        .data()
+        .storage
+        .read_with(|birthdays| {
+            birthdays
+                .guild_configs
+                .get(&guild_id)
+                .is_some_and(|config| {
+                    config
+                        .disabled_commands
+                        .iter()
+                        .any(|command| *command == ctx.command().name)
+                })
+        })
+        .await;
+    if disabled {
+        ctx.send(
+            poise::CreateReply::default()
+                .content("🔇 This command is disabled here!")
+                .ephemeral(true),
+        )
+        .await?;
+    }
+    Ok(!disabled)
+}
+
+/// The birth year of the entry for calculations based on the age. Replies why there is none and
+/// returns None if the guild hides ages or the user didn't set a year.
+async fn birth_year_or_refuse(
+    ctx: Context<'_>,
+    entry: &BirthdayEntry,
+    what: &str,
+) -> Result<Option<i32>, Error> {
+    let birthdays = ctx.data().storage.read().await;
+    if !birthdays.shows_ages(entry.guild_id) {
+        ctx.say(format!(
+            "🐺🎩❌ Can't calculate {} (This server has disabled showing ages)!",
+            what
+        ))
+        .await?;
+        return Ok(None);
+    }
+    if entry.private_year {
+        ctx.say(format!(
+            "🔒 Can't calculate {} (User keeps their birth year private)!",
+            what
+        ))
+        .await?;
+        return Ok(None);
+    }
+    let year = birthdays.birth_year(entry);
+    if year.is_none() {
+        ctx.say(format!(
+            "🐺🎩❌ Can't calculate {} (User has not set year)!",
+            what
+        ))
+        .await?;
+    }
+    Ok(year)
+}
+
+/// When someone born on `date` in `birth_year` reaches `expectancy`, None if that is over
+fn expected_end(
+    date: NaiveDate,
+    birth_year: i32,
+    expectancy: i32,
+    today: NaiveDate,
+    leap_day: LeapDay,
+) -> Option<NaiveDate> {
+    let date = dates::birthday_in_year(date, birth_year + expectancy, leap_day);
+    (date >= today).then_some(date)
+}
+
+/// Calculates how much time you or another user have left
+#[poise::command(slash_command, prefix_command)]
 async fn time_left(
     ctx: Context<'_>,
     #[description = "User to get the skibidi for (defaults to yourself)"] user: Option<
         serenity::User,
     >,
+    #[description = "Life expectancy to use instead of the server's (1 to 150)"] expectancy: Option<
+        i32,
+    >,
+    #[description = "Only show the reply to you"] quiet: Option<bool>,
 ) -> Result<(), Error> {
+    let birthdays = ctx.data().storage.read().await;
+    let language = birthdays.language(ctx.guild_id().unwrap_or_default());
+    let quiet = birthdays.replies_quietly(ctx.guild_id().unwrap_or_default(), quiet);
+    if expectancy.is_some_and(|expectancy| !LIFE_EXPECTANCY_RANGE.contains(&expectancy)) {
+        let message = text(
+            language,
+            "life_expectancy_range",
+            &[
+                ("min", LIFE_EXPECTANCY_RANGE.start()),
+                ("max", LIFE_EXPECTANCY_RANGE.end()),
+            ],
+        );
+        send_reply(ctx, quiet, poise::CreateReply::default().content(message)).await?;
+        return Ok(());
+    }
     let user = user.unwrap_or_else(|| ctx.author().clone());
-    let entry = get_birthday_from_file(user.id, ctx.guild_id().unwrap()).await?;
+    let entry = get_visible_birthday(ctx, user.id).await?;
     let entry = match entry {
         Some(entry) => entry,
         None => {
-            ctx.say("☹️🎈 No birthday set for this user for this guild!")
-                .await?;
+            let message = text(language, "no_birthday", &[]);
+            send_reply(ctx, quiet, poise::CreateReply::default().content(message)).await?;
             return Ok(());
         }
     };
 
-    if entry.date.year() == 2024 {
-        ctx.say("🐺🎩❌ Can't calculate skibidi (User has not set year)!")
-            .await?;
-        return Ok(());
+    let Some(year) = birth_year_or_refuse(ctx, &entry, "skibidi").await? else {
+        return Ok(());
+    };
+
+    let configured = birthdays
+        .guild_configs
+        .get(&entry.guild_id)
+        .and_then(|config| config.life_expectancy);
+    let (expectancy, source) = match (expectancy, configured) {
+        (Some(expectancy), _) => (expectancy, "expectancy_given"),
+        (None, Some(expectancy)) => (expectancy, "expectancy_server"),
+        (None, None) => (LIFE_EXPECTANCY, "expectancy_default"),
+    };
+    let name = format::escape(&entry.name);
+    let source = text(language, source, &[]);
+    let message = match expected_end(
+        entry.date,
+        year,
+        expectancy,
+        Utc::now().date_naive(),
+        birthdays.leap_day(entry.guild_id),
+    ) {
+        Some(date) => text(
+            language,
+            "time_left",
+            &[
+                ("name", &name),
+                (
+                    "time",
+                    &date_to_discord_timestamp(date, entry.utc_offset, true),
+                ),
+                ("source", &source),
+                ("expectancy", &expectancy),
+            ],
+        ),
+        None => text(
+            language,
+            "time_left_beaten",
+            &[
+                ("name", &name),
+                ("source", &source),
+                ("expectancy", &expectancy),
+            ],
+        ),
+    };
+    send_reply(ctx, quiet, poise::CreateReply::default().content(message)).await?;
+    Ok(())
+}
+
+/// Sets the life expectancy `time_left` counts down to in this server, leave it empty for the
+/// default
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn set_life_expectancy(
+    ctx: Context<'_>,
+    #[description = "Life expectancy (defaults to 83)"] expectancy: Option<i32>,
+) -> Result<(), Error> {
+    if expectancy.is_some_and(|expectancy| !LIFE_EXPECTANCY_RANGE.contains(&expectancy)) {
+        ctx.say(format!(
+            "🐺🎩❌ The life expectancy must be between {} and {}!",
+            LIFE_EXPECTANCY_RANGE.start(),
+            LIFE_EXPECTANCY_RANGE.end()
+        ))
+        .await?;
+        return Ok(());
+    }
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .life_expectancy = expectancy;
+    let expectancy = expectancy.unwrap_or(LIFE_EXPECTANCY);
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("set the life expectancy to {}", expectancy),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+    ctx.say(format!("💀🎈 The life expectancy is now {}!", expectancy))
+        .await?;
+    Ok(())
+}
+
+/// The guild's system channel if birthdays may fall back to it, it is never stored as the
+/// announcement channel
+async fn fallback_channel(
+    http: &serenity::Http,
+    birthdays: &BirthdayList,
+    guild_id: GuildId,
+) -> Option<ChannelId> {
+    let fallback = birthdays
+        .guild_configs
+        .get(&guild_id)
+        .is_none_or(|config| config.system_channel_fallback);
+    if !fallback {
+        return None;
+    }
+    match guild_id.to_partial_guild(http).await {
+        Ok(guild) => guild.system_channel_id,
+        Err(error) => {
+            warn!(guild = %guild_id, %error, "Failed to look up the system channel");
+            None
+        }
+    }
+}
+
+/// The age and names an announcement of the entries fills in, the names once as the guild
+/// refers to members and once for embed titles, which can't show mentions. A lone celebrant's
+/// age goes into the sentence, with several it follows each name.
+fn celebrant_names(
+    birthdays: &BirthdayList,
+    entries: &[&BirthdayEntry],
+    occurrence: NaiveDate,
+) -> (String, String, String) {
+    let language = birthdays.language(entries[0].guild_id);
+    let ordinal = |entry: &BirthdayEntry| {
+        birthdays
+            .age_on(entry, occurrence)
+            .map(|age| i18n::ordinal(language, age))
+    };
+    if let [entry] = entries {
+        return (
+            ordinal(entry).map_or(String::new(), |age| format!(" {}", age)),
+            birthdays.display_name(entry),
+            format::escape(&entry.name),
+        );
+    }
+    let names = |name: fn(&BirthdayList, &BirthdayEntry) -> String| {
+        let names: Vec<String> = entries
+            .iter()
+            .map(|entry| match ordinal(entry) {
+                Some(age) => format!("{} ({})", name(birthdays, entry), age),
+                None => name(birthdays, entry),
+            })
+            .collect();
+        i18n::list(language, &names)
+    };
+    (
+        String::new(),
+        names(BirthdayList::display_name),
+        names(|_, entry| format::escape(&entry.name)),
+    )
+}
+
+/// Sends one announcement for the birthdays of members of a guild that fall on the same
+/// occurrence, returns false without sending anything if the guild has no announcement channel
+/// and the system channel can't be used either. `caught_up` announces birthdays that were
+/// missed on the day with a belated message of their own.
+#[allow(clippy::too_many_arguments)]
+async fn send_announcement<S: facts::FactSource>(
+    http: &serenity::Http,
+    birthdays: &BirthdayList,
+    facts: &facts::Facts<S>,
+    entries: &[&BirthdayEntry],
+    occurrence: NaiveDate,
+    today: NaiveDate,
+    celebrating: &[(GuildId, serenity::UserId)],
+    caught_up: bool,
+) -> Result<bool, serenity::Error> {
+    let entry = entries[0];
+    let (channel, fallback) = match birthdays.server_channels.get(&entry.guild_id) {
+        Some(channel) => (*channel, false),
+        None => match fallback_channel(http, birthdays, entry.guild_id).await {
+            Some(channel) => (channel, true),
+            None => return Ok(false),
+        },
+    };
+    let config = birthdays.guild_configs.get(&entry.guild_id);
+    let language = birthdays.language(entry.guild_id);
+    let (age, name, title_name) = celebrant_names(birthdays, entries, occurrence);
+    let belated = if occurrence < announcement_date(entry, config, Utc::now()) {
+        text(language, "announcement_belated", &[])
+    } else {
+        String::new()
+    };
+    // Birthdays that are caught up on keep their own belated message
+    let template = config
+        .filter(|_| !caught_up)
+        .and_then(|config| templates::pick(&config.announcement_messages));
+    let (headline, title) = match template {
+        Some(template) => {
+            let mentions: Vec<String> = entries
+                .iter()
+                .map(|entry| format!("<@{}>", entry.user_id))
+                .collect();
+            let mentions = i18n::list(language, &mentions);
+            (
+                templates::render(template, &title_name, &mentions, age.trim()) + &belated,
+                // Embed titles can't show mentions
+                templates::render(template, &title_name, &title_name, age.trim()) + &belated,
+            )
+        }
+        None => {
+            let (key, title_key) = if caught_up {
+                ("announcement_caught_up", "announcement_caught_up_title")
+            } else {
+                ("announcement", "announcement_title")
+            };
+            (
+                text(
+                    language,
+                    key,
+                    &[("age", &age), ("name", &name), ("belated", &belated)],
+                ),
+                text(
+                    language,
+                    title_key,
+                    &[("age", &age), ("name", &title_name), ("belated", &belated)],
+                ),
+            )
+        }
+    };
+    let mut details = String::new();
+    if config.is_some_and(|config| config.announcement_notes) {
+        for entry in entries {
+            let Some(note) = &entry.note else {
+                continue;
+            };
+            // Several celebrants need to be told apart
+            if entries.len() > 1 {
+                details.push_str(&format!(
+                    "\n📝 {}: {}",
+                    birthdays.display_name(entry),
+                    notes::display(note)
+                ));
+            } else {
+                details.push_str(&format!("\n📝 {}", notes::display(note)));
+            }
+        }
+    }
+    if let Some(kind) = config.and_then(|config| config.fun_facts) {
+        if let Some(fact) = facts.fact(kind, today).await {
+            details.push_str(&format!("\n📜 On this day in {}", fact));
+        }
+    }
+    if config.is_some_and(|config| config.next_up_footer) {
+        if let Some((next, date)) = next_up(birthdays, entry.guild_id, today, celebrating) {
+            details.push_str(&text(
+                language,
+                "announcement_next_up",
+                &[
+                    ("name", &birthdays.display_name(next)),
+                    (
+                        "time",
+                        &date_to_discord_timestamp(date, next.utc_offset, true),
+                    ),
+                ],
+            ));
+        }
+    }
+    let notice = if fallback {
+        text(language, "announcement_fallback", &[])
+    } else {
+        String::new()
+    };
+    let ping = ping_role(http, config, entry.guild_id).await;
+
+    let embed = match config.and_then(|config| config.announcement_embed.as_ref()) {
+        Some(embed) => {
+            let description = themes::decorate(
+                birthdays,
+                entry.guild_id,
+                today,
+                details.trim_start().to_string(),
+            );
+            let mention = config.is_some_and(|config| config.mention_celebrants);
+            embeds::announcement(
+                http,
+                entries,
+                embed,
+                title,
+                format!("{}{}", description.trim(), notice),
+                mention,
+                ping,
+            )
+            .await
+        }
+        None => None,
+    };
+    // Also the fallback if the embed couldn't be built
+    let message = embed.unwrap_or_else(|| {
+        let message = headline + &details;
+        let message = themes::decorate(birthdays, entry.guild_id, today, message);
+        let message = match ping {
+            Some(role) => format!("<@&{}> {}{}", role, message, notice),
+            None => format!("{}{}", message, notice),
+        };
+        announcement_message(message, entries, ping)
+    });
+    match deliver(http, birthdays, channel, entries, occurrence, message).await {
+        Ok(()) => Ok(true),
+        // Without permissions for the system channel it is as if there was no channel
+        Err(error)
+            if fallback
+                && matches!(
+                    retry::FailureKind::of(&error),
+                    retry::FailureKind::MissingPermissions | retry::FailureKind::MissingChannel
+                ) =>
+        {
+            Ok(false)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// Posts the announcement in the channel, or opens a post for it if the channel is a forum
+async fn deliver(
+    http: &serenity::Http,
+    birthdays: &BirthdayList,
+    channel: ChannelId,
+    entries: &[&BirthdayEntry],
+    occurrence: NaiveDate,
+    message: serenity::CreateMessage,
+) -> Result<(), serenity::Error> {
+    let forum = channel
+        .to_channel(http)
+        .await?
+        .guild()
+        .filter(|channel| channel.kind == serenity::ChannelType::Forum);
+    let Some(forum) = forum else {
+        channel.send_message(http, message).await?;
+        return Ok(());
+    };
+    let guild_id = entries[0].guild_id;
+    let date = birthdays
+        .date_format(guild_id)
+        .format(occurrence.day(), occurrence.month(), None);
+    let names: Vec<String> = entries.iter().map(|entry| entry.name.clone()).collect();
+    let names = i18n::list(birthdays.language(guild_id), &names);
+    let title: String = format!("🎂 {} — {}", names, date)
+        .chars()
+        .take(FORUM_TITLE_LENGTH)
+        .collect();
+    let tags = birthdays
+        .guild_configs
+        .get(&guild_id)
+        .map(|config| config.forum_tags.clone())
+        .unwrap_or_default();
+    let mut post = serenity::CreateForumPost::new(title, message).set_applied_tags(tags);
+    if let Some(duration) = forum.default_auto_archive_duration {
+        post = post.auto_archive_duration(duration);
+    }
+    forum.create_forum_post(http, post).await?;
+    Ok(())
+}
+
+/// Builds an announcement that can only ever ping the celebrants and the guild's ping role,
+/// never everyone, here or other roles, even if a name contains such mentions
+fn announcement_message(
+    content: String,
+    entries: &[&BirthdayEntry],
+    ping: Option<serenity::RoleId>,
+) -> serenity::CreateMessage {
+    serenity::CreateMessage::new()
+        .content(content)
+        .allowed_mentions(announcement_mentions(entries, ping))
+}
+
+fn announcement_mentions(
+    entries: &[&BirthdayEntry],
+    ping: Option<serenity::RoleId>,
+) -> serenity::CreateAllowedMentions {
+    serenity::CreateAllowedMentions::new()
+        .users(entries.iter().map(|entry| entry.user_id))
+        .roles(ping)
+}
+
+/// The guild's ping role, None if there is none or it was deleted since it was set
+async fn ping_role(
+    http: &serenity::Http,
+    config: Option<&GuildConfig>,
+    guild_id: GuildId,
+) -> Option<serenity::RoleId> {
+    let role = config.and_then(|config| config.ping_role)?;
+    match guild_id.roles(http).await {
+        Ok(roles) if roles.contains_key(&role) => Some(role),
+        Ok(_) => {
+            warn!(
+                %role,
+                guild = %guild_id,
+                "The ping role no longer exists, announcing without it"
+            );
+            None
+        }
+        Err(error) => {
+            warn!(
+                guild = %guild_id,
+                %error,
+                "Failed to look up the roles, announcing without the ping role"
+            );
+            None
+        }
+    }
+}
+
+/// Looks the announcement channel up again after sending there failed. Fails with
+/// MissingChannel or MissingPermissions only if the channel is really gone or closed to the bot.
+async fn verify_channel(
+    http: &serenity::Http,
+    guild_id: GuildId,
+    channel: ChannelId,
+) -> Result<(), retry::FailureKind> {
+    let kind = |error: serenity::Error| retry::FailureKind::of(&error);
+    let channel = match channel.to_channel(http).await.map_err(kind)? {
+        serenity::Channel::Guild(channel) if channel.guild_id == guild_id => channel,
+        // Nothing can be announced in a channel outside the guild
+        _ => return Err(retry::FailureKind::MissingChannel),
+    };
+    let bot = http.get_current_user().await.map_err(kind)?;
+    let guild = guild_id.to_partial_guild(http).await.map_err(kind)?;
+    let member = guild.member(http, bot.id).await.map_err(kind)?;
+    let permissions = guild.user_permissions_in(&channel, &member);
+    if permissions.view_channel() && permissions.send_messages() {
+        Ok(())
+    } else {
+        Err(retry::FailureKind::MissingPermissions)
     }
+}
 
-    // Check whether the birthday already happened this year
-    let entry = BirthdayEntry {
-        date: NaiveDate::from_ymd_opt(
-            entry.date.year() + LIFE_EXPECTANCY,
-            entry.date.month(),
-            entry.date.day(),
+/// Tells the guild that announcements stopped going to its channel, in the system channel or
+/// else to the owner
+async fn report_broken_channel(
+    http: &serenity::Http,
+    guild_id: GuildId,
+    channel: ChannelId,
+    kind: retry::FailureKind,
+) -> Result<(), Error> {
+    let guild = guild_id.to_partial_guild(http).await?;
+    let problem = if kind == retry::FailureKind::MissingChannel {
+        format!(
+            "the birthday announcement channel <#{}> of {} no longer exists, so I removed it",
+            channel, guild.name
+        )
+    } else {
+        format!(
+            "I can't post in <#{}> anymore, so birthday announcements in {} are paused",
+            channel, guild.name
         )
-        .unwrap(),
-        ..entry
     };
-
-    ctx.say(format!(
-        "💀 {} is expected to skibidi out of this world {} (🇩🇪 avg)",
-        entry.name,
-        date_to_discord_timestamp(entry.date, entry.utc_offset, true)
-    ))
-    .await?;
+    let message = format!(
+        "⚠️🎈 Heads up, {}. Run `set_announcement_channel` to pick a channel I can post in!",
+        problem
+    );
+    // The broken channel may well be the system channel
+    if let Some(system_channel) = guild.system_channel_id.filter(|system| *system != channel) {
+        match system_channel
+            .send_message(http, quiet_message(&message))
+            .await
+        {
+            Ok(_) => return Ok(()),
+            Err(error) => warn!(
+                guild = %guild_id,
+                %error,
+                "Failed to post about the broken channel in the system channel"
+            ),
+        }
+    }
+    guild
+        .owner_id
+        .create_dm_channel(http)
+        .await?
+        .send_message(http, quiet_message(message))
+        .await?;
     Ok(())
 }
 
-async fn check_for_announcements(context: Arc<serenity::Http>) {
-    println!("Checking for birthdays...");
+/// Logs why the announcement of the entries failed and tells what kind of failure it was
+fn failure_kind(
+    birthdays: &BirthdayList,
+    entries: &[&BirthdayEntry],
+    error: serenity::Error,
+) -> retry::FailureKind {
+    let guild_id = entries[0].guild_id;
+    let channel = match birthdays.server_channels.get(&guild_id) {
+        Some(channel) => channel.to_string(),
+        None => "system channel".to_string(),
+    };
+    let users: Vec<String> = entries
+        .iter()
+        .map(|entry| entry.user_id.to_string())
+        .collect();
+    warn!(
+        users = %users.join(", "),
+        guild = %guild_id,
+        %channel,
+        %error,
+        "Failed to announce the birthday"
+    );
+    retry::FailureKind::of(&error)
+}
+
+/// Asks the announcement task for an immediate check of one guild or, if None, all guilds
+struct CheckRequest {
+    guild_id: Option<GuildId>,
+    reply: oneshot::Sender<CheckSummary>,
+}
+
+/// What a check did with the entries it looked at
+#[derive(Debug, Default)]
+struct CheckSummary {
+    examined: usize,
+    sent: usize,
+    retried: usize,
+    not_due: usize,
+    already_announced: usize,
+    opted_out: usize,
+    no_channel: usize,
+    failed: usize,
+    // Announced after the day as nobody announced them on it, also counted as sent
+    caught_up: usize,
+    anniversaries: usize,
+    events: usize,
+}
+
+/// Checks for birthdays whenever one becomes due, see `schedule::next_wakeup`, and whenever a
+/// check is requested. Checks run one after another, so requests arriving during a check wait
+/// for it to finish. Returns once `shutdown` turns true, a running check is finished first.
+async fn check_for_announcements(
+    context: Arc<serenity::Http>,
+    storage: Storage,
+    mut requests: mpsc::Receiver<CheckRequest>,
+    scheduled: Arc<Mutex<DateTime<Utc>>>,
+    reschedule: Arc<Notify>,
+    metrics: Arc<metrics::Metrics>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    info!("Checking for birthdays");
+    let facts = Arc::new(facts::Facts::new(facts::Wikipedia::new()));
+    let mut next_check = tokio::time::Instant::now();
+    // Shows the loop is alive while the next check is hours away, see `metrics`
+    let mut heartbeat = tokio::time::interval(tokio::time::Duration::from_secs(CHECK_TIME));
 
     loop {
-        let mut birthdays = read_from_file().await.unwrap();
+        tokio::select! {
+            // It only ever changes to true
+            _ = shutdown.changed() => {
+                info!("Stopped checking for birthdays");
+                return;
+            }
+            _ = tokio::time::sleep_until(next_check) => {
+                supervised_check(&context, &storage, &facts, &metrics, None).await;
+            }
+            _ = reschedule.notified() => {}
+            _ = heartbeat.tick() => {}
+            Some(request) = requests.recv() => {
+                let summary =
+                    supervised_check(&context, &storage, &facts, &metrics, request.guild_id).await;
+                // The command may have timed out in the meantime
+                if request.reply.send(summary).is_err() {
+                    debug!("The command that requested the check is gone");
+                }
+            }
+        }
+        let now = Utc::now();
+        metrics.tick(now);
+        let wakeup = storage
+            .read_with(|birthdays| schedule::next_wakeup(birthdays, now))
+            .await;
+        next_check = tokio::time::Instant::now() + (wakeup - now).to_std().unwrap_or_default();
+        *scheduled.lock().await = wakeup;
+    }
+}
 
-        // Lock the file to prevent overwrites while we're checking
-        {
-            let _ = FILE_LOCK.lock().await;
-
-            let today = Utc::now().naive_utc().date();
-            for entry in birthdays.entries.iter_mut() {
-                let offset_entry = entry.date - chrono::Duration::hours(entry.utc_offset as i64);
-                if offset_entry.month() == today.month()
-                    && offset_entry.day() == today.day()
-                    && (entry.last_announcement.is_none()
-                        || entry.last_announcement.unwrap().year() != today.year())
-                {
-                    let channel = birthdays.server_channels.get(&entry.guild_id);
-                    if let Some(channel) = channel {
-                        let channel = channel.clone();
-                        channel
-                            .say(
-                                &context,
-                                format!("🎉🎈 Happy Birthday {}! 🎈🎉", entry.name),
-                            )
-                            .await
-                            .unwrap();
-                    }
-
-                    entry.last_announcement = Some(today);
+/// Runs a check in its own task, so a panic is reported to the owner and the next check runs
+/// as usual instead of the announcements stopping until the bot restarts
+async fn supervised_check(
+    context: &Arc<serenity::Http>,
+    storage: &Storage,
+    facts: &Arc<facts::Facts<facts::Wikipedia>>,
+    metrics: &metrics::Metrics,
+    only_guild: Option<GuildId>,
+) -> CheckSummary {
+    let (context, storage, facts) = (context.clone(), storage.clone(), facts.clone());
+    let span = tracing::info_span!("check", guild = only_guild.map(|guild| guild.get()));
+    let check = async move { check_once(&context, &storage, &facts, only_guild).await };
+    match tokio::spawn(check.instrument(span)).await {
+        Ok(summary) => {
+            metrics.checked(Utc::now(), summary.sent);
+            info!(
+                guild = only_guild.map(|guild| guild.get()),
+                examined = summary.examined,
+                sent = summary.sent,
+                retried = summary.retried,
+                failed = summary.failed,
+                caught_up = summary.caught_up,
+                no_channel = summary.no_channel,
+                "Finished the birthday check"
+            );
+            summary
+        }
+        Err(error) => {
+            alerts::raise(format!(
+                "The birthday check failed, it runs again on schedule: {}",
+                error
+            ));
+            CheckSummary::default()
+        }
+    }
+}
+
+/// Whether the member holds the opt-out role of the guild. Announces if the roles can't be
+/// looked up, a hiccup mustn't cost anyone their announcement.
+async fn has_opted_out(
+    http: &serenity::Http,
+    birthdays: &BirthdayList,
+    entry: &BirthdayEntry,
+) -> bool {
+    let Some(role) = birthdays
+        .guild_configs
+        .get(&entry.guild_id)
+        .and_then(|config| config.opt_out_role)
+    else {
+        return false;
+    };
+    match entry.guild_id.member(http, entry.user_id).await {
+        Ok(member) => member.roles.contains(&role),
+        Err(error) => {
+            warn!(
+                user = %entry.user_id,
+                guild = %entry.guild_id,
+                %error,
+                "Failed to look up the roles of the member, announcing anyway"
+            );
+            false
+        }
+    }
+}
+
+async fn check_once<S: facts::FactSource>(
+    context: &serenity::Http,
+    storage: &Storage,
+    facts: &facts::Facts<S>,
+    only_guild: Option<GuildId>,
+) -> CheckSummary {
+    let mut summary = CheckSummary::default();
+    // The data as it was at the start, changes made while sending are picked up next time
+    let mut birthdays = storage.read().await;
+    let in_scope = |guild_id: GuildId| only_guild.is_none_or(|only| only == guild_id);
+
+    let now = Utc::now();
+    let today = now.date_naive();
+    if let Err(error) = birthday_role::remove_expired(context, storage, today, in_scope).await {
+        error!(%error, "Failed to take the birthday roles away");
+    }
+    // Announced like any other entry, `birthdays` is never saved
+    let global = global::add_due(context, &mut birthdays, now, in_scope).await;
+    let birthdays = birthdays;
+    // Birthdays from the catch-up window that were missed, they get a belated announcement
+    let mut caught_up = Vec::new();
+    let due: Vec<(&BirthdayEntry, NaiveDate)> = birthdays
+        .entries
+        .iter()
+        // Members who left and guilds that removed the bot aren't announced, see `prune`
+        .filter(|entry| {
+            in_scope(entry.guild_id)
+                && entry.missing_since.is_none()
+                && !birthdays.left_guilds.contains_key(&entry.guild_id)
+        })
+        .filter_map(|entry| {
+            summary.examined += 1;
+            let config = birthdays.guild_configs.get(&entry.guild_id);
+            let occurrence =
+                due_occurrence(entry, now, config, &birthdays.announced).or_else(|| {
+                    let missed = missed_occurrence(entry, now, config, &birthdays.announced)?;
+                    caught_up.push((entry.guild_id, entry.user_id));
+                    Some(missed)
+                });
+            if occurrence.is_none() {
+                summary.not_due += 1;
+            }
+            Some((entry, occurrence?))
+        })
+        .collect();
+    let celebrating: Vec<_> = due
+        .iter()
+        .map(|(entry, _)| (entry.guild_id, entry.user_id))
+        .collect();
+
+    // Earlier failures are retried on every check until they run out of attempts
+    let mut attempts = Vec::new();
+    // Outcome of every message that was sent, for the channel's failure count
+    let mut deliveries = Vec::new();
+    let mut opted_out = Vec::new();
+    let mut missed = Vec::new();
+    let mut roles = Vec::new();
+    let not_configured = |entry: &BirthdayEntry, occurrence: NaiveDate| missed::Missed {
+        guild_id: entry.guild_id,
+        user_id: entry.user_id,
+        name: entry.name.clone(),
+        occurrence,
+        reason: missed::MissReason::NotConfigured,
+    };
+    for failed in birthdays.failed_announcements.iter().filter(|failed| {
+        !failed.exhausted()
+            && in_scope(failed.guild_id)
+            && !birthdays.channel_broken(failed.guild_id)
+    }) {
+        let entry = birthdays
+            .entries
+            .iter()
+            .find(|entry| entry.guild_id == failed.guild_id && entry.user_id == failed.user_id);
+        // Removed birthdays count as done
+        let result = match entry {
+            Some(entry) => send_announcement(
+                context,
+                &birthdays,
+                facts,
+                &[entry],
+                failed.occurrence,
+                today,
+                &celebrating,
+                false,
+            )
+            .await
+            .map_err(|error| failure_kind(&birthdays, &[entry], error)),
+            None => Ok(false),
+        };
+        match result {
+            Ok(true) => {
+                summary.retried += 1;
+                if let Some(entry) = entry {
+                    roles.extend(birthday_role::grant(context, &birthdays, entry, today).await);
+                }
+            }
+            Ok(false) => {
+                if let Some(entry) = entry {
+                    missed.push(not_configured(entry, failed.occurrence));
+                }
+            }
+            Err(_) => summary.failed += 1,
+        }
+        let result = result.map(|_| ());
+        attempts.push((failed.guild_id, failed.user_id, failed.occurrence, result));
+        deliveries.push((failed.guild_id, result));
+    }
+
+    // Recorded before sending, a command or another instance of the bot may have announced or
+    // snoozed them in the meantime
+    let announcements: Vec<Announcement> = due
+        .iter()
+        .map(|(entry, occurrence)| Announcement::of(entry, *occurrence))
+        .collect();
+    let recorded = storage
+        .update(|birthdays| {
+            announcements
+                .iter()
+                .map(|announcement| birthdays.announced.insert(*announcement))
+                .collect::<Vec<bool>>()
+        })
+        .await;
+    let recorded = match recorded {
+        // Saved right away, a crash after sending mustn't announce them again
+        Ok(recorded) => {
+            if recorded.contains(&true) {
+                if let Err(error) = storage.flush().await {
+                    error!(%error, "Failed to save the announcements");
+                }
+            }
+            recorded
+        }
+        // Not recorded, so they are still due on the next check
+        Err(error) => {
+            error!(%error, "Failed to record the announcements");
+            summary.failed += due.len();
+            Vec::new()
+        }
+    };
+
+    // Members of a guild celebrating on the same day share a single announcement, belated ones
+    // get their own
+    let mut groups: Vec<(_, Vec<&BirthdayEntry>)> = Vec::new();
+    for (&(entry, occurrence), recorded) in due.iter().zip(recorded) {
+        if !recorded {
+            summary.already_announced += 1;
+            continue;
+        }
+        // Skipped for the whole year, so the roles aren't looked up on every check
+        if has_opted_out(context, &birthdays, entry).await {
+            summary.opted_out += 1;
+            opted_out.push(entry.guild_id);
+            continue;
+        }
+        // The admins are told instead of trying the channel again and again
+        if birthdays.channel_broken(entry.guild_id) {
+            summary.no_channel += 1;
+            missed.push(missed::Missed {
+                reason: missed::MissReason::BrokenChannel,
+                ..not_configured(entry, occurrence)
+            });
+            continue;
+        }
+        let late = caught_up.contains(&(entry.guild_id, entry.user_id));
+        let key = (entry.guild_id, occurrence, late);
+        match groups.iter_mut().find(|(group, _)| *group == key) {
+            Some((_, entries)) => entries.push(entry),
+            None => groups.push((key, vec![entry])),
+        }
+    }
+
+    for ((guild_id, occurrence, late), entries) in groups {
+        let result = send_announcement(
+            context,
+            &birthdays,
+            facts,
+            &entries,
+            occurrence,
+            today,
+            &celebrating,
+            late,
+        )
+        .await
+        .map_err(|error| failure_kind(&birthdays, &entries, error));
+        match result {
+            Ok(true) => {
+                summary.sent += entries.len();
+                if late {
+                    summary.caught_up += entries.len();
                 }
+                for entry in &entries {
+                    roles.extend(birthday_role::grant(context, &birthdays, entry, today).await);
+                }
+            }
+            Ok(false) => {
+                summary.no_channel += entries.len();
+                missed.extend(
+                    entries
+                        .iter()
+                        .map(|entry| not_configured(entry, occurrence)),
+                );
             }
+            Err(_) => summary.failed += entries.len(),
+        }
+        // Successes are recorded too, they tell that the channel works
+        if result != Ok(false) {
+            let result = result.map(|_| ());
+            for entry in &entries {
+                attempts.push((entry.guild_id, entry.user_id, occurrence, result));
+            }
+            deliveries.push((guild_id, result));
+        }
+    }
 
-            let data = serde_json::to_string_pretty(&birthdays).unwrap();
-            std::fs::write(FILE_PATH, data).unwrap();
+    for (entry, years) in anniversaries::due(&birthdays, today, in_scope)
+        .into_iter()
+        // Global birthdays are only there on the birthday itself
+        .filter(|(entry, _)| !global.contains(&(entry.guild_id, entry.user_id)))
+    {
+        let announcement = Announcement::of(entry, today);
+        if !storage
+            .update(|birthdays| birthdays.announced_anniversaries.insert(announcement))
+            .await
+            .unwrap_or(false)
+        {
+            continue;
+        }
+        match anniversaries::announce(context, &birthdays, entry, years).await {
+            Ok(true) => summary.anniversaries += 1,
+            Ok(false) => {}
+            // Anniversaries are a bonus, they aren't retried
+            Err(error) => warn!(
+                user = %entry.user_id,
+                guild = %entry.guild_id,
+                %error,
+                "Failed to announce the account anniversary"
+            ),
+        }
+    }
+
+    for (entry, occurrence) in events::due(&birthdays, now, in_scope) {
+        let announcement = events::EventAnnouncement::of(entry, occurrence);
+        if !storage
+            .update(|birthdays| birthdays.announced_events.insert(announcement.clone()))
+            .await
+            .unwrap_or(false)
+        {
+            continue;
         }
+        match events::announce(context, &birthdays, entry, occurrence).await {
+            Ok(true) => summary.events += 1,
+            Ok(false) => {}
+            // Like account anniversaries they aren't retried
+            Err(error) => warn!(
+                anniversary = %entry.name,
+                guild = %entry.guild_id,
+                %error,
+                "Failed to announce the anniversary"
+            ),
+        }
+    }
+
+    if only_guild.is_none() {
+        year_review::post_due(context, storage, &birthdays, today).await;
+        if let Err(error) = reminders::send_due(context, storage).await {
+            error!(%error, "Failed to send the birthday reminders");
+        }
+    }
+
+    // A failure only counts against a channel if looking the channel up confirms it, so an
+    // outage of the API can't make the bot give up on a working channel
+    let mut outcomes = retry::channel_outcomes(&deliveries);
+    for (guild_id, outcome) in outcomes.iter_mut() {
+        let Err(kind) = *outcome else {
+            continue;
+        };
+        let Some(channel) = birthdays.server_channels.get(guild_id) else {
+            continue;
+        };
+        if kind.is_channel_fault() {
+            let verified = verify_channel(context, *guild_id, *channel).await;
+            *outcome = Err(verified.err().unwrap_or(retry::FailureKind::Other));
+        }
+    }
 
-        tokio::time::sleep(tokio::time::Duration::from_secs(CHECK_TIME)).await;
+    let (given_up, broken, purged, purged_guilds) = storage
+        .update(|birthdays| {
+            for guild_id in &opted_out {
+                *birthdays.opt_out_skips.entry(*guild_id).or_default() += 1;
+            }
+            let given_up: Vec<_> = attempts
+                .iter()
+                .filter_map(|(guild_id, user_id, occurrence, result)| {
+                    retry::record(
+                        &mut birthdays.failed_announcements,
+                        *guild_id,
+                        *user_id,
+                        *occurrence,
+                        *result,
+                    )
+                })
+                .collect();
+            let mut broken = Vec::new();
+            for (guild_id, result) in &outcomes {
+                let config = birthdays.guild_configs.entry(*guild_id).or_default();
+                if !retry::count_channel_failure(&mut config.channel_failures, *result) {
+                    continue;
+                }
+                let Some(channel) = birthdays.server_channels.get(guild_id).copied() else {
+                    continue;
+                };
+                let kind = result.err().unwrap_or(retry::FailureKind::Other);
+                // A deleted channel can't be fixed, birthdays fall back to the system channel
+                if kind == retry::FailureKind::MissingChannel {
+                    config.forum_tags.clear();
+                    config.channel_failures = 0;
+                    birthdays.server_channels.remove(guild_id);
+                }
+                broken.push((*guild_id, channel, kind));
+            }
+            birthdays.missed.extend(missed.iter().cloned());
+            birthdays.birthday_roles.extend(roles.iter().cloned());
+            for failed in &given_up {
+                let Some(entry) = birthdays.entries.iter().find(|entry| {
+                    entry.guild_id == failed.guild_id && entry.user_id == failed.user_id
+                }) else {
+                    continue;
+                };
+                birthdays.missed.push(missed::Missed {
+                    reason: missed::MissReason::Failed(failed.kind),
+                    ..not_configured(entry, failed.occurrence)
+                });
+            }
+            // Deferred announcements can reach back into the previous year at most
+            birthdays
+                .announced
+                .retain(|announcement| announcement.year >= today.year() - 1);
+            birthdays
+                .failed_announcements
+                .retain(|failed| failed.occurrence.year() >= today.year() - 1);
+            anniversaries::prune(&mut birthdays.announced_anniversaries, today);
+            events::prune(&mut birthdays.announced_events, today);
+            wishes::prune(&mut birthdays.wishes, today);
+            missed::prune(&mut birthdays.missed, today);
+            let now = Utc::now();
+            (
+                given_up,
+                broken,
+                purge_deleted(birthdays, now),
+                prune::purge_left_guilds(birthdays, now),
+            )
+        })
+        .await
+        .unwrap_or_else(|error| {
+            error!(%error, "Failed to save the results of the check");
+            (Vec::new(), Vec::new(), 0, Vec::new())
+        });
+    for (guild_id, channel, kind) in broken {
+        warn!(
+            guild = %guild_id,
+            %channel,
+            kind = kind.describe(),
+            "Stopped announcing in the channel after failing on {} checks in a row",
+            retry::MAX_CHANNEL_FAILURES
+        );
+        if let Err(error) = report_broken_channel(context, guild_id, channel, kind).await {
+            warn!(
+                guild = %guild_id,
+                %error,
+                "Failed to tell the guild about its broken announcement channel"
+            );
+        }
+    }
+    for (guild_id, count, left_at) in purged_guilds {
+        info!(
+            guild = %guild_id,
+            birthdays = count,
+            %left_at,
+            "Purged the data of a guild that removed the bot"
+        );
     }
+    for failed in given_up {
+        warn!(
+            user = %failed.user_id,
+            guild = %failed.guild_id,
+            kind = failed.kind.describe(),
+            "Gave up announcing the birthday"
+        );
+    }
+    if purged > 0 {
+        info!(purged, "Purged removed birthdays");
+    }
+    if let Err(error) = missed::notify_due(context, storage).await {
+        error!(%error, "Failed to tell guilds about missed birthdays");
+    }
+    summary
 }
 
 #[tokio::main]
 async fn main() {
-    dotenv::dotenv().unwrap();
+    // The .env file may set the log level, so it is read first
+    let dotenv = dotenv::dotenv();
+    logging::init();
+    if let Err(error) = dotenv {
+        warn!(%error, "No .env file was loaded, using the environment only");
+    }
+    let args: Vec<String> = std::env::args().collect();
+    // The data file is in the working directory unless it is kept elsewhere, `--data` wins
+    let data_path = match args.iter().position(|arg| arg == "--data") {
+        Some(position) => Some(
+            args.get(position + 1)
+                .cloned()
+                .expect("--data needs the path of the data file"),
+        ),
+        None => std::env::var("BIRTHDAYBOT_DATA_PATH").ok(),
+    };
+    let data_path = match data_path {
+        Some(path) => storage::DataPath::new(path.clone().into())
+            .unwrap_or_else(|error| panic!("Can't keep the data at {}: {}", path, error)),
+        None => storage::DataPath::default(),
+    };
+    // Kept for setups that still run the old subcommand
+    #[cfg(feature = "postgres")]
+    let args = match args.get(1).map(String::as_str) {
+        Some("migrate-to-postgres") => vec![
+            args[0].clone(),
+            "migrate-storage".to_string(),
+            "--from".to_string(),
+            storage::migrate::default_format(&data_path)
+                .extension()
+                .to_string(),
+            "--to".to_string(),
+            "postgres".to_string(),
+        ],
+        _ => args,
+    };
+    if args.get(1).map(String::as_str) == Some("migrate-storage") {
+        let result = match storage::migrate::parse_args(&args[2..], &data_path) {
+            Ok((from, to)) => storage::migrate::migrate(&from, &to).await,
+            Err(error) => Err(error),
+        };
+        match result {
+            Ok((guilds, count)) => info!(
+                birthdays = count,
+                guilds, "Copied the data, the counts and contents match"
+            ),
+            Err(error) => {
+                error!(%error, "Failed to migrate the data");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
     let token = std::env::var("DISCORD_TOKEN").expect("missing DISCORD_TOKEN");
-    let intents = serenity::GatewayIntents::non_privileged();
+    let default_prefix =
+        std::env::var("BIRTHDAYBOT_PREFIX").unwrap_or_else(|_| DEFAULT_PREFIX.to_string());
+    // Pruning of members that left their guild is off unless a number of days is configured
+    let prune_after_days = std::env::var("BIRTHDAYBOT_PRUNE_AFTER_DAYS")
+        .ok()
+        .map(|days| {
+            days.parse::<i64>()
+                .expect("BIRTHDAYBOT_PRUNE_AFTER_DAYS must be a number of days")
+        })
+        .filter(|days| *days > 0);
+    // Starts over without birthdays if the data file is broken, it is moved to the backups
+    let force_reset = args.iter().any(|arg| arg == "--force-reset");
+    let storage = storage::open(data_path, force_reset).await;
+    let storage_handle = storage.clone();
+    // Reading birthday wishes needs the privileged message content intent, which has to be
+    // turned on for the bot in the developer portal first
+    let message_content = std::env::var("BIRTHDAYBOT_MESSAGE_CONTENT").is_ok();
+    // Noticing members who leave needs the privileged server members intent
+    let member_events = std::env::var("BIRTHDAYBOT_MEMBER_EVENTS").is_ok();
+    // Fatal errors are sent to the application owners unless someone else should get them
+    let alert_owner = std::env::var("BIRTHDAYBOT_ALERT_USER_ID").ok().map(|id| {
+        serenity::UserId::new(
+            id.parse()
+                .expect("BIRTHDAYBOT_ALERT_USER_ID must be a Discord user ID"),
+        )
+    });
+    // The health and metrics endpoints are only served if a port is configured
+    let metrics_port = std::env::var("BIRTHDAYBOT_METRICS_PORT").ok().map(|port| {
+        port.parse::<u16>()
+            .expect("BIRTHDAYBOT_METRICS_PORT must be a port number")
+    });
+    let metrics = Arc::new(metrics::Metrics::default());
+    // Tells the announcement task to stop, which is joined before the data is flushed
+    let (shutdown, shutdown_requested) = watch::channel(false);
+    let announcer = Arc::new(Mutex::new(None));
+    let announcer_handle = announcer.clone();
+    // Kept here as well so the counters of the last minutes are saved on shutdown
+    let pending_usage = Arc::new(Mutex::new(UsageStats::default()));
+    let usage_handle = pending_usage.clone();
+    if let Some(port) = metrics_port {
+        tokio::spawn(metrics::serve(metrics.clone(), port));
+    }
+    let google_calendar = std::env::var("BIRTHDAYBOT_GOOGLE_KEY_FILE")
+        .ok()
+        .map(|path| {
+            Arc::new(
+                google_calendar::GoogleCalendar::from_key_file(&path)
+                    .expect("BIRTHDAYBOT_GOOGLE_KEY_FILE must be a service account key file"),
+            )
+        });
+    let mut intents = serenity::GatewayIntents::non_privileged();
+    if message_content {
+        intents |= serenity::GatewayIntents::MESSAGE_CONTENT;
+    }
+    if member_events {
+        intents |= serenity::GatewayIntents::GUILD_MEMBERS;
+    }
 
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
             commands: vec![
                 set_birthday(),
+                picker::set_birthday_picker(),
                 get_birthday(),
+                get_birthday_menu(),
+                countdown(),
+                list_birthdays(),
+                next_birthday(),
+                upcoming_birthdays(),
+                reminders::remind_me(),
+                reminders::unremind_me(),
+                reminders::my_reminders(),
                 time_left(),
+                retirement::retirement(),
+                retirement::set_retirement_age(),
+                set_life_expectancy(),
                 set_announcement_channel(),
+                unset_announcement_channel(),
+                snooze_announcement(),
+                snooze_all_today(),
+                unsnooze(),
+                audit_log(),
+                add_quiet_date(),
+                remove_quiet_date(),
+                list_quiet_dates(),
+                toggle_command(),
+                config(),
+                set_prefix(),
+                set_date_format(),
+                i18n::set_language(),
+                set_export_channel(),
+                export::export_birthdays(),
+                ical::export_ical(),
+                import::import_birthdays(),
+                set_fun_facts(),
+                set_next_up_footer(),
+                set_announcement_notes(),
+                set_catch_up_days(),
+                templates::add_announcement_message(),
+                templates::remove_announcement_message(),
+                templates::list_announcement_messages(),
+                set_system_channel_fallback(),
+                set_mention_celebrants(),
+                set_quiet_replies(),
+                embeds::set_announcement_embed(),
+                set_announcement_time(),
+                set_leap_day(),
+                prune::set_member_leave_action(),
+                prune::purge_birthdays(),
+                year_review::set_year_in_review(),
+                year_review::preview_year_in_review(),
+                themes::birthday_themes(),
+                google_calendar::set_google_calendar(),
+                google_calendar::resync_google_calendar(),
+                set_show_ages(),
+                set_month_role(),
+                set_organizer_role(),
+                birthday_role::set_birthday_role(),
+                set_opt_out_role(),
+                set_birthday_manager_role(),
+                global::set_global_birthday(),
+                global::remove_global_birthday(),
+                global::enable_global_birthdays(),
+                set_announcement_ping_role(),
+                reactions::set_birthday_reactions(),
+                wishes::wish_leaderboard(),
+                wishes::reset_wish_leaderboard(),
+                anniversaries::set_account_anniversaries(),
+                anniversaries::account_anniversary(),
+                events::set_anniversary(),
+                events::remove_anniversary(),
+                gift_notes::gift_note(),
+                quiz::birthday_quiz(),
+                coverage::birthday_coverage(),
+                stats::birthday_stats(),
+                create_month_roles(),
+                sync_month_roles(),
+                set_birthday_visibility(),
+                set_birthday_privacy(),
+                notes::set_birthday_note(),
+                usage(),
+                force_check(),
+                remove_birthday(),
+                restore_birthday(),
+                delete_my_data(),
+                birthday_optout(),
+                snapshot(),
+                backup::backup(),
+                restore(),
+                merge_data(),
+                reload(),
+                convert_storage(),
             ],
+            pre_command: |ctx| Box::pin(before_command(ctx)),
+            post_command: |ctx| Box::pin(after_command(ctx)),
+            on_error: |error| Box::pin(on_error(error)),
+            prefix_options: poise::PrefixFrameworkOptions {
+                dynamic_prefix: Some(|ctx| Box::pin(guild_prefix(ctx))),
+                // Always allow invoking commands through a mention so a forgotten prefix isn't a lockout
+                mention_as_prefix: true,
+                ..Default::default()
+            },
+            command_check: Some(|ctx| Box::pin(check_command_enabled(ctx))),
+            // Replies echo names and other user text, none of it may ping anyone
+            allowed_mentions: Some(serenity::CreateAllowedMentions::new()),
+            event_handler: |ctx, event, _framework, data| Box::pin(event_handler(ctx, event, data)),
             ..Default::default()
         })
-        .setup(|ctx, _ready, framework| {
+        .setup(move |ctx, _ready, framework| {
             Box::pin(async move {
-                tokio::spawn(check_for_announcements(ctx.http.clone()));
-                poise::builtins::register_globally(
-                    ctx.clone(),
-                    &framework.options().commands
-                )
-                .await?;
-                Ok(Data {})
+                let (check_requests, requests) = mpsc::channel(CHECK_QUEUE);
+                let next_check = Arc::new(Mutex::new(Utc::now()));
+                let reschedule = Arc::new(Notify::new());
+                // Setup runs once the first Ready event arrived
+                metrics.set_connected(true);
+                let storage = storage_handle;
+                let task = tokio::spawn(check_for_announcements(
+                    ctx.http.clone(),
+                    storage.clone(),
+                    requests,
+                    next_check.clone(),
+                    reschedule.clone(),
+                    metrics.clone(),
+                    shutdown_requested,
+                ));
+                *announcer_handle.lock().await = Some(task);
+                let owners = match alert_owner {
+                    Some(owner) => [owner].into(),
+                    None => framework.options().owners.clone(),
+                };
+                tokio::spawn(alerts::deliver_periodically(ctx.http.clone(), owners));
+                tokio::spawn(storage.clone().watch());
+                tokio::spawn(storage.clone().compact_periodically());
+                tokio::spawn(storage.clone().flush_periodically());
+                tokio::spawn(export::export_periodically(
+                    ctx.http.clone(),
+                    storage.clone(),
+                ));
+                if let Some(calendar) = &google_calendar {
+                    tokio::spawn(google_calendar::sync_periodically(
+                        calendar.clone(),
+                        storage.clone(),
+                    ));
+                }
+                if let Some(days) = prune_after_days {
+                    tokio::spawn(prune::prune_absent_members(
+                        ctx.http.clone(),
+                        storage.clone(),
+                        ctx.cache.current_user().id,
+                        days,
+                    ));
+                }
+                let pending_usage = usage_handle;
+                tokio::spawn(flush_usage_periodically(
+                    storage.clone(),
+                    pending_usage.clone(),
+                ));
+                poise::builtins::register_globally(ctx.clone(), &framework.options().commands)
+                    .await?;
+                Ok(Data {
+                    default_prefix,
+                    storage,
+                    pending_usage,
+                    check_requests,
+                    next_check,
+                    reschedule,
+                    message_content,
+                    reactions: Mutex::new(reactions::RateLimiter::default()),
+                    google_calendar,
+                    metrics,
+                })
             })
         })
         .build();
 
     let client = serenity::ClientBuilder::new(token, intents)
-        .framework(framework)
+        .framework(logging::Traced(framework))
         .await;
-    client.unwrap().start().await.unwrap();
+    let mut client = client.unwrap();
+    let shard_manager = client.shard_manager.clone();
+    let start = client.start();
+    tokio::pin!(start);
+    // None while the client is still running
+    let failed = tokio::select! {
+        result = &mut start => match result {
+            Ok(()) => Some(false),
+            Err(error) => {
+                error!(%error, "The Discord client stopped");
+                Some(true)
+            }
+        },
+        signal = shutdown_signal() => {
+            info!(signal, "Shutting down");
+            None
+        }
+    };
+
+    // No new commands or checks are started from here on
+    shutdown.send_replace(true);
+    let time = tokio::time::Duration::from_secs(SHUTDOWN_TIME);
+    if failed.is_none() {
+        shard_manager.shutdown_all().await;
+        if tokio::time::timeout(time, &mut start).await.is_err() {
+            warn!("The gateway didn't close in time");
+        }
+    }
+    if let Some(task) = announcer.lock().await.take() {
+        if tokio::time::timeout(time, task).await.is_err() {
+            warn!("The birthday check didn't finish in time, saving anyway");
+        }
+    }
+    if let Err(error) = flush_usage(&storage, &pending_usage).await {
+        error!(%error, "Failed to save the command usage");
+    }
+    if let Err(error) = storage.flush().await {
+        error!(%error, "Failed to save the birthdays");
+    }
+    // Leave a compacted file behind so the journal doesn't have to be replayed on the next start
+    if let Err(error) = storage.compact().await {
+        error!(%error, "Failed to compact the journal");
+    }
+    if failed == Some(true) {
+        std::process::exit(1);
+    }
+}
+
+/// Waits for ctrl-c or SIGTERM, which `docker stop` sends, and tells which it was
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => "ctrl-c",
+            _ = terminate.recv() => "SIGTERM",
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "ctrl-c"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(user_id: u64, guild_id: u64) -> BirthdayEntry {
+        BirthdayEntry::example(user_id, guild_id)
+    }
+
+    #[test]
+    fn soft_delete_moves_only_the_matching_entry() {
+        let mut birthdays = BirthdayList {
+            entries: vec![entry(1, 1), entry(1, 2), entry(2, 1)],
+            ..Default::default()
+        };
+
+        assert!(soft_delete(
+            &mut birthdays,
+            serenity::UserId::new(1),
+            GuildId::new(1),
+            Utc::now()
+        ));
+        assert_eq!(birthdays.entries.len(), 2);
+        assert_eq!(birthdays.deleted.len(), 1);
+        assert_eq!(birthdays.deleted[0].entry.guild_id, GuildId::new(1));
+        assert!(!soft_delete(
+            &mut birthdays,
+            serenity::UserId::new(1),
+            GuildId::new(1),
+            Utc::now()
+        ));
+    }
+
+    #[test]
+    fn opting_out_removes_the_entry_for_good() {
+        let mut birthdays = BirthdayList {
+            entries: vec![entry(1, 1), entry(1, 2)],
+            ..Default::default()
+        };
+        soft_delete(
+            &mut birthdays,
+            serenity::UserId::new(1),
+            GuildId::new(1),
+            Utc::now(),
+        );
+        birthdays.entries.push(entry(1, 1));
+
+        assert!(opt_out(
+            &mut birthdays,
+            serenity::UserId::new(1),
+            GuildId::new(1)
+        ));
+        assert_eq!(birthdays.entries.len(), 1);
+        assert_eq!(birthdays.entries[0].guild_id, GuildId::new(2));
+        assert!(birthdays.deleted.is_empty());
+        assert!(birthdays
+            .birthday_opt_outs
+            .contains(&(GuildId::new(1), serenity::UserId::new(1))));
+        assert!(!opt_out(
+            &mut birthdays,
+            serenity::UserId::new(1),
+            GuildId::new(1)
+        ));
+    }
+
+    #[test]
+    fn next_up_skips_todays_celebrants() {
+        let today = NaiveDate::from_ymd_opt(2024, 6, 14).unwrap();
+        let mut later = entry(2, 1);
+        later.date = NaiveDate::from_ymd_opt(1990, 8, 1).unwrap();
+        let mut earlier = entry(3, 1);
+        earlier.date = NaiveDate::from_ymd_opt(1990, 3, 1).unwrap();
+        let mut birthdays = BirthdayList {
+            entries: vec![entry(1, 1), later, earlier, entry(4, 2)],
+            ..Default::default()
+        };
+        let celebrating = [(GuildId::new(1), serenity::UserId::new(1))];
+
+        let (next, date) = next_up(&birthdays, GuildId::new(1), today, &celebrating).unwrap();
+        assert_eq!(next.user_id, serenity::UserId::new(2));
+        assert_eq!(date, NaiveDate::from_ymd_opt(2024, 8, 1).unwrap());
+
+        // Neither are members who left, nor anyone once the bot left the guild
+        birthdays.entries[1].missing_since = Some(Utc::now());
+        let (next, date) = next_up(&birthdays, GuildId::new(1), today, &celebrating).unwrap();
+        assert_eq!(next.user_id, serenity::UserId::new(3));
+        assert_eq!(date, NaiveDate::from_ymd_opt(2025, 3, 1).unwrap());
+        birthdays.left_guilds.insert(GuildId::new(1), Utc::now());
+        assert!(next_up(&birthdays, GuildId::new(1), today, &celebrating).is_none());
+        birthdays.left_guilds.clear();
+
+        // A lone celebrant isn't next up
+        birthdays.entries.retain(|entry| entry.user_id.get() == 1);
+        assert!(next_up(&birthdays, GuildId::new(1), today, &celebrating).is_none());
+    }
+
+    #[test]
+    fn hidden_ages_keep_the_stored_year() {
+        let born = entry(1, 1);
+        let mut birthdays = BirthdayList {
+            entries: vec![born.clone(), entry(2, 1)],
+            ..Default::default()
+        };
+        birthdays.entries[1].date = NaiveDate::from_ymd_opt(NO_YEAR, 6, 14).unwrap();
+        birthdays.entries[1].year = None;
+        assert_eq!(birthdays.birth_year(&birthdays.entries[0]), Some(1995));
+        assert_eq!(birthdays.birth_year(&birthdays.entries[1]), None);
+
+        birthdays
+            .guild_configs
+            .entry(GuildId::new(1))
+            .or_default()
+            .show_ages = false;
+        assert_eq!(birthdays.birth_year(&birthdays.entries[0]), None);
+        assert_eq!(birthdays.entries[0].date, born.date);
+    }
+
+    #[test]
+    fn privacy_and_notes_survive_setting_the_birthday_again() {
+        let mut private = entry(1, 1);
+        private.private_year = true;
+        private.note = Some("https://example.com/wishlist".to_string());
+        let mut birthdays = BirthdayList {
+            entries: vec![private],
+            ..Default::default()
+        };
+        assert_eq!(birthdays.birth_year(&birthdays.entries[0]), None);
+        assert_eq!(
+            birthdays.age_on(&birthdays.entries[0], date(2025, 6, 14)),
+            None
+        );
+
+        put_birthday(
+            &mut birthdays,
+            serenity::UserId::new(1),
+            GuildId::new(1),
+            "user".to_string(),
+            date(1996, 7, 1),
+            Some(1996),
+            UtcOffset::default(),
+            serenity::UserId::new(1),
+            Utc::now(),
+        );
+        assert!(birthdays.entries[0].private_year);
+        assert_eq!(
+            birthdays.entries[0].note.as_deref(),
+            Some("https://example.com/wishlist")
+        );
+        assert_eq!(birthdays.entries[0].year, Some(1996));
+        assert_eq!(birthdays.birth_year(&birthdays.entries[0]), None);
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn upcoming_birthdays_wrap_into_the_next_year() {
+        let mut january = entry(1, 1);
+        january.date = date(1990, 1, 5);
+        let mut leap_day = entry(2, 1);
+        leap_day.date = date(2000, 2, 29);
+        let mut december = entry(3, 1);
+        december.date = date(1980, 12, 24);
+
+        let entries = vec![&january, &leap_day, &december];
+        let found: Vec<(NaiveDate, serenity::UserId)> = upcoming(
+            entries.clone(),
+            noon(date(2024, 12, 20)),
+            30,
+            LeapDay::February28,
+        )
+        .into_iter()
+        .map(|(next, entry)| (next, entry.user_id))
+        .collect();
+        assert_eq!(
+            found,
+            vec![
+                (date(2024, 12, 24), serenity::UserId::new(3)),
+                (date(2025, 1, 5), serenity::UserId::new(1)),
+            ]
+        );
+
+        // Announced on the 28th in non-leap years
+        let found = upcoming(entries, noon(date(2025, 2, 20)), 10, LeapDay::February28);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, date(2025, 2, 28));
+    }
+
+    #[test]
+    fn leap_day_birthdays_follow_the_guilds_setting() {
+        let born = date(2000, 2, 29);
+        for leap_day in [LeapDay::February28, LeapDay::March1] {
+            assert_eq!(
+                dates::next_occurrence(born, date(2024, 1, 1), leap_day),
+                date(2024, 2, 29)
+            );
+            assert_eq!(
+                dates::last_occurrence(born, date(2024, 12, 31), leap_day),
+                date(2024, 2, 29)
+            );
+        }
+        assert_eq!(
+            dates::next_occurrence(born, date(2025, 1, 1), LeapDay::February28),
+            date(2025, 2, 28)
+        );
+        assert_eq!(
+            dates::next_occurrence(born, date(2025, 1, 1), LeapDay::March1),
+            date(2025, 3, 1)
+        );
+        assert_eq!(
+            dates::last_occurrence(born, date(2025, 2, 28), LeapDay::March1),
+            date(2024, 2, 29)
+        );
+
+        let mut config = GuildConfig {
+            leap_day: LeapDay::March1,
+            ..Default::default()
+        };
+        let mut celebrant = entry(1, 1);
+        celebrant.date = born;
+        let announced = BTreeSet::new();
+        assert_eq!(
+            due_occurrence(
+                &celebrant,
+                noon(date(2025, 2, 28)),
+                Some(&config),
+                &announced
+            ),
+            None
+        );
+        assert_eq!(
+            due_occurrence(
+                &celebrant,
+                noon(date(2025, 3, 1)),
+                Some(&config),
+                &announced
+            ),
+            Some(date(2025, 3, 1))
+        );
+        config.leap_day = LeapDay::February28;
+        assert_eq!(
+            due_occurrence(
+                &celebrant,
+                noon(date(2025, 2, 28)),
+                Some(&config),
+                &announced
+            ),
+            Some(date(2025, 2, 28))
+        );
+    }
+
+    #[test]
+    fn entries_from_before_the_year_field_are_migrated() {
+        let stored = |date: &str, year: &str| {
+            let json = format!(
+                r#"{{ "user_id": "1", "guild_id": "1", "name": "user", "date": "{}", {} "utc_offset": 0 }}"#,
+                date, year
+            );
+            serde_json::from_str::<BirthdayEntry>(&json).unwrap().year
+        };
+        assert_eq!(stored("1995-06-14", ""), Some(1995));
+        assert_eq!(stored("2024-06-14", ""), None);
+        assert_eq!(stored("2024-06-14", r#""year": 2024,"#), Some(2024));
+        assert_eq!(stored("2024-06-14", r#""year": null,"#), None);
+    }
+
+    #[test]
+    fn dates_are_parsed_in_every_format() {
+        let cases = [
+            ("14.06.", Some((14, 6, None))),
+            ("14.6", Some((14, 6, None))),
+            ("14.06.1995", Some((14, 6, Some(1995)))),
+            (" 1995-06-14 ", Some((14, 6, Some(1995)))),
+            ("14/06/1995", Some((14, 6, Some(1995)))),
+            // Leap days need a leap year, or no year at all
+            ("29.02.", Some((29, 2, None))),
+            ("29.02.2000", Some((29, 2, Some(2000)))),
+            ("2023-02-29", None),
+            ("31.04.", None),
+            // Two-digit years are ambiguous
+            ("3/12/95", None),
+            ("14.06.95", None),
+            ("14-06-1995", None),
+            ("1995/06/14", None),
+            ("14.06.1995.1", None),
+            ("14", None),
+            ("", None),
+            ("tomorrow", None),
+            ("+1.06.", None),
+        ];
+        let parse = |text| parse_date(text, Language::English);
+        for (text, expected) in cases {
+            assert_eq!(parse(text).ok(), expected, "{}", text);
+        }
+        assert_eq!(
+            parse("3/12/95").unwrap_err(),
+            "Couldn't parse '3/12/95', use DD.MM.YYYY or DD.MM. without a year"
+        );
+        assert_eq!(parse("31.04.").unwrap_err(), "April only has 30 days");
+        assert_eq!(parse("30.02.").unwrap_err(), "February only has 29 days");
+        assert_eq!(
+            parse("29.02.2023").unwrap_err(),
+            "February 2023 only has 28 days"
+        );
+        assert_eq!(parse("1.13.").unwrap_err(), "13 is not a month");
+        assert_eq!(parse("0.1.").unwrap_err(), "0 is not a day");
+        assert_eq!(
+            parse_date("31.04.", Language::German).unwrap_err(),
+            "Der April hat nur 30 Tage"
+        );
+
+        // Prefix commands take the month's number as well as its name
+        use poise::ChoiceParameter;
+        assert_eq!(
+            MonthChoice::from_name("2").map(MonthChoice::number),
+            Some(2)
+        );
+        assert_eq!(
+            MonthChoice::from_name("december").map(MonthChoice::number),
+            Some(12)
+        );
+    }
+
+    #[test]
+    fn guilds_can_make_every_reply_quiet() {
+        let mut birthdays = BirthdayList::default();
+        assert!(!birthdays.replies_quietly(GuildId::new(1), None));
+        assert!(birthdays.replies_quietly(GuildId::new(1), Some(true)));
+
+        birthdays
+            .guild_configs
+            .entry(GuildId::new(1))
+            .or_default()
+            .quiet_replies = true;
+        assert!(birthdays.replies_quietly(GuildId::new(1), Some(false)));
+        assert!(!birthdays.replies_quietly(GuildId::new(2), Some(false)));
+    }
+
+    #[test]
+    fn birth_years_must_be_plausible() {
+        // 2025 has begun in UTC+14 but not in UTC
+        let now = DateTime::parse_from_rfc3339("2024-12-31T12:00:00Z")
+            .unwrap()
+            .to_utc();
+        let utc = UtcOffset::default();
+        let checked =
+            |year, utc_offset| checked_birth_year(year, utc_offset, now, Language::English);
+        assert_eq!(checked(None, utc), Ok(()));
+        assert_eq!(checked(Some(2024), utc), Ok(()));
+        assert_eq!(
+            checked(Some(2025), utc),
+            Err("2025 is in the future".to_string())
+        );
+        assert_eq!(checked(Some(2025), UtcOffset::from_hours(14)), Ok(()));
+        assert_eq!(checked(Some(1904), utc), Ok(()));
+        assert_eq!(
+            checked(Some(1903), utc),
+            Err("1903 is more than 120 years ago".to_string())
+        );
+    }
+
+    #[test]
+    fn timestamps_start_at_local_midnight() {
+        // 2024-06-14T00:00:00Z
+        let midnight = 1718323200;
+        for (offset, timestamp) in [
+            (UtcOffset::default(), midnight),
+            (UtcOffset::from_hours(2), midnight - 2 * 3600),
+            (UtcOffset::from_hours(-5), midnight + 5 * 3600),
+            (
+                UtcOffset::from_minutes(5 * 60 + 30),
+                midnight - 5 * 3600 - 1800,
+            ),
+            (UtcOffset::from_hours(14), midnight - 14 * 3600),
+        ] {
+            assert_eq!(
+                date_to_discord_timestamp(date(2024, 6, 14), offset, false),
+                format!("<t:{}:f>", timestamp)
+            );
+        }
+        assert_eq!(
+            date_to_discord_timestamp(date(2024, 6, 14), UtcOffset::from_hours(-12), true),
+            format!("<t:{}:R>", midnight + 12 * 3600)
+        );
+    }
+
+    #[test]
+    fn february_29th_can_be_set_without_a_year() {
+        assert_eq!(args_to_date(29, 2, None).unwrap(), date(NO_YEAR, 2, 29));
+        assert!(args_to_date(29, 2, Some(2023)).is_err());
+    }
+
+    #[test]
+    fn ages_are_only_shown_when_they_make_sense() {
+        let mut birthdays = BirthdayList::default();
+        let mut celebrant = entry(1, 1);
+        assert_eq!(birthdays.age_on(&celebrant, date(2025, 6, 14)), Some(30));
+
+        // No year, a year in the future and a typo'd year
+        for year in [None, Some(2030), Some(1800)] {
+            celebrant.date = date(year.unwrap_or(NO_YEAR), 6, 14);
+            celebrant.year = year;
+            assert_eq!(birthdays.age_on(&celebrant, date(2025, 6, 14)), None);
+        }
+
+        // Born in the year that stands in for no year
+        celebrant.date = date(2024, 6, 14);
+        celebrant.year = Some(2024);
+        assert_eq!(birthdays.age_on(&celebrant, date(2025, 6, 14)), Some(1));
+
+        celebrant.date = date(1995, 6, 14);
+        celebrant.year = Some(1995);
+        birthdays
+            .guild_configs
+            .entry(GuildId::new(1))
+            .or_default()
+            .show_ages = false;
+        assert_eq!(birthdays.age_on(&celebrant, date(2025, 6, 14)), None);
+    }
+
+    fn noon(date: NaiveDate) -> DateTime<Utc> {
+        date.and_hms_opt(12, 0, 0).unwrap().and_utc()
+    }
+
+    #[test]
+    fn birthdays_are_due_at_local_midnight() {
+        let at = |day: u32, hour: u32| {
+            date(2024, 6, day)
+                .and_hms_opt(hour, 0, 0)
+                .unwrap()
+                .and_utc()
+        };
+        let mut announced = BTreeSet::new();
+        for (offset, before, midnight) in [
+            (-11, at(14, 10), at(14, 11)),
+            (0, at(13, 23), at(14, 0)),
+            (13, at(13, 10), at(13, 11)),
+        ] {
+            let mut celebrant = entry(1, 1);
+            celebrant.utc_offset = UtcOffset::from_hours(offset);
+            assert_eq!(due_occurrence(&celebrant, before, None, &announced), None);
+            assert_eq!(
+                due_occurrence(&celebrant, midnight, None, &announced),
+                Some(date(2024, 6, 14))
+            );
+
+            // Later checks on the same local day don't announce it again
+            announced.insert(Announcement::of(&celebrant, date(2024, 6, 14)));
+            let late = midnight + chrono::Duration::hours(23);
+            assert_eq!(due_occurrence(&celebrant, late, None, &announced), None);
+            announced.clear();
+        }
+    }
+
+    #[test]
+    fn guilds_can_announce_at_a_later_hour() {
+        let at = |day: u32, hour: u32| {
+            date(2024, 6, day)
+                .and_hms_opt(hour, 0, 0)
+                .unwrap()
+                .and_utc()
+        };
+        let config = GuildConfig {
+            announcement_time: Some(AnnouncementTime {
+                hour: 9,
+                utc_offset: UtcOffset::from_hours(2),
+            }),
+            ..Default::default()
+        };
+        let mut announced = BTreeSet::new();
+        let mut celebrant = entry(1, 1);
+        celebrant.utc_offset = UtcOffset::from_hours(13);
+
+        // 9:00 at UTC+2 is 7:00 UTC, no matter the member's own time zone
+        assert_eq!(
+            due_occurrence(&celebrant, at(14, 6), Some(&config), &announced),
+            None
+        );
+        assert_eq!(
+            due_occurrence(&celebrant, at(14, 7), Some(&config), &announced),
+            Some(date(2024, 6, 14))
+        );
+        announced.insert(Announcement::of(&celebrant, date(2024, 6, 14)));
+        assert_eq!(
+            due_occurrence(&celebrant, at(15, 6), Some(&config), &announced),
+            None
+        );
+    }
+
+    #[test]
+    fn snoozing_today_covers_birthdays_that_are_not_due_yet() {
+        let early = date(2024, 6, 14).and_hms_opt(2, 0, 0).unwrap().and_utc();
+        let mut announced = BTreeSet::new();
+        let mut celebrant = entry(1, 1);
+        celebrant.utc_offset = UtcOffset::from_hours(-8);
+
+        // It is still the 13th at UTC-8, the birthday is announced in six hours
+        assert_eq!(due_occurrence(&celebrant, early, None, &announced), None);
+        assert_eq!(
+            todays_occurrence(&celebrant, early, None, &announced),
+            Some(date(2024, 6, 14))
+        );
+        snooze_entry(
+            &mut celebrant,
+            &mut announced,
+            date(2024, 6, 14),
+            early.date_naive(),
+        );
+        let midnight = early + chrono::Duration::hours(6);
+        assert_eq!(due_occurrence(&celebrant, midnight, None, &announced), None);
+
+        // Tomorrow's birthdays aren't today's
+        let mut tomorrow = entry(2, 1);
+        tomorrow.date = date(1995, 6, 15);
+        assert_eq!(todays_occurrence(&tomorrow, early, None, &announced), None);
+    }
+
+    #[test]
+    fn moving_a_birthday_later_does_not_announce_it_twice() {
+        let mut announced = BTreeSet::new();
+        let mut moved = entry(1, 1);
+        announced.insert(Announcement::of(&moved, date(2024, 6, 14)));
+
+        moved.date = date(1995, 8, 1);
+        assert_eq!(
+            due_occurrence(&moved, noon(date(2024, 8, 1)), None, &announced),
+            None
+        );
+        assert_eq!(
+            due_occurrence(&moved, noon(date(2025, 8, 1)), None, &announced),
+            Some(date(2025, 8, 1))
+        );
+    }
+
+    #[test]
+    fn birthdays_missed_while_down_are_caught_up() {
+        let mut announced = BTreeSet::new();
+        let mut celebrant = entry(1, 1);
+        celebrant.utc_offset = UtcOffset::from_hours(2);
+        let at = |text: &str| DateTime::parse_from_rfc3339(text).unwrap().to_utc();
+
+        // The day itself is up to `due_occurrence`
+        let day_after = at("2024-06-15T12:00:00Z");
+        assert_eq!(
+            missed_occurrence(&celebrant, at("2024-06-14T12:00:00Z"), None, &announced),
+            None
+        );
+        assert_eq!(
+            missed_occurrence(&celebrant, day_after, None, &announced),
+            Some(date(2024, 6, 14))
+        );
+        // It is already June 16th at UTC+2
+        let two_days_after = at("2024-06-15T22:00:00Z");
+        assert_eq!(
+            missed_occurrence(&celebrant, two_days_after, None, &announced),
+            None
+        );
+        let config = GuildConfig {
+            catch_up_days: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            missed_occurrence(&celebrant, two_days_after, Some(&config), &announced),
+            Some(date(2024, 6, 14))
+        );
+        let config = GuildConfig {
+            catch_up_days: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            missed_occurrence(&celebrant, day_after, Some(&config), &announced),
+            None
+        );
+
+        // Set after midnight at UTC+2, it wasn't missed
+        celebrant.created_at = Some(at("2024-06-13T22:30:00Z"));
+        assert_eq!(
+            missed_occurrence(&celebrant, day_after, None, &announced),
+            None
+        );
+        celebrant.created_at = Some(at("2024-06-13T21:30:00Z"));
+        assert_eq!(
+            missed_occurrence(&celebrant, day_after, None, &announced),
+            Some(date(2024, 6, 14))
+        );
+
+        // Announced before the bot went down
+        announced.insert(Announcement::of(&celebrant, date(2024, 6, 14)));
+        assert_eq!(
+            missed_occurrence(&celebrant, day_after, None, &announced),
+            None
+        );
+    }
+
+    #[test]
+    fn moving_a_birthday_earlier_still_announces_it() {
+        // Announced last year, so a date before last year's occurrence must not be suppressed
+        let mut announced = BTreeSet::new();
+        let mut moved = entry(1, 1);
+        announced.insert(Announcement::of(&moved, date(2023, 6, 14)));
+
+        moved.date = date(1995, 3, 1);
+        assert_eq!(
+            due_occurrence(&moved, noon(date(2024, 3, 1)), None, &announced),
+            Some(date(2024, 3, 1))
+        );
+    }
+
+    #[test]
+    fn old_announcement_dates_are_migrated() {
+        let mut old = entry(1, 1);
+        old.last_announcement = Some(date(2024, 6, 14));
+        let mut birthdays = BirthdayList {
+            entries: vec![old],
+            ..Default::default()
+        };
+        birthdays.migrate_announcements();
+        assert_eq!(birthdays.entries[0].last_announcement, None);
+        assert!(birthdays
+            .announced
+            .contains(&Announcement::of(&birthdays.entries[0], date(2024, 6, 14))));
+    }
+
+    #[test]
+    fn time_left_counts_down_to_the_life_expectancy() {
+        let today = date(2025, 6, 14);
+        assert_eq!(
+            expected_end(date(1995, 6, 14), 1995, 83, today, LeapDay::February28),
+            Some(date(2078, 6, 14))
+        );
+        assert_eq!(
+            expected_end(date(1942, 6, 14), 1942, 83, today, LeapDay::February28),
+            Some(today)
+        );
+        assert_eq!(
+            expected_end(date(1930, 2, 28), 1930, 83, today, LeapDay::February28),
+            None
+        );
+        assert_eq!(
+            expected_end(date(2024, 2, 29), 1960, 67, today, LeapDay::March1),
+            Some(date(2027, 3, 1))
+        );
+    }
+
+    #[test]
+    fn announcements_only_allow_pinging_the_celebrant() {
+        let mut celebrant = entry(1, 1);
+        celebrant.name = "@everyone <@&5>".to_string();
+        let message = announcement_message(
+            format!("🎉🎈 Happy Birthday {}!", celebrant.name),
+            &[&celebrant],
+            None,
+        );
+        let payload = serde_json::to_value(message).unwrap();
+        assert_eq!(
+            payload["allowed_mentions"],
+            serde_json::json!({ "parse": [], "users": ["1"], "roles": [] })
+        );
+
+        let message = announcement_message(
+            "<@&6> 🎉🎈 Happy Birthday!".to_string(),
+            &[&celebrant, &entry(2, 1)],
+            Some(serenity::RoleId::new(6)),
+        );
+        let payload = serde_json::to_value(message).unwrap();
+        assert_eq!(
+            payload["allowed_mentions"],
+            serde_json::json!({ "parse": [], "users": ["1", "2"], "roles": ["6"] })
+        );
+    }
+
+    #[test]
+    fn shared_birthdays_are_announced_together() {
+        let mut birthdays = BirthdayList::default();
+        let mut anna = entry(1, 1);
+        anna.name = "Anna".to_string();
+        let mut ben = entry(2, 1);
+        ben.name = "Ben".to_string();
+        ben.year = None;
+        let mut chris = entry(3, 1);
+        chris.name = "Chris_".to_string();
+        let occurrence = date(2025, 6, 14);
+
+        assert_eq!(
+            celebrant_names(&birthdays, &[&anna], occurrence),
+            (" 30th".to_string(), "Anna".to_string(), "Anna".to_string())
+        );
+        assert_eq!(
+            celebrant_names(&birthdays, &[&anna, &ben], occurrence).1,
+            "Anna (30th) and Ben"
+        );
+        birthdays
+            .guild_configs
+            .entry(GuildId::new(1))
+            .or_default()
+            .mention_celebrants = true;
+        assert_eq!(
+            celebrant_names(&birthdays, &[&anna, &ben, &chris], occurrence),
+            (
+                String::new(),
+                "<@1> (30th), <@2> and <@3> (30th)".to_string(),
+                "Anna (30th), Ben and Chris\\_ (30th)".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn celebrants_are_mentioned_unless_they_left() {
+        let mut birthdays = BirthdayList {
+            entries: vec![entry(1, 1)],
+            ..Default::default()
+        };
+        birthdays.entries[0].name = "old_name".to_string();
+        assert_eq!(birthdays.display_name(&birthdays.entries[0]), "old\\_name");
+
+        birthdays
+            .guild_configs
+            .entry(GuildId::new(1))
+            .or_default()
+            .mention_celebrants = true;
+        assert_eq!(birthdays.display_name(&birthdays.entries[0]), "<@1>");
+        birthdays.entries[0].missing_since = Some(Utc::now());
+        assert_eq!(birthdays.display_name(&birthdays.entries[0]), "old\\_name");
+    }
+
+    #[test]
+    fn forum_tags_are_found_by_name() {
+        let mut forum = serenity::GuildChannel::default();
+        forum.available_tags = serde_json::from_value(serde_json::json!([
+            { "id": "1", "name": "Birthday", "moderated": false, "emoji_id": null, "emoji_name": null },
+            { "id": "2", "name": "Party", "moderated": false, "emoji_id": null, "emoji_name": "🎉" },
+        ]))
+        .unwrap();
+        assert_eq!(
+            forum_tag_ids(&forum, "party, birthday,"),
+            Ok(vec![
+                serenity::ForumTagId::new(2),
+                serenity::ForumTagId::new(1)
+            ])
+        );
+        assert_eq!(
+            forum_tag_ids(&forum, "Birthday, Cake"),
+            Err("Cake".to_string())
+        );
+    }
+
+    #[test]
+    fn existing_configs_fall_back_to_the_system_channel() {
+        let config: GuildConfig = serde_json::from_str(r#"{ "prefix": "?" }"#).unwrap();
+        assert!(config.system_channel_fallback);
+    }
+
+    #[test]
+    fn purge_respects_the_restore_window() {
+        let deleted_at = DateTime::parse_from_rfc3339("2024-03-01T12:00:00Z")
+            .unwrap()
+            .to_utc();
+        let mut birthdays = BirthdayList {
+            entries: vec![entry(1, 1)],
+            ..Default::default()
+        };
+        soft_delete(
+            &mut birthdays,
+            serenity::UserId::new(1),
+            GuildId::new(1),
+            deleted_at,
+        );
+
+        let window = chrono::Duration::days(RESTORE_DAYS);
+        let just_before = deleted_at + window - chrono::Duration::seconds(1);
+        assert_eq!(purge_deleted(&mut birthdays, just_before), 0);
+        assert_eq!(birthdays.deleted.len(), 1);
+
+        assert_eq!(purge_deleted(&mut birthdays, deleted_at + window), 1);
+        assert!(birthdays.deleted.is_empty());
+    }
 }
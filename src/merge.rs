@@ -0,0 +1,164 @@
+use std::{collections::HashMap, path::Path};
+
+use poise::serenity_prelude::{GuildId, UserId};
+use serde::Serialize;
+
+use crate::{storage::StorageFormat, BirthdayList, Error};
+
+/// What merging another data file did, conflicts are left as they were for manual resolution
+#[derive(Debug, Default)]
+pub struct MergeReport {
+    pub added: usize,
+    pub overwritten: usize,
+    pub unchanged: usize,
+    pub conflicts: Vec<String>,
+}
+
+/// Loads a data file of another instance, the format is picked by the file extension
+pub fn read_data_file(path: &Path) -> Result<BirthdayList, Error> {
+    let format = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(StorageFormat::from_extension)
+        .ok_or_else(|| format!("{} is neither a json nor a toml file", path.display()))?;
+    let mut birthdays = format.deserialize(&std::fs::read_to_string(path)?)?;
+    birthdays.migrate_announcements();
+    Ok(birthdays)
+}
+
+fn same<T: Serialize>(a: &T, b: &T) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+/// Merges `theirs` into `ours`. Entries are matched by guild and user and the one changed last
+/// wins. Guild settings and channels are only taken over for guilds `ours` doesn't know.
+pub fn merge(ours: &mut BirthdayList, theirs: BirthdayList) -> MergeReport {
+    let mut report = MergeReport::default();
+    let positions: HashMap<(GuildId, UserId), usize> = ours
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(position, entry)| ((entry.guild_id, entry.user_id), position))
+        .collect();
+
+    for entry in theirs.entries {
+        let Some(&position) = positions.get(&(entry.guild_id, entry.user_id)) else {
+            let deleted = ours.deleted.iter().any(|deleted| {
+                deleted.entry.guild_id == entry.guild_id && deleted.entry.user_id == entry.user_id
+            });
+            if deleted {
+                report.conflicts.push(format!(
+                    "Birthday of {} ({}) in guild {}: removed here but still set in the other file",
+                    entry.name, entry.user_id, entry.guild_id
+                ));
+            } else {
+                ours.entries.push(entry);
+                report.added += 1;
+            }
+            continue;
+        };
+        let current = &mut ours.entries[position];
+        if same(current, &entry) {
+            report.unchanged += 1;
+        } else if entry.updated_at > current.updated_at {
+            *current = entry;
+            report.overwritten += 1;
+        } else if entry.updated_at == current.updated_at {
+            report.conflicts.push(format!(
+                "Birthday of {} ({}) in guild {}: differs, but neither was changed later",
+                entry.name, entry.user_id, entry.guild_id
+            ));
+        } else {
+            report.unchanged += 1;
+        }
+    }
+
+    for (guild_id, config) in theirs.guild_configs {
+        match ours.guild_configs.get(&guild_id) {
+            None => {
+                ours.guild_configs.insert(guild_id, config);
+            }
+            Some(current) if !same(current, &config) => report
+                .conflicts
+                .push(format!("Settings of guild {}: differ", guild_id)),
+            Some(_) => {}
+        }
+    }
+    for (guild_id, channel) in theirs.server_channels {
+        match ours.server_channels.get(&guild_id) {
+            None => {
+                ours.server_channels.insert(guild_id, channel);
+            }
+            Some(current) if *current != channel => report.conflicts.push(format!(
+                "Announcement channel of guild {}: <#{}> here, <#{}> in the other file",
+                guild_id, current, channel
+            )),
+            Some(_) => {}
+        }
+    }
+    for (user_id, global) in theirs.global_birthdays {
+        match ours.global_birthdays.get(&user_id) {
+            Some(current) if current.updated_at >= global.updated_at => {}
+            _ => {
+                ours.global_birthdays.insert(user_id, global);
+            }
+        }
+    }
+    // Whatever either instance announced must not be announced again
+    ours.announced.extend(theirs.announced);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveDate};
+    use poise::serenity_prelude::ChannelId;
+
+    use super::*;
+    use crate::BirthdayEntry;
+
+    fn entry(user_id: u64, day: u32, updated_at: Option<&str>) -> BirthdayEntry {
+        BirthdayEntry {
+            date: NaiveDate::from_ymd_opt(1995, 6, day).unwrap(),
+            updated_at: updated_at.map(|time| DateTime::parse_from_rfc3339(time).unwrap().to_utc()),
+            ..BirthdayEntry::example(user_id, 1)
+        }
+    }
+
+    #[test]
+    fn newer_entries_win_and_ties_are_conflicts() {
+        let mut ours = BirthdayList {
+            entries: vec![
+                entry(1, 1, Some("2024-01-01T00:00:00Z")),
+                entry(2, 2, Some("2024-05-01T00:00:00Z")),
+                entry(3, 3, None),
+            ],
+            server_channels: [(GuildId::new(1), ChannelId::new(10))].into(),
+            ..Default::default()
+        };
+        let theirs = BirthdayList {
+            entries: vec![
+                entry(1, 11, Some("2024-02-01T00:00:00Z")),
+                entry(2, 12, Some("2024-03-01T00:00:00Z")),
+                entry(3, 13, None),
+                entry(4, 4, None),
+            ],
+            server_channels: [(GuildId::new(1), ChannelId::new(20))].into(),
+            ..Default::default()
+        };
+
+        let report = merge(&mut ours, theirs);
+        assert_eq!(report.added, 1);
+        assert_eq!(report.overwritten, 1);
+        assert_eq!(report.unchanged, 1);
+        assert_eq!(report.conflicts.len(), 2);
+
+        let days: Vec<u32> = ours
+            .entries
+            .iter()
+            .map(|entry| chrono::Datelike::day(&entry.date))
+            .collect();
+        assert_eq!(days, vec![11, 2, 3, 4]);
+        assert_eq!(ours.server_channels[&GuildId::new(1)], ChannelId::new(10));
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use chrono::{DateTime, Utc};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use tracing::error;
+
+use crate::{storage, CHECK_TIME};
+
+/// What the health and metrics endpoints report, updated by the event handler, the commands
+/// and `check_for_announcements`
+#[derive(Default)]
+pub struct Metrics {
+    // Whether the gateway connection is up
+    connected: AtomicBool,
+    // Unix timestamps, 0 until it happened for the first time
+    last_tick: AtomicI64,
+    last_check: AtomicI64,
+    announcements: AtomicU64,
+    commands: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Metrics {
+    pub fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::Relaxed);
+    }
+
+    /// Called by the announcement loop whenever it runs, also while there is nothing to check
+    pub fn tick(&self, now: DateTime<Utc>) {
+        self.last_tick.store(now.timestamp(), Ordering::Relaxed);
+    }
+
+    /// Records a check that ran to the end and the announcements it sent
+    pub fn checked(&self, now: DateTime<Utc>, sent: usize) {
+        self.last_check.store(now.timestamp(), Ordering::Relaxed);
+        self.announcements.fetch_add(sent as u64, Ordering::Relaxed);
+    }
+
+    pub fn command(&self, name: &str) {
+        *self
+            .commands
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default() += 1;
+    }
+
+    /// Healthy while connected to Discord and the announcement loop ran within two CHECK_TIMEs
+    pub fn is_healthy(&self, now: DateTime<Utc>) -> bool {
+        let last_tick = self.last_tick.load(Ordering::Relaxed);
+        self.connected.load(Ordering::Relaxed)
+            && last_tick > 0
+            && now.timestamp() - last_tick <= 2 * CHECK_TIME as i64
+    }
+
+    /// The metrics in the Prometheus text format
+    pub fn render(&self, write_errors: usize) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, lines: Vec<String>| {
+            text.push_str(&format!("# HELP birthdaybot_{} {}\n", name, help));
+            text.push_str(&format!("# TYPE birthdaybot_{} {}\n", name, kind));
+            for line in lines {
+                text.push_str(&format!("birthdaybot_{}{}\n", name, line));
+            }
+        };
+        metric(
+            "announcements_sent_total",
+            "counter",
+            "Birthday announcements sent.",
+            vec![format!(" {}", self.announcements.load(Ordering::Relaxed))],
+        );
+        metric(
+            "commands_total",
+            "counter",
+            "Successful command invocations by command.",
+            self.commands
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(name, count)| format!("{{command=\"{}\"}} {}", label(name), count))
+                .collect(),
+        );
+        metric(
+            "write_errors_total",
+            "counter",
+            "Failed saves of the birthdays.",
+            vec![format!(" {}", write_errors)],
+        );
+        metric(
+            "last_check_timestamp_seconds",
+            "gauge",
+            "When the last birthday check finished, 0 before the first one.",
+            vec![format!(" {}", self.last_check.load(Ordering::Relaxed))],
+        );
+        text
+    }
+}
+
+/// Escapes a label value of the text format
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn respond(metrics: &Metrics, path: &str, now: DateTime<Utc>) -> Response<Body> {
+    let (status, body) = match path {
+        "/healthz" if metrics.is_healthy(now) => (StatusCode::OK, "ok".to_string()),
+        "/healthz" => (StatusCode::SERVICE_UNAVAILABLE, "unhealthy".to_string()),
+        "/metrics" => (StatusCode::OK, metrics.render(storage::write_errors())),
+        _ => (StatusCode::NOT_FOUND, "not found".to_string()),
+    };
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+}
+
+/// Serves `/healthz` and `/metrics` on the port until the bot stops
+pub async fn serve(metrics: Arc<Metrics>, port: u16) {
+    let address = SocketAddr::from(([0, 0, 0, 0], port));
+    let service = make_service_fn(move |_| {
+        let metrics = metrics.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let response = respond(&metrics, request.uri().path(), Utc::now());
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+    let server = match Server::try_bind(&address) {
+        Ok(server) => server,
+        Err(error) => {
+            error!(%address, %error, "Failed to serve the metrics");
+            return;
+        }
+    };
+    if let Err(error) = server.serve(service).await {
+        error!(%error, "The metrics server stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn health_needs_the_gateway_and_a_recent_tick() {
+        let metrics = Metrics::default();
+        let now = Utc::now();
+        assert!(!metrics.is_healthy(now));
+        metrics.tick(now);
+        assert!(!metrics.is_healthy(now));
+        metrics.set_connected(true);
+        assert!(metrics.is_healthy(now));
+        assert_eq!(respond(&metrics, "/healthz", now).status(), StatusCode::OK);
+
+        let late = now + chrono::Duration::seconds(2 * CHECK_TIME as i64 + 1);
+        assert!(!metrics.is_healthy(late));
+        assert_eq!(
+            respond(&metrics, "/healthz", late).status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        metrics.tick(late);
+        metrics.set_connected(false);
+        assert!(!metrics.is_healthy(late));
+        assert_eq!(
+            respond(&metrics, "/other", late).status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn metrics_are_rendered_as_prometheus_text() {
+        let metrics = Metrics::default();
+        metrics.command("get_birthday");
+        metrics.command("get_birthday");
+        metrics.command("gift_note set");
+        metrics.checked(DateTime::from_timestamp(1_700_000_000, 0).unwrap(), 2);
+        metrics.checked(DateTime::from_timestamp(1_700_003_600, 0).unwrap(), 1);
+
+        let text = metrics.render(4);
+        assert!(text.contains("# TYPE birthdaybot_announcements_sent_total counter\n"));
+        assert!(text.contains("\nbirthdaybot_announcements_sent_total 3\n"));
+        assert!(text.contains("\nbirthdaybot_commands_total{command=\"get_birthday\"} 2\n"));
+        assert!(text.contains("\nbirthdaybot_commands_total{command=\"gift_note set\"} 1\n"));
+        assert!(text.contains("\nbirthdaybot_write_errors_total 4\n"));
+        assert!(text.contains("\nbirthdaybot_last_check_timestamp_seconds 1700003600\n"));
+        assert_eq!(label("a\"b\\"), "a\\\"b\\\\");
+    }
+}
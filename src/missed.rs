@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use poise::serenity_prelude::{self as serenity, GuildId, UserId};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    format::{self, DateFormat},
+    quiet_message,
+    retry::FailureKind,
+    storage::Storage,
+    BirthdayList, Error,
+};
+
+// Guilds are told about missed birthdays at most this often
+static NOTIFY_INTERVAL_DAYS: i64 = 7;
+// Keeps the notification below Discord's message length limit
+static MAX_LISTED: usize = 20;
+
+/// Why a birthday wasn't announced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MissReason {
+    NotConfigured,
+    Failed(FailureKind),
+    // The announcement channel failed too often in a row, see `retry::count_channel_failure`
+    BrokenChannel,
+}
+
+impl MissReason {
+    fn describe(self) -> &'static str {
+        match self {
+            MissReason::NotConfigured => "no announcement channel is set",
+            MissReason::Failed(kind) => kind.describe(),
+            MissReason::BrokenChannel => {
+                "the announcement channel kept failing, set it again with `set_announcement_channel`"
+            }
+        }
+    }
+}
+
+/// A birthday that passed without an announcement, kept until the guild was told
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Missed {
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    // Kept in case the entry is removed before the guild is told
+    pub name: String,
+    pub occurrence: NaiveDate,
+    pub reason: MissReason,
+}
+
+/// Guilds with missed birthdays that weren't told about any within the last week
+fn due(birthdays: &BirthdayList, now: DateTime<Utc>) -> BTreeMap<GuildId, Vec<&Missed>> {
+    let mut due: BTreeMap<GuildId, Vec<&Missed>> = BTreeMap::new();
+    for missed in &birthdays.missed {
+        let notified_at = birthdays
+            .guild_configs
+            .get(&missed.guild_id)
+            .and_then(|config| config.missed_notified_at);
+        if notified_at
+            .is_none_or(|notified| now - notified >= chrono::Duration::days(NOTIFY_INTERVAL_DAYS))
+        {
+            due.entry(missed.guild_id).or_default().push(missed);
+        }
+    }
+    due
+}
+
+/// Prunes missed birthdays from before the previous year, the guild can't be reached anymore
+pub fn prune(missed: &mut Vec<Missed>, today: NaiveDate) {
+    missed.retain(|missed| missed.occurrence.year() >= today.year() - 1);
+}
+
+/// Tells the guild in its system channel, or its owner if that fails
+async fn notify(
+    http: &serenity::Http,
+    guild_id: GuildId,
+    missed: &[&Missed],
+    date_format: DateFormat,
+) -> Result<(), Error> {
+    let guild = guild_id.to_partial_guild(http).await?;
+    let mut lines: Vec<String> = missed
+        .iter()
+        .take(MAX_LISTED)
+        .map(|missed| {
+            format!(
+                "- {} on {}: {}",
+                format::escape(&missed.name),
+                date_format.format(missed.occurrence.day(), missed.occurrence.month(), None),
+                missed.reason.describe()
+            )
+        })
+        .collect();
+    if missed.len() > MAX_LISTED {
+        lines.push(format!("- and {} more", missed.len() - MAX_LISTED));
+    }
+    let message = format!(
+        "⚠️🎈 These birthdays in {} passed without an announcement:\n{}\n\
+        Run `set_announcement_channel` to pick a channel I can post in.",
+        guild.name,
+        lines.join("\n")
+    );
+    if let Some(channel) = guild.system_channel_id {
+        match channel.send_message(http, quiet_message(&message)).await {
+            Ok(_) => return Ok(()),
+            Err(error) => warn!(
+                guild = %guild_id,
+                %error,
+                "Failed to post the missed birthdays in the system channel"
+            ),
+        }
+    }
+    guild
+        .owner_id
+        .create_dm_channel(http)
+        .await?
+        .send_message(http, quiet_message(message))
+        .await?;
+    Ok(())
+}
+
+/// Tells the guilds about the birthdays they missed, at most once a week
+pub async fn notify_due(http: &serenity::Http, storage: &Storage) -> Result<(), Error> {
+    let birthdays = storage.read().await;
+    let now = Utc::now();
+    let mut notified = Vec::new();
+    for (guild_id, missed) in due(&birthdays, now) {
+        let date_format = birthdays.date_format(guild_id);
+        match notify(http, guild_id, &missed, date_format).await {
+            Ok(()) => notified.push(guild_id),
+            Err(error) => warn!(
+                guild = %guild_id,
+                %error,
+                "Failed to tell the guild about its missed birthdays"
+            ),
+        }
+    }
+    if notified.is_empty() {
+        return Ok(());
+    }
+    storage
+        .update(|birthdays| {
+            for guild_id in &notified {
+                birthdays
+                    .missed
+                    .retain(|missed| missed.guild_id != *guild_id);
+                birthdays
+                    .guild_configs
+                    .entry(*guild_id)
+                    .or_default()
+                    .missed_notified_at = Some(now);
+            }
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GuildConfig;
+
+    fn missed(guild_id: u64, user_id: u64) -> Missed {
+        Missed {
+            guild_id: GuildId::new(guild_id),
+            user_id: UserId::new(user_id),
+            name: format!("user {}", user_id),
+            occurrence: NaiveDate::from_ymd_opt(2024, 6, 14).unwrap(),
+            reason: MissReason::NotConfigured,
+        }
+    }
+
+    #[test]
+    fn guilds_are_told_at_most_once_a_week() {
+        let now = DateTime::parse_from_rfc3339("2024-06-15T12:00:00Z")
+            .unwrap()
+            .to_utc();
+        let mut birthdays = BirthdayList {
+            missed: vec![missed(1, 1), missed(1, 2), missed(2, 3)],
+            guild_configs: [(
+                GuildId::new(2),
+                GuildConfig {
+                    missed_notified_at: Some(now - chrono::Duration::days(3)),
+                    ..Default::default()
+                },
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let due_now = due(&birthdays, now);
+        assert_eq!(due_now.len(), 1);
+        assert_eq!(due_now[&GuildId::new(1)].len(), 2);
+
+        birthdays
+            .guild_configs
+            .get_mut(&GuildId::new(2))
+            .unwrap()
+            .missed_notified_at = Some(now - chrono::Duration::days(7));
+        assert_eq!(due(&birthdays, now).len(), 2);
+    }
+}
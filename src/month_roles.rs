@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use chrono::Datelike;
+use poise::serenity_prelude::{self as serenity, EditRole, GuildId, RoleId, UserId};
+use tracing::warn;
+
+use crate::{BirthdayList, Error};
+
+static MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+static AUDIT_LOG_REASON: &str = "Birth month role";
+
+// Members are updated in batches so a big guild doesn't hit the rate limits
+static BATCH_SIZE: usize = 10;
+static BATCH_PAUSE: u64 = 5; // seconds
+
+pub fn month_name(month: u32) -> &'static str {
+    MONTH_NAMES[month as usize - 1]
+}
+
+/// Gives a member the role of their birth month and takes the other month roles away, a month of
+/// None takes all of them away. Failures like missing permissions or deleted roles are only
+/// logged, they must never break the command that changed the birthday. Returns whether every
+/// change went through.
+pub async fn apply(
+    http: &serenity::Http,
+    birthdays: &BirthdayList,
+    guild_id: GuildId,
+    user_id: UserId,
+    month: Option<u32>,
+) -> bool {
+    let Some(month_roles) = birthdays
+        .guild_configs
+        .get(&guild_id)
+        .map(|config| &config.month_roles)
+        .filter(|month_roles| !month_roles.is_empty())
+    else {
+        return true;
+    };
+
+    let member = match guild_id.member(http, user_id).await {
+        Ok(member) => member,
+        Err(error) => {
+            warn!(
+                user = %user_id,
+                guild = %guild_id,
+                %error,
+                "Failed to look up the member for their month role"
+            );
+            return false;
+        }
+    };
+
+    let wanted = month.and_then(|month| month_roles.get(&month));
+    let mut success = true;
+    for role in month_roles.values() {
+        let has_role = member.roles.contains(role);
+        let result = if Some(role) == wanted {
+            if has_role {
+                continue;
+            }
+            http.add_member_role(guild_id, user_id, *role, Some(AUDIT_LOG_REASON))
+                .await
+        } else {
+            if !has_role {
+                continue;
+            }
+            http.remove_member_role(guild_id, user_id, *role, Some(AUDIT_LOG_REASON))
+                .await
+        };
+        if let Err(error) = result {
+            warn!(
+                %role,
+                user = %user_id,
+                guild = %guild_id,
+                %error,
+                "Failed to update the month role"
+            );
+            success = false;
+        }
+    }
+    success
+}
+
+/// Creates a role for every month that has none yet, stops at the first failure
+pub async fn create_roles(
+    http: &serenity::Http,
+    guild_id: GuildId,
+    month_roles: &mut HashMap<u32, RoleId>,
+) -> Result<(), Error> {
+    for month in 1..=12 {
+        if month_roles.contains_key(&month) {
+            continue;
+        }
+        let role = guild_id
+            .create_role(
+                http,
+                EditRole::new()
+                    .name(format!("{} Crew", month_name(month)))
+                    .audit_log_reason(AUDIT_LOG_REASON),
+            )
+            .await?;
+        month_roles.insert(month, role.id);
+    }
+    Ok(())
+}
+
+/// Reconciles the month roles of everyone with a birthday in the guild, including members whose
+/// birthday was removed. Returns the number of members that were updated and failed.
+pub async fn sync(
+    http: &serenity::Http,
+    birthdays: &BirthdayList,
+    guild_id: GuildId,
+) -> (usize, usize) {
+    let mut members: Vec<(UserId, Option<u32>)> = birthdays
+        .deleted
+        .iter()
+        .filter(|deleted| deleted.entry.guild_id == guild_id)
+        .map(|deleted| (deleted.entry.user_id, None))
+        .collect();
+    members.extend(
+        birthdays
+            .entries
+            .iter()
+            .filter(|entry| entry.guild_id == guild_id)
+            .map(|entry| (entry.user_id, Some(entry.date.month()))),
+    );
+    // A removed and re-added birthday only counts once, the entry wins
+    let members: HashMap<UserId, Option<u32>> = members.into_iter().collect();
+    let members: Vec<(UserId, Option<u32>)> = members.into_iter().collect();
+
+    let (mut updated, mut failed) = (0, 0);
+    for batch in members.chunks(BATCH_SIZE) {
+        for (user_id, month) in batch {
+            if apply(http, birthdays, guild_id, *user_id, *month).await {
+                updated += 1;
+            } else {
+                failed += 1;
+            }
+        }
+        tokio::time::sleep(tokio::time::Duration::from_secs(BATCH_PAUSE)).await;
+    }
+    (updated, failed)
+}
+
+/// Stores the months as string keys, TOML doesn't support numbers as keys
+pub mod string_keys {
+    use std::collections::{BTreeMap, HashMap};
+
+    use poise::serenity_prelude::RoleId;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        month_roles: &HashMap<u32, RoleId>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let month_roles: BTreeMap<String, RoleId> = month_roles
+            .iter()
+            .map(|(month, role)| (month.to_string(), *role))
+            .collect();
+        month_roles.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<u32, RoleId>, D::Error> {
+        HashMap::<String, RoleId>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(month, role)| Ok((month.parse().map_err(D::Error::custom)?, role)))
+            .collect()
+    }
+}
@@ -0,0 +1,119 @@
+use chrono::Utc;
+
+use crate::{format, send_reply, Context, Error};
+
+static NOTE_LIMIT: usize = 200; // characters
+
+/// The note as it is stored: trimmed and on a single line, an error message if it is empty or
+/// too long
+pub fn clean(text: &str) -> Result<String, String> {
+    let note = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if note.is_empty() || note.chars().count() > NOTE_LIMIT {
+        return Err(format!(
+            "🐺🎩❌ Birthday notes must be 1 to {} characters long!",
+            NOTE_LIMIT
+        ));
+    }
+    Ok(note)
+}
+
+/// Whether the word can be shown as a link, anything that could break out of the angle
+/// brackets or form a mention is shown as text instead
+fn is_link(word: &str) -> bool {
+    (word.starts_with("https://") || word.starts_with("http://"))
+        && !word.contains(['<', '>', '@', '`'])
+}
+
+/// Makes a note safe to put into a message, links are kept clickable without an embed
+pub fn display(note: &str) -> String {
+    note.split_whitespace()
+        .map(|word| {
+            if is_link(word) {
+                format!("<{}>", word)
+            } else {
+                format::escape(word)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Attaches a note or wishlist link to your birthday, leave it empty to remove it
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn set_birthday_note(
+    ctx: Context<'_>,
+    #[description = "The note, like a wishlist link (leave empty to remove it)"]
+    #[rest]
+    note: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let note = match note.as_deref().map(clean) {
+        Some(Ok(note)) => Some(note),
+        Some(Err(message)) => {
+            ctx.say(message).await?;
+            return Ok(());
+        }
+        None => None,
+    };
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let quiet = birthdays.replies_quietly(guild_id, None);
+    let entry = birthdays
+        .entries
+        .iter_mut()
+        .find(|entry| entry.user_id == ctx.author().id && entry.guild_id == guild_id);
+    let Some(entry) = entry else {
+        ctx.say("☹️🎈 You haven't set a birthday for this guild!")
+            .await?;
+        return Ok(());
+    };
+    let message = match &note {
+        Some(note) => format!("📝🎈 Your birthday note is now: {}", display(note)),
+        None => "📝 Your birthday note is removed!".to_string(),
+    };
+    entry.note = note;
+    entry.touch(ctx.author().id, Utc::now());
+    ctx.data().storage.write(&birthdays).await?;
+
+    send_reply(
+        ctx,
+        quiet,
+        poise::CreateReply::default()
+            .content(message)
+            .allowed_mentions(poise::serenity_prelude::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_are_kept_on_one_line_and_capped() {
+        assert_eq!(
+            clean("  a new\nbike \n\n please ").unwrap(),
+            "a new bike please"
+        );
+        assert!(clean(" \n ").is_err());
+        assert!(clean(&"a".repeat(NOTE_LIMIT)).is_ok());
+        assert!(clean(&"a".repeat(NOTE_LIMIT + 1)).is_err());
+    }
+
+    #[test]
+    fn notes_cannot_ping_or_break_the_layout() {
+        assert_eq!(
+            display("**books** @everyone"),
+            "\\*\\*books\\*\\* @\u{200B}everyone"
+        );
+        assert_eq!(
+            display("wishlist: https://example.com/list?id=1_2"),
+            "wishlist: <https://example.com/list?id=1_2>"
+        );
+        // Links that could close the brackets or mention someone are plain text
+        assert_eq!(
+            display("https://a.com/>@here"),
+            "https://a.com/\\>@\u{200B}here"
+        );
+    }
+}
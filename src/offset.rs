@@ -0,0 +1,147 @@
+use chrono::Duration;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::i18n::{text, Language};
+
+// Time zones in use range from UTC-12:00 to UTC+14:00
+static MIN_MINUTES: i32 = -12 * 60;
+static MAX_MINUTES: i32 = 14 * 60;
+
+/// Offset of a time zone from UTC in minutes, so +5:30 and +5:45 work as well. Whole hours are
+/// stored as a number of hours like before, other offsets as text like "+5:30".
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct UtcOffset {
+    minutes: i32,
+}
+
+impl UtcOffset {
+    pub const fn from_hours(hours: i32) -> Self {
+        UtcOffset {
+            minutes: hours * 60,
+        }
+    }
+
+    pub const fn from_minutes(minutes: i32) -> Self {
+        UtcOffset { minutes }
+    }
+
+    pub fn minutes(self) -> i32 {
+        self.minutes
+    }
+
+    /// How far the time zone is ahead of UTC
+    pub fn duration(self) -> Duration {
+        Duration::minutes(self.minutes as i64)
+    }
+
+    /// Parses an offset like `+2`, `-5`, `+5:30` or `UTC+05:45` and checks that such a time
+    /// zone exists
+    pub fn parse(input: &str, language: Language) -> Result<Self, String> {
+        let offset = parse_unchecked(input)
+            .ok_or_else(|| text(language, "offset_unparsable", &[("offset", &input)]))?;
+        if !(MIN_MINUTES..=MAX_MINUTES).contains(&offset.minutes) {
+            return Err(text(
+                language,
+                "offset_out_of_range",
+                &[("offset", &offset)],
+            ));
+        }
+        Ok(offset)
+    }
+}
+
+fn parse_unchecked(text: &str) -> Option<UtcOffset> {
+    let text = text.trim();
+    let text = match text.get(..3) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("utc") => &text[3..],
+        _ => text,
+    };
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(text) => (-1, text),
+        None => (1, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (hours, minutes) = text.split_once(':').unwrap_or((text, "0"));
+    let digits = |part: &str, max_len| {
+        (!part.is_empty() && part.len() <= max_len && part.chars().all(|c| c.is_ascii_digit()))
+            .then(|| part.parse::<i32>().ok())
+            .flatten()
+    };
+    let hours = digits(hours, 2)?;
+    let minutes = digits(minutes, 2).filter(|minutes| *minutes < 60)?;
+    Some(UtcOffset::from_minutes(sign * (hours * 60 + minutes)))
+}
+
+/// Renders the offset like `+2`, `-5` or `+5:30`
+impl std::fmt::Display for UtcOffset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.minutes < 0 { '-' } else { '+' };
+        let minutes = self.minutes.abs();
+        match minutes % 60 {
+            0 => write!(f, "{}{}", sign, minutes / 60),
+            rest => write!(f, "{}{}:{:02}", sign, minutes / 60, rest),
+        }
+    }
+}
+
+impl Serialize for UtcOffset {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.minutes % 60 == 0 {
+            serializer.serialize_i32(self.minutes / 60)
+        } else {
+            serializer.serialize_str(&self.to_string())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for UtcOffset {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Stored {
+            Hours(i32),
+            Text(String),
+        }
+        // Offsets that are out of range today were accepted before, they are kept as they are
+        match Stored::deserialize(deserializer)? {
+            Stored::Hours(hours) => Ok(UtcOffset::from_hours(hours)),
+            Stored::Text(text) => parse_unchecked(&text)
+                .ok_or_else(|| serde::de::Error::custom(format!("`{}` is no UTC offset", text))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_are_parsed_checked_and_stored() {
+        let parse = |text| UtcOffset::parse(text, Language::English);
+        assert_eq!(parse("2"), Ok(UtcOffset::from_hours(2)));
+        assert_eq!(parse("-5"), Ok(UtcOffset::from_hours(-5)));
+        assert_eq!(parse("+5:30"), Ok(UtcOffset::from_minutes(330)));
+        assert_eq!(parse("UTC+05:45"), Ok(UtcOffset::from_minutes(345)));
+        assert_eq!(parse("-9:30"), Ok(UtcOffset::from_minutes(-570)));
+        assert_eq!(parse("+14"), Ok(UtcOffset::from_hours(14)));
+        assert_eq!(parse("-12"), Ok(UtcOffset::from_hours(-12)));
+        for invalid in [
+            "9999", "+14:30", "-12:15", "5:60", "five", "", "+", "5:3:0", "1.5",
+        ] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+
+        assert_eq!(UtcOffset::from_hours(0).to_string(), "+0");
+        assert_eq!(UtcOffset::from_hours(-5).to_string(), "-5");
+        assert_eq!(UtcOffset::from_minutes(330).to_string(), "+5:30");
+        assert_eq!(UtcOffset::from_minutes(-570).to_string(), "-9:30");
+
+        // Whole hours stay numbers, so older files and versions keep working
+        let offsets = [UtcOffset::from_hours(-5), UtcOffset::from_minutes(345)];
+        let json = serde_json::to_string(&offsets).unwrap();
+        assert_eq!(json, r#"[-5,"+5:45"]"#);
+        assert_eq!(
+            serde_json::from_str::<Vec<UtcOffset>>(&json).unwrap(),
+            offsets
+        );
+    }
+}
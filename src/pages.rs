@@ -0,0 +1,190 @@
+use std::time::Duration;
+
+use poise::futures_util::StreamExt;
+use poise::serenity_prelude::{
+    self as serenity, ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateButton,
+    CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage,
+};
+use poise::CreateReply;
+
+use crate::{send_reply, Context, Error};
+
+// The buttons stop working once nobody flipped a page for this long
+static FLIP_TIMEOUT: u64 = 3 * 60; // seconds
+static PAGE_LINES: usize = 15;
+// Discord rejects embed descriptions longer than this
+static DESCRIPTION_LIMIT: usize = 4096;
+
+/// Splits the lines into pages of at most PAGE_LINES lines that each fit into an embed
+fn chunk(lines: &[String]) -> Vec<String> {
+    let mut pages = Vec::new();
+    let mut current = String::new();
+    let mut count = 0;
+    for line in lines {
+        let full = count == PAGE_LINES
+            || (count > 0 && current.len() + 1 + line.len() > DESCRIPTION_LIMIT);
+        if full {
+            pages.push(std::mem::take(&mut current));
+            count = 0;
+        }
+        if count > 0 {
+            current.push('\n');
+        }
+        current.push_str(line);
+        count += 1;
+    }
+    if count > 0 {
+        pages.push(current);
+    }
+    pages
+}
+
+fn embed(title: &str, pages: &[String], index: usize) -> CreateEmbed {
+    let embed = CreateEmbed::new().title(title).description(&pages[index]);
+    if pages.len() == 1 {
+        return embed;
+    }
+    embed.footer(CreateEmbedFooter::new(format!(
+        "Page {}/{}",
+        index + 1,
+        pages.len()
+    )))
+}
+
+/// The ◀/▶ buttons, disabled where there is no page to flip to or once they timed out
+fn buttons(prefix: &str, index: usize, count: usize, expired: bool) -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{}-previous", prefix))
+            .style(ButtonStyle::Secondary)
+            .label("◀")
+            .disabled(expired || index == 0),
+        CreateButton::new(format!("{}-next", prefix))
+            .style(ButtonStyle::Secondary)
+            .label("▶")
+            .disabled(expired || index + 1 == count),
+    ])]
+}
+
+/// Sends the lines as an embed with ◀/▶ buttons to browse them page by page, only whoever ran
+/// the command can flip the pages. Quiet replies of prefix commands are deleted once the
+/// buttons time out.
+pub async fn send(
+    ctx: Context<'_>,
+    quiet: bool,
+    title: &str,
+    lines: &[String],
+) -> Result<(), Error> {
+    let pages = chunk(lines);
+    if pages.is_empty() {
+        return Ok(());
+    }
+    if pages.len() == 1 {
+        let reply = CreateReply::default()
+            .embed(embed(title, &pages, 0))
+            .allowed_mentions(serenity::CreateAllowedMentions::new());
+        return send_reply(ctx, quiet, reply).await;
+    }
+
+    let prefix = ctx.id().to_string();
+    let mut index = 0;
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .embed(embed(title, &pages, index))
+                .components(buttons(&prefix, index, pages.len(), false))
+                .allowed_mentions(serenity::CreateAllowedMentions::new())
+                .ephemeral(quiet),
+        )
+        .await?;
+
+    let filter_prefix = format!("{}-", prefix);
+    let mut interactions = ComponentInteractionCollector::new(ctx)
+        .channel_id(ctx.channel_id())
+        .filter(move |interaction| interaction.data.custom_id.starts_with(&filter_prefix))
+        .stream();
+    let timeout = Duration::from_secs(FLIP_TIMEOUT);
+    while let Ok(Some(interaction)) = tokio::time::timeout(timeout, interactions.next()).await {
+        if interaction.user.id != ctx.author().id {
+            interaction
+                .create_response(
+                    ctx,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content("☝️ Only whoever ran the command can flip the pages!")
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+            continue;
+        }
+        match interaction.data.custom_id.strip_prefix(&prefix) {
+            Some("-previous") => index = index.saturating_sub(1),
+            Some("-next") => index = (index + 1).min(pages.len() - 1),
+            _ => continue,
+        }
+        interaction
+            .create_response(
+                ctx,
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new()
+                        .embed(embed(title, &pages, index))
+                        .components(buttons(&prefix, index, pages.len(), false)),
+                ),
+            )
+            .await?;
+    }
+
+    if quiet && matches!(ctx, poise::Context::Prefix(_)) {
+        reply.delete(ctx).await?;
+        return Ok(());
+    }
+    reply
+        .edit(
+            ctx,
+            CreateReply::default()
+                .embed(embed(title, &pages, index))
+                .components(buttons(&prefix, index, pages.len(), true)),
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_are_capped_by_lines_and_length() {
+        let lines: Vec<String> = (0..300).map(|i| format!("line {}", i)).collect();
+        let pages = chunk(&lines);
+        assert_eq!(pages.len(), 20);
+        assert_eq!(pages[0].lines().count(), PAGE_LINES);
+        assert!(pages[0].starts_with("line 0\nline 1\n"));
+        assert!(pages[19].ends_with("line 299"));
+
+        let long: Vec<String> = (0..3).map(|_| "a".repeat(3000)).collect();
+        let pages = chunk(&long);
+        assert_eq!(pages.len(), 3);
+        assert!(pages.iter().all(|page| page.len() == 3000));
+        assert!(chunk(&[]).is_empty());
+    }
+
+    #[test]
+    fn buttons_stop_at_the_ends() {
+        let disabled = |index, expired| {
+            serde_json::to_value(&buttons("1", index, 3, expired)[0])
+                .unwrap()
+                .get("components")
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|button| button["disabled"].as_bool().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(disabled(0, false), [true, false]);
+        assert_eq!(disabled(1, false), [false, false]);
+        assert_eq!(disabled(2, false), [false, true]);
+        assert_eq!(disabled(1, true), [true, true]);
+    }
+}
@@ -0,0 +1,456 @@
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDate, Utc};
+use poise::serenity_prelude::{
+    ButtonStyle, ComponentInteraction, ComponentInteractionCollector, ComponentInteractionDataKind,
+    CreateActionRow, CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage,
+    CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption,
+};
+use poise::CreateReply;
+
+use crate::{
+    append_birthday, date_to_discord_timestamp, format, i18n::text, may_set_birthday,
+    missing_channel_notice, month_roles, offset::UtcOffset, Context, Error, MAX_AGE, NO_YEAR,
+};
+
+// Every step of the picker waits this long for a choice before giving up
+static STEP_TIMEOUT: u64 = 2 * 60; // seconds
+static FIRST_DECADE: i32 = 1900;
+// Select menus hold at most 25 options, so only the common offsets are offered, +5:30 is India
+static COMMON_OFFSETS: [UtcOffset; 21] = [
+    UtcOffset::from_hours(-10),
+    UtcOffset::from_hours(-8),
+    UtcOffset::from_hours(-7),
+    UtcOffset::from_hours(-6),
+    UtcOffset::from_hours(-5),
+    UtcOffset::from_hours(-4),
+    UtcOffset::from_hours(-3),
+    UtcOffset::from_hours(0),
+    UtcOffset::from_hours(1),
+    UtcOffset::from_hours(2),
+    UtcOffset::from_hours(3),
+    UtcOffset::from_hours(4),
+    UtcOffset::from_hours(5),
+    UtcOffset::from_minutes(5 * 60 + 30),
+    UtcOffset::from_hours(6),
+    UtcOffset::from_hours(7),
+    UtcOffset::from_hours(8),
+    UtcOffset::from_hours(9),
+    UtcOffset::from_hours(10),
+    UtcOffset::from_hours(11),
+    UtcOffset::from_hours(12),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Month,
+    Day,
+    Decade,
+    Year,
+    Offset,
+    Confirm,
+}
+
+/// The choices made so far, the steps that led there are kept to allow going back
+#[derive(Debug)]
+struct Picker {
+    steps: Vec<Step>,
+    month: u32,
+    day: u32,
+    decade: i32,
+    year: Option<i32>,
+    utc_offset: UtcOffset,
+}
+
+enum Outcome {
+    Continue,
+    Cancelled,
+    Confirmed,
+}
+
+fn days_in_month(month: u32) -> u32 {
+    // NO_YEAR is a leap year, so February 29th can be picked before the year is known
+    (28..=31)
+        .rev()
+        .find(|day| NaiveDate::from_ymd_opt(NO_YEAR, month, *day).is_some())
+        .unwrap()
+}
+
+fn select(
+    id: String,
+    placeholder: &str,
+    options: impl IntoIterator<Item = (String, String)>,
+) -> CreateActionRow {
+    let options = options
+        .into_iter()
+        .map(|(label, value)| CreateSelectMenuOption::new(label, value))
+        .collect();
+    CreateActionRow::SelectMenu(
+        CreateSelectMenu::new(id, CreateSelectMenuKind::String { options })
+            .placeholder(placeholder),
+    )
+}
+
+impl Picker {
+    fn new() -> Picker {
+        Picker {
+            steps: vec![Step::Month],
+            month: 1,
+            day: 1,
+            decade: FIRST_DECADE,
+            year: None,
+            utc_offset: UtcOffset::default(),
+        }
+    }
+
+    fn step(&self) -> Step {
+        *self.steps.last().unwrap()
+    }
+
+    fn date(&self) -> NaiveDate {
+        NaiveDate::from_ymd_opt(self.year.unwrap_or(NO_YEAR), self.month, self.day).unwrap()
+    }
+
+    /// Years of the chosen decade in which the chosen day exists and that already began
+    fn years(&self, today: NaiveDate) -> Vec<i32> {
+        (self.decade..self.decade + 10)
+            .filter(|year| {
+                (today.year() - MAX_AGE..=today.year()).contains(year)
+                    && NaiveDate::from_ymd_opt(*year, self.month, self.day).is_some()
+            })
+            .collect()
+    }
+
+    fn content(&self) -> String {
+        let picked = match self.step() {
+            Step::Month => String::new(),
+            Step::Day => format!(" ({})", month_roles::month_name(self.month)),
+            _ => format!(" ({} {})", month_roles::month_name(self.month), self.day),
+        };
+        match self.step() {
+            Step::Month => "📅🎈 In which month is your birthday?".to_string(),
+            Step::Day => format!("📅🎈 On which day is your birthday?{}", picked),
+            Step::Decade => format!(
+                "📅🎈 In which decade were you born?{} The year is optional.",
+                picked
+            ),
+            Step::Year => format!("📅🎈 In which year were you born?{}", picked),
+            Step::Offset => format!("🌍🎈 Which UTC offset do you live in?{}", picked),
+            Step::Confirm => format!(
+                "📅🎈 Set your birthday to {} {}{} (UTC{}), which is {} for you?",
+                month_roles::month_name(self.month),
+                self.day,
+                self.year.map_or(String::new(), |year| format!(" {}", year)),
+                self.utc_offset,
+                date_to_discord_timestamp(self.date(), self.utc_offset, false)
+            ),
+        }
+    }
+
+    fn components(&self, prefix: &str, today: NaiveDate) -> Vec<CreateActionRow> {
+        let id = |name: &str| format!("{}:{}", prefix, name);
+        let numbers = |range: std::ops::RangeInclusive<u32>| {
+            range.map(|number| (number.to_string(), number.to_string()))
+        };
+
+        let mut rows = match self.step() {
+            Step::Month => vec![select(
+                id("month"),
+                "Month",
+                (1..=12).map(|month| {
+                    (
+                        month_roles::month_name(month).to_string(),
+                        month.to_string(),
+                    )
+                }),
+            )],
+            // A month can have more days than a select menu has options
+            Step::Day => vec![
+                select(id("day"), "1 - 15", numbers(1..=15)),
+                select(
+                    id("day_end"),
+                    &format!("16 - {}", days_in_month(self.month)),
+                    numbers(16..=days_in_month(self.month)),
+                ),
+            ],
+            Step::Decade => vec![select(
+                id("decade"),
+                "Decade",
+                (FIRST_DECADE..=today.year())
+                    .step_by(10)
+                    .map(|decade| (format!("{}s", decade), decade.to_string())),
+            )],
+            Step::Year => vec![select(
+                id("year"),
+                "Year",
+                self.years(today)
+                    .into_iter()
+                    .map(|year| (year.to_string(), year.to_string())),
+            )],
+            Step::Offset => vec![select(
+                id("offset"),
+                "UTC offset",
+                COMMON_OFFSETS
+                    .iter()
+                    .map(|offset| (format!("UTC{}", offset), offset.minutes().to_string())),
+            )],
+            Step::Confirm => Vec::new(),
+        };
+
+        let mut buttons = Vec::new();
+        match self.step() {
+            Step::Decade | Step::Year => buttons.push(
+                CreateButton::new(id("skip"))
+                    .label("Skip the year")
+                    .style(ButtonStyle::Secondary),
+            ),
+            Step::Confirm => buttons.push(
+                CreateButton::new(id("confirm"))
+                    .label("Confirm")
+                    .style(ButtonStyle::Success),
+            ),
+            _ => {}
+        }
+        if self.steps.len() > 1 {
+            buttons.push(
+                CreateButton::new(id("back"))
+                    .label("Back")
+                    .style(ButtonStyle::Secondary),
+            );
+        }
+        buttons.push(
+            CreateButton::new(id("cancel"))
+                .label("Cancel")
+                .style(ButtonStyle::Danger),
+        );
+        rows.push(CreateActionRow::Buttons(buttons));
+        rows
+    }
+
+    /// Applies a button press or a selection, unknown or malformed choices are ignored
+    fn handle(&mut self, action: &str, value: Option<&str>) -> Outcome {
+        let number = value.and_then(|value| value.parse::<i32>().ok());
+        let next = match (self.step(), action, number) {
+            (_, "cancel", _) => return Outcome::Cancelled,
+            (Step::Confirm, "confirm", _) => return Outcome::Confirmed,
+            (_, "back", _) => {
+                if self.steps.len() > 1 {
+                    self.steps.pop();
+                }
+                return Outcome::Continue;
+            }
+            (Step::Month, "month", Some(month)) if (1..=12).contains(&month) => {
+                self.month = month as u32;
+                Step::Day
+            }
+            (Step::Day, "day" | "day_end", Some(day))
+                if day >= 1 && day as u32 <= days_in_month(self.month) =>
+            {
+                self.day = day as u32;
+                Step::Decade
+            }
+            (Step::Decade, "decade", Some(decade)) => {
+                self.decade = decade;
+                Step::Year
+            }
+            (Step::Decade | Step::Year, "skip", _) => {
+                self.year = None;
+                Step::Offset
+            }
+            (Step::Year, "year", Some(year)) => {
+                self.year = Some(year);
+                Step::Offset
+            }
+            (Step::Offset, "offset", Some(offset)) => {
+                self.utc_offset = UtcOffset::from_minutes(offset);
+                Step::Confirm
+            }
+            _ => return Outcome::Continue,
+        };
+        self.steps.push(next);
+        Outcome::Continue
+    }
+}
+
+fn selected_value(interaction: &ComponentInteraction) -> Option<&str> {
+    match &interaction.data.kind {
+        ComponentInteractionDataKind::StringSelect { values } => values.first().map(String::as_str),
+        _ => None,
+    }
+}
+
+async fn update(
+    ctx: Context<'_>,
+    interaction: &ComponentInteraction,
+    content: String,
+    components: Vec<CreateActionRow>,
+) -> Result<(), Error> {
+    interaction
+        .create_response(
+            ctx,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .components(components),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Sets your birthday by picking the date from menus instead of typing it
+#[poise::command(slash_command, guild_only)]
+pub async fn set_birthday_picker(ctx: Context<'_>) -> Result<(), Error> {
+    // The picker itself is ephemeral, so are the refusals
+    if !may_set_birthday(ctx, ctx.author().id, true).await? {
+        return Ok(());
+    }
+    let prefix = ctx.id().to_string();
+    let today = Utc::now().naive_utc().date();
+    let mut picker = Picker::new();
+
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .content(picker.content())
+                .components(picker.components(&prefix, today))
+                .ephemeral(true),
+        )
+        .await?;
+
+    loop {
+        let filter_prefix = format!("{}:", prefix);
+        let interaction = ComponentInteractionCollector::new(ctx.serenity_context())
+            .author_id(ctx.author().id)
+            .channel_id(ctx.channel_id())
+            .filter(move |interaction| interaction.data.custom_id.starts_with(&filter_prefix))
+            .timeout(Duration::from_secs(STEP_TIMEOUT))
+            .await;
+        let Some(interaction) = interaction else {
+            reply
+                .edit(
+                    ctx,
+                    CreateReply::default()
+                        .content(
+                            "⌛🎈 The date picker timed out, run it again to set your birthday!",
+                        )
+                        .components(Vec::new()),
+                )
+                .await?;
+            return Ok(());
+        };
+
+        let action = interaction.data.custom_id[prefix.len() + 1..].to_string();
+        match picker.handle(&action, selected_value(&interaction)) {
+            Outcome::Continue => {
+                update(
+                    ctx,
+                    &interaction,
+                    picker.content(),
+                    picker.components(&prefix, today),
+                )
+                .await?
+            }
+            Outcome::Cancelled => {
+                interaction
+                    .create_response(ctx, CreateInteractionResponse::Acknowledge)
+                    .await?;
+                reply.delete(ctx).await?;
+                return Ok(());
+            }
+            Outcome::Confirmed => {
+                return confirm(ctx, &interaction, &picker).await;
+            }
+        }
+    }
+}
+
+async fn confirm(
+    ctx: Context<'_>,
+    interaction: &ComponentInteraction,
+    picker: &Picker,
+) -> Result<(), Error> {
+    let user = ctx.author();
+    let guild_id = ctx.guild_id().unwrap();
+    append_birthday(
+        &ctx.data().storage,
+        user.id,
+        guild_id,
+        user.name.clone(),
+        picker.day as usize,
+        picker.month as usize,
+        picker.year.map(|year| year as usize),
+        picker.utc_offset,
+        None,
+        user.id,
+    )
+    .await?;
+
+    let birthdays = ctx.data().storage.read().await;
+    month_roles::apply(
+        ctx.http(),
+        &birthdays,
+        guild_id,
+        user.id,
+        Some(picker.month),
+    )
+    .await;
+    let format = birthdays.date_format(guild_id);
+    let language = birthdays.language(guild_id);
+    let notice = missing_channel_notice(ctx.http(), &ctx.data().storage, guild_id, language).await;
+    let message = text(
+        language,
+        "birthday_set",
+        &[
+            ("name", &format::escape(&user.name)),
+            ("date", &format.format(picker.day, picker.month, None)),
+            ("offset", &picker.utc_offset),
+            (
+                "time",
+                &date_to_discord_timestamp(picker.date(), picker.utc_offset, false),
+            ),
+        ],
+    );
+    let content = format!("{}{}", message, notice);
+    update(ctx, interaction, content, Vec::new()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn back_returns_to_the_previous_step() {
+        let mut picker = Picker::new();
+        picker.handle("month", Some("2"));
+        picker.handle("day_end", Some("29"));
+        assert_eq!(picker.step(), Step::Decade);
+        picker.handle("back", None);
+        assert_eq!(picker.step(), Step::Day);
+        picker.handle("back", None);
+        picker.handle("back", None);
+        assert_eq!(picker.step(), Step::Month);
+    }
+
+    #[test]
+    fn only_valid_days_and_years_are_offered() {
+        let today = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        let mut picker = Picker::new();
+        picker.handle("month", Some("4"));
+        // April has no 31st
+        picker.handle("day_end", Some("31"));
+        assert_eq!(picker.step(), Step::Day);
+
+        picker.handle("back", None);
+        picker.handle("month", Some("2"));
+        picker.handle("day", Some("29"));
+        picker.handle("decade", Some("2020"));
+        assert_eq!(picker.years(today), vec![2020, 2024]);
+        picker.handle("skip", None);
+        // Offsets are picked in minutes
+        picker.handle("offset", Some("330"));
+        assert_eq!(picker.step(), Step::Confirm);
+        assert_eq!(picker.utc_offset.to_string(), "+5:30");
+        assert_eq!(picker.date(), NaiveDate::from_ymd_opt(2024, 2, 29).unwrap());
+    }
+}
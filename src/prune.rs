@@ -0,0 +1,580 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::{self as serenity, GuildId, UserId};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{audit, confirm, format, soft_delete, storage::Storage, BirthdayList, Context, Error};
+
+static PRUNE_CHECK_TIME: u64 = 24 * 60 * 60; // 1 day
+
+// Data of a guild that removed the bot is kept this long in case it was an accident
+static LEFT_GUILD_DAYS: i64 = 7;
+
+// Member lookups are spread out so a big data set doesn't hit the rate limits
+static BATCH_SIZE: usize = 10;
+static BATCH_PAUSE: u64 = 5; // seconds
+
+// JSON error code Discord returns for members that aren't in the guild
+static UNKNOWN_MEMBER: isize = 10007;
+
+/// What happens to the birthday of a member who leaves the guild
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, poise::ChoiceParameter,
+)]
+pub enum LeaveAction {
+    // The data stays in case they come back, they just aren't announced meanwhile
+    #[default]
+    #[name = "Keep it but don't announce it"]
+    Mute,
+    #[name = "Remove it"]
+    Remove,
+}
+
+impl LeaveAction {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            LeaveAction::Mute => "kept but not announced",
+            LeaveAction::Remove => "removed",
+        }
+    }
+}
+
+/// Applies the guild's leave action to the member's entry, returns what was done for the log
+fn member_left(
+    birthdays: &mut BirthdayList,
+    guild_id: GuildId,
+    user_id: UserId,
+    bot_id: UserId,
+    now: DateTime<Utc>,
+) -> Option<String> {
+    let action = birthdays
+        .guild_configs
+        .get(&guild_id)
+        .map(|config| config.on_member_leave)
+        .unwrap_or_default();
+    let entry = birthdays
+        .entries
+        .iter_mut()
+        .find(|entry| entry.guild_id == guild_id && entry.user_id == user_id)?;
+    let name = format::escape(&entry.name);
+    let message = match action {
+        LeaveAction::Mute => {
+            entry.missing_since.get_or_insert(now);
+            format!(
+                "muted the birthday of {} ({}) who left the server",
+                name, user_id
+            )
+        }
+        LeaveAction::Remove => {
+            soft_delete(birthdays, user_id, guild_id, now);
+            birthdays.reminders.retain(|reminder| {
+                reminder.guild_id != guild_id
+                    || (reminder.target != user_id && reminder.subscriber != user_id)
+            });
+            format!(
+                "removed the birthday of {} ({}) who left the server",
+                name, user_id
+            )
+        }
+    };
+    audit(birthdays, guild_id, bot_id, message.clone());
+    Some(message)
+}
+
+/// Handles a member leaving the guild, see `member_left`
+pub async fn on_member_removal(
+    storage: &Storage,
+    guild_id: GuildId,
+    user_id: UserId,
+    bot_id: UserId,
+) {
+    let result = storage
+        .update(|birthdays| member_left(birthdays, guild_id, user_id, bot_id, Utc::now()))
+        .await;
+    match result {
+        Ok(Some(message)) => info!(guild = %guild_id, "Automatically {}", message),
+        Ok(None) => {}
+        Err(error) => error!(
+            user = %user_id,
+            guild = %guild_id,
+            %error,
+            "Failed to handle a member leaving"
+        ),
+    }
+}
+
+/// Announces a returning member again
+pub async fn on_member_addition(storage: &Storage, guild_id: GuildId, user_id: UserId) {
+    let result = storage
+        .update(|birthdays| {
+            if let Some(entry) = birthdays
+                .entries
+                .iter_mut()
+                .find(|entry| entry.guild_id == guild_id && entry.user_id == user_id)
+            {
+                entry.missing_since = None;
+            }
+        })
+        .await;
+    if let Err(error) = result {
+        error!(
+            user = %user_id,
+            guild = %guild_id,
+            %error,
+            "Failed to handle a member joining"
+        );
+    }
+}
+
+/// Remembers when the bot was removed from the guild, outages don't count
+pub async fn on_guild_delete(storage: &Storage, guild: &serenity::UnavailableGuild) {
+    if guild.unavailable {
+        return;
+    }
+    let result = storage
+        .update(|birthdays| {
+            birthdays.left_guilds.entry(guild.id).or_insert(Utc::now());
+        })
+        .await;
+    match result {
+        Ok(()) => info!(
+            guild = %guild.id,
+            "Removed from the guild, its data is purged in {} days unless the bot is added back",
+            LEFT_GUILD_DAYS
+        ),
+        Err(error) => error!(guild = %guild.id, %error, "Failed to handle the removal"),
+    }
+}
+
+/// Keeps the data of a guild that added the bot back within the grace period
+pub async fn on_guild_create(storage: &Storage, guild_id: GuildId) {
+    let result = storage
+        .update(|birthdays| birthdays.left_guilds.remove(&guild_id))
+        .await;
+    match result {
+        Ok(Some(_)) => info!(guild = %guild_id, "Added back to the guild, its data is kept"),
+        Ok(None) => {}
+        Err(error) => error!(guild = %guild_id, %error, "Failed to handle being added"),
+    }
+}
+
+/// Removes everything stored about the guild, returns how many birthdays it had
+fn purge_guild(birthdays: &mut BirthdayList, guild_id: GuildId) -> usize {
+    let count = birthdays.entries.len();
+    birthdays.entries.retain(|entry| entry.guild_id != guild_id);
+    let count = count - birthdays.entries.len();
+    birthdays
+        .deleted
+        .retain(|deleted| deleted.entry.guild_id != guild_id);
+    birthdays.server_channels.remove(&guild_id);
+    birthdays.guild_configs.remove(&guild_id);
+    birthdays
+        .audit_log
+        .retain(|entry| entry.guild_id != guild_id);
+    birthdays
+        .announced
+        .retain(|announcement| announcement.guild_id != guild_id);
+    birthdays
+        .announced_anniversaries
+        .retain(|announcement| announcement.guild_id != guild_id);
+    birthdays.events.retain(|event| event.guild_id != guild_id);
+    birthdays
+        .announced_events
+        .retain(|announcement| announcement.guild_id != guild_id);
+    birthdays
+        .failed_announcements
+        .retain(|failed| failed.guild_id != guild_id);
+    birthdays.opt_out_skips.remove(&guild_id);
+    birthdays.coverage_history.remove(&guild_id);
+    birthdays
+        .birthday_opt_outs
+        .retain(|(opted_out, _)| *opted_out != guild_id);
+    birthdays
+        .missed
+        .retain(|missed| missed.guild_id != guild_id);
+    birthdays.wishes.retain(|wish| wish.guild_id != guild_id);
+    // The bot can't take the roles away anymore
+    birthdays
+        .birthday_roles
+        .retain(|applied| applied.guild_id != guild_id);
+    birthdays
+        .reminders
+        .retain(|reminder| reminder.guild_id != guild_id);
+    birthdays.usage.guilds.remove(&guild_id);
+    // Calendar events are left for `google_calendar` to delete
+    count
+}
+
+/// Purges the guilds whose grace period is over, returns each with its number of birthdays
+/// and when the bot was removed
+pub fn purge_left_guilds(
+    birthdays: &mut BirthdayList,
+    now: DateTime<Utc>,
+) -> Vec<(GuildId, usize, DateTime<Utc>)> {
+    let expired: Vec<(GuildId, DateTime<Utc>)> = birthdays
+        .left_guilds
+        .iter()
+        .filter(|(_, left_at)| now - **left_at >= chrono::Duration::days(LEFT_GUILD_DAYS))
+        .map(|(guild_id, left_at)| (*guild_id, *left_at))
+        .collect();
+    expired
+        .into_iter()
+        .map(|(guild_id, left_at)| {
+            birthdays.left_guilds.remove(&guild_id);
+            (guild_id, purge_guild(birthdays, guild_id), left_at)
+        })
+        .collect()
+}
+
+/// Removes the birthdays of a guild that is still using the bot along with everything about
+/// them, and its channel and settings too if `settings` is true. Returns how many birthdays
+/// were removed.
+fn purge_birthdays_of(birthdays: &mut BirthdayList, guild_id: GuildId, settings: bool) -> usize {
+    let count = birthdays.entries.len();
+    birthdays.entries.retain(|entry| entry.guild_id != guild_id);
+    let count = count - birthdays.entries.len();
+    birthdays
+        .deleted
+        .retain(|deleted| deleted.entry.guild_id != guild_id);
+    birthdays
+        .announced
+        .retain(|announcement| announcement.guild_id != guild_id);
+    birthdays
+        .failed_announcements
+        .retain(|failed| failed.guild_id != guild_id);
+    birthdays
+        .missed
+        .retain(|missed| missed.guild_id != guild_id);
+    birthdays.wishes.retain(|wish| wish.guild_id != guild_id);
+    birthdays
+        .reminders
+        .retain(|reminder| reminder.guild_id != guild_id);
+    // Birthday roles that were handed out are still taken away, opt-outs stay as well
+    if settings {
+        birthdays.server_channels.remove(&guild_id);
+        birthdays.guild_configs.remove(&guild_id);
+    }
+    count
+}
+
+/// Removes every birthday of this server for good, after asking for confirmation
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn purge_birthdays(
+    ctx: Context<'_>,
+    #[description = "Also reset the announcement channel and all settings"] settings: Option<bool>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let settings = settings.unwrap_or(false);
+    let count = ctx
+        .data()
+        .storage
+        .read()
+        .await
+        .entries
+        .iter()
+        .filter(|entry| entry.guild_id == guild_id)
+        .count();
+    let prompt = format!(
+        "🧨 This removes all {} birthday(s) of this server{} and can't be undone, continue?",
+        count,
+        if settings {
+            " and resets the announcement channel and all settings"
+        } else {
+            ""
+        }
+    );
+    if !confirm(ctx, prompt).await? {
+        return Ok(());
+    }
+
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let removed = purge_birthdays_of(&mut birthdays, guild_id, settings);
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!(
+            "purged {} birthdays{}",
+            removed,
+            if settings { " and the settings" } else { "" }
+        ),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+    ctx.say(format!(
+        "🧨 Removed {} birthday(s) of this server!",
+        removed
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Sets what happens to the birthday of a member who leaves this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_member_leave_action(
+    ctx: Context<'_>,
+    #[description = "What happens to the birthday of a member who leaves"] action: LeaveAction,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .on_member_leave = action;
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!(
+            "set birthdays of members who leave to be {}",
+            action.describe()
+        ),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.say(format!(
+        "🚪🎈 Birthdays of members who leave are {} now!",
+        action.describe()
+    ))
+    .await?;
+    Ok(())
+}
+
+enum Membership {
+    Present,
+    Missing,
+    // Lookup failed for another reason, e.g. a network error or the bot left the guild
+    Unknown,
+}
+
+async fn membership(http: &serenity::Http, guild_id: GuildId, user_id: UserId) -> Membership {
+    match guild_id.member(http, user_id).await {
+        Ok(_) => Membership::Present,
+        Err(serenity::Error::Http(serenity::HttpError::UnsuccessfulRequest(response)))
+            if response.error.code == UNKNOWN_MEMBER =>
+        {
+            Membership::Missing
+        }
+        Err(error) => {
+            warn!(user = %user_id, guild = %guild_id, %error, "Failed to look up the member");
+            Membership::Unknown
+        }
+    }
+}
+
+/// Removes entries of users that haven't been members of their guild for `after_days`
+pub async fn prune_absent_members(
+    http: Arc<serenity::Http>,
+    storage: Storage,
+    bot_id: UserId,
+    after_days: i64,
+) {
+    loop {
+        if let Err(error) = prune_once(&http, &storage, bot_id, after_days).await {
+            error!(%error, "Failed to prune absent members");
+        }
+        tokio::time::sleep(tokio::time::Duration::from_secs(PRUNE_CHECK_TIME)).await;
+    }
+}
+
+async fn prune_once(
+    http: &serenity::Http,
+    storage: &Storage,
+    bot_id: UserId,
+    after_days: i64,
+) -> Result<(), crate::Error> {
+    let members: Vec<(GuildId, UserId)> = storage
+        .read()
+        .await
+        .entries
+        .iter()
+        .map(|entry| (entry.guild_id, entry.user_id))
+        .collect();
+
+    let mut results = Vec::with_capacity(members.len());
+    for batch in members.chunks(BATCH_SIZE) {
+        for (guild_id, user_id) in batch {
+            results.push((
+                *guild_id,
+                *user_id,
+                membership(http, *guild_id, *user_id).await,
+            ));
+        }
+        tokio::time::sleep(tokio::time::Duration::from_secs(BATCH_PAUSE)).await;
+    }
+
+    // The lookups take a while, so apply the results to the latest data
+    let now = Utc::now();
+    storage
+        .update(|birthdays| {
+            let mut expired = Vec::new();
+            for (guild_id, user_id, membership) in &results {
+                let (guild_id, user_id) = (*guild_id, *user_id);
+                let Some(entry) = birthdays
+                    .entries
+                    .iter_mut()
+                    .find(|entry| entry.guild_id == guild_id && entry.user_id == user_id)
+                else {
+                    continue;
+                };
+
+                match membership {
+                    Membership::Present => entry.missing_since = None,
+                    Membership::Missing => {
+                        let missing_since = *entry.missing_since.get_or_insert(now);
+                        if now - missing_since >= chrono::Duration::days(after_days) {
+                            expired.push((guild_id, user_id, entry.name.clone()));
+                        }
+                    }
+                    Membership::Unknown => {}
+                }
+            }
+
+            for (guild_id, user_id, name) in expired {
+                soft_delete(birthdays, user_id, guild_id, now);
+                audit(
+                    birthdays,
+                    guild_id,
+                    bot_id,
+                    format!(
+                        "removed the birthday of {} ({}) who left the server more than {} days ago",
+                        format::escape(&name),
+                        user_id,
+                        after_days
+                    ),
+                );
+            }
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{reminders::Reminder, BirthdayEntry, GuildConfig};
+
+    fn entry(user_id: u64) -> BirthdayEntry {
+        BirthdayEntry {
+            name: "user".to_string(),
+            ..BirthdayEntry::example(user_id, 1)
+        }
+    }
+
+    #[test]
+    fn leaving_members_are_muted_or_removed() {
+        let (guild_id, bot_id, now) = (GuildId::new(1), UserId::new(99), Utc::now());
+        let mut birthdays = BirthdayList {
+            entries: vec![entry(1), entry(2)],
+            reminders: vec![Reminder {
+                guild_id,
+                subscriber: UserId::new(1),
+                target: UserId::new(2),
+                days_before: 7,
+                reminded: None,
+            }],
+            ..Default::default()
+        };
+
+        assert!(member_left(&mut birthdays, guild_id, UserId::new(1), bot_id, now).is_some());
+        assert_eq!(birthdays.entries[0].missing_since, Some(now));
+        assert_eq!(birthdays.reminders.len(), 1);
+        assert!(member_left(&mut birthdays, guild_id, UserId::new(3), bot_id, now).is_none());
+
+        birthdays.guild_configs.insert(
+            guild_id,
+            GuildConfig {
+                on_member_leave: LeaveAction::Remove,
+                ..Default::default()
+            },
+        );
+        assert!(member_left(&mut birthdays, guild_id, UserId::new(2), bot_id, now).is_some());
+        assert_eq!(birthdays.entries.len(), 1);
+        assert_eq!(birthdays.deleted[0].entry.user_id, UserId::new(2));
+        assert!(birthdays.reminders.is_empty());
+        assert_eq!(birthdays.audit_log.len(), 2);
+    }
+
+    #[test]
+    fn left_guilds_are_purged_after_the_grace_period() {
+        let now = Utc::now();
+        let mut other = entry(1);
+        other.guild_id = GuildId::new(2);
+        let mut birthdays = BirthdayList {
+            entries: vec![entry(1), entry(2), other],
+            server_channels: [(GuildId::new(1), serenity::ChannelId::new(3))].into(),
+            left_guilds: [(GuildId::new(1), now - chrono::Duration::days(1))].into(),
+            ..Default::default()
+        };
+        birthdays
+            .guild_configs
+            .insert(GuildId::new(1), GuildConfig::default());
+
+        assert!(purge_left_guilds(&mut birthdays, now).is_empty());
+        assert_eq!(birthdays.entries.len(), 3);
+
+        let later = now + chrono::Duration::days(LEFT_GUILD_DAYS);
+        let purged = purge_left_guilds(&mut birthdays, later);
+        assert_eq!(purged.len(), 1);
+        assert_eq!(purged[0].0, GuildId::new(1));
+        assert_eq!(purged[0].1, 2);
+        assert_eq!(birthdays.entries.len(), 1);
+        assert!(birthdays.server_channels.is_empty());
+        assert!(birthdays.guild_configs.is_empty());
+        assert!(birthdays.left_guilds.is_empty());
+    }
+
+    #[test]
+    fn purging_birthdays_leaves_other_guilds_alone() {
+        let mut other = entry(1);
+        other.guild_id = GuildId::new(2);
+        let mut birthdays = BirthdayList {
+            entries: vec![entry(1), other, entry(2)],
+            server_channels: [
+                (GuildId::new(1), serenity::ChannelId::new(3)),
+                (GuildId::new(2), serenity::ChannelId::new(4)),
+            ]
+            .into(),
+            reminders: vec![Reminder {
+                guild_id: GuildId::new(2),
+                subscriber: UserId::new(2),
+                target: UserId::new(1),
+                days_before: 7,
+                reminded: None,
+            }],
+            ..Default::default()
+        };
+        for guild_id in [GuildId::new(1), GuildId::new(2)] {
+            birthdays
+                .guild_configs
+                .insert(guild_id, GuildConfig::default());
+        }
+
+        assert_eq!(
+            purge_birthdays_of(&mut birthdays, GuildId::new(1), false),
+            2
+        );
+        assert_eq!(birthdays.entries.len(), 1);
+        assert_eq!(birthdays.server_channels.len(), 2);
+        assert_eq!(birthdays.guild_configs.len(), 2);
+
+        assert_eq!(purge_birthdays_of(&mut birthdays, GuildId::new(1), true), 0);
+        assert_eq!(birthdays.entries[0].guild_id, GuildId::new(2));
+        assert_eq!(birthdays.reminders.len(), 1);
+        assert!(birthdays.server_channels.contains_key(&GuildId::new(2)));
+        assert!(!birthdays.server_channels.contains_key(&GuildId::new(1)));
+        assert!(birthdays.guild_configs.contains_key(&GuildId::new(2)));
+        assert_eq!(birthdays.guild_configs.len(), 1);
+    }
+}
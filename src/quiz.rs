@@ -0,0 +1,242 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    hash::BuildHasher,
+    time::Duration,
+};
+
+use chrono::Datelike;
+use poise::futures_util::StreamExt;
+use poise::serenity_prelude::{
+    self as serenity, ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateButton,
+    CreateInteractionResponse, CreateInteractionResponseMessage, EditMessage, UserId,
+};
+use poise::CreateReply;
+
+use crate::{format, BirthdayEntry, Context, Error, Visibility};
+
+static ROUND_TIME: u64 = 20; // seconds
+static DEFAULT_ROUNDS: u32 = 5;
+static MAX_ROUNDS: u32 = 10;
+static CHOICES: usize = 4;
+
+/// A random number below `bound`, quizzes and announcement messages don't need anything better
+pub fn random_below(bound: usize) -> usize {
+    (RandomState::new().hash_one(std::time::SystemTime::now()) % bound as u64) as usize
+}
+
+fn shuffle<T>(items: &mut [T]) {
+    for i in (1..items.len()).rev() {
+        items.swap(i, random_below(i + 1));
+    }
+}
+
+/// A round's answer and the entries offered as choices, the answer among them. Entries that
+/// share the answer's date are never offered, there would be more than one right choice.
+fn round<'a>(
+    entries: &[&'a BirthdayEntry],
+    used: &HashSet<UserId>,
+) -> Option<(&'a BirthdayEntry, Vec<&'a BirthdayEntry>)> {
+    let unused: Vec<_> = entries
+        .iter()
+        .filter(|entry| !used.contains(&entry.user_id))
+        .collect();
+    let answer = **unused.get(random_below(unused.len().max(1)))?;
+
+    let same_day = |entry: &BirthdayEntry| {
+        entry.date.day() == answer.date.day() && entry.date.month() == answer.date.month()
+    };
+    let mut decoys: Vec<_> = entries
+        .iter()
+        .copied()
+        .filter(|entry| !same_day(entry))
+        .collect();
+    if decoys.len() < CHOICES - 1 {
+        return None;
+    }
+    shuffle(&mut decoys);
+    let mut choices = decoys;
+    choices.truncate(CHOICES - 1);
+    choices.push(answer);
+    shuffle(&mut choices);
+    Some((answer, choices))
+}
+
+/// Starts a quiz in which everyone guesses whose birthday is on the shown date
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn birthday_quiz(
+    ctx: Context<'_>,
+    #[description = "Number of rounds (defaults to 5, at most 10)"] rounds: Option<u32>,
+) -> Result<(), Error> {
+    let rounds = rounds.unwrap_or(DEFAULT_ROUNDS).clamp(1, MAX_ROUNDS);
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    let format = birthdays.date_format(guild_id);
+    // Only entries everyone may look up take part
+    let entries: Vec<&BirthdayEntry> = birthdays
+        .entries
+        .iter()
+        .filter(|entry| entry.guild_id == guild_id && entry.visibility == Visibility::Public)
+        .collect();
+    if entries.len() < CHOICES {
+        ctx.say(format!(
+            "🐺🎩❌ A quiz needs at least {} public birthdays in this server!",
+            CHOICES
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    ctx.say(format!(
+        "🧩🎈 Birthday quiz with {} round(s)! Guess whose birthday is on the shown date, you have {} seconds per round.",
+        rounds, ROUND_TIME
+    ))
+    .await?;
+
+    let mut scores: HashMap<UserId, u32> = HashMap::new();
+    let mut used = HashSet::new();
+    let mut played = 0;
+    for number in 1..=rounds {
+        let Some((answer, choices)) = round(&entries, &used) else {
+            break;
+        };
+        used.insert(answer.user_id);
+        played += 1;
+
+        let prefix = format!("{}:{}", ctx.id(), number);
+        let buttons = choices
+            .iter()
+            .map(|choice| {
+                CreateButton::new(format!("{}:{}", prefix, choice.user_id))
+                    .label(choice.name.clone())
+                    .style(ButtonStyle::Primary)
+            })
+            .collect();
+        let question = format!(
+            "🧩 Round {}/{}: Whose birthday is on {}?",
+            number,
+            rounds,
+            format.format(answer.date.day(), answer.date.month(), None)
+        );
+        let reply = ctx
+            .send(
+                CreateReply::default()
+                    .content(question.clone())
+                    .components(vec![CreateActionRow::Buttons(buttons)]),
+            )
+            .await?;
+        let mut message = reply.into_message().await?;
+
+        // Everyone gets one guess per round
+        let mut guesses: HashMap<UserId, UserId> = HashMap::new();
+        let mut interactions = ComponentInteractionCollector::new(ctx.serenity_context())
+            .message_id(message.id)
+            .timeout(Duration::from_secs(ROUND_TIME))
+            .stream();
+        while let Some(interaction) = interactions.next().await {
+            let guess = interaction
+                .data
+                .custom_id
+                .strip_prefix(&format!("{}:", prefix))
+                .and_then(|guess| guess.parse::<u64>().ok());
+            let content = match guess {
+                Some(_) if guesses.contains_key(&interaction.user.id) => {
+                    "☝️ You already guessed this round!"
+                }
+                Some(guess) => {
+                    guesses.insert(interaction.user.id, UserId::new(guess));
+                    "✍️ Your guess is locked in!"
+                }
+                None => continue,
+            };
+            interaction
+                .create_response(
+                    ctx,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content(content)
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+        }
+
+        let winners: Vec<UserId> = guesses
+            .into_iter()
+            .filter(|(_, guess)| *guess == answer.user_id)
+            .map(|(user, _)| user)
+            .collect();
+        for winner in &winners {
+            *scores.entry(*winner).or_default() += 1;
+        }
+        message
+            .edit(
+                ctx,
+                EditMessage::new()
+                    .content(format!(
+                        "{}\n✅ It was {}! {} guessed right.",
+                        question,
+                        format::escape(&answer.name),
+                        winners.len()
+                    ))
+                    .components(Vec::new()),
+            )
+            .await?;
+    }
+
+    let mut scores: Vec<(UserId, u32)> = scores.into_iter().collect();
+    scores.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    let scoreboard = if scores.is_empty() {
+        "Nobody guessed right, better luck next time!".to_string()
+    } else {
+        scores
+            .iter()
+            .map(|(user, score)| format!("- <@{}>: {}/{}", user, score, played))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    ctx.send(
+        CreateReply::default()
+            .content(format!("🏆🎈 Quiz over!\n{}", scoreboard))
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn entry(user_id: u64, day: u32) -> BirthdayEntry {
+        BirthdayEntry {
+            date: NaiveDate::from_ymd_opt(1995, 6, day).unwrap(),
+            ..BirthdayEntry::example(user_id, 1)
+        }
+    }
+
+    #[test]
+    fn rounds_offer_one_right_choice_and_skip_used_entries() {
+        let entries = [
+            entry(1, 1),
+            entry(2, 2),
+            entry(3, 3),
+            entry(4, 4),
+            entry(5, 4),
+        ];
+        let entries: Vec<&BirthdayEntry> = entries.iter().collect();
+        let mut used = HashSet::new();
+        // The two entries on the 4th are never offered together
+        while let Some((answer, choices)) = round(&entries, &used) {
+            assert!(used.insert(answer.user_id));
+            assert_eq!(choices.len(), CHOICES);
+            let right = choices
+                .iter()
+                .filter(|choice| choice.date == answer.date)
+                .count();
+            assert_eq!(right, 1);
+        }
+        assert_eq!(used.len(), entries.len());
+    }
+}
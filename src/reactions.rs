@@ -0,0 +1,218 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use poise::serenity_prelude::{self as serenity, GuildId, UserId};
+use serde::{Deserialize, Serialize};
+
+use crate::{audit, dates, wishes, BirthdayEntry, Context, Data, Error, LeapDay, Toggle};
+
+static DEFAULT_PHRASES: [&str; 3] = ["happy birthday", "happy bday", "hbd"];
+static REACTION: char = '🎉';
+// At most this many reactions per guild within RATE_WINDOW
+static RATE_LIMIT: usize = 10;
+static RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Reacting to birthday wishes in the announcement channel, None in the config if turned off
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReactionConfig {
+    // Lowercase phrases that count as a wish, empty for DEFAULT_PHRASES
+    pub phrases: Vec<String>,
+}
+
+impl ReactionConfig {
+    fn matches(&self, content: &str) -> bool {
+        let content = content.to_lowercase();
+        if self.phrases.is_empty() {
+            DEFAULT_PHRASES
+                .iter()
+                .any(|phrase| content.contains(phrase))
+        } else {
+            self.phrases.iter().any(|phrase| content.contains(phrase))
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        if self.phrases.is_empty() {
+            DEFAULT_PHRASES.join(", ")
+        } else {
+            self.phrases.join(", ")
+        }
+    }
+}
+
+/// Counts the reactions of every guild, so a busy channel can't use up Discord's rate limits
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    reactions: HashMap<GuildId, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    /// Whether the guild may get another reaction at `now`, counts it if so
+    fn allow(&mut self, guild_id: GuildId, now: Instant) -> bool {
+        let recent = self.reactions.entry(guild_id).or_default();
+        while recent
+            .front()
+            .is_some_and(|reacted| now.duration_since(*reacted) >= RATE_WINDOW)
+        {
+            recent.pop_front();
+        }
+        if recent.len() >= RATE_LIMIT {
+            return false;
+        }
+        recent.push_back(now);
+        true
+    }
+}
+
+/// The member's local date if it is their birthday in their own time zone
+fn birthday_today(
+    entry: &BirthdayEntry,
+    now: DateTime<Utc>,
+    leap_day: LeapDay,
+) -> Option<NaiveDate> {
+    dates::is_birthday_now(entry, now, leap_day).then(|| dates::local_date(entry, now))
+}
+
+/// Adds a 🎉 to messages in the announcement channel that wish a celebrant a happy birthday,
+/// by mentioning them or replying to them, and counts the wish for the leaderboard
+pub async fn react_to_wishes(
+    ctx: &serenity::Context,
+    message: &serenity::Message,
+    data: &Data,
+) -> Result<(), Error> {
+    let Some(guild_id) = message.guild_id else {
+        return Ok(());
+    };
+    let mut wished: Vec<UserId> = message.mentions.iter().map(|user| user.id).collect();
+    if let Some(replied) = &message.referenced_message {
+        wished.push(replied.author.id);
+    }
+    // Most messages are ruled out before the data has to be read
+    if !data.message_content || message.author.bot || wished.is_empty() {
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let celebrants: Vec<(UserId, NaiveDate)> = data
+        .storage
+        .read_with(|birthdays| {
+            let Some(config) = birthdays
+                .guild_configs
+                .get(&guild_id)
+                .and_then(|config| config.reactions.as_ref())
+            else {
+                return Vec::new();
+            };
+            if birthdays.server_channels.get(&guild_id) != Some(&message.channel_id)
+                || !config.matches(&message.content)
+            {
+                return Vec::new();
+            }
+            birthdays
+                .entries
+                .iter()
+                .filter(|entry| entry.guild_id == guild_id && wished.contains(&entry.user_id))
+                .filter_map(|entry| {
+                    let today = birthday_today(entry, now, birthdays.leap_day(guild_id))?;
+                    Some((entry.user_id, today))
+                })
+                .collect()
+        })
+        .await;
+    if celebrants.is_empty() {
+        return Ok(());
+    }
+    if data.reactions.lock().await.allow(guild_id, Instant::now()) {
+        message.react(ctx, REACTION).await?;
+    }
+    wishes::record(&data.storage, guild_id, message.author.id, celebrants).await
+}
+
+/// Reacts with 🎉 to birthday wishes for the members celebrated today
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_birthday_reactions(
+    ctx: Context<'_>,
+    #[description = "Whether to react to birthday wishes"] state: Toggle,
+    #[description = "Comma separated phrases that count as a wish (defaults to happy birthday, happy bday, hbd)"]
+    phrases: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let config = birthdays.guild_configs.entry(guild_id).or_default();
+    config.reactions = match state {
+        Toggle::On => {
+            let mut reactions = config.reactions.take().unwrap_or_default();
+            if let Some(phrases) = phrases {
+                reactions.phrases = phrases
+                    .split(',')
+                    .map(|phrase| phrase.trim().to_lowercase())
+                    .filter(|phrase| !phrase.is_empty())
+                    .collect();
+            }
+            Some(reactions)
+        }
+        Toggle::Off => None,
+    };
+    let message = match &config.reactions {
+        Some(reactions) => format!(
+            "🎉🎈 Birthday wishes in the announcement channel now get a reaction! Phrases: {}",
+            reactions.describe()
+        ),
+        None => "🎉 Birthday wishes no longer get a reaction!".to_string(),
+    };
+    let turned_off = config.reactions.is_none();
+    let state = if turned_off { "off" } else { "on" };
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("turned {} reactions to birthday wishes", state),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    if ctx.data().message_content || turned_off {
+        ctx.say(message).await?;
+    } else {
+        ctx.say(format!(
+            "{}\n⚠️ This bot runs without the message content intent, so it can't read the wishes until the owner turns it on.",
+            message
+        ))
+        .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reactions_are_limited_per_guild() {
+        let mut limiter = RateLimiter::default();
+        let start = Instant::now();
+        for _ in 0..RATE_LIMIT {
+            assert!(limiter.allow(GuildId::new(1), start));
+        }
+        assert!(!limiter.allow(GuildId::new(1), start));
+        assert!(limiter.allow(GuildId::new(2), start));
+        assert!(limiter.allow(GuildId::new(1), start + RATE_WINDOW));
+    }
+
+    #[test]
+    fn custom_phrases_replace_the_defaults() {
+        assert!(ReactionConfig::default().matches("HBD <@1>!"));
+        let custom = ReactionConfig {
+            phrases: vec!["alles gute".to_string()],
+        };
+        assert!(custom.matches("Alles Gute zum Geburtstag"));
+        assert!(!custom.matches("happy birthday"));
+    }
+}
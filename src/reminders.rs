@@ -0,0 +1,317 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use poise::serenity_prelude::{self as serenity, GuildId, UserId};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    date_to_discord_timestamp, dates, format, get_visible_birthday, quiet_message,
+    retry::FailureKind, storage::Storage, BirthdayEntry, BirthdayList, Context, Error,
+};
+
+// Reminders can be set at most this long before the birthday
+static MAX_DAYS_BEFORE: u32 = 60;
+// Keeps a single member from filling the data with reminders
+static MAX_REMINDERS: usize = 25;
+
+/// A member who wants a DM some days before another member's birthday
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reminder {
+    pub guild_id: GuildId,
+    pub subscriber: UserId,
+    pub target: UserId,
+    pub days_before: u32,
+    // Occurrence of the birthday the subscriber was last reminded of
+    pub reminded: Option<NaiveDate>,
+}
+
+/// Reminders due at `now` with the target's entry and the birthday they remind of. The day is
+/// the one in the target's time zone, like the announcement.
+fn due(
+    birthdays: &BirthdayList,
+    now: DateTime<Utc>,
+) -> Vec<(&Reminder, &BirthdayEntry, NaiveDate)> {
+    birthdays
+        .reminders
+        .iter()
+        .filter_map(|reminder| {
+            let entry = birthdays.entries.iter().find(|entry| {
+                entry.guild_id == reminder.guild_id && entry.user_id == reminder.target
+            })?;
+            let today = dates::local_date(entry, now);
+            let remind_on = today + chrono::Duration::days(reminder.days_before as i64);
+            let leap_day = birthdays.leap_day(entry.guild_id);
+            (dates::is_birthday_on(entry.date, remind_on, leap_day)
+                && reminder.reminded != Some(remind_on))
+            .then_some((reminder, entry, remind_on))
+        })
+        .collect()
+}
+
+/// The reminder of the entry's birthday on `occurrence`, with the gift note for organizers
+fn message(
+    birthdays: &BirthdayList,
+    entry: &BirthdayEntry,
+    occurrence: NaiveDate,
+    gift_note: Option<&str>,
+) -> String {
+    let mut message = format!(
+        "⏰🎈 {}'s birthday is {} ({}), time to get a gift!",
+        format::escape(&entry.name),
+        date_to_discord_timestamp(occurrence, entry.utc_offset, true),
+        birthdays
+            .date_format(entry.guild_id)
+            .format(occurrence.day(), occurrence.month(), None)
+    );
+    if let Some(note) = gift_note {
+        message.push_str(&format!("\n🎁 Gift note: {}", note));
+    }
+    message
+}
+
+/// Whether the subscriber has the organizer role of the guild, who gets to see gift notes
+async fn is_organizer(
+    http: &serenity::Http,
+    birthdays: &BirthdayList,
+    reminder: &Reminder,
+) -> bool {
+    let role = birthdays
+        .guild_configs
+        .get(&reminder.guild_id)
+        .and_then(|config| config.organizer_role);
+    let Some(role) = role else {
+        return false;
+    };
+    reminder
+        .guild_id
+        .member(http, reminder.subscriber)
+        .await
+        .is_ok_and(|member| member.roles.contains(&role))
+}
+
+/// DMs the subscribers whose reminders are due, organizers get the gift note as well. Reminders
+/// of members who don't accept DMs are dropped, other failures are retried on the next check.
+pub async fn send_due(http: &serenity::Http, storage: &Storage) -> Result<(), Error> {
+    let birthdays = storage.read().await;
+    let mut sent = Vec::new();
+    let mut closed = Vec::new();
+    for (reminder, entry, occurrence) in due(&birthdays, Utc::now()) {
+        let gift_note = match &entry.gift_note {
+            Some(note) if is_organizer(http, &birthdays, reminder).await => Some(note.as_str()),
+            _ => None,
+        };
+        let message = message(&birthdays, entry, occurrence, gift_note);
+        let result = match reminder.subscriber.create_dm_channel(http).await {
+            Ok(channel) => channel
+                .send_message(http, quiet_message(message))
+                .await
+                .map(|_| ()),
+            Err(error) => Err(error),
+        };
+        match result {
+            Ok(()) => sent.push((reminder.clone(), occurrence)),
+            Err(error) => {
+                warn!(
+                    subscriber = %reminder.subscriber,
+                    target = %reminder.target,
+                    guild = %reminder.guild_id,
+                    %error,
+                    "Failed to send a birthday reminder"
+                );
+                if FailureKind::of(&error) == FailureKind::MissingPermissions {
+                    closed.push(reminder.clone());
+                }
+            }
+        }
+    }
+    if sent.is_empty() && closed.is_empty() {
+        return Ok(());
+    }
+    storage
+        .update(|birthdays| {
+            birthdays
+                .reminders
+                .retain(|reminder| !closed.contains(reminder));
+            for reminder in &mut birthdays.reminders {
+                if let Some((_, occurrence)) = sent.iter().find(|(sent, _)| {
+                    sent.guild_id == reminder.guild_id
+                        && sent.subscriber == reminder.subscriber
+                        && sent.target == reminder.target
+                }) {
+                    reminder.reminded = Some(*occurrence);
+                }
+            }
+        })
+        .await
+}
+
+/// Sends you a DM some days before a member's birthday
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn remind_me(
+    ctx: Context<'_>,
+    #[description = "Member whose birthday you want to be reminded of"] user: serenity::User,
+    #[description = "Days before the birthday (at most 60)"] days_before: u32,
+) -> Result<(), Error> {
+    if days_before > MAX_DAYS_BEFORE {
+        ctx.say(format!(
+            "🐺🎩❌ Reminders can be at most {} days before the birthday!",
+            MAX_DAYS_BEFORE
+        ))
+        .await?;
+        return Ok(());
+    }
+    if get_visible_birthday(ctx, user.id).await?.is_none() {
+        ctx.say("☹️🎈 No birthday set for this user for this guild!")
+            .await?;
+        return Ok(());
+    }
+
+    let guild_id = ctx.guild_id().unwrap();
+    let subscriber = ctx.author().id;
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .reminders
+        .retain(|reminder| !matches(reminder, guild_id, subscriber, user.id));
+    let count = birthdays
+        .reminders
+        .iter()
+        .filter(|reminder| reminder.subscriber == subscriber)
+        .count();
+    if count >= MAX_REMINDERS {
+        ctx.say(format!(
+            "🐺🎩❌ You can have at most {} reminders, remove one with `unremind_me` first!",
+            MAX_REMINDERS
+        ))
+        .await?;
+        return Ok(());
+    }
+    birthdays.reminders.push(Reminder {
+        guild_id,
+        subscriber,
+        target: user.id,
+        days_before,
+        reminded: None,
+    });
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!(
+                "⏰🎈 I'll DM you {} day(s) before {}'s birthday, make sure your DMs are open!",
+                days_before,
+                format::escape(&user.name)
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Stops reminding you of a member's birthday
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn unremind_me(
+    ctx: Context<'_>,
+    #[description = "Member you no longer want to be reminded of"] user: serenity::User,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let before = birthdays.reminders.len();
+    birthdays
+        .reminders
+        .retain(|reminder| !matches(reminder, guild_id, ctx.author().id, user.id));
+    if birthdays.reminders.len() == before {
+        ctx.say("☹️🎈 You have no reminder for this user in this guild!")
+            .await?;
+        return Ok(());
+    }
+    ctx.data().storage.write(&birthdays).await?;
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!(
+                "🔕🎈 You won't be reminded of {}'s birthday anymore!",
+                format::escape(&user.name)
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Lists your birthday reminders in this server
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn my_reminders(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    let lines: Vec<String> = birthdays
+        .reminders
+        .iter()
+        .filter(|reminder| reminder.guild_id == guild_id && reminder.subscriber == ctx.author().id)
+        .map(|reminder| {
+            format!(
+                "- <@{}>: {} day(s) before",
+                reminder.target, reminder.days_before
+            )
+        })
+        .collect();
+    let content = if lines.is_empty() {
+        "☹️🎈 You have no birthday reminders in this guild!".to_string()
+    } else {
+        format!("⏰🎈 Your birthday reminders:\n{}", lines.join("\n"))
+    };
+    ctx.send(
+        poise::CreateReply::default()
+            .content(content)
+            .ephemeral(true)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+fn matches(reminder: &Reminder, guild_id: GuildId, subscriber: UserId, target: UserId) -> bool {
+    reminder.guild_id == guild_id && reminder.subscriber == subscriber && reminder.target == target
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offset::UtcOffset;
+
+    #[test]
+    fn reminders_are_due_once_in_the_targets_time_zone() {
+        let entry = BirthdayEntry {
+            name: "user".to_string(),
+            date: NaiveDate::from_ymd_opt(1995, 1, 3).unwrap(),
+            utc_offset: UtcOffset::from_hours(5),
+            ..BirthdayEntry::example(2, 1)
+        };
+        let mut birthdays = BirthdayList {
+            entries: vec![entry],
+            reminders: vec![Reminder {
+                guild_id: GuildId::new(1),
+                subscriber: UserId::new(1),
+                target: UserId::new(2),
+                days_before: 7,
+                reminded: None,
+            }],
+            ..Default::default()
+        };
+        let at = |text: &str| DateTime::parse_from_rfc3339(text).unwrap().to_utc();
+
+        // December 27th starts at 19:00 UTC the day before in UTC+5
+        assert!(due(&birthdays, at("2024-12-26T18:00:00Z")).is_empty());
+        let found = due(&birthdays, at("2024-12-26T19:00:00Z"));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].2, NaiveDate::from_ymd_opt(2025, 1, 3).unwrap());
+
+        birthdays.reminders[0].reminded = Some(found[0].2);
+        assert!(due(&birthdays, at("2024-12-26T20:00:00Z")).is_empty());
+
+        let occurrence = NaiveDate::from_ymd_opt(2025, 1, 3).unwrap();
+        let entry = &birthdays.entries[0];
+        assert!(message(&birthdays, entry, occurrence, None).ends_with("time to get a gift!"));
+        assert!(
+            message(&birthdays, entry, occurrence, Some("fountain pens"))
+                .ends_with("time to get a gift!\n🎁 Gift note: fountain pens")
+        );
+    }
+}
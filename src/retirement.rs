@@ -0,0 +1,175 @@
+use chrono::{Datelike, NaiveDate, Utc};
+use poise::serenity_prelude as serenity;
+
+use crate::{
+    audit, birth_year_or_refuse, date_to_discord_timestamp, dates::birthday_in_year, format,
+    get_visible_birthday, Context, Error, GuildConfig, LeapDay,
+};
+
+pub static DEFAULT_AGE: i32 = 67;
+static AGE_RANGE: std::ops::RangeInclusive<i32> = 50..=100;
+
+pub fn age(config: Option<&GuildConfig>) -> i32 {
+    config
+        .and_then(|config| config.retirement_age)
+        .unwrap_or(DEFAULT_AGE)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Retirement {
+    Upcoming(NaiveDate),
+    Today,
+    // Number of years since retiring
+    Since(i32),
+}
+
+/// When someone born on `birthday` reaches `age`, seen from `today`
+fn retirement_on(birthday: NaiveDate, age: i32, today: NaiveDate, leap_day: LeapDay) -> Retirement {
+    let date = birthday_in_year(birthday, birthday.year() + age, leap_day);
+    if date > today {
+        Retirement::Upcoming(date)
+    } else if date == today {
+        Retirement::Today
+    } else {
+        let mut years = today.year() - date.year();
+        if birthday_in_year(birthday, today.year(), leap_day) > today {
+            years -= 1;
+        }
+        Retirement::Since(years)
+    }
+}
+
+/// Calculates when you or another user retire
+#[poise::command(slash_command, prefix_command)]
+pub async fn retirement(
+    ctx: Context<'_>,
+    #[description = "User to calculate the retirement for (defaults to yourself)"] user: Option<
+        serenity::User,
+    >,
+) -> Result<(), Error> {
+    let user = user.unwrap_or_else(|| ctx.author().clone());
+    let Some(entry) = get_visible_birthday(ctx, user.id).await? else {
+        ctx.say("☹️🎈 No birthday set for this user for this guild!")
+            .await?;
+        return Ok(());
+    };
+    if birth_year_or_refuse(ctx, &entry, "retirement")
+        .await?
+        .is_none()
+    {
+        return Ok(());
+    }
+
+    let birthdays = ctx.data().storage.read().await;
+    let age = age(birthdays.guild_configs.get(&entry.guild_id));
+    let today = Utc::now().date_naive();
+    let name = format::escape(&entry.name);
+    let leap_day = birthdays.leap_day(entry.guild_id);
+    let message = match retirement_on(entry.date, age, today, leap_day) {
+        Retirement::Upcoming(date) => format!(
+            "🏖️ {} retires at {} {}",
+            name,
+            age,
+            date_to_discord_timestamp(date, entry.utc_offset, true)
+        ),
+        Retirement::Today => format!("🏖️🎉 {} retires today, congratulations!", name),
+        Retirement::Since(0) => format!("🏖️🎉 {} retired this year, congratulations!", name),
+        Retirement::Since(years) => format!(
+            "🏖️🎉 {} has been retired for {} year(s) already, congratulations!",
+            name, years
+        ),
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Sets the age `retirement` counts down to in this server, leave it empty for the default
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_retirement_age(
+    ctx: Context<'_>,
+    #[description = "Retirement age (defaults to 67)"] age: Option<i32>,
+) -> Result<(), Error> {
+    if age.is_some_and(|age| !AGE_RANGE.contains(&age)) {
+        ctx.say(format!(
+            "🐺🎩❌ The retirement age must be between {} and {}!",
+            AGE_RANGE.start(),
+            AGE_RANGE.end()
+        ))
+        .await?;
+        return Ok(());
+    }
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .retirement_age = age;
+    let age = age.unwrap_or(DEFAULT_AGE);
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("set the retirement age to {}", age),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+    ctx.say(format!("🏖️🎈 The retirement age is now {}!", age))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn retirement_relative_to_today() {
+        let birthday = date(1960, 6, 14);
+        assert_eq!(
+            retirement_on(birthday, 67, date(2024, 1, 1), LeapDay::February28),
+            Retirement::Upcoming(date(2027, 6, 14))
+        );
+        assert_eq!(
+            retirement_on(birthday, 67, date(2027, 6, 14), LeapDay::February28),
+            Retirement::Today
+        );
+        assert_eq!(
+            retirement_on(birthday, 67, date(2027, 6, 15), LeapDay::February28),
+            Retirement::Since(0)
+        );
+        assert_eq!(
+            retirement_on(birthday, 60, date(2024, 6, 13), LeapDay::February28),
+            Retirement::Since(3)
+        );
+        assert_eq!(
+            retirement_on(birthday, 60, date(2024, 6, 14), LeapDay::February28),
+            Retirement::Since(4)
+        );
+    }
+
+    #[test]
+    fn leap_day_birthdays_retire_on_the_guilds_leap_day() {
+        assert_eq!(
+            retirement_on(
+                date(1960, 2, 29),
+                67,
+                date(2027, 2, 28),
+                LeapDay::February28
+            ),
+            Retirement::Today
+        );
+        assert_eq!(
+            retirement_on(date(1960, 2, 29), 67, date(2027, 2, 28), LeapDay::March1),
+            Retirement::Upcoming(date(2027, 3, 1))
+        );
+    }
+}
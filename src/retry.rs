@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use poise::serenity_prelude::{self as serenity, GuildId, HttpError, UserId};
+use serde::{Deserialize, Serialize};
+
+// Failed announcements are retried on this many checks before they are given up
+static MAX_ATTEMPTS: u32 = 5;
+// Announcements stop going to a channel that failed on this many checks in a row
+pub static MAX_CHANNEL_FAILURES: u32 = 3;
+
+/// Why sending an announcement failed, coarse enough to tell admins what to fix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FailureKind {
+    MissingChannel,
+    MissingPermissions,
+    Network,
+    Other,
+}
+
+impl FailureKind {
+    pub fn of(error: &serenity::Error) -> FailureKind {
+        match error {
+            serenity::Error::Http(HttpError::Request(_)) => FailureKind::Network,
+            serenity::Error::Http(error) => match error.status_code().map(|code| code.as_u16()) {
+                Some(404) => FailureKind::MissingChannel,
+                Some(403) => FailureKind::MissingPermissions,
+                Some(code) if code >= 500 => FailureKind::Network,
+                _ => FailureKind::Other,
+            },
+            _ => FailureKind::Other,
+        }
+    }
+
+    /// Whether an admin has to fix the channel before sending there can work again
+    pub fn is_channel_fault(self) -> bool {
+        matches!(
+            self,
+            FailureKind::MissingChannel | FailureKind::MissingPermissions
+        )
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            FailureKind::MissingChannel => "the channel is gone",
+            FailureKind::MissingPermissions => "missing permissions",
+            FailureKind::Network => "Discord couldn't be reached",
+            FailureKind::Other => "unknown error",
+        }
+    }
+}
+
+/// An announcement that couldn't be sent, it stays announced so only the retries send it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedAnnouncement {
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    pub occurrence: NaiveDate,
+    pub attempts: u32,
+    pub kind: FailureKind,
+}
+
+impl FailedAnnouncement {
+    pub fn exhausted(&self) -> bool {
+        self.attempts >= MAX_ATTEMPTS
+    }
+}
+
+/// Applies the outcome of sending an announcement. A success clears its record, a failure
+/// counts an attempt. Returns the record if the announcement just ran out of attempts.
+pub fn record(
+    failed: &mut Vec<FailedAnnouncement>,
+    guild_id: GuildId,
+    user_id: UserId,
+    occurrence: NaiveDate,
+    result: Result<(), FailureKind>,
+) -> Option<FailedAnnouncement> {
+    let position = failed.iter().position(|failed| {
+        failed.guild_id == guild_id && failed.user_id == user_id && failed.occurrence == occurrence
+    });
+    let kind = match (result, position) {
+        (Ok(()), Some(position)) => {
+            failed.remove(position);
+            return None;
+        }
+        (Ok(()), None) => return None,
+        (Err(kind), _) => kind,
+    };
+
+    let record = match position {
+        Some(position) => &mut failed[position],
+        None => {
+            failed.push(FailedAnnouncement {
+                guild_id,
+                user_id,
+                occurrence,
+                attempts: 0,
+                kind,
+            });
+            failed.last_mut().unwrap()
+        }
+    };
+    record.attempts += 1;
+    record.kind = kind;
+    record.exhausted().then(|| record.clone())
+}
+
+/// How sending to each guild's channel went on a check, one outcome per guild so a burst of
+/// birthdays can't break a channel on a single check. Any success means the channel works, a
+/// failure that is the channel's fault wins over other failures.
+pub fn channel_outcomes(
+    deliveries: &[(GuildId, Result<(), FailureKind>)],
+) -> BTreeMap<GuildId, Result<(), FailureKind>> {
+    let mut outcomes = BTreeMap::new();
+    for (guild_id, result) in deliveries {
+        let outcome = outcomes.entry(*guild_id).or_insert(*result);
+        if result.is_ok() || (outcome.is_err() && result.is_err_and(FailureKind::is_channel_fault))
+        {
+            *outcome = *result;
+        }
+    }
+    outcomes
+}
+
+/// Counts the checks in a row that failed because of the channel, a success starts over.
+/// Returns true if the channel just reached MAX_CHANNEL_FAILURES.
+pub fn count_channel_failure(failures: &mut u32, result: Result<(), FailureKind>) -> bool {
+    match result {
+        Ok(()) => {
+            *failures = 0;
+            false
+        }
+        Err(kind) if kind.is_channel_fault() => {
+            *failures += 1;
+            *failures == MAX_CHANNEL_FAILURES
+        }
+        // Discord being down says nothing about the channel
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_attempt(
+        failed: &mut Vec<FailedAnnouncement>,
+        result: Result<(), FailureKind>,
+    ) -> Option<FailedAnnouncement> {
+        record(
+            failed,
+            GuildId::new(1),
+            UserId::new(2),
+            NaiveDate::from_ymd_opt(2024, 6, 14).unwrap(),
+            result,
+        )
+    }
+
+    #[test]
+    fn failures_are_counted_until_they_are_given_up() {
+        let mut failed = Vec::new();
+        for _ in 1..MAX_ATTEMPTS {
+            assert_eq!(record_attempt(&mut failed, Err(FailureKind::Network)), None);
+        }
+        let given_up = record_attempt(&mut failed, Err(FailureKind::MissingPermissions)).unwrap();
+        assert_eq!(given_up.kind, FailureKind::MissingPermissions);
+        assert_eq!(failed.len(), 1);
+        assert!(failed[0].exhausted());
+    }
+
+    #[test]
+    fn a_successful_retry_clears_the_record() {
+        let mut failed = Vec::new();
+        record_attempt(&mut failed, Err(FailureKind::Network));
+        record_attempt(&mut failed, Ok(()));
+        assert!(failed.is_empty());
+    }
+
+    #[test]
+    fn each_guild_gets_one_outcome_per_check() {
+        let (first, second) = (GuildId::new(1), GuildId::new(2));
+        let outcomes = channel_outcomes(&[
+            (first, Err(FailureKind::MissingChannel)),
+            (first, Ok(())),
+            (first, Err(FailureKind::MissingChannel)),
+            (second, Err(FailureKind::Network)),
+            (second, Err(FailureKind::MissingPermissions)),
+            (second, Err(FailureKind::Other)),
+        ]);
+        assert_eq!(
+            outcomes,
+            [
+                (first, Ok(())),
+                (second, Err(FailureKind::MissingPermissions))
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn channels_break_after_failures_in_a_row() {
+        let mut failures = 0;
+        assert!(!count_channel_failure(
+            &mut failures,
+            Err(FailureKind::MissingPermissions)
+        ));
+        assert!(!count_channel_failure(&mut failures, Ok(())));
+        assert_eq!(failures, 0);
+        for _ in 1..MAX_CHANNEL_FAILURES {
+            assert!(!count_channel_failure(
+                &mut failures,
+                Err(FailureKind::MissingChannel)
+            ));
+            assert!(!count_channel_failure(
+                &mut failures,
+                Err(FailureKind::Network)
+            ));
+        }
+        assert!(count_channel_failure(
+            &mut failures,
+            Err(FailureKind::MissingChannel)
+        ));
+        assert!(!count_channel_failure(
+            &mut failures,
+            Err(FailureKind::MissingChannel)
+        ));
+    }
+}
@@ -0,0 +1,207 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+
+use crate::{dates::next_occurrence, global, BirthdayEntry, BirthdayList, GuildConfig};
+
+// Checks run at least this often, so a wrong schedule can't hold the announcements back long
+static MAX_SLEEP: Duration = Duration::hours(6);
+// Failed announcements are retried this often
+static RETRY_SLEEP: Duration = Duration::hours(1);
+
+/// The moment `date` begins in a time zone `offset` ahead of UTC
+pub fn start_of(date: NaiveDate, offset: Duration) -> DateTime<Utc> {
+    date.and_time(NaiveTime::MIN).and_utc() - offset
+}
+
+/// How far the entry's announcement day is ahead of UTC, see `announcement_date`
+fn day_offset(entry: &BirthdayEntry, config: Option<&GuildConfig>) -> Duration {
+    match config.and_then(|config| config.announcement_time) {
+        Some(time) => time.utc_offset.duration() - Duration::hours(time.hour as i64),
+        None => entry.utc_offset.duration(),
+    }
+}
+
+/// When the entry's next birthday after the current day becomes due. A quiet day defers the
+/// announcement to the next day, so that one counts as well.
+fn next_announcement(
+    entry: &BirthdayEntry,
+    config: Option<&GuildConfig>,
+    now: DateTime<Utc>,
+) -> DateTime<Utc> {
+    let offset = day_offset(entry, config);
+    let today = (now + offset).date_naive();
+    let tomorrow = today.succ_opt().unwrap();
+    if config.is_some_and(|config| config.is_quiet(today)) {
+        return start_of(tomorrow, offset);
+    }
+    let leap_day = config.map(|config| config.leap_day).unwrap_or_default();
+    start_of(next_occurrence(entry.date, tomorrow, leap_day), offset)
+}
+
+/// When the next check is needed: the next birthday, anniversary or reminder that becomes due, the next
+/// day in UTC for the roles and anniversaries, or the next retry. Never later than
+/// MAX_SLEEP after `now`.
+pub fn next_wakeup(birthdays: &BirthdayList, now: DateTime<Utc>) -> DateTime<Utc> {
+    let mut wakeup = now + MAX_SLEEP;
+    wakeup = wakeup.min(start_of(
+        now.date_naive().succ_opt().unwrap(),
+        Duration::zero(),
+    ));
+    if birthdays
+        .failed_announcements
+        .iter()
+        .any(|failed| !failed.exhausted())
+    {
+        wakeup = wakeup.min(now + RETRY_SLEEP);
+    }
+
+    for entry in birthdays
+        .entries
+        .iter()
+        .chain(&global::candidates(birthdays))
+        .chain(&birthdays.events)
+    {
+        if entry.missing_since.is_some() || birthdays.left_guilds.contains_key(&entry.guild_id) {
+            continue;
+        }
+        let config = birthdays.guild_configs.get(&entry.guild_id);
+        wakeup = wakeup.min(next_announcement(entry, config, now));
+    }
+
+    // Reminders are due on the day in the target's time zone, like the announcement
+    for reminder in &birthdays.reminders {
+        let Some(entry) = birthdays
+            .entries
+            .iter()
+            .find(|entry| entry.guild_id == reminder.guild_id && entry.user_id == reminder.target)
+        else {
+            continue;
+        };
+        let offset = entry.utc_offset.duration();
+        let before = Duration::days(reminder.days_before as i64);
+        let tomorrow = (now + offset).date_naive().succ_opt().unwrap();
+        let leap_day = birthdays.leap_day(entry.guild_id);
+        let occurrence = next_occurrence(entry.date, tomorrow + before, leap_day);
+        wakeup = wakeup.min(start_of(occurrence - before, offset));
+    }
+    wakeup
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::{GuildId, UserId};
+
+    use super::*;
+    use crate::{offset::UtcOffset, reminders::Reminder, AnnouncementTime, LeapDay, QuietDate};
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    fn list(month: u32, day: u32, utc_offset: i32) -> BirthdayList {
+        BirthdayList {
+            entries: vec![BirthdayEntry {
+                name: "user".to_string(),
+                date: NaiveDate::from_ymd_opt(2024, month, day).unwrap(),
+                year: None,
+                utc_offset: UtcOffset::from_hours(utc_offset),
+                ..BirthdayEntry::example(1, 1)
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn config(birthdays: &mut BirthdayList) -> &mut GuildConfig {
+        birthdays.guild_configs.entry(GuildId::new(1)).or_default()
+    }
+
+    #[test]
+    fn wakes_up_when_the_next_birthday_starts() {
+        // New Year's birthdays start in the previous year east of UTC and the next UTC day
+        // west of it, the check at UTC midnight comes first otherwise
+        let now = at("2024-12-31T20:00:00Z");
+        assert_eq!(next_wakeup(&list(1, 1, 2), now), at("2024-12-31T22:00:00Z"));
+        assert_eq!(
+            next_wakeup(&list(1, 1, -5), now),
+            at("2025-01-01T00:00:00Z")
+        );
+        let now = at("2025-01-01T01:00:00Z");
+        assert_eq!(
+            next_wakeup(&list(1, 1, -5), now),
+            at("2025-01-01T05:00:00Z")
+        );
+
+        // Already started in UTC+14, so it is next year's birthday that is waited for
+        let now = at("2024-12-31T11:00:00Z");
+        assert_eq!(next_wakeup(&list(1, 1, 14), now), now + MAX_SLEEP);
+    }
+
+    #[test]
+    fn follows_the_guild_settings() {
+        // February 29th starts at 21:00 UTC the day before in UTC+3
+        let mut birthdays = list(2, 29, 3);
+        assert_eq!(
+            next_wakeup(&birthdays, at("2025-02-27T19:00:00Z")),
+            at("2025-02-27T21:00:00Z")
+        );
+        config(&mut birthdays).leap_day = LeapDay::March1;
+        assert_eq!(
+            next_wakeup(&birthdays, at("2025-02-28T16:00:00Z")),
+            at("2025-02-28T21:00:00Z")
+        );
+
+        // Announced at 9:00 in UTC+2, which is 7:00 UTC
+        let mut birthdays = list(6, 14, -10);
+        config(&mut birthdays).announcement_time = Some(AnnouncementTime {
+            hour: 9,
+            utc_offset: UtcOffset::from_hours(2),
+        });
+        assert_eq!(
+            next_wakeup(&birthdays, at("2025-06-14T03:00:00Z")),
+            at("2025-06-14T07:00:00Z")
+        );
+
+        // A quiet birthday is announced the next day
+        let mut birthdays = list(6, 14, 2);
+        config(&mut birthdays).quiet_dates.push(QuietDate {
+            day: 14,
+            month: 6,
+            year: None,
+        });
+        let now = at("2025-06-14T12:00:00Z");
+        assert_eq!(next_wakeup(&birthdays, now), now + MAX_SLEEP);
+        assert_eq!(
+            next_wakeup(&birthdays, at("2025-06-14T20:00:00Z")),
+            at("2025-06-14T22:00:00Z")
+        );
+    }
+
+    #[test]
+    fn wakes_up_for_reminders_and_retries() {
+        let mut birthdays = list(1, 3, 5);
+        birthdays.reminders.push(Reminder {
+            guild_id: GuildId::new(1),
+            subscriber: UserId::new(2),
+            target: UserId::new(1),
+            days_before: 7,
+            reminded: None,
+        });
+        // December 27th starts at 19:00 UTC the day before in UTC+5
+        assert_eq!(
+            next_wakeup(&birthdays, at("2024-12-26T18:00:00Z")),
+            at("2024-12-26T19:00:00Z")
+        );
+
+        let mut birthdays = list(6, 14, 0);
+        birthdays
+            .failed_announcements
+            .push(crate::retry::FailedAnnouncement {
+                guild_id: GuildId::new(1),
+                user_id: UserId::new(1),
+                occurrence: NaiveDate::from_ymd_opt(2025, 6, 14).unwrap(),
+                attempts: 1,
+                kind: crate::retry::FailureKind::Network,
+            });
+        let now = at("2025-06-14T12:00:00Z");
+        assert_eq!(next_wakeup(&birthdays, now), now + RETRY_SLEEP);
+    }
+}
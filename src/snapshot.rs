@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+
+use crate::{BirthdayList, Error};
+
+static SNAPSHOT_PREFIX: &str = "snapshot-";
+
+/// Writes a timestamped copy of the data set to `dir`, returns the path of the snapshot
+pub async fn write_snapshot(dir: &Path, birthdays: &BirthdayList) -> Result<PathBuf, Error> {
+    std::fs::create_dir_all(dir)?;
+    let name = format!(
+        "{}{}.json",
+        SNAPSHOT_PREFIX,
+        Utc::now().format("%Y-%m-%dT%H-%M-%S")
+    );
+    let path = dir.join(name);
+    std::fs::write(&path, serde_json::to_string_pretty(birthdays)?)?;
+    Ok(path)
+}
+
+/// Names of all snapshots in `dir`, newest first
+pub fn list_snapshots(dir: &Path) -> Vec<String> {
+    let Ok(dir) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = dir
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.starts_with(SNAPSHOT_PREFIX) && name.ends_with(".json"))
+        .collect();
+    // The timestamps sort lexicographically
+    names.sort_unstable_by(|a, b| b.cmp(a));
+    names
+}
+
+/// Loads a snapshot by name, only names returned by `list_snapshots` are accepted
+pub fn read_snapshot(dir: &Path, name: &str) -> Result<BirthdayList, Error> {
+    if !list_snapshots(dir).iter().any(|snapshot| snapshot == name) {
+        return Err(format!("There is no snapshot called {}", name).into());
+    }
+    let data = std::fs::read_to_string(dir.join(name))?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::{ChannelId, GuildId};
+
+    use super::*;
+    use crate::storage::DataPath;
+
+    #[tokio::test]
+    async fn snapshots_are_kept_next_to_the_data_file() {
+        let root =
+            std::env::temp_dir().join(format!("birthdaybot-snapshots-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let data_path = DataPath::new(root.join("data").join("birthdays.json")).unwrap();
+        let dir = data_path.backup_dir();
+        assert_eq!(dir, root.join("data").join("backups"));
+
+        let mut birthdays = BirthdayList::default();
+        birthdays
+            .server_channels
+            .insert(GuildId::new(1), ChannelId::new(2));
+        let path = write_snapshot(&dir, &birthdays).await.unwrap();
+        assert_eq!(path.parent(), Some(dir.as_path()));
+
+        let names = list_snapshots(&dir);
+        assert_eq!(names.len(), 1);
+        let restored = read_snapshot(&dir, &names[0]).unwrap();
+        assert_eq!(restored.server_channels, birthdays.server_channels);
+        assert!(read_snapshot(&dir, "../birthdays.json").is_err());
+    }
+}
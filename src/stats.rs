@@ -0,0 +1,144 @@
+use chrono::{Datelike, Month, NaiveDate, Utc};
+use poise::serenity_prelude::GuildId;
+
+use crate::{dates::last_occurrence, BirthdayEntry, BirthdayList, Context, Error};
+
+// Width of the longest bar in the chart
+static BAR_WIDTH: usize = 20;
+
+/// Bar of `count` scaled to the busiest month, any birthday gets at least a sliver
+fn bar(count: usize, max: usize) -> String {
+    if count == 0 {
+        return String::new();
+    }
+    let eighths = (count * BAR_WIDTH * 8).div_ceil(max);
+    let mut bar = "█".repeat(eighths / 8);
+    if !eighths.is_multiple_of(8) {
+        bar.push([' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'][eighths % 8]);
+    }
+    bar
+}
+
+/// A summary of the guild's birthdays that stays short no matter how many there are, None if
+/// the guild has none
+fn stats(birthdays: &BirthdayList, guild_id: GuildId, today: NaiveDate) -> Option<String> {
+    let entries: Vec<&BirthdayEntry> = birthdays
+        .entries
+        .iter()
+        .filter(|entry| entry.guild_id == guild_id)
+        .collect();
+    if entries.is_empty() {
+        return None;
+    }
+
+    let mut months = [0; 12];
+    let mut days = std::collections::BTreeMap::new();
+    for entry in &entries {
+        months[entry.date.month0() as usize] += 1;
+        *days
+            .entry((entry.date.month(), entry.date.day()))
+            .or_insert(0) += 1;
+    }
+    let max = *months.iter().max().unwrap();
+    let chart: Vec<String> = months
+        .iter()
+        .enumerate()
+        .map(|(month, count)| {
+            let name = Month::try_from(month as u8 + 1).unwrap().name();
+            format!("{} {:>3} {}", &name[..3], count, bar(*count, max))
+        })
+        .collect();
+
+    let mut lines = vec![
+        format!("📊🎈 **{} birthday(s) in this server**", entries.len()),
+        format!("```\n{}\n```", chart.join("\n")),
+    ];
+    // The earliest day wins a tie
+    let (common, shared) = days
+        .iter()
+        .max_by_key(|(day, count)| (**count, std::cmp::Reverse(**day)))
+        .unwrap();
+    if *shared > 1 {
+        lines.push(format!(
+            "- 👯 Most shared birthday: {} ({} members)",
+            birthdays
+                .date_format(guild_id)
+                .format(common.1, common.0, None),
+            shared
+        ));
+    }
+
+    let leap_day = birthdays.leap_day(guild_id);
+    let ages: Vec<i32> = entries
+        .iter()
+        .filter_map(|entry| birthdays.age_on(entry, last_occurrence(entry.date, today, leap_day)))
+        .collect();
+    if birthdays.shows_ages(guild_id) {
+        lines.push(format!(
+            "- 📅 With a birth year: {} of {}",
+            ages.len(),
+            entries.len()
+        ));
+        if !ages.is_empty() {
+            let average = ages.iter().sum::<i32>() as f64 / ages.len() as f64;
+            lines.push(format!("- 🎂 Average age: {:.1}", average));
+        }
+    }
+    Some(lines.join("\n"))
+}
+
+/// Shows how the birthdays in this server are spread over the year
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn birthday_stats(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    let message = stats(&birthdays, guild_id, Utc::now().date_naive())
+        .unwrap_or_else(|| "☹️🎈 Nobody has set a birthday in this guild yet!".to_string());
+    ctx.say(message).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(user_id: u64, date: NaiveDate, year: Option<i32>) -> BirthdayEntry {
+        BirthdayEntry {
+            name: "user".to_string(),
+            date,
+            year,
+            ..BirthdayEntry::example(user_id, 1)
+        }
+    }
+
+    #[test]
+    fn stats_summarize_the_guild() {
+        let date = |year, month, day| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        let today = date(2025, 1, 1);
+        let mut private = entry(4, date(1980, 3, 2), Some(1980));
+        private.private_year = true;
+        let mut birthdays = BirthdayList {
+            entries: vec![
+                entry(1, date(1995, 6, 14), Some(1995)),
+                entry(2, date(2000, 6, 14), Some(2000)),
+                entry(3, date(2024, 3, 1), None),
+                private,
+            ],
+            ..Default::default()
+        };
+        assert_eq!(stats(&birthdays, GuildId::new(2), today), None);
+
+        let stats = stats(&birthdays, GuildId::new(1), today).unwrap();
+        assert!(stats.contains("4 birthday(s)"));
+        assert!(stats.contains("Mar   2 ████████████████████\n"));
+        assert!(stats.contains("Jun   2 ████████████████████\n"));
+        assert!(stats.contains("Jul   0 \n"));
+        assert!(stats.contains("Most shared birthday: 14.6 (2 members)"));
+        assert!(stats.contains("With a birth year: 2 of 4"));
+        assert!(stats.contains("Average age: 26.5"));
+
+        birthdays.entries.truncate(1);
+        let stats = super::stats(&birthdays, GuildId::new(1), today).unwrap();
+        assert!(!stats.contains("Most shared"));
+    }
+}
@@ -0,0 +1,651 @@
+mod file;
+mod journal;
+pub mod migrate;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+mod serialization;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+use std::collections::{HashMap, HashSet};
+
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+use poise::serenity_prelude::{ChannelId, GuildId};
+use tokio::sync::RwLock;
+use tracing::{debug, error, info};
+
+use crate::{alerts, BirthdayList, Error};
+pub use file::DataPath;
+use file::FileStore;
+pub use serialization::StorageFormat;
+
+static WATCH_TIME: u64 = 2; // seconds
+static COMPACT_TIME: u64 = 10 * 60; // 10 minutes
+
+// Changes are saved at most this often unless BIRTHDAYBOT_FLUSH_SECONDS says otherwise, 0 saves
+// every change right away
+static FLUSH_TIME: u64 = 5; // seconds
+
+// How often `Storage::update` retries when another instance saved at the same time
+static UPDATE_ATTEMPTS: usize = 3;
+// The owner is alerted after this many failed saves in a row
+static FAILED_SAVES_ALERT: usize = 3;
+static FAILED_SAVES: AtomicUsize = AtomicUsize::new(0);
+// Failed saves since the start, for the metrics
+static WRITE_ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+/// The live data and where it is persisted, opened once on startup and shared by everything
+/// that reads or changes the birthdays. All reads and writes go through it, so the data is
+/// only loaded again when it was changed elsewhere.
+#[derive(Clone)]
+pub struct Storage {
+    state: Arc<RwLock<State>>,
+    data_path: DataPath,
+}
+
+struct State {
+    // Replaced as a whole on every change, so readers can keep the data as it was without
+    // copying it
+    birthdays: Arc<BirthdayList>,
+    backend: Backend,
+    // The data as it was last saved while there are changes that aren't, see `flush`
+    saved: Option<BirthdayList>,
+    // Whether changes are only saved by `flush`, see FLUSH_TIME
+    write_behind: bool,
+}
+
+/// Where the data is persisted, DATABASE_URL picks PostgreSQL and BIRTHDAYBOT_SQLITE_PATH
+/// picks SQLite over the data file
+enum Backend {
+    File(FileStore),
+    #[cfg(feature = "postgres")]
+    Postgres(postgres::PostgresStore),
+    #[cfg(feature = "sqlite")]
+    Sqlite(sqlite::SqliteStore, PathBuf),
+}
+
+impl Backend {
+    fn describe(&self) -> String {
+        match self {
+            Backend::File(store) => store.path().display().to_string(),
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(_) => "The database".to_string(),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(_, path) => path.display().to_string(),
+        }
+    }
+
+    fn conflict(&self) -> Option<&String> {
+        match self {
+            Backend::File(store) => store.conflict(),
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(_) => None,
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(..) => None,
+        }
+    }
+
+    /// Returns the latest data if it was changed by anyone else since it was loaded
+    async fn changes(&mut self) -> Result<Option<BirthdayList>, Error> {
+        match self {
+            Backend::File(store) => store.changes(),
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(store) => store.changes().await,
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store, _) => store.changes().await,
+        }
+    }
+
+    /// Returns false without saving anything if another instance saved first
+    async fn save(&mut self, old: &BirthdayList, new: &BirthdayList) -> Result<bool, Error> {
+        let saved = self.save_unchecked(old, new).await;
+        match &saved {
+            Ok(saved) => {
+                FAILED_SAVES.store(0, Ordering::Relaxed);
+                debug!(backend = %self.describe(), saved, "Saved the birthdays");
+            }
+            Err(error) => {
+                WRITE_ERRORS.fetch_add(1, Ordering::Relaxed);
+                let failures = FAILED_SAVES.fetch_add(1, Ordering::Relaxed) + 1;
+                if failures.is_multiple_of(FAILED_SAVES_ALERT) {
+                    alerts::raise(format!(
+                        "Saving the birthdays failed {} times in a row: {}",
+                        failures, error
+                    ));
+                }
+            }
+        }
+        saved
+    }
+
+    async fn save_unchecked(
+        &mut self,
+        old: &BirthdayList,
+        new: &BirthdayList,
+    ) -> Result<bool, Error> {
+        match self {
+            Backend::File(store) => {
+                store.save(old, new)?;
+                if store.needs_compaction() {
+                    store.compact(new)?;
+                }
+                Ok(true)
+            }
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(store) => store.save(old, new).await,
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store, _) => store.save(old, new).await,
+        }
+    }
+
+    /// Whether the journal holds mutations that aren't in the data file yet
+    fn pending(&self) -> bool {
+        match self {
+            Backend::File(store) => store.pending(),
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(_) => false,
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(..) => false,
+        }
+    }
+
+    fn compact(&mut self, birthdays: &BirthdayList) -> Result<(), Error> {
+        match self {
+            Backend::File(store) => {
+                store.compact(birthdays)?;
+                debug!(backend = %self.describe(), "Compacted the journal");
+                Ok(())
+            }
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(_) => Ok(()),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(..) => Ok(()),
+        }
+    }
+
+    /// Changes whenever the data was changed, be it by us or by anyone else
+    async fn change_marker(&self) -> Option<u128> {
+        match self {
+            Backend::File(store) => store
+                .modified()?
+                .duration_since(std::time::UNIX_EPOCH)
+                .ok()
+                .map(|modified| modified.as_nanos()),
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(store) => {
+                store.revision().await.ok().map(|revision| revision as u128)
+            }
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store, _) => {
+                store.revision().await.ok().map(|revision| revision as u128)
+            }
+        }
+    }
+}
+
+/// Announcement channel and config of a guild as stored in its database row
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+type GuildRow = (Option<ChannelId>, Option<serde_json::Value>);
+
+/// Announcement channel and config of every guild that has either
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+fn guild_rows(birthdays: &BirthdayList) -> Result<HashMap<GuildId, GuildRow>, Error> {
+    let guild_ids: HashSet<GuildId> = birthdays
+        .server_channels
+        .keys()
+        .chain(birthdays.guild_configs.keys())
+        .copied()
+        .collect();
+    guild_ids
+        .into_iter()
+        .map(|guild_id| {
+            let config = birthdays
+                .guild_configs
+                .get(&guild_id)
+                .map(serde_json::to_value)
+                .transpose()?;
+            let channel = birthdays.server_channels.get(&guild_id).copied();
+            Ok((guild_id, (channel, config)))
+        })
+        .collect()
+}
+
+async fn load(data_path: &DataPath, force_reset: bool) -> State {
+    #[cfg(feature = "postgres")]
+    if let Ok(url) = std::env::var("DATABASE_URL") {
+        let (store, birthdays) = postgres::PostgresStore::connect(&url)
+            .await
+            .unwrap_or_else(|error| panic!("Can't open the database: {}", error));
+        return State::new(birthdays, Backend::Postgres(store));
+    }
+    #[cfg(not(feature = "postgres"))]
+    if std::env::var("DATABASE_URL").is_ok() {
+        tracing::warn!("Ignoring DATABASE_URL as the bot was built without the postgres feature");
+    }
+
+    #[cfg(feature = "sqlite")]
+    if let Ok(path) = std::env::var("BIRTHDAYBOT_SQLITE_PATH") {
+        let path = PathBuf::from(path);
+        let format = data_path.detect_format();
+        let (store, birthdays) = sqlite::SqliteStore::open(&path, &data_path.file(format), format)
+            .await
+            .unwrap_or_else(|error| panic!("Can't open {}: {}", path.display(), error));
+        return State::new(birthdays, Backend::Sqlite(store, path));
+    }
+    #[cfg(not(feature = "sqlite"))]
+    if std::env::var("BIRTHDAYBOT_SQLITE_PATH").is_ok() {
+        tracing::warn!(
+            "Ignoring BIRTHDAYBOT_SQLITE_PATH as the bot was built without the sqlite feature"
+        );
+    }
+
+    let (store, birthdays) = FileStore::open_in(data_path, force_reset);
+    State::new(birthdays, Backend::File(store))
+}
+
+fn flush_time() -> u64 {
+    std::env::var("BIRTHDAYBOT_FLUSH_SECONDS")
+        .ok()
+        .and_then(|seconds| seconds.parse().ok())
+        .unwrap_or(FLUSH_TIME)
+}
+
+/// The data with the changes from `saved` to `current` applied on top, like the journal is
+/// applied on top of a data file that was edited
+fn rebase(
+    saved: &BirthdayList,
+    current: &BirthdayList,
+    onto: &BirthdayList,
+) -> Result<BirthdayList, Error> {
+    let mut value = serde_json::to_value(onto)?;
+    let changes = journal::diff(
+        &serde_json::to_value(saved)?,
+        &serde_json::to_value(current)?,
+    );
+    if let Some(patch) = changes {
+        journal::apply(&mut value, &patch);
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+impl State {
+    fn new(birthdays: BirthdayList, backend: Backend) -> State {
+        State {
+            birthdays: Arc::new(birthdays),
+            backend,
+            saved: None,
+            write_behind: flush_time() > 0,
+        }
+    }
+
+    /// Picks up changes that were made behind our back, returns whether there were any.
+    /// Changes that weren't saved yet are kept on top of them.
+    async fn sync(&mut self) -> Result<bool, Error> {
+        let Some(mut birthdays) = self.backend.changes().await? else {
+            return Ok(false);
+        };
+        birthdays.migrate_announcements();
+        if let Some(saved) = self.saved.replace(birthdays.clone()) {
+            birthdays = rebase(&saved, &self.birthdays, &birthdays)?;
+        }
+        birthdays.version = self.birthdays.version + 1;
+        self.birthdays = Arc::new(birthdays);
+        Ok(true)
+    }
+
+    /// Returns false without saving anything if another instance saved first. With write-behind
+    /// the change is only kept in memory until the next `flush`.
+    async fn save(&mut self, mut birthdays: BirthdayList) -> Result<bool, Error> {
+        if self.write_behind {
+            if self.saved.is_none() {
+                self.saved = Some(BirthdayList::clone(&self.birthdays));
+            }
+        } else if !self.backend.save(&self.birthdays, &birthdays).await? {
+            return Ok(false);
+        }
+        birthdays.version = self.birthdays.version + 1;
+        self.birthdays = Arc::new(birthdays);
+        Ok(true)
+    }
+
+    /// Saves the changes that were only kept in memory. Runs with the state locked, so no
+    /// change can slip in between saving and marking the data as saved.
+    async fn flush(&mut self) -> Result<(), Error> {
+        for _ in 0..UPDATE_ATTEMPTS {
+            let State {
+                birthdays,
+                backend,
+                saved,
+                ..
+            } = self;
+            let Some(old) = saved else {
+                return Ok(());
+            };
+            if backend.save(old, birthdays).await? {
+                *saved = None;
+                return Ok(());
+            }
+            // Another instance saved first, our changes go on top of theirs
+            self.sync().await?;
+        }
+        Err(format!(
+            "{} kept changing, the changes weren't saved yet",
+            self.backend.describe()
+        )
+        .into())
+    }
+
+    fn check_conflict(&self) -> Result<(), Error> {
+        match self.backend.conflict() {
+            Some(conflict) => Err(format!(
+                "{}, fix the file or run `reload` before making changes",
+                conflict
+            )
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Makes sure a write can't clobber a change that was made elsewhere
+    async fn check_writable(&mut self) -> Result<(), Error> {
+        self.check_conflict()?;
+        if self.sync().await? {
+            return Err(format!(
+                "{} was just changed, please try again",
+                self.backend.describe()
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// How many saves failed since the start
+pub fn write_errors() -> usize {
+    WRITE_ERRORS.load(Ordering::Relaxed)
+}
+
+/// Loads the data, so problems like a locked data file show up on startup. The data file is at
+/// `data_path` unless a database is configured. `force_reset` starts without birthdays if the
+/// data file can't be loaded.
+pub async fn open(data_path: DataPath, force_reset: bool) -> Storage {
+    let mut state = load(&data_path, force_reset).await;
+    Arc::make_mut(&mut state.birthdays).migrate_announcements();
+    Storage {
+        state: Arc::new(RwLock::new(state)),
+        data_path,
+    }
+}
+
+impl Storage {
+    /// The data as it is now, without copying it. It stays as it was even if the data is
+    /// changed in the meantime, so it can be held across slow calls like sending a message.
+    pub async fn read(&self) -> Arc<BirthdayList> {
+        self.state.read().await.birthdays.clone()
+    }
+
+    /// Runs `read` on the data, for paths like the prefix lookup that run on every message.
+    /// Changes wait until `read` returns.
+    pub async fn read_with<R>(&self, read: impl FnOnce(&BirthdayList) -> R) -> R {
+        read(&self.state.read().await.birthdays)
+    }
+
+    /// A copy of the data to change and save with `write`
+    pub async fn read_owned(&self) -> BirthdayList {
+        BirthdayList::clone(&*self.read().await)
+    }
+
+    /// Saves data that was previously returned by `read_owned`. Fails without writing anything
+    /// if the data was changed in the meantime, as the write would undo that change.
+    pub async fn write(&self, birthdays: &BirthdayList) -> Result<(), Error> {
+        let mut state = self.state.write().await;
+        state.check_writable().await?;
+        if birthdays.version != state.birthdays.version || !state.save(birthdays.clone()).await? {
+            return Err(
+                "The birthdays were changed while this command was running, please try again"
+                    .into(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Modifies the latest data and saves it, without any chance of a concurrent change getting
+    /// lost. `modify` runs again if another instance saved in the meantime.
+    pub async fn update<R>(
+        &self,
+        mut modify: impl FnMut(&mut BirthdayList) -> R,
+    ) -> Result<R, Error> {
+        let mut state = self.state.write().await;
+        state.check_conflict()?;
+        for _ in 0..UPDATE_ATTEMPTS {
+            state.sync().await?;
+            let mut birthdays = BirthdayList::clone(&state.birthdays);
+            let result = modify(&mut birthdays);
+            if state.save(birthdays).await? {
+                return Ok(result);
+            }
+        }
+        Err(format!(
+            "{} kept changing, the update was given up",
+            state.backend.describe()
+        )
+        .into())
+    }
+
+    /// Replaces all data, regardless of what it was before
+    pub async fn replace(&self, birthdays: BirthdayList) -> Result<(), Error> {
+        let mut state = self.state.write().await;
+        if !state.save(birthdays).await? {
+            return Err(format!(
+                "{} was just changed, please try again",
+                state.backend.describe()
+            )
+            .into());
+        }
+        state.flush().await
+    }
+
+    /// Re-reads the data, returns the data before and after if it was changed elsewhere
+    pub async fn reload(&self) -> Result<Option<(Arc<BirthdayList>, Arc<BirthdayList>)>, Error> {
+        let mut state = self.state.write().await;
+        let previous = state.birthdays.clone();
+        if state.sync().await? {
+            Ok(Some((previous, state.birthdays.clone())))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Rewrites the data file in another format, the previous file is kept with a .bak
+    /// extension. Returns the paths of the previous and the new file.
+    pub async fn convert(&self, format: StorageFormat) -> Result<(PathBuf, PathBuf), Error> {
+        let mut state = self.state.write().await;
+        state.check_writable().await?;
+        state.flush().await?;
+        let State {
+            birthdays, backend, ..
+        } = &mut *state;
+        match backend {
+            Backend::File(store) => store.convert(birthdays, format),
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(_) => Err("The data is stored in the database, not in a file".into()),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(..) => Err("The data is stored in the database, not in a file".into()),
+        }
+    }
+
+    /// Writes all mutations from the journal to the main file
+    pub async fn compact(&self) -> Result<(), Error> {
+        let mut state = self.state.write().await;
+        state.flush().await?;
+        if !state.backend.pending() {
+            return Ok(());
+        }
+        state.check_writable().await?;
+        let State {
+            birthdays, backend, ..
+        } = &mut *state;
+        backend.compact(birthdays)
+    }
+
+    /// Saves every change that is only kept in memory so far, for anything that must not be
+    /// lost in a crash
+    pub async fn flush(&self) -> Result<(), Error> {
+        self.state.write().await.flush().await
+    }
+
+    /// Where snapshots are kept, next to the data file
+    pub fn snapshot_dir(&self) -> PathBuf {
+        self.data_path.backup_dir()
+    }
+
+    /// Saves the changes at most every FLUSH_TIME, so a burst of changes is written at once
+    pub async fn flush_periodically(self) {
+        let seconds = flush_time();
+        if seconds == 0 {
+            return;
+        }
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(seconds)).await;
+            if let Err(error) = self.flush().await {
+                error!(%error, "Failed to save the birthdays");
+            }
+        }
+    }
+
+    pub async fn compact_periodically(self) {
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(COMPACT_TIME)).await;
+            if let Err(error) = self.compact().await {
+                error!(%error, "Failed to compact the journal");
+            }
+        }
+    }
+
+    async fn change_marker(&self) -> Option<u128> {
+        self.state.read().await.backend.change_marker().await
+    }
+
+    /// Reloads the data whenever it is changed elsewhere. Changes are only picked up once they
+    /// were stable for a whole interval, so an editor writing in several steps is handled as
+    /// one edit.
+    pub async fn watch(self) {
+        let mut last_seen = self.change_marker().await;
+        let mut handled = last_seen;
+
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(WATCH_TIME)).await;
+            let current = self.change_marker().await;
+            let stable = current == last_seen;
+            last_seen = current;
+            if !stable || current == handled {
+                continue;
+            }
+            handled = current;
+
+            match self.reload().await {
+                Ok(Some((_, birthdays))) => {
+                    let backend = self.state.read().await.backend.describe();
+                    info!(
+                        %backend,
+                        birthdays = birthdays.entries.len(),
+                        "Reloaded the data after it was changed elsewhere"
+                    );
+                }
+                // Our own write
+                Ok(None) => {}
+                Err(error) => error!(%error, "Failed to reload the data"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::{ChannelId, GuildId};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn the_data_is_kept_at_the_data_path() {
+        let dir = std::env::temp_dir().join(format!("birthdaybot-open-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("data").join("birthdays.json");
+
+        let storage = open(DataPath::new(path.clone()).unwrap(), false).await;
+        assert_eq!(storage.snapshot_dir(), dir.join("data").join("backups"));
+        storage
+            .update(|birthdays| {
+                birthdays
+                    .server_channels
+                    .insert(GuildId::new(1), ChannelId::new(2))
+            })
+            .await
+            .unwrap();
+        storage.flush().await.unwrap();
+        drop(storage);
+
+        let (_, birthdays) = FileStore::open_in(&DataPath::new(path).unwrap(), false);
+        assert_eq!(
+            birthdays.server_channels,
+            [(GuildId::new(1), ChannelId::new(2))].into()
+        );
+    }
+
+    #[tokio::test]
+    async fn write_behind_keeps_changes_made_elsewhere() {
+        let dir = std::env::temp_dir().join(format!("birthdaybot-flush-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("birthdays.json");
+        let json = |birthdays: &BirthdayList| StorageFormat::Json.serialize(birthdays).unwrap();
+        std::fs::write(&path, json(&BirthdayList::default())).unwrap();
+        let journal = path.with_extension("journal");
+
+        let (store, birthdays) = FileStore::open(path.clone(), StorageFormat::Json, false);
+        let mut state = State::new(birthdays, Backend::File(store));
+        state.write_behind = true;
+        for channel in [2, 3] {
+            let mut birthdays = BirthdayList::clone(&state.birthdays);
+            birthdays
+                .server_channels
+                .insert(GuildId::new(1), ChannelId::new(channel));
+            assert!(state.save(birthdays).await.unwrap());
+        }
+        assert!(!journal.exists());
+
+        // Edited on disk before the flush, both the edit and our change survive
+        let mut edited = BirthdayList::default();
+        edited
+            .server_channels
+            .insert(GuildId::new(5), ChannelId::new(6));
+        std::fs::write(&path, json(&edited)).unwrap();
+        assert!(state.sync().await.unwrap());
+        state.flush().await.unwrap();
+        assert!(state.saved.is_none());
+        assert_eq!(
+            std::fs::read_to_string(&journal).unwrap().lines().count(),
+            1
+        );
+        drop(state);
+
+        let (_, birthdays) = FileStore::open(path, StorageFormat::Json, false);
+        assert_eq!(
+            birthdays.server_channels,
+            [
+                (GuildId::new(1), ChannelId::new(3)),
+                (GuildId::new(5), ChannelId::new(6))
+            ]
+            .into()
+        );
+    }
+}
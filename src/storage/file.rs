@@ -0,0 +1,745 @@
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use chrono::Utc;
+use tracing::warn;
+
+use super::{journal, StorageFormat};
+use crate::{alerts, BirthdayList, Error};
+
+static FILE_STEM: &str = "birthdays";
+static JOURNAL_EXTENSION: &str = "journal";
+static LOCK_EXTENSION: &str = "lock";
+static TEMP_EXTENSION: &str = "tmp";
+// The journal is also compacted once it holds this many mutations
+static JOURNAL_LIMIT: u64 = 500;
+// Copies of the data file are kept in this directory next to it, the oldest are deleted
+static BACKUP_DIR: &str = "backups";
+static BACKUP_COUNT: usize = 10;
+
+/// Where the data file is kept, `birthdays.json` or `.toml` in the working directory unless a
+/// path was given. Its extension is only ever swapped for the one of another format.
+#[derive(Debug, Clone, Default)]
+pub struct DataPath {
+    path: Option<PathBuf>,
+}
+
+/// The data file, with a journal of the mutations since it was last written
+pub struct FileStore {
+    path: PathBuf,
+    format: StorageFormat,
+    // Hash of the file content as we last read or wrote it, tells our own writes apart from edits
+    file_hash: u64,
+    // Set while the file was edited on disk in a way that couldn't be loaded, writes are refused
+    // until it is fixed so the edit doesn't get clobbered
+    conflict: Option<String>,
+    // Number of the last mutation in the journal
+    journal_seq: u64,
+    // Number of the last mutation that made it into the main file
+    compacted_seq: u64,
+    // Held as long as the store is open, None if it was opened read-only
+    lock: Option<File>,
+}
+
+fn hash(data: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The format of the file at `path` going by its extension
+fn format_of(path: &Path) -> Option<StorageFormat> {
+    path.extension()
+        .and_then(|extension| StorageFormat::from_extension(&extension.to_string_lossy()))
+}
+
+impl DataPath {
+    /// The data file at `path`, creating the directory it is in. The path has to end in .json
+    /// or .toml, which also picks the format of a new file.
+    pub fn new(path: PathBuf) -> Result<DataPath, Error> {
+        if format_of(&path).is_none() {
+            return Err(format!("{} doesn't end in .json or .toml", path.display()).into());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(DataPath { path: Some(path) })
+    }
+
+    /// The data file in the format, the given path if it is in that format
+    pub fn file(&self, format: StorageFormat) -> PathBuf {
+        self.path
+            .as_deref()
+            .unwrap_or(Path::new(FILE_STEM))
+            .with_extension(format.extension())
+    }
+
+    /// The directory next to the data file that backups and snapshots are kept in
+    pub fn backup_dir(&self) -> PathBuf {
+        self.file(StorageFormat::Json).with_file_name(BACKUP_DIR)
+    }
+
+    /// Uses the preferred format, unless only a file in another format exists
+    pub(super) fn detect_format(&self) -> StorageFormat {
+        let setting = std::env::var("BIRTHDAYBOT_STORAGE_FORMAT").ok();
+        let preferred = preferred_format(setting.as_deref(), self.path.as_deref())
+            .unwrap_or_else(|error| panic!("{}", error));
+        let existing = StorageFormat::ALL
+            .into_iter()
+            .find(|format| self.file(*format).exists());
+
+        match (preferred, existing) {
+            (Some(preferred), _) if self.file(preferred).exists() => preferred,
+            (preferred, Some(existing)) => {
+                if let Some(preferred) = preferred {
+                    warn!(
+                        existing = %self.file(existing).display(),
+                        preferred = %self.file(preferred).display(),
+                        "Using the existing data file, run `convert_storage` to switch formats"
+                    );
+                }
+                existing
+            }
+            (preferred, None) => preferred.unwrap_or(StorageFormat::Json),
+        }
+    }
+}
+
+/// The format named by BIRTHDAYBOT_STORAGE_FORMAT, or else by the extension of the data path.
+/// The two must not disagree, the data path is used as it was given.
+fn preferred_format(
+    setting: Option<&str>,
+    path: Option<&Path>,
+) -> Result<Option<StorageFormat>, String> {
+    let setting = setting
+        .map(|setting| {
+            StorageFormat::from_extension(setting)
+                .ok_or("BIRTHDAYBOT_STORAGE_FORMAT must be either json or toml")
+        })
+        .transpose()?;
+    let Some(path) = path else {
+        return Ok(setting);
+    };
+    match (setting, format_of(path)) {
+        (Some(setting), Some(named)) if setting != named => Err(format!(
+            "BIRTHDAYBOT_STORAGE_FORMAT is {} but the data path {} is not",
+            setting.extension(),
+            path.display()
+        )),
+        (setting, named) => Ok(setting.or(named)),
+    }
+}
+
+/// Replaces the file without a crash or a concurrent reader ever seeing a partial file. The data
+/// is written to a temporary file in the same directory, synced and renamed over the original.
+pub(super) fn write_atomically(path: &Path, data: &str) -> Result<(), Error> {
+    write_atomically_with(path, |file| file.write_all(data.as_bytes()))
+}
+
+fn write_atomically_with(
+    path: &Path,
+    write: impl FnOnce(&mut File) -> io::Result<()>,
+) -> Result<(), Error> {
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    let temp = path.with_extension(format!("{}.{}", extension, TEMP_EXTENSION));
+    let result = File::create(&temp)
+        .and_then(|mut file| {
+            write(&mut file)?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&temp, path));
+    if let Err(error) = result {
+        let _ = std::fs::remove_file(&temp);
+        return Err(error.into());
+    }
+    // The rename itself only survives a crash once the directory was synced
+    #[cfg(unix)]
+    {
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Locks the data against other processes, the lock is released once the file is dropped.
+/// The lock file contains the PID of the process holding it.
+pub(super) fn lock(path: &Path) -> Result<File, Error> {
+    let lock_path = path.with_extension(LOCK_EXTENSION);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let pid = std::fs::read_to_string(&lock_path).unwrap_or_default();
+            return Err(format!(
+                "{} is already in use by another instance of the bot (PID {}), set BIRTHDAYBOT_READ_ONLY=1 to only read it",
+                path.display(),
+                pid.trim()
+            )
+            .into());
+        }
+        Err(TryLockError::Error(error)) => return Err(error.into()),
+    }
+    file.set_len(0)?;
+    write!(file, "{}", std::process::id())?;
+    Ok(file)
+}
+
+/// Path for a timestamped copy of `path` in the backup directory next to it
+fn backup_path(path: &Path, label: &str) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(BACKUP_DIR).join(format!(
+        "{}.{}-{}",
+        name,
+        label,
+        Utc::now().format("%Y-%m-%dT%H-%M-%S%.3f")
+    ))
+}
+
+/// Copies the data file into the backup directory and deletes all but the newest BACKUP_COUNT
+/// copies. Nothing is copied before the file exists.
+fn rotate_backups(path: &Path) -> Result<(), Error> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let backup = backup_path(path, "backup");
+    std::fs::create_dir_all(backup.parent().unwrap())?;
+    std::fs::copy(path, &backup)?;
+
+    let prefix = format!(
+        "{}.backup-",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let mut backups: Vec<PathBuf> = std::fs::read_dir(backup.parent().unwrap())?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|backup| {
+            backup
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+        })
+        .collect();
+    // The timestamps sort lexicographically, newest first
+    backups.sort_unstable_by(|a, b| b.cmp(a));
+    for old in backups.iter().skip(BACKUP_COUNT) {
+        std::fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+/// Deals with a data file that exists but can't be loaded. It is copied to the backup directory
+/// and returned as the conflict, so writes are refused instead of replacing it with an empty
+/// list. With `force_reset` the file and its journal are moved there instead, to start over.
+fn unloadable(path: &Path, error: String, force_reset: bool) -> Option<String> {
+    let backup = backup_path(path, "corrupted");
+    let saved = std::fs::create_dir_all(backup.parent().unwrap()).and_then(|_| {
+        if force_reset {
+            let journal = path.with_extension(JOURNAL_EXTENSION);
+            if journal.exists() {
+                std::fs::rename(&journal, backup.with_extension(JOURNAL_EXTENSION))?;
+            }
+            std::fs::rename(path, &backup)
+        } else {
+            std::fs::copy(path, &backup).map(|_| ())
+        }
+    });
+    let saved = match saved {
+        Ok(()) => format!("a copy is at {}", backup.display()),
+        Err(copy_error) => format!("it couldn't be backed up ({})", copy_error),
+    };
+
+    if force_reset {
+        alerts::raise(format!(
+            "{} couldn't be loaded ({}), starting without birthdays as --force-reset was passed, {}",
+            path.display(),
+            error,
+            saved
+        ));
+        return None;
+    }
+    let conflict = format!(
+        "{} couldn't be loaded ({}), {}",
+        path.display(),
+        error,
+        saved
+    );
+    alerts::raise(format!(
+        "{}. Changes are refused until the file is fixed, or start the bot with --force-reset \
+        to start over without birthdays",
+        conflict
+    ));
+    Some(conflict)
+}
+
+/// Applies the journal records that are newer than the main file, returns the number of the last one
+fn replay(birthdays: &mut BirthdayList, journal: &Path) -> Result<u64, Error> {
+    let records: Vec<journal::Record> = journal::read(journal)?
+        .into_iter()
+        .filter(|record| record.seq > birthdays.journal_seq)
+        .collect();
+    let Some(last) = records.last().map(|record| record.seq) else {
+        return Ok(birthdays.journal_seq);
+    };
+
+    let mut value = serde_json::to_value(&*birthdays)?;
+    for record in &records {
+        journal::apply(&mut value, &record.patch);
+    }
+    let journal_seq = birthdays.journal_seq;
+    *birthdays = serde_json::from_value(value)?;
+    birthdays.journal_seq = journal_seq;
+    Ok(last)
+}
+
+impl FileStore {
+    /// Opens the data file at the path in the format it is in
+    pub fn open_in(data_path: &DataPath, force_reset: bool) -> (FileStore, BirthdayList) {
+        let format = data_path.detect_format();
+        FileStore::open(data_path.file(format), format, force_reset)
+    }
+
+    /// Opens the data and locks it for this process, unless BIRTHDAYBOT_READ_ONLY is set. A
+    /// missing or empty file starts without birthdays, see `unloadable` for broken files.
+    pub fn open(
+        path: PathBuf,
+        format: StorageFormat,
+        force_reset: bool,
+    ) -> (FileStore, BirthdayList) {
+        let lock = match std::env::var("BIRTHDAYBOT_READ_ONLY") {
+            Ok(_) => None,
+            Err(_) => Some(lock(&path).unwrap_or_else(|error| panic!("{}", error))),
+        };
+        let loaded = match std::fs::read_to_string(&path) {
+            Ok(data) if data.trim().is_empty() => Ok((BirthdayList::default(), data)),
+            Ok(data) => format
+                .deserialize(&data)
+                .map(|birthdays| (birthdays, data))
+                .map_err(|error| error.to_string()),
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                Ok((BirthdayList::default(), String::new()))
+            }
+            Err(error) => Err(error.to_string()),
+        };
+        let journal = path.with_extension(JOURNAL_EXTENSION);
+        let (birthdays, data, conflict) = match loaded {
+            Ok((birthdays, data)) => (birthdays, data, None),
+            Err(error) => (
+                BirthdayList::default(),
+                String::new(),
+                unloadable(&path, error, force_reset),
+            ),
+        };
+        let mut birthdays = birthdays;
+        let compacted_seq = birthdays.journal_seq;
+        let journal_seq = if conflict.is_some() {
+            // The journal only applies on top of the broken file, it's replayed once it's fixed
+            journal::read(&journal)
+                .ok()
+                .and_then(|records| records.last().map(|record| record.seq))
+                .unwrap_or(compacted_seq)
+        } else {
+            replay(&mut birthdays, &journal)
+                .unwrap_or_else(|error| panic!("Can't replay {}: {}", journal.display(), error))
+        };
+        let store = FileStore {
+            path,
+            format,
+            file_hash: hash(&data),
+            conflict,
+            journal_seq,
+            compacted_seq,
+            lock,
+        };
+        (store, birthdays)
+    }
+
+    /// Opens the data and locks it for this process, fails instead of panicking if the data is
+    /// missing, can't be loaded or is in use
+    pub fn try_open(
+        path: PathBuf,
+        format: StorageFormat,
+    ) -> Result<(FileStore, BirthdayList), Error> {
+        let lock = lock(&path)?;
+        let data = std::fs::read_to_string(&path)?;
+        let mut birthdays = format.deserialize(&data)?;
+        let compacted_seq = birthdays.journal_seq;
+        let journal_seq = replay(&mut birthdays, &path.with_extension(JOURNAL_EXTENSION))?;
+        let store = FileStore {
+            path,
+            format,
+            file_hash: hash(&data),
+            conflict: None,
+            journal_seq,
+            compacted_seq,
+            lock: Some(lock),
+        };
+        Ok((store, birthdays))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn conflict(&self) -> Option<&String> {
+        self.conflict.as_ref()
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.path.with_extension(JOURNAL_EXTENSION)
+    }
+
+    pub fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Picks up changes that were made to the file behind our back
+    pub fn changes(&mut self) -> Result<Option<BirthdayList>, Error> {
+        // Like on startup a missing file is no data, it is only written by the first compaction
+        let data = match std::fs::read_to_string(&self.path) {
+            Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+            data => data?,
+        };
+        let file_hash = hash(&data);
+        if file_hash == self.file_hash && self.conflict.is_none() {
+            return Ok(None);
+        }
+
+        // Mutations that haven't been compacted yet still apply on top of the edited file
+        let loaded = self.format.deserialize(&data).and_then(|mut birthdays| {
+            let compacted_seq = birthdays.journal_seq;
+            let journal_seq = replay(&mut birthdays, &self.journal_path())?;
+            Ok((birthdays, compacted_seq, journal_seq))
+        });
+        match loaded {
+            Ok((birthdays, compacted_seq, journal_seq)) => {
+                self.file_hash = file_hash;
+                self.conflict = None;
+                self.compacted_seq = compacted_seq;
+                self.journal_seq = journal_seq;
+                Ok(Some(birthdays))
+            }
+            Err(error) => {
+                let error = format!(
+                    "{} was changed on disk but can't be loaded: {}",
+                    self.path.display(),
+                    error
+                );
+                self.conflict = Some(error.clone());
+                Err(error.into())
+            }
+        }
+    }
+
+    fn check_locked(&self) -> Result<(), Error> {
+        match self.lock {
+            Some(_) => Ok(()),
+            None => Err(format!("{} was opened read-only", self.path.display()).into()),
+        }
+    }
+
+    /// Appends the changes to the journal, the main file is only rewritten by `compact`
+    pub fn save(&mut self, old: &BirthdayList, new: &BirthdayList) -> Result<(), Error> {
+        self.check_locked()?;
+        let old = serde_json::to_value(old)?;
+        let mut new = serde_json::to_value(new)?;
+        // Only compaction moves the journal position stored in the main file
+        new["journal_seq"] = old["journal_seq"].clone();
+        if let Some(patch) = journal::diff(&old, &new) {
+            let seq = self.journal_seq + 1;
+            journal::append(&self.journal_path(), &journal::Record { seq, patch })?;
+            self.journal_seq = seq;
+        }
+        Ok(())
+    }
+
+    pub fn needs_compaction(&self) -> bool {
+        self.journal_seq - self.compacted_seq >= JOURNAL_LIMIT
+    }
+
+    pub fn pending(&self) -> bool {
+        self.journal_seq != self.compacted_seq
+    }
+
+    /// Writes everything to the main file and empties the journal. A crash in between is fine,
+    /// the journal records are skipped on startup as the main file already includes them.
+    pub fn compact(&mut self, birthdays: &BirthdayList) -> Result<(), Error> {
+        self.check_locked()?;
+        let mut birthdays = birthdays.clone();
+        birthdays.journal_seq = self.journal_seq;
+        let data = self.format.serialize(&birthdays)?;
+        if let Err(error) = rotate_backups(&self.path) {
+            warn!(path = %self.path.display(), %error, "Failed to back up the data file");
+        }
+        write_atomically(&self.path, &data)?;
+        self.compacted_seq = self.journal_seq;
+        self.file_hash = hash(&data);
+        journal::clear(&self.journal_path())
+    }
+
+    /// Rewrites the data in another format, the previous file is kept with a .bak extension.
+    /// Returns the paths of the previous and the new file.
+    pub fn convert(
+        &mut self,
+        birthdays: &BirthdayList,
+        format: StorageFormat,
+    ) -> Result<(PathBuf, PathBuf), Error> {
+        self.check_locked()?;
+        if self.format == format {
+            return Err(format!("The data is already stored as {}", format.extension()).into());
+        }
+
+        // Make sure nothing gets lost before touching any files
+        let data = format.serialize(birthdays)?;
+        if serde_json::to_value(format.deserialize(&data)?)? != serde_json::to_value(birthdays)? {
+            return Err(format!("Converting to {} would lose data", format.extension()).into());
+        }
+
+        let previous = (self.path.clone(), self.format);
+        self.path = self.path.with_extension(format.extension());
+        self.format = format;
+        if let Err(error) = self.compact(birthdays) {
+            (self.path, self.format) = previous;
+            return Err(error);
+        }
+        std::fs::rename(&previous.0, format!("{}.bak", previous.0.display()))?;
+        Ok((previous.0, self.path.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::{ChannelId, GuildId};
+
+    use super::*;
+    use crate::GuildConfig;
+
+    /// A data file in its own temporary directory
+    fn data_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("birthdaybot-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("birthdays.json");
+        let data = StorageFormat::Json
+            .serialize(&BirthdayList::default())
+            .unwrap();
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn the_data_path_can_pick_the_format() {
+        let path = Path::new("/data/staging.toml");
+        assert_eq!(
+            preferred_format(None, Some(path)),
+            Ok(Some(StorageFormat::Toml))
+        );
+        assert_eq!(
+            preferred_format(Some("toml"), Some(path)),
+            Ok(Some(StorageFormat::Toml))
+        );
+        assert!(preferred_format(Some("json"), Some(path)).is_err());
+        assert!(preferred_format(Some("yaml"), None).is_err());
+        assert_eq!(
+            preferred_format(Some("json"), None),
+            Ok(Some(StorageFormat::Json))
+        );
+        assert_eq!(preferred_format(None, None), Ok(None));
+    }
+
+    #[test]
+    fn the_data_path_is_used_as_given() {
+        let dir = data_file("data-path").with_file_name("data");
+        assert!(DataPath::new(dir.join("birthdays.db")).is_err());
+        assert!(DataPath::new(dir.join("birthdays")).is_err());
+
+        let data_path = DataPath::new(dir.join("staging.toml")).unwrap();
+        assert!(dir.is_dir());
+        assert_eq!(
+            data_path.file(StorageFormat::Toml),
+            dir.join("staging.toml")
+        );
+        assert_eq!(
+            data_path.file(StorageFormat::Json),
+            dir.join("staging.json")
+        );
+        assert_eq!(
+            DataPath::default().file(StorageFormat::Json),
+            Path::new("birthdays.json")
+        );
+
+        let (store, _) = FileStore::open_in(&data_path, false);
+        assert_eq!(store.path(), dir.join("staging.toml"));
+    }
+
+    fn assert_same(a: &BirthdayList, b: &BirthdayList) {
+        assert_eq!(
+            serde_json::to_value(a).unwrap(),
+            serde_json::to_value(b).unwrap()
+        );
+    }
+
+    /// Saves a few mutations, including one that sets a value back to None
+    fn mutate(store: &mut FileStore, birthdays: &BirthdayList) -> BirthdayList {
+        let mut first = birthdays.clone();
+        first
+            .server_channels
+            .insert(GuildId::new(1), ChannelId::new(2));
+        first.guild_configs.insert(
+            GuildId::new(1),
+            GuildConfig {
+                prefix: Some("?".to_string()),
+                ..Default::default()
+            },
+        );
+        store.save(birthdays, &first).unwrap();
+
+        let mut second = first.clone();
+        second
+            .guild_configs
+            .get_mut(&GuildId::new(1))
+            .unwrap()
+            .prefix = None;
+        second
+            .server_channels
+            .insert(GuildId::new(3), ChannelId::new(4));
+        store.save(&first, &second).unwrap();
+        second
+    }
+
+    #[test]
+    fn replay_after_crash_restores_the_latest_state() {
+        let path = data_file("replay");
+        let original = std::fs::read_to_string(&path).unwrap();
+        let (mut store, birthdays) = FileStore::open(path.clone(), StorageFormat::Json, false);
+        let expected = mutate(&mut store, &birthdays);
+        // Crash before any compaction happened
+        drop(store);
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+        let (store, birthdays) = FileStore::open(path, StorageFormat::Json, false);
+        assert_same(&birthdays, &expected);
+        assert_eq!(store.journal_seq, 2);
+    }
+
+    #[test]
+    fn crash_during_compaction_does_not_replay_twice() {
+        let path = data_file("compaction");
+        let (mut store, birthdays) = FileStore::open(path.clone(), StorageFormat::Json, false);
+        let expected = mutate(&mut store, &birthdays);
+        let journal = std::fs::read_to_string(store.journal_path()).unwrap();
+        store.compact(&expected).unwrap();
+        // Crash after the main file was written but before the journal was cleared
+        std::fs::write(store.journal_path(), journal).unwrap();
+        drop(store);
+
+        let (mut store, birthdays) = FileStore::open(path.clone(), StorageFormat::Json, false);
+        let mut compacted = expected.clone();
+        compacted.journal_seq = 2;
+        assert_same(&birthdays, &compacted);
+        assert_eq!(store.journal_seq, 2);
+
+        // New mutations continue after the compacted ones
+        let mut changed = birthdays.clone();
+        changed.server_channels.clear();
+        store.save(&birthdays, &changed).unwrap();
+        drop(store);
+        let (store, birthdays) = FileStore::open(path, StorageFormat::Json, false);
+        assert_same(&birthdays, &changed);
+        assert_eq!(store.journal_seq, 3);
+    }
+
+    #[test]
+    fn second_instance_is_refused() {
+        let path = data_file("lock");
+        let (store, _) = FileStore::open(path.clone(), StorageFormat::Json, false);
+        let error = lock(&path).unwrap_err().to_string();
+        assert!(error.contains(&format!("PID {}", std::process::id())));
+
+        drop(store);
+        assert!(lock(&path).is_ok());
+    }
+
+    #[test]
+    fn broken_file_is_kept_until_reset() {
+        let path = data_file("broken");
+        let backups = path.with_file_name(BACKUP_DIR);
+        std::fs::write(&path, "{ \"entries\": [").unwrap();
+        let (store, birthdays) = FileStore::open(path.clone(), StorageFormat::Json, false);
+        assert!(store.conflict().is_some());
+        assert!(birthdays.entries.is_empty());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{ \"entries\": [");
+        assert_eq!(std::fs::read_dir(&backups).unwrap().count(), 1);
+        drop(store);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+
+        let (store, _) = FileStore::open(path.clone(), StorageFormat::Json, true);
+        assert!(store.conflict().is_none());
+        assert!(!path.exists());
+        assert_eq!(std::fs::read_dir(&backups).unwrap().count(), 2);
+        drop(store);
+
+        // A missing file is a fresh start
+        let (store, _) = FileStore::open(path, StorageFormat::Json, false);
+        assert!(store.conflict().is_none());
+    }
+
+    #[test]
+    fn compaction_keeps_the_newest_backups() {
+        let path = data_file("backups");
+        let (mut store, birthdays) = FileStore::open(path.clone(), StorageFormat::Json, false);
+        for _ in 0..BACKUP_COUNT + 2 {
+            store.compact(&birthdays).unwrap();
+            // Backups are told apart by their timestamp
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        let backups = std::fs::read_dir(path.with_file_name(BACKUP_DIR)).unwrap();
+        assert_eq!(backups.count(), BACKUP_COUNT);
+    }
+
+    #[test]
+    fn interrupted_write_keeps_the_previous_file() {
+        let path = data_file("atomic");
+        let original = std::fs::read_to_string(&path).unwrap();
+        let result = write_atomically_with(&path, |file| {
+            file.write_all(b"{\"entries\": [")?;
+            Err(io::Error::other("killed"))
+        });
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+        assert_eq!(
+            std::fs::read_dir(path.parent().unwrap()).unwrap().count(),
+            1
+        );
+
+        write_atomically(&path, "{}").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+    }
+
+    #[test]
+    fn incomplete_append_is_ignored() {
+        let path = data_file("incomplete");
+        let (mut store, birthdays) = FileStore::open(path.clone(), StorageFormat::Json, false);
+        let expected = mutate(&mut store, &birthdays);
+        let mut journal = std::fs::read_to_string(store.journal_path()).unwrap();
+        journal.push_str("{\"seq\":3,\"patch\":{\"serv");
+        std::fs::write(store.journal_path(), journal).unwrap();
+        drop(store);
+
+        let (store, birthdays) = FileStore::open(path, StorageFormat::Json, false);
+        assert_same(&birthdays, &expected);
+        assert_eq!(store.journal_seq, 2);
+    }
+}
@@ -0,0 +1,116 @@
+use std::{fs::OpenOptions, io::Write, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::warn;
+
+use crate::Error;
+
+/// One mutation of the data, stored as a JSON merge patch (RFC 7396) on one line of the journal
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Record {
+    pub seq: u64,
+    pub patch: Value,
+}
+
+/// Builds the merge patch turning `old` into `new`, None if there is no difference.
+/// Missing fields and nulls are the same thing for the data, so removing a key is fine for None.
+pub fn diff(old: &Value, new: &Value) -> Option<Value> {
+    if old == new {
+        return None;
+    }
+    let (Value::Object(old), Value::Object(new)) = (old, new) else {
+        return Some(new.clone());
+    };
+
+    let mut patch = Map::new();
+    for (key, old_value) in old {
+        match new.get(key) {
+            Some(new_value) => {
+                if let Some(value) = diff(old_value, new_value) {
+                    patch.insert(key.clone(), value);
+                }
+            }
+            None => {
+                patch.insert(key.clone(), Value::Null);
+            }
+        }
+    }
+    for (key, new_value) in new {
+        if !old.contains_key(key) {
+            patch.insert(key.clone(), new_value.clone());
+        }
+    }
+    Some(Value::Object(patch))
+}
+
+pub fn apply(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        unreachable!()
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            apply(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// Appends a record and only returns once it reached the disk
+pub fn append(path: &Path, record: &Record) -> Result<(), Error> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    file.sync_data()?;
+    Ok(())
+}
+
+/// All records of the journal, oldest first
+pub fn read(path: &Path) -> Result<Vec<Record>, Error> {
+    let data = match std::fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+
+    let lines: Vec<&str> = data.lines().filter(|line| !line.is_empty()).collect();
+    let mut records = Vec::with_capacity(lines.len());
+    for (index, line) in lines.iter().enumerate() {
+        match serde_json::from_str(line) {
+            Ok(record) => records.push(record),
+            // A crash in the middle of an append leaves a partial last line, that mutation never
+            // completed so it is simply dropped
+            Err(_) if index == lines.len() - 1 => {
+                warn!(path = %path.display(), "Ignoring the incomplete last line of the journal")
+            }
+            Err(error) => {
+                return Err(format!(
+                    "Line {} of {} is broken: {}",
+                    index + 1,
+                    path.display(),
+                    error
+                )
+                .into())
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Empties the journal once everything in it was written to the main file
+pub fn clear(path: &Path) -> Result<(), Error> {
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)?;
+    file.sync_all()?;
+    Ok(())
+}
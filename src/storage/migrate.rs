@@ -0,0 +1,331 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+};
+
+use poise::serenity_prelude::GuildId;
+
+#[cfg(feature = "postgres")]
+use super::postgres::PostgresStore;
+use super::{
+    file::{self, DataPath, FileStore},
+    StorageFormat,
+};
+use crate::{BirthdayList, Error};
+
+// Destination files are written under this extension until they are verified
+static INCOMPLETE_EXTENSION: &str = "incomplete";
+
+/// Where `migrate-storage` reads from or writes to
+pub enum Endpoint {
+    File(PathBuf, StorageFormat),
+    #[cfg(feature = "postgres")]
+    Postgres(String),
+}
+
+/// Format of the data file the bot would use, as picked by BIRTHDAYBOT_STORAGE_FORMAT
+#[cfg(feature = "postgres")]
+pub fn default_format(data_path: &DataPath) -> StorageFormat {
+    data_path.detect_format()
+}
+
+impl Endpoint {
+    /// Parses json, toml or postgres, the files are at the data path and the database is the
+    /// one in DATABASE_URL
+    fn parse(name: &str, data_path: &DataPath) -> Result<Endpoint, Error> {
+        #[cfg(feature = "postgres")]
+        if name == "postgres" {
+            let url = std::env::var("DATABASE_URL")
+                .map_err(|_| "Migrating to or from postgres needs DATABASE_URL")?;
+            return Ok(Endpoint::Postgres(url));
+        }
+        let format = StorageFormat::from_extension(name).ok_or_else(|| {
+            format!(
+                "Unknown storage {}, use json, toml{}",
+                name,
+                if cfg!(feature = "postgres") {
+                    " or postgres"
+                } else {
+                    ""
+                }
+            )
+        })?;
+        Ok(Endpoint::File(data_path.file(format), format))
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Endpoint::File(path, _) => path.display().to_string(),
+            #[cfg(feature = "postgres")]
+            Endpoint::Postgres(_) => "the database".to_string(),
+        }
+    }
+}
+
+/// Parses `--from <storage> --to <storage>`
+pub fn parse_args(args: &[String], data_path: &DataPath) -> Result<(Endpoint, Endpoint), Error> {
+    let value = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .and_then(|position| args.get(position + 1))
+            .ok_or_else(|| {
+                format!(
+                    "Usage: migrate-storage --from <storage> --to <storage>, {} is missing",
+                    flag
+                )
+            })
+    };
+    let from = Endpoint::parse(value("--from")?, data_path)?;
+    let to = Endpoint::parse(value("--to")?, data_path)?;
+    if from.describe() == to.describe() {
+        return Err("The source and the destination are the same".into());
+    }
+    Ok((from, to))
+}
+
+/// The number of birthdays and a hash of everything stored for each guild
+fn guild_digests(birthdays: &BirthdayList) -> Result<BTreeMap<GuildId, (usize, u64)>, Error> {
+    let guild_ids: HashSet<GuildId> = birthdays
+        .entries
+        .iter()
+        .map(|entry| entry.guild_id)
+        .chain(birthdays.server_channels.keys().copied())
+        .chain(birthdays.guild_configs.keys().copied())
+        .collect();
+    guild_ids
+        .into_iter()
+        .map(|guild_id| {
+            let mut entries: Vec<_> = birthdays
+                .entries
+                .iter()
+                .filter(|entry| entry.guild_id == guild_id)
+                .collect();
+            entries.sort_by_key(|entry| entry.user_id);
+            // Going through a Value sorts all map keys, so equal data always hashes the same
+            let content = serde_json::to_value((
+                &entries,
+                birthdays.server_channels.get(&guild_id),
+                birthdays.guild_configs.get(&guild_id),
+            ))?;
+            let mut hasher = DefaultHasher::new();
+            content.to_string().hash(&mut hasher);
+            Ok((guild_id, (entries.len(), hasher.finish())))
+        })
+        .collect()
+}
+
+fn verify(expected: &BirthdayList, written: &BirthdayList) -> Result<(), Error> {
+    let expected = guild_digests(expected)?;
+    let written = guild_digests(written)?;
+    for (guild_id, (count, hash)) in &expected {
+        match written.get(guild_id) {
+            Some((written_count, _)) if written_count != count => {
+                return Err(format!(
+                    "Guild {} has {} birthdays instead of {} after the migration",
+                    guild_id, written_count, count
+                )
+                .into())
+            }
+            Some((_, written_hash)) if written_hash == hash => {}
+            _ => return Err(format!("Guild {} differs after the migration", guild_id).into()),
+        }
+    }
+    if written.len() != expected.len() {
+        return Err("The migration added guilds that weren't in the source".into());
+    }
+    Ok(())
+}
+
+/// Keeps the source from being changed while it is copied
+enum Source {
+    File(FileStore),
+    #[cfg(feature = "postgres")]
+    Postgres(PostgresStore, i64),
+}
+
+async fn open_source(endpoint: &Endpoint) -> Result<(Source, BirthdayList), Error> {
+    let (source, mut birthdays) = match endpoint {
+        // Fails while the bot runs, as it holds the lock
+        Endpoint::File(path, format) => {
+            let (store, birthdays) = FileStore::try_open(path.clone(), *format)?;
+            (Source::File(store), birthdays)
+        }
+        #[cfg(feature = "postgres")]
+        Endpoint::Postgres(url) => {
+            let (store, birthdays) = PostgresStore::connect(url).await?;
+            let revision = store.revision().await?;
+            (Source::Postgres(store, revision), birthdays)
+        }
+    };
+    birthdays.migrate_announcements();
+    birthdays.journal_seq = 0;
+    Ok((source, birthdays))
+}
+
+/// The database has no lock, so a running bot only shows by having written in the meantime
+async fn check_unchanged(source: &Source) -> Result<(), Error> {
+    match source {
+        Source::File(_) => Ok(()),
+        #[cfg(feature = "postgres")]
+        Source::Postgres(store, revision) => {
+            if store.revision().await? != *revision {
+                return Err(
+                    "The database was changed during the migration, stop the bot and try again"
+                        .into(),
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Writes the data into a file that doesn't exist yet. It is written as `<name>.incomplete`
+/// and only gets its real name once it was read back and verified.
+fn write_file(
+    path: &PathBuf,
+    format: StorageFormat,
+    source: &Source,
+    birthdays: &BirthdayList,
+) -> Result<(), Error> {
+    if path.exists() {
+        return Err(format!("{} already exists, move it away first", path.display()).into());
+    }
+    // All formats of the default data file share one lock, which the source holds already
+    let _lock: Option<File> = match source {
+        Source::File(store)
+            if store.path().with_extension("lock") == path.with_extension("lock") =>
+        {
+            None
+        }
+        _ => Some(file::lock(path)?),
+    };
+    let incomplete = PathBuf::from(format!("{}.{}", path.display(), INCOMPLETE_EXTENSION));
+    std::fs::write(&incomplete, format.serialize(birthdays)?)?;
+    let written = format.deserialize(&std::fs::read_to_string(&incomplete)?)?;
+    verify(birthdays, &written)
+        .map_err(|error| format!("{}, the copy is left in {}", error, incomplete.display()))?;
+    std::fs::rename(&incomplete, path)?;
+    Ok(())
+}
+
+/// Writes the data into an empty database, which is marked as incomplete until the copy is
+/// verified
+#[cfg(feature = "postgres")]
+async fn write_database(url: &str, from: &str, birthdays: &BirthdayList) -> Result<(), Error> {
+    let (mut store, existing) = PostgresStore::connect_empty(url).await?;
+    store.start_migration(from).await?;
+    if !store.save(&existing, birthdays).await? {
+        return Err(
+            "The database was changed during the migration, it is marked as incomplete".into(),
+        );
+    }
+    let written = store.load().await?;
+    verify(birthdays, &written)
+        .map_err(|error| format!("{}, the database is marked as incomplete", error))?;
+    store.finish_migration().await
+}
+
+/// Copies everything from one storage to another, returns the number of guilds and birthdays
+pub async fn migrate(from: &Endpoint, to: &Endpoint) -> Result<(usize, usize), Error> {
+    let (source, birthdays) = open_source(from).await?;
+    match to {
+        Endpoint::File(path, format) => write_file(path, *format, &source, &birthdays)?,
+        #[cfg(feature = "postgres")]
+        Endpoint::Postgres(url) => write_database(url, &from.describe(), &birthdays).await?,
+    }
+    check_unchanged(&source).await?;
+    Ok((guild_digests(&birthdays)?.len(), birthdays.entries.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::ChannelId;
+
+    use super::*;
+    use crate::{offset::UtcOffset, BirthdayEntry, GuildConfig};
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "birthdaybot-migrate-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn fixture() -> BirthdayList {
+        let entry = |user_id: u64, guild_id: u64| BirthdayEntry {
+            utc_offset: UtcOffset::from_hours(2),
+            gift_note: Some("a kite".to_string()),
+            ..BirthdayEntry::example(user_id, guild_id)
+        };
+        BirthdayList {
+            entries: vec![entry(1, 1), entry(2, 1), entry(3, 2)],
+            server_channels: [(GuildId::new(1), ChannelId::new(10))].into(),
+            guild_configs: [(
+                GuildId::new(2),
+                GuildConfig {
+                    prefix: Some("?".to_string()),
+                    ..Default::default()
+                },
+            )]
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn migrates_in_both_directions() {
+        let dir = dir("both");
+        let json = Endpoint::File(dir.join("birthdays.json"), StorageFormat::Json);
+        let toml = Endpoint::File(dir.join("copy").join("birthdays.toml"), StorageFormat::Toml);
+        let back = Endpoint::File(dir.join("back").join("birthdays.json"), StorageFormat::Json);
+        std::fs::create_dir_all(dir.join("copy")).unwrap();
+        std::fs::create_dir_all(dir.join("back")).unwrap();
+        let original = fixture();
+        std::fs::write(
+            dir.join("birthdays.json"),
+            StorageFormat::Json.serialize(&original).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(migrate(&json, &toml).await.unwrap(), (2, 3));
+        assert_eq!(migrate(&toml, &back).await.unwrap(), (2, 3));
+        let (_, copied) = open_source(&back).await.unwrap();
+        verify(&original, &copied).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_to_overwrite_or_copy_a_locked_file() {
+        let dir = dir("refuse");
+        let path = dir.join("birthdays.json");
+        std::fs::write(&path, StorageFormat::Json.serialize(&fixture()).unwrap()).unwrap();
+        let source = Endpoint::File(path.clone(), StorageFormat::Json);
+        let existing = Endpoint::File(path.clone(), StorageFormat::Json);
+        assert!(migrate(&source, &existing).await.is_err());
+
+        // A running bot holds the lock
+        let _running = FileStore::try_open(path.clone(), StorageFormat::Json).unwrap();
+        std::fs::create_dir_all(dir.join("copy")).unwrap();
+        let destination =
+            Endpoint::File(dir.join("copy").join("birthdays.toml"), StorageFormat::Toml);
+        let error = migrate(&source, &destination).await.unwrap_err();
+        assert!(error.to_string().contains("already in use"));
+        assert!(!dir.join("copy").join("birthdays.toml").exists());
+    }
+
+    #[test]
+    fn changed_guilds_fail_verification() {
+        let original = fixture();
+        let mut changed = fixture();
+        changed.entries[2].utc_offset = UtcOffset::from_hours(3);
+        assert!(verify(&original, &original.clone()).is_ok());
+        assert!(verify(&original, &changed).is_err());
+        changed.entries.pop();
+        assert!(verify(&original, &changed).is_err());
+    }
+}
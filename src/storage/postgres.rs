@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use poise::serenity_prelude::{ChannelId, GuildId};
+use sqlx::{postgres::PgPoolOptions, types::Json, PgPool};
+
+use super::guild_rows;
+use crate::{BirthdayEntry, BirthdayList, Error, GuildConfig};
+
+static MAX_CONNECTIONS: u32 = 5;
+
+/// Data in PostgreSQL, shared by any number of bot instances. Every write bumps the revision in
+/// `bot_state` and only goes through if nobody else wrote since we last loaded.
+pub struct PostgresStore {
+    pool: PgPool,
+    revision: i64,
+}
+
+async fn load(pool: &PgPool) -> Result<(i64, BirthdayList), Error> {
+    // All tables have to be read at the same revision
+    let mut transaction = pool.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+        .execute(&mut *transaction)
+        .await?;
+
+    let (revision, Json(mut birthdays)): (i64, Json<BirthdayList>) =
+        sqlx::query_as("SELECT revision, data FROM bot_state WHERE id = 1")
+            .fetch_one(&mut *transaction)
+            .await?;
+    let entries: Vec<(Json<BirthdayEntry>,)> =
+        sqlx::query_as("SELECT entry FROM birthday_entries ORDER BY guild_id, user_id")
+            .fetch_all(&mut *transaction)
+            .await?;
+    birthdays.entries = entries.into_iter().map(|(Json(entry),)| entry).collect();
+
+    let guilds: Vec<(i64, Option<i64>, Option<Json<GuildConfig>>)> =
+        sqlx::query_as("SELECT guild_id, announcement_channel, config FROM guild_configs")
+            .fetch_all(&mut *transaction)
+            .await?;
+    for (guild_id, channel, config) in guilds {
+        let guild_id = GuildId::new(guild_id as u64);
+        if let Some(channel) = channel {
+            birthdays
+                .server_channels
+                .insert(guild_id, ChannelId::new(channel as u64));
+        }
+        if let Some(Json(config)) = config {
+            birthdays.guild_configs.insert(guild_id, config);
+        }
+    }
+
+    transaction.commit().await?;
+    Ok((revision, birthdays))
+}
+
+impl PostgresStore {
+    pub async fn connect(url: &str) -> Result<(PostgresStore, BirthdayList), Error> {
+        let pool = PgPoolOptions::new()
+            .max_connections(MAX_CONNECTIONS)
+            .connect(url)
+            .await?;
+        sqlx::migrate!().run(&pool).await?;
+        let (incomplete,): (Option<String>,) =
+            sqlx::query_as("SELECT incomplete_migration FROM bot_state WHERE id = 1")
+                .fetch_one(&pool)
+                .await?;
+        if let Some(source) = incomplete {
+            return Err(format!(
+                "The database holds an incomplete migration from {}, empty it and migrate again",
+                source
+            )
+            .into());
+        }
+        let (revision, birthdays) = load(&pool).await?;
+        Ok((PostgresStore { pool, revision }, birthdays))
+    }
+
+    /// Connects to a database nothing was saved to yet, to copy data into it
+    pub async fn connect_empty(url: &str) -> Result<(PostgresStore, BirthdayList), Error> {
+        let (store, existing) = PostgresStore::connect(url).await?;
+        if store.revision != 0 || !existing.entries.is_empty() {
+            return Err("The database already contains data".into());
+        }
+        Ok((store, existing))
+    }
+
+    /// Marks the database as incomplete until `finish_migration`, nobody connects to it meanwhile
+    pub async fn start_migration(&self, source: &str) -> Result<(), Error> {
+        sqlx::query("UPDATE bot_state SET incomplete_migration = $1 WHERE id = 1")
+            .bind(source)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn finish_migration(&self) -> Result<(), Error> {
+        sqlx::query("UPDATE bot_state SET incomplete_migration = NULL WHERE id = 1")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Loads everything again, regardless of whether anything changed
+    pub async fn load(&self) -> Result<BirthdayList, Error> {
+        Ok(load(&self.pool).await?.1)
+    }
+
+    pub async fn revision(&self) -> Result<i64, Error> {
+        let (revision,): (i64,) = sqlx::query_as("SELECT revision FROM bot_state WHERE id = 1")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(revision)
+    }
+
+    /// Returns the latest data if another instance saved since we last loaded
+    pub async fn changes(&mut self) -> Result<Option<BirthdayList>, Error> {
+        if self.revision().await? == self.revision {
+            return Ok(None);
+        }
+        let (revision, birthdays) = load(&self.pool).await?;
+        self.revision = revision;
+        Ok(Some(birthdays))
+    }
+
+    /// Writes the rows that differ between `old` and `new`. Returns false without writing
+    /// anything if another instance saved since we last loaded.
+    pub async fn save(&mut self, old: &BirthdayList, new: &BirthdayList) -> Result<bool, Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        // Everything without a table of its own is stored as one document
+        let rest = BirthdayList {
+            entries: Vec::new(),
+            server_channels: HashMap::new(),
+            guild_configs: HashMap::new(),
+            ..new.clone()
+        };
+        let claimed = sqlx::query(
+            "UPDATE bot_state SET revision = revision + 1, data = $1 WHERE id = 1 AND revision = $2",
+        )
+        .bind(Json(&rest))
+        .bind(self.revision)
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+        if claimed == 0 {
+            return Ok(false);
+        }
+
+        let mut old_entries = HashMap::new();
+        for entry in &old.entries {
+            old_entries.insert(
+                (entry.guild_id, entry.user_id),
+                serde_json::to_value(entry)?,
+            );
+        }
+        for entry in &new.entries {
+            let value = serde_json::to_value(entry)?;
+            if old_entries
+                .remove(&(entry.guild_id, entry.user_id))
+                .as_ref()
+                == Some(&value)
+            {
+                continue;
+            }
+            sqlx::query(
+                "INSERT INTO birthday_entries (guild_id, user_id, entry) VALUES ($1, $2, $3)
+                 ON CONFLICT (guild_id, user_id) DO UPDATE SET entry = EXCLUDED.entry",
+            )
+            .bind(entry.guild_id.get() as i64)
+            .bind(entry.user_id.get() as i64)
+            .bind(Json(value))
+            .execute(&mut *transaction)
+            .await?;
+        }
+        for (guild_id, user_id) in old_entries.into_keys() {
+            sqlx::query("DELETE FROM birthday_entries WHERE guild_id = $1 AND user_id = $2")
+                .bind(guild_id.get() as i64)
+                .bind(user_id.get() as i64)
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        let mut old_guilds = guild_rows(old)?;
+        for (guild_id, row) in guild_rows(new)? {
+            if old_guilds.remove(&guild_id).as_ref() == Some(&row) {
+                continue;
+            }
+            let (channel, config) = row;
+            sqlx::query(
+                "INSERT INTO guild_configs (guild_id, announcement_channel, config) VALUES ($1, $2, $3)
+                 ON CONFLICT (guild_id) DO UPDATE
+                 SET announcement_channel = EXCLUDED.announcement_channel, config = EXCLUDED.config",
+            )
+            .bind(guild_id.get() as i64)
+            .bind(channel.map(|channel| channel.get() as i64))
+            .bind(config.map(Json))
+            .execute(&mut *transaction)
+            .await?;
+        }
+        for guild_id in old_guilds.into_keys() {
+            sqlx::query("DELETE FROM guild_configs WHERE guild_id = $1")
+                .bind(guild_id.get() as i64)
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+        self.revision += 1;
+        Ok(true)
+    }
+}
@@ -0,0 +1,325 @@
+use crate::{BirthdayList, Error};
+
+/// File formats the data can be stored in, picked by the file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum StorageFormat {
+    #[name = "json"]
+    Json,
+    #[name = "toml"]
+    Toml,
+}
+
+impl StorageFormat {
+    pub const ALL: [StorageFormat; 2] = [StorageFormat::Json, StorageFormat::Toml];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            StorageFormat::Json => "json",
+            StorageFormat::Toml => "toml",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<StorageFormat> {
+        StorageFormat::ALL
+            .into_iter()
+            .find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+
+    /// Serializes with sorted keys and one block per entry, so hand edits produce small diffs
+    pub fn serialize(self, birthdays: &BirthdayList) -> Result<String, Error> {
+        Ok(match self {
+            // Going through a Value sorts all map keys
+            StorageFormat::Json => serde_json::to_string_pretty(&serde_json::to_value(birthdays)?)?,
+            StorageFormat::Toml => toml::to_string_pretty(&toml::Value::try_from(birthdays)?)?,
+        })
+    }
+
+    pub fn deserialize(self, data: &str) -> Result<BirthdayList, Error> {
+        Ok(match self {
+            StorageFormat::Json => serde_json::from_str(data)?,
+            StorageFormat::Toml => toml::from_str(data)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveDate};
+    use poise::serenity_prelude::{ChannelId, ForumTagId, GuildId, RoleId, UserId};
+
+    use super::*;
+    use crate::{
+        birthday_role::AppliedRole,
+        embeds::EmbedConfig,
+        events::{EventAnnouncement, EventKind},
+        export::ExportConfig,
+        facts::FactKind,
+        format::{DateFormat, DateOrder},
+        global::GlobalBirthday,
+        google_calendar::CalendarEvent,
+        missed::{MissReason, Missed},
+        offset::UtcOffset,
+        prune::LeaveAction,
+        reactions::ReactionConfig,
+        reminders::Reminder,
+        retry::{FailedAnnouncement, FailureKind},
+        themes::{DayOfYear, Theme, ThemeConfig},
+        wishes::Wish,
+        Announcement, AnnouncementTime, AuditLogEntry, BirthdayEntry, DeletedEntry, GuildConfig,
+        LeapDay, QuietDate, Snooze, Visibility,
+    };
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    /// Data with every optional field set, so a lost field shows up as a difference
+    fn fixture() -> BirthdayList {
+        let timestamp = DateTime::parse_from_rfc3339("2024-06-14T12:30:00Z")
+            .unwrap()
+            .to_utc();
+        let entry = BirthdayEntry {
+            user_id: UserId::new(1),
+            guild_id: GuildId::new(2),
+            name: "Anna \"the\" = [Tester]".to_string(),
+            date: date(1995, 6, 14),
+            year: Some(1995),
+            last_announcement: None,
+            utc_offset: UtcOffset::from_hours(-5),
+            snoozed: Some(Snooze {
+                date: date(2024, 6, 13),
+                years: vec![2024],
+            }),
+            visibility: Visibility::ModsOnly,
+            private_year: true,
+            missing_since: Some(timestamp),
+            created_at: Some(timestamp),
+            updated_at: Some(timestamp),
+            set_by: Some(UserId::new(4)),
+            gift_note: Some("fountain pens".to_string()),
+            note: Some("https://example.com/wishlist".to_string()),
+            kind: EventKind::Birthday,
+        };
+
+        let mut birthdays = BirthdayList {
+            entries: vec![entry.clone()],
+            server_channels: [(GuildId::new(2), ChannelId::new(3))].into(),
+            audit_log: vec![AuditLogEntry {
+                timestamp,
+                guild_id: GuildId::new(2),
+                actor: UserId::new(4),
+                action: "snoozed something".to_string(),
+            }],
+            guild_configs: [(
+                GuildId::new(2),
+                GuildConfig {
+                    quiet_dates: vec![QuietDate {
+                        day: 31,
+                        month: 12,
+                        year: Some(2024),
+                    }],
+                    disabled_commands: vec!["time_left".to_string()],
+                    prefix: Some("?".to_string()),
+                    date_format: DateFormat {
+                        order: DateOrder::Ymd,
+                        separator: '-',
+                    },
+                    export: Some(ExportConfig {
+                        channel: ChannelId::new(5),
+                        last_export: Some(timestamp),
+                        failed_at: Some(timestamp),
+                    }),
+                    fun_facts: Some(FactKind::Births),
+                    month_roles: [(6, RoleId::new(6))].into(),
+                    next_up_footer: true,
+                    show_ages: false,
+                    channel_nudged_at: Some(timestamp),
+                    organizer_role: Some(RoleId::new(7)),
+                    opt_out_role: Some(RoleId::new(8)),
+                    birthday_manager_role: Some(RoleId::new(12)),
+                    reactions: Some(ReactionConfig {
+                        phrases: vec!["hbd".to_string()],
+                    }),
+                    account_anniversaries: true,
+                    retirement_age: Some(65),
+                    life_expectancy: Some(90),
+                    themes: ThemeConfig {
+                        enabled: true,
+                        custom: vec![Theme {
+                            name: "pride".to_string(),
+                            start: DayOfYear { month: 6, day: 1 },
+                            end: DayOfYear { month: 6, day: 30 },
+                            emoji: "🏳️‍🌈".to_string(),
+                            line: "Happy pride!".to_string(),
+                        }],
+                    },
+                    google_calendar: Some("staff@group.calendar.google.com".to_string()),
+                    system_channel_fallback: false,
+                    forum_tags: vec![ForumTagId::new(12)],
+                    year_in_review: true,
+                    year_reviewed: Some(2023),
+                    birthday_role: Some(RoleId::new(13)),
+                    announcement_time: Some(AnnouncementTime {
+                        hour: 9,
+                        utc_offset: UtcOffset::from_hours(-5),
+                    }),
+                    leap_day: LeapDay::March1,
+                    mention_celebrants: true,
+                    on_member_leave: LeaveAction::Remove,
+                    announcement_embed: Some(EmbedConfig {
+                        color: Some(0xff66cc),
+                        image_url: Some("https://example.com/cake.png".to_string()),
+                    }),
+                    ping_role: Some(RoleId::new(14)),
+                    global_birthdays: true,
+                    channel_failures: 2,
+                    language: Some("de".to_string()),
+                    quiet_replies: true,
+                    announcement_notes: true,
+                    catch_up_days: 3,
+                    announcement_messages: vec!["🥳 {mention} turns {age}!".to_string()],
+                    missed_notified_at: Some(
+                        DateTime::parse_from_rfc3339("2024-06-20T08:00:00Z")
+                            .unwrap()
+                            .to_utc(),
+                    ),
+                },
+            )]
+            .into(),
+            deleted: vec![DeletedEntry {
+                entry: entry.clone(),
+                deleted_at: timestamp,
+            }],
+            announced: [Announcement {
+                guild_id: GuildId::new(2),
+                user_id: UserId::new(1),
+                year: 2024,
+            }]
+            .into(),
+            failed_announcements: vec![FailedAnnouncement {
+                guild_id: GuildId::new(2),
+                user_id: UserId::new(1),
+                occurrence: date(2024, 6, 14),
+                attempts: 2,
+                kind: FailureKind::MissingPermissions,
+            }],
+            opt_out_skips: [(GuildId::new(2), 3)].into(),
+            announced_anniversaries: [Announcement {
+                guild_id: GuildId::new(2),
+                user_id: UserId::new(1),
+                year: 2024,
+            }]
+            .into(),
+            anniversary_opt_outs: [UserId::new(3)].into(),
+            left_guilds: [(GuildId::new(5), timestamp)].into(),
+            birthday_opt_outs: [(GuildId::new(2), UserId::new(3))].into(),
+            global_birthdays: [(
+                UserId::new(4),
+                GlobalBirthday {
+                    name: "global".to_string(),
+                    date: date(1990, 2, 3),
+                    year: Some(1990),
+                    utc_offset: UtcOffset::from_minutes(-3 * 60 - 30),
+                    private_year: true,
+                    updated_at: timestamp,
+                },
+            )]
+            .into(),
+            coverage_history: [(GuildId::new(2), [(date(2024, 6, 1), 63)].into())].into(),
+            calendar_events: vec![CalendarEvent {
+                guild_id: GuildId::new(2),
+                user_id: UserId::new(1),
+                calendar_id: "staff@group.calendar.google.com".to_string(),
+                event_id: "event1".to_string(),
+                summary: "🎂 user's birthday".to_string(),
+                date: date(2000, 6, 14),
+            }],
+            missed: vec![Missed {
+                guild_id: GuildId::new(2),
+                user_id: UserId::new(1),
+                name: "user".to_string(),
+                occurrence: date(2024, 6, 14),
+                reason: MissReason::Failed(FailureKind::MissingPermissions),
+            }],
+            wishes: vec![Wish {
+                guild_id: GuildId::new(2),
+                from: UserId::new(3),
+                to: UserId::new(1),
+                date: date(2024, 6, 14),
+            }],
+            birthday_roles: vec![AppliedRole {
+                guild_id: GuildId::new(2),
+                user_id: UserId::new(1),
+                role_id: RoleId::new(13),
+                day: date(2024, 6, 14),
+            }],
+            reminders: vec![Reminder {
+                guild_id: GuildId::new(2),
+                subscriber: UserId::new(3),
+                target: UserId::new(1),
+                days_before: 7,
+                reminded: Some(date(2024, 6, 14)),
+            }],
+            events: vec![BirthdayEntry {
+                name: "the founding day".to_string(),
+                kind: EventKind::Custom {
+                    label: "the founding day".to_string(),
+                    server_wide: true,
+                },
+                ..entry
+            }],
+            announced_events: [EventAnnouncement {
+                guild_id: GuildId::new(2),
+                label: "the founding day".to_string(),
+                year: 2024,
+            }]
+            .into(),
+            ..Default::default()
+        };
+        birthdays
+            .usage
+            .record("get_birthday", Some(GuildId::new(2)), date(2024, 6, 14));
+        birthdays
+    }
+
+    fn assert_same(a: &BirthdayList, b: &BirthdayList) {
+        assert_eq!(
+            serde_json::to_value(a).unwrap(),
+            serde_json::to_value(b).unwrap()
+        );
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let birthdays = fixture();
+        let data = StorageFormat::Json.serialize(&birthdays).unwrap();
+        assert_same(&birthdays, &StorageFormat::Json.deserialize(&data).unwrap());
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let birthdays = fixture();
+        let data = StorageFormat::Toml.serialize(&birthdays).unwrap();
+        assert_same(&birthdays, &StorageFormat::Toml.deserialize(&data).unwrap());
+    }
+
+    #[test]
+    fn conversion_in_both_directions() {
+        let birthdays = fixture();
+        let toml = StorageFormat::Toml.serialize(&birthdays).unwrap();
+        let from_toml = StorageFormat::Toml.deserialize(&toml).unwrap();
+        let json = StorageFormat::Json.serialize(&from_toml).unwrap();
+        let from_json = StorageFormat::Json.deserialize(&json).unwrap();
+        assert_same(&birthdays, &from_json);
+        assert_eq!(toml, StorageFormat::Toml.serialize(&from_json).unwrap());
+    }
+
+    #[test]
+    fn json_keys_are_sorted() {
+        let data = StorageFormat::Json.serialize(&fixture()).unwrap();
+        let audit_log = data.find("\"audit_log\"").unwrap();
+        let entries = data.find("\"entries\"").unwrap();
+        let usage = data.find("\"usage\"").unwrap();
+        assert!(audit_log < entries && entries < usage);
+    }
+}
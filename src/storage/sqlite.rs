@@ -0,0 +1,264 @@
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use poise::serenity_prelude::{ChannelId, GuildId};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    types::Json,
+    SqlitePool,
+};
+use tracing::info;
+
+use super::{guild_rows, StorageFormat};
+use crate::{BirthdayEntry, BirthdayList, Error, GuildConfig};
+
+// SQLite only allows one writer at a time anyway
+static MAX_CONNECTIONS: u32 = 1;
+
+/// Data in an SQLite database, every write only touches the rows that changed. The revision in
+/// `bot_state` is bumped by every write, so edits made with other tools are picked up.
+pub struct SqliteStore {
+    pool: SqlitePool,
+    revision: i64,
+}
+
+async fn load(pool: &SqlitePool) -> Result<(i64, BirthdayList), Error> {
+    // A transaction reads all tables at the same revision
+    let mut transaction = pool.begin().await?;
+
+    let (revision, Json(mut birthdays)): (i64, Json<BirthdayList>) =
+        sqlx::query_as("SELECT revision, data FROM bot_state WHERE id = 1")
+            .fetch_one(&mut *transaction)
+            .await?;
+    let entries: Vec<(Json<BirthdayEntry>,)> =
+        sqlx::query_as("SELECT entry FROM birthday_entries ORDER BY guild_id, user_id")
+            .fetch_all(&mut *transaction)
+            .await?;
+    birthdays.entries = entries.into_iter().map(|(Json(entry),)| entry).collect();
+
+    let guilds: Vec<(i64, Option<i64>, Option<Json<GuildConfig>>)> =
+        sqlx::query_as("SELECT guild_id, announcement_channel, config FROM guild_configs")
+            .fetch_all(&mut *transaction)
+            .await?;
+    for (guild_id, channel, config) in guilds {
+        let guild_id = GuildId::new(guild_id as u64);
+        if let Some(channel) = channel {
+            birthdays
+                .server_channels
+                .insert(guild_id, ChannelId::new(channel as u64));
+        }
+        if let Some(Json(config)) = config {
+            birthdays.guild_configs.insert(guild_id, config);
+        }
+    }
+
+    transaction.commit().await?;
+    Ok((revision, birthdays))
+}
+
+impl SqliteStore {
+    /// Opens the database, creating it if needed. A database nothing was saved to yet gets the
+    /// contents of `data_file` if it exists, the file itself is left untouched.
+    pub async fn open(
+        path: &Path,
+        data_file: &Path,
+        format: StorageFormat,
+    ) -> Result<(SqliteStore, BirthdayList), Error> {
+        let options = SqliteConnectOptions::from_str(&format!("sqlite://{}", path.display()))?
+            .create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(MAX_CONNECTIONS)
+            .connect_with(options)
+            .await?;
+        sqlx::migrate!("./migrations_sqlite").run(&pool).await?;
+        let (revision, birthdays) = load(&pool).await?;
+        let mut store = SqliteStore { pool, revision };
+        if revision != 0 {
+            return Ok((store, birthdays));
+        }
+
+        let Ok(data) = std::fs::read_to_string(data_file) else {
+            return Ok((store, birthdays));
+        };
+        if data.trim().is_empty() {
+            return Ok((store, birthdays));
+        }
+        let imported = format.deserialize(&data).map_err(|error| {
+            format!(
+                "{} couldn't be imported into the database: {}",
+                data_file.display(),
+                error
+            )
+        })?;
+        if !store.save(&birthdays, &imported).await? {
+            return Err("The database was changed while importing the data file".into());
+        }
+        info!(
+            birthdays = imported.entries.len(),
+            from = %data_file.display(),
+            into = %path.display(),
+            "Imported the data file into the database"
+        );
+        Ok((store, imported))
+    }
+
+    pub async fn revision(&self) -> Result<i64, Error> {
+        let (revision,): (i64,) = sqlx::query_as("SELECT revision FROM bot_state WHERE id = 1")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(revision)
+    }
+
+    /// Returns the latest data if anyone else saved since we last loaded
+    pub async fn changes(&mut self) -> Result<Option<BirthdayList>, Error> {
+        if self.revision().await? == self.revision {
+            return Ok(None);
+        }
+        let (revision, birthdays) = load(&self.pool).await?;
+        self.revision = revision;
+        Ok(Some(birthdays))
+    }
+
+    /// Writes the rows that differ between `old` and `new`. Returns false without writing
+    /// anything if anyone else saved since we last loaded.
+    pub async fn save(&mut self, old: &BirthdayList, new: &BirthdayList) -> Result<bool, Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        // Everything without a table of its own is stored as one document
+        let rest = BirthdayList {
+            entries: Vec::new(),
+            server_channels: HashMap::new(),
+            guild_configs: HashMap::new(),
+            ..new.clone()
+        };
+        let claimed = sqlx::query(
+            "UPDATE bot_state SET revision = revision + 1, data = ? WHERE id = 1 AND revision = ?",
+        )
+        .bind(Json(&rest))
+        .bind(self.revision)
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+        if claimed == 0 {
+            return Ok(false);
+        }
+
+        let mut old_entries = HashMap::new();
+        for entry in &old.entries {
+            old_entries.insert(
+                (entry.guild_id, entry.user_id),
+                serde_json::to_value(entry)?,
+            );
+        }
+        for entry in &new.entries {
+            let value = serde_json::to_value(entry)?;
+            if old_entries
+                .remove(&(entry.guild_id, entry.user_id))
+                .as_ref()
+                == Some(&value)
+            {
+                continue;
+            }
+            sqlx::query(
+                "INSERT INTO birthday_entries (guild_id, user_id, entry) VALUES (?, ?, ?)
+                 ON CONFLICT (guild_id, user_id) DO UPDATE SET entry = excluded.entry",
+            )
+            .bind(entry.guild_id.get() as i64)
+            .bind(entry.user_id.get() as i64)
+            .bind(Json(value))
+            .execute(&mut *transaction)
+            .await?;
+        }
+        for (guild_id, user_id) in old_entries.into_keys() {
+            sqlx::query("DELETE FROM birthday_entries WHERE guild_id = ? AND user_id = ?")
+                .bind(guild_id.get() as i64)
+                .bind(user_id.get() as i64)
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        let mut old_guilds = guild_rows(old)?;
+        for (guild_id, row) in guild_rows(new)? {
+            if old_guilds.remove(&guild_id).as_ref() == Some(&row) {
+                continue;
+            }
+            let (channel, config) = row;
+            sqlx::query(
+                "INSERT INTO guild_configs (guild_id, announcement_channel, config) VALUES (?, ?, ?)
+                 ON CONFLICT (guild_id) DO UPDATE
+                 SET announcement_channel = excluded.announcement_channel, config = excluded.config",
+            )
+            .bind(guild_id.get() as i64)
+            .bind(channel.map(|channel| channel.get() as i64))
+            .bind(config.map(Json))
+            .execute(&mut *transaction)
+            .await?;
+        }
+        for guild_id in old_guilds.into_keys() {
+            sqlx::query("DELETE FROM guild_configs WHERE guild_id = ?")
+                .bind(guild_id.get() as i64)
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+        self.revision += 1;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::UserId;
+
+    use super::*;
+
+    fn dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "birthdaybot-sqlite-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entry(user_id: u64) -> BirthdayEntry {
+        BirthdayEntry::example(user_id, 1)
+    }
+
+    #[tokio::test]
+    async fn imports_the_data_file_once_and_saves_changes() {
+        let dir = dir("import");
+        let database = dir.join("birthdays.db");
+        let data_file = dir.join("birthdays.json");
+        let original = BirthdayList {
+            entries: vec![entry(1), entry(2)],
+            server_channels: [(GuildId::new(1), ChannelId::new(10))].into(),
+            ..Default::default()
+        };
+        std::fs::write(
+            &data_file,
+            StorageFormat::Json.serialize(&original).unwrap(),
+        )
+        .unwrap();
+
+        let (mut store, imported) = SqliteStore::open(&database, &data_file, StorageFormat::Json)
+            .await
+            .unwrap();
+        assert_eq!(imported.entries.len(), 2);
+        let mut changed = imported.clone();
+        changed.entries.remove(0);
+        assert!(store.save(&imported, &changed).await.unwrap());
+        drop(store);
+
+        // The file isn't imported again on top of the saved data
+        let (store, reopened) = SqliteStore::open(&database, &data_file, StorageFormat::Json)
+            .await
+            .unwrap();
+        assert_eq!(store.revision().await.unwrap(), 2);
+        assert_eq!(reopened.entries.len(), 1);
+        assert_eq!(reopened.entries[0].user_id, UserId::new(2));
+        assert_eq!(reopened.server_channels, original.server_channels);
+    }
+}
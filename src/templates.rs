@@ -0,0 +1,249 @@
+use poise::serenity_prelude as serenity;
+
+use crate::{audit, format, i18n, quiz, Context, Error};
+
+static TEMPLATE_LIMIT: usize = 500; // characters
+static MAX_TEMPLATES: usize = 25;
+static PLACEHOLDERS: [&str; 3] = ["name", "mention", "age"];
+
+/// The template as it is stored, an error message if it is empty, too long, uses unknown
+/// placeholders or doesn't say whose birthday it is
+pub fn check(text: &str) -> Result<String, String> {
+    let template = text.trim();
+    if template.is_empty() || template.chars().count() > TEMPLATE_LIMIT {
+        return Err(format!(
+            "🐺🎩❌ Announcement messages must be 1 to {} characters long!",
+            TEMPLATE_LIMIT
+        ));
+    }
+    let unknown = template
+        .split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+        .find(|name| !PLACEHOLDERS.contains(name));
+    if let Some(unknown) = unknown {
+        return Err(format!(
+            "🐺🎩❌ There is no placeholder `{{{}}}`, use `{{name}}`, `{{mention}}` or `{{age}}`!",
+            unknown
+        ));
+    }
+    if !template.contains("{name}") && !template.contains("{mention}") {
+        return Err(
+            "🐺🎩❌ Announcement messages need `{name}` or `{mention}`, or nobody knows whose birthday it is!"
+                .to_string(),
+        );
+    }
+    Ok(template.to_string())
+}
+
+/// Fills in the placeholders like `i18n::text` does. Announcements of several members have no
+/// `{age}`, their `{name}` carries everyone's age like `Anna (30th) and Ben`.
+pub fn render(template: &str, name: &str, mention: &str, age: &str) -> String {
+    i18n::fill(
+        template,
+        &[("name", &name), ("mention", &mention), ("age", &age)],
+    )
+}
+
+/// A random template of the guild's pool, None to use the built-in announcement
+pub fn pick(templates: &[String]) -> Option<&str> {
+    if templates.is_empty() {
+        return None;
+    }
+    Some(&templates[quiz::random_below(templates.len())])
+}
+
+/// Adds a message to the pool birthday announcements pick from at random
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn add_announcement_message(
+    ctx: Context<'_>,
+    #[description = "The message, with {name}, {mention} and {age} as placeholders"]
+    #[rest]
+    message: String,
+) -> Result<(), Error> {
+    let template = match check(&message) {
+        Ok(template) => template,
+        Err(message) => {
+            ctx.say(message).await?;
+            return Ok(());
+        }
+    };
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let templates = &mut birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .announcement_messages;
+    if templates.len() >= MAX_TEMPLATES {
+        ctx.say(format!(
+            "🐺🎩❌ There can be at most {} announcement messages, remove one first!",
+            MAX_TEMPLATES
+        ))
+        .await?;
+        return Ok(());
+    }
+    templates.push(template);
+    let index = templates.len();
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("added announcement message #{}", index),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.say(format!(
+        "💬🎈 Added announcement message #{}, announcements now pick one of {} at random!",
+        index, index
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Removes a message from the pool of birthday announcements, see `list_announcement_messages`
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn remove_announcement_message(
+    ctx: Context<'_>,
+    #[description = "Number of the message in list_announcement_messages"]
+    #[min = 1]
+    index: usize,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let templates = &mut birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .announcement_messages;
+    if index == 0 || index > templates.len() {
+        ctx.say(format!(
+            "🐺🎩❌ There is no announcement message #{}, see `list_announcement_messages`!",
+            index
+        ))
+        .await?;
+        return Ok(());
+    }
+    templates.remove(index - 1);
+    let message = if templates.is_empty() {
+        format!(
+            "💬 Removed announcement message #{}, announcements use the default message again!",
+            index
+        )
+    } else {
+        format!(
+            "💬 Removed announcement message #{}, the ones after it moved up by one!",
+            index
+        )
+    };
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("removed announcement message #{}", index),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Lists the messages birthday announcements pick from in this server, with their numbers
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn list_announcement_messages(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    let templates = birthdays
+        .guild_configs
+        .get(&guild_id)
+        .map(|config| config.announcement_messages.as_slice())
+        .unwrap_or_default();
+    if templates.is_empty() {
+        ctx.say(
+            "💬 No announcement messages yet, announcements use the default message. Add one with `add_announcement_message`!",
+        )
+        .await?;
+        return Ok(());
+    }
+    let lines: Vec<String> = templates
+        .iter()
+        .enumerate()
+        .map(|(index, template)| format!("{}. {}", index + 1, format::escape(template)))
+        .collect();
+    for message in format::split_message("💬🎈 Announcement messages:", &lines) {
+        ctx.send(
+            poise::CreateReply::default()
+                .content(message)
+                .allowed_mentions(serenity::CreateAllowedMentions::new()),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_need_a_name_and_known_placeholders() {
+        assert_eq!(
+            check("  Party time, {mention}! 🎂 \n").unwrap(),
+            "Party time, {mention}! 🎂"
+        );
+        assert!(check("Happy {age} birthday {name}!").is_ok());
+        assert!(check("Happy birthday!").is_err());
+        assert!(check("Happy birthday {nickname}, {name}!").is_err());
+        assert!(check(" ").is_err());
+        assert!(check(&format!("{{name}}{}", "a".repeat(TEMPLATE_LIMIT))).is_err());
+    }
+
+    #[test]
+    fn placeholders_are_filled_in_once() {
+        assert_eq!(
+            render(
+                "{mention} turns {age}, cheers {name}! {other}",
+                "{age}",
+                "<@1>",
+                "21st"
+            ),
+            "<@1> turns 21st, cheers {age}! {other}"
+        );
+        assert_eq!(render("🎉 {name}", "Anna", "<@1>", ""), "🎉 Anna");
+        // Several members share one announcement
+        assert_eq!(
+            render(
+                "Happy {age} birthday {name}!",
+                "Anna (30th) and Ben",
+                "<@1> and <@2>",
+                ""
+            ),
+            "Happy birthday Anna (30th) and Ben!"
+        );
+        assert_eq!(
+            render("{age} cheers, {name}", "Anna", "<@1>", ""),
+            "cheers, Anna"
+        );
+        assert_eq!(
+            render("{mention} turns {age}!", "Anna", "<@1>", ""),
+            "<@1> turns!"
+        );
+        assert_eq!(pick(&[]), None);
+        assert_eq!(pick(&["{name}".to_string()]), Some("{name}"));
+    }
+}
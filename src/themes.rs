@@ -0,0 +1,368 @@
+use chrono::{Datelike, NaiveDate};
+use poise::serenity_prelude::GuildId;
+use serde::{Deserialize, Serialize};
+
+use crate::{args_to_date, audit, BirthdayList, Context, Error, Toggle};
+
+/// A day of the year, themes span from one to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DayOfYear {
+    pub month: u32,
+    pub day: u32,
+}
+
+impl DayOfYear {
+    const fn new(month: u32, day: u32) -> DayOfYear {
+        DayOfYear { month, day }
+    }
+
+    fn of(date: NaiveDate) -> DayOfYear {
+        DayOfYear::new(date.month(), date.day())
+    }
+}
+
+/// Decoration added around announcements between `start` and `end`, both included
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    pub start: DayOfYear,
+    pub end: DayOfYear,
+    // Put in front of the announcement
+    pub emoji: String,
+    // Added as the last line of the announcement
+    pub line: String,
+}
+
+impl Theme {
+    /// Whether the date falls into the theme, ranges like December 20th to January 5th wrap
+    /// around the end of the year
+    fn contains(&self, date: NaiveDate) -> bool {
+        let date = DayOfYear::of(date);
+        if self.start <= self.end {
+            self.start <= date && date <= self.end
+        } else {
+            date >= self.start || date <= self.end
+        }
+    }
+
+    pub fn decorate(&self, message: &str) -> String {
+        format!("{} {}\n{}", self.emoji, message, self.line)
+    }
+}
+
+fn builtin() -> Vec<Theme> {
+    let theme = |name: &str, start, end, emoji: &str, line: &str| Theme {
+        name: name.to_string(),
+        start,
+        end,
+        emoji: emoji.to_string(),
+        line: line.to_string(),
+    };
+    vec![
+        theme(
+            "spring",
+            DayOfYear::new(3, 20),
+            DayOfYear::new(4, 10),
+            "🌷",
+            "🐣 Have a blooming spring birthday!",
+        ),
+        theme(
+            "summer",
+            DayOfYear::new(7, 1),
+            DayOfYear::new(8, 31),
+            "☀️",
+            "🍦 Enjoy the sun on your special day!",
+        ),
+        theme(
+            "halloween",
+            DayOfYear::new(10, 24),
+            DayOfYear::new(10, 31),
+            "🎃",
+            "👻 Have a spooktacular birthday!",
+        ),
+        theme(
+            "winter",
+            DayOfYear::new(12, 1),
+            DayOfYear::new(1, 5),
+            "❄️",
+            "☃️ Stay warm and cozy on your birthday!",
+        ),
+    ]
+}
+
+/// Seasonal decorations of the announcements of a guild
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub enabled: bool,
+    // Added by admins, a custom theme replaces the built-in theme of the same name
+    pub custom: Vec<Theme>,
+}
+
+impl ThemeConfig {
+    /// All themes of the guild, custom ones first so they win where they overlap
+    fn themes(&self) -> Vec<Theme> {
+        let builtin = builtin()
+            .into_iter()
+            .filter(|theme| !self.custom.iter().any(|custom| custom.name == theme.name));
+        self.custom.iter().cloned().chain(builtin).collect()
+    }
+
+    /// The theme for announcements on `date`, None if themes are turned off
+    pub fn active(&self, date: NaiveDate) -> Option<Theme> {
+        if !self.enabled {
+            return None;
+        }
+        self.themes().into_iter().find(|theme| theme.contains(date))
+    }
+}
+
+/// Decorates an announcement with the guild's theme for `date`
+pub fn decorate(
+    birthdays: &BirthdayList,
+    guild_id: GuildId,
+    date: NaiveDate,
+    message: String,
+) -> String {
+    match birthdays
+        .guild_configs
+        .get(&guild_id)
+        .and_then(|config| config.themes.active(date))
+    {
+        Some(theme) => theme.decorate(&message),
+        None => message,
+    }
+}
+
+/// Seasonal decorations for the birthday announcements
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("toggle", "add", "remove", "list"),
+    subcommand_required
+)]
+pub async fn birthday_themes(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Turns the seasonal themes on or off
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn toggle(
+    ctx: Context<'_>,
+    #[description = "Whether announcements get seasonal themes"] state: Toggle,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let enabled = matches!(state, Toggle::On);
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .themes
+        .enabled = enabled;
+    let state = if enabled { "on" } else { "off" };
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("turned {} the seasonal themes", state),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = if enabled {
+        "🎨🎈 Birthday announcements now dress up for the season!"
+    } else {
+        "🎨 Birthday announcements no longer follow the seasons!"
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Adds a theme, or replaces the theme with the same name
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+#[allow(clippy::too_many_arguments)]
+async fn add(
+    ctx: Context<'_>,
+    #[description = "Name of the theme"] name: String,
+    #[description = "First day"] start_day: usize,
+    #[description = "Month of the first day"] start_month: usize,
+    #[description = "Last day"] end_day: usize,
+    #[description = "Month of the last day"] end_month: usize,
+    #[description = "Emoji in front of the announcement"] emoji: String,
+    #[description = "Line below the announcement"]
+    #[rest]
+    line: String,
+) -> Result<(), Error> {
+    if args_to_date(start_day, start_month, None).is_err()
+        || args_to_date(end_day, end_month, None).is_err()
+    {
+        ctx.say("🐺🎩❌ Invalid date!").await?;
+        return Ok(());
+    }
+    let name = name.trim().to_lowercase();
+    let theme = Theme {
+        name: name.clone(),
+        start: DayOfYear::new(start_month as u32, start_day as u32),
+        end: DayOfYear::new(end_month as u32, end_day as u32),
+        emoji,
+        line,
+    };
+
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let custom = &mut birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .themes
+        .custom;
+    custom.retain(|custom| custom.name != name);
+    custom.push(theme);
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("set the theme {}", name),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+    ctx.say(format!("🎨🎈 Saved the theme `{}`!", name)).await?;
+    Ok(())
+}
+
+/// Removes a custom theme, built-in themes of the same name apply again
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+async fn remove(
+    ctx: Context<'_>,
+    #[description = "Name of the theme"] name: String,
+) -> Result<(), Error> {
+    let name = name.trim().to_lowercase();
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let custom = &mut birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .themes
+        .custom;
+    let count = custom.len();
+    custom.retain(|custom| custom.name != name);
+    if custom.len() == count {
+        ctx.say(format!("☹️🎈 There is no custom theme called `{}`!", name))
+            .await?;
+        return Ok(());
+    }
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("removed the theme {}", name),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+    ctx.say(format!("🗑️🎈 Removed the theme `{}`!", name))
+        .await?;
+    Ok(())
+}
+
+/// Lists the themes of this server
+#[poise::command(slash_command, prefix_command, guild_only)]
+async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    let format = birthdays.date_format(guild_id);
+    let config = birthdays
+        .guild_configs
+        .get(&guild_id)
+        .map(|config| config.themes.clone())
+        .unwrap_or_default();
+    let lines: Vec<String> = config
+        .themes()
+        .iter()
+        .map(|theme| {
+            format!(
+                "- {} `{}`: {} to {}",
+                theme.emoji,
+                theme.name,
+                format.format(theme.start.day, theme.start.month, None),
+                format.format(theme.end.day, theme.end.month, None)
+            )
+        })
+        .collect();
+    let state = if config.enabled { "on" } else { "off" };
+    ctx.say(format!(
+        "🎨 Seasonal themes are {}:\n{}",
+        state,
+        lines.join("\n")
+    ))
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    fn theme(name: &str, start: DayOfYear, end: DayOfYear) -> Theme {
+        Theme {
+            name: name.to_string(),
+            start,
+            end,
+            emoji: "🎨".to_string(),
+            line: String::new(),
+        }
+    }
+
+    #[test]
+    fn ranges_wrap_around_the_new_year() {
+        let holidays = theme("holidays", DayOfYear::new(12, 20), DayOfYear::new(1, 5));
+        assert!(holidays.contains(date(12, 20)));
+        assert!(holidays.contains(date(12, 31)));
+        assert!(holidays.contains(date(1, 1)));
+        assert!(holidays.contains(date(1, 5)));
+        assert!(!holidays.contains(date(1, 6)));
+        assert!(!holidays.contains(date(12, 19)));
+        assert!(!holidays.contains(date(6, 14)));
+    }
+
+    #[test]
+    fn custom_themes_override_builtin_ones() {
+        let mut config = ThemeConfig::default();
+        assert_eq!(config.active(date(10, 31)), None);
+
+        config.enabled = true;
+        assert_eq!(config.active(date(10, 31)).unwrap().name, "halloween");
+        assert_eq!(config.active(date(6, 14)), None);
+
+        config.custom.push(theme(
+            "halloween",
+            DayOfYear::new(10, 31),
+            DayOfYear::new(10, 31),
+        ));
+        config
+            .custom
+            .push(theme("pride", DayOfYear::new(6, 1), DayOfYear::new(6, 30)));
+        assert_eq!(config.active(date(10, 24)), None);
+        assert_eq!(config.active(date(10, 31)).unwrap().emoji, "🎨");
+        assert_eq!(config.active(date(6, 14)).unwrap().name, "pride");
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDate;
+use poise::serenity_prelude::GuildId;
+use serde::{Deserialize, Serialize};
+
+/// How many days of daily usage buckets are kept around
+static HISTORY_DAYS: i64 = 30;
+
+/// Command invocation counters, both the persisted totals and the pending ones held in memory
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct UsageStats {
+    pub commands: HashMap<String, u64>,
+    pub guilds: HashMap<GuildId, HashMap<String, u64>>,
+    pub daily: BTreeMap<NaiveDate, HashMap<String, u64>>,
+}
+
+impl UsageStats {
+    pub fn record(&mut self, command: &str, guild_id: Option<GuildId>, today: NaiveDate) {
+        *self.commands.entry(command.to_string()).or_default() += 1;
+        if let Some(guild_id) = guild_id {
+            *self
+                .guilds
+                .entry(guild_id)
+                .or_default()
+                .entry(command.to_string())
+                .or_default() += 1;
+        }
+        *self
+            .daily
+            .entry(today)
+            .or_default()
+            .entry(command.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Adds all counters of `other` to this one and drops buckets that are too old
+    pub fn merge(&mut self, other: UsageStats, today: NaiveDate) {
+        add_counts(&mut self.commands, other.commands);
+        for (guild_id, counts) in other.guilds {
+            add_counts(self.guilds.entry(guild_id).or_default(), counts);
+        }
+        for (date, counts) in other.daily {
+            add_counts(self.daily.entry(date).or_default(), counts);
+        }
+
+        let oldest = today - chrono::Duration::days(HISTORY_DAYS);
+        self.daily.retain(|date, _| *date > oldest);
+    }
+
+    /// Per-command invocations since (and including) `since`
+    pub fn since(&self, since: NaiveDate) -> HashMap<String, u64> {
+        let mut counts = HashMap::new();
+        for (_, day) in self.daily.range(since..) {
+            add_counts(&mut counts, day.clone());
+        }
+        counts
+    }
+
+    /// Guilds sorted by their total number of invocations, busiest first
+    pub fn top_guilds(&self, count: usize) -> Vec<(GuildId, u64)> {
+        let mut guilds: Vec<(GuildId, u64)> = self
+            .guilds
+            .iter()
+            .map(|(guild_id, counts)| (*guild_id, counts.values().sum()))
+            .collect();
+        guilds.sort_by_key(|(_, total)| std::cmp::Reverse(*total));
+        guilds.truncate(count);
+        guilds
+    }
+}
+
+fn add_counts(counts: &mut HashMap<String, u64>, other: HashMap<String, u64>) {
+    for (command, count) in other {
+        *counts.entry(command).or_default() += count;
+    }
+}
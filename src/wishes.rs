@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use chrono::{Datelike, Months, NaiveDate, Utc};
+use poise::serenity_prelude::{self as serenity, GuildId, UserId};
+use serde::{Deserialize, Serialize};
+
+use crate::{audit, confirm, storage::Storage, Context, Error};
+
+// Number of well-wishers shown on the leaderboard
+static LEADERBOARD_SIZE: usize = 10;
+
+/// A member wishing a celebrant a happy birthday, counted once per celebrant and year
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Wish {
+    pub guild_id: GuildId,
+    pub from: UserId,
+    pub to: UserId,
+    // The celebrant's local date when the wish was sent
+    pub date: NaiveDate,
+}
+
+/// Adds the wish unless it was counted this year already or is someone wishing themselves,
+/// returns whether it was added
+fn add(wishes: &mut Vec<Wish>, wish: Wish) -> bool {
+    let counted = wishes.iter().any(|counted| {
+        counted.guild_id == wish.guild_id
+            && counted.from == wish.from
+            && counted.to == wish.to
+            && counted.date.year() == wish.date.year()
+    });
+    if counted || wish.from == wish.to {
+        return false;
+    }
+    wishes.push(wish);
+    true
+}
+
+/// Counts the wishes the author of a message sent to the celebrants
+pub async fn record(
+    storage: &Storage,
+    guild_id: GuildId,
+    from: UserId,
+    celebrants: Vec<(UserId, NaiveDate)>,
+) -> Result<(), Error> {
+    let today = Utc::now().date_naive();
+    storage
+        .update(|birthdays| {
+            for &(to, date) in &celebrants {
+                add(
+                    &mut birthdays.wishes,
+                    Wish {
+                        guild_id,
+                        from,
+                        to,
+                        date,
+                    },
+                );
+            }
+            prune(&mut birthdays.wishes, today);
+        })
+        .await
+}
+
+/// Prunes the wishes sent more than a year ago
+pub fn prune(wishes: &mut Vec<Wish>, today: NaiveDate) {
+    let cutoff = today - Months::new(12);
+    wishes.retain(|wish| wish.date > cutoff);
+}
+
+/// Members of the guild by the number of wishes sent since `since`, most first
+fn ranking(wishes: &[Wish], guild_id: GuildId, since: NaiveDate) -> Vec<(UserId, usize)> {
+    let mut counts: HashMap<UserId, usize> = HashMap::new();
+    for wish in wishes {
+        if wish.guild_id == guild_id && wish.date >= since {
+            *counts.entry(wish.from).or_default() += 1;
+        }
+    }
+    let mut ranking: Vec<(UserId, usize)> = counts.into_iter().collect();
+    ranking.sort_by_key(|(user, count)| (std::cmp::Reverse(*count), *user));
+    ranking
+}
+
+/// Shows who wished the most members a happy birthday in the announcement channel
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn wish_leaderboard(
+    ctx: Context<'_>,
+    #[description = "Only count this month's wishes (defaults to the last 12 months)"]
+    this_month: Option<bool>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    let today = Utc::now().date_naive();
+    let (since, period) = if this_month.unwrap_or(false) {
+        (today.with_day(1).unwrap(), "this month")
+    } else {
+        (today - Months::new(12), "in the last 12 months")
+    };
+    let ranking = ranking(&birthdays.wishes, guild_id, since);
+    if ranking.is_empty() {
+        ctx.say(format!("☹️🎈 Nobody sent birthday wishes {}!", period))
+            .await?;
+        return Ok(());
+    }
+    let lines: Vec<String> = ranking
+        .iter()
+        .take(LEADERBOARD_SIZE)
+        .enumerate()
+        .map(|(place, (user, count))| format!("{}. <@{}>: {}", place + 1, user, count))
+        .collect();
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!(
+                "🏆🎈 Top well-wishers {}:\n{}",
+                period,
+                lines.join("\n")
+            ))
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Resets the wish leaderboard of this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn reset_wish_leaderboard(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    if !confirm(
+        ctx,
+        "🏆 Forget all birthday wishes counted in this server?".to_string(),
+    )
+    .await?
+    {
+        return Ok(());
+    }
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    birthdays.wishes.retain(|wish| wish.guild_id != guild_id);
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        "reset the wish leaderboard".to_string(),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+    ctx.say("🗑️🎈 The wish leaderboard starts over!").await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn wish(from: u64, to: u64, date: NaiveDate) -> Wish {
+        Wish {
+            guild_id: GuildId::new(1),
+            from: UserId::new(from),
+            to: UserId::new(to),
+            date,
+        }
+    }
+
+    #[test]
+    fn wishes_count_once_per_celebrant_and_year() {
+        let mut wishes = Vec::new();
+        assert!(add(&mut wishes, wish(1, 2, date(2024, 6, 14))));
+        assert!(!add(&mut wishes, wish(1, 2, date(2024, 6, 14))));
+        assert!(!add(&mut wishes, wish(2, 2, date(2024, 6, 14))));
+        assert!(add(&mut wishes, wish(1, 3, date(2024, 6, 14))));
+        assert!(add(&mut wishes, wish(3, 2, date(2024, 6, 14))));
+        assert!(add(&mut wishes, wish(1, 2, date(2025, 6, 14))));
+
+        assert_eq!(
+            ranking(&wishes, GuildId::new(1), date(2024, 1, 1)),
+            vec![(UserId::new(1), 3), (UserId::new(3), 1)]
+        );
+        assert_eq!(
+            ranking(&wishes, GuildId::new(1), date(2025, 1, 1)),
+            vec![(UserId::new(1), 1)]
+        );
+        assert!(ranking(&wishes, GuildId::new(2), date(2024, 1, 1)).is_empty());
+
+        prune(&mut wishes, date(2025, 6, 14));
+        assert_eq!(wishes, vec![wish(1, 2, date(2025, 6, 14))]);
+    }
+}
@@ -0,0 +1,280 @@
+use chrono::{Datelike, Month, NaiveDate};
+use poise::serenity_prelude::{self as serenity, GuildId};
+use tracing::{error, warn};
+
+use crate::{
+    audit, format, quiet_message, storage::Storage, BirthdayEntry, BirthdayList, Context, Error,
+    Toggle, Visibility,
+};
+
+// The review is posted on the first check within these days of December
+static REVIEW_DAYS: std::ops::RangeInclusive<u32> = 29..=31;
+// Number of January birthdays teased at the end
+static TEASER_SIZE: usize = 5;
+
+/// The guild's entries that were announced in `year`, by their birthday
+fn celebrated(birthdays: &BirthdayList, guild_id: GuildId, year: i32) -> Vec<&BirthdayEntry> {
+    let mut celebrated: Vec<&BirthdayEntry> = birthdays
+        .announced
+        .iter()
+        .filter(|announcement| announcement.guild_id == guild_id && announcement.year == year)
+        .filter_map(|announcement| {
+            birthdays
+                .entries
+                .iter()
+                .find(|entry| entry.guild_id == guild_id && entry.user_id == announcement.user_id)
+        })
+        .collect();
+    celebrated.sort_by_key(|entry| (entry.date.month(), entry.date.day()));
+    celebrated
+}
+
+/// The year in review of the guild, None if nobody was celebrated
+pub fn review(birthdays: &BirthdayList, guild_id: GuildId, year: i32) -> Option<String> {
+    let celebrated = celebrated(birthdays, guild_id, year);
+    let (first, last) = (celebrated.first()?, celebrated.last()?);
+    let mut months = [0; 12];
+    for entry in &celebrated {
+        months[entry.date.month0() as usize] += 1;
+    }
+    // The earliest month wins a tie
+    let busiest = (0..12)
+        .max_by_key(|month| (months[*month], 11 - month))
+        .unwrap();
+    let busiest = Month::try_from(busiest as u8 + 1).unwrap();
+
+    let mut lines = vec![
+        format!("📆🎈 **{} in review**", year),
+        format!("- 🎂 Birthdays celebrated: {}", celebrated.len()),
+        format!(
+            "- 📈 Busiest month: {} with {}",
+            busiest.name(),
+            months[busiest.number_from_month() as usize - 1]
+        ),
+        format!(
+            "- 🥇 First celebrant: {}, 🏁 last celebrant: {}",
+            format::escape(&first.name),
+            format::escape(&last.name)
+        ),
+    ];
+    let wishes = birthdays
+        .wishes
+        .iter()
+        .filter(|wish| wish.guild_id == guild_id && wish.date.year() == year)
+        .count();
+    if wishes > 0 {
+        lines.push(format!("- 💌 Wishes sent: {}", wishes));
+    }
+
+    // Like the next birthday, the teaser only names public birthdays of members still here
+    let mut january: Vec<&BirthdayEntry> = birthdays
+        .entries
+        .iter()
+        .filter(|entry| {
+            entry.guild_id == guild_id
+                && entry.date.month() == 1
+                && entry.visibility == Visibility::Public
+                && entry.missing_since.is_none()
+        })
+        .collect();
+    january.sort_by_key(|entry| entry.date.day());
+    if !january.is_empty() {
+        let names: Vec<String> = january
+            .iter()
+            .take(TEASER_SIZE)
+            .map(|entry| format::escape(&entry.name))
+            .collect();
+        let more = january.len().saturating_sub(TEASER_SIZE);
+        lines.push(format!(
+            "- ⏭️ Coming up in January: {}{}",
+            names.join(", "),
+            if more > 0 {
+                format!(" and {} more", more)
+            } else {
+                String::new()
+            }
+        ));
+    }
+    Some(lines.join("\n"))
+}
+
+/// Whether the review of the year is due to be posted today
+pub fn is_due(today: NaiveDate) -> bool {
+    today.month() == 12 && REVIEW_DAYS.contains(&today.day())
+}
+
+/// Posts the reviews of all guilds that opted in and didn't get theirs this year
+pub async fn post_due(
+    http: &serenity::Http,
+    storage: &Storage,
+    birthdays: &BirthdayList,
+    today: NaiveDate,
+) {
+    if !is_due(today) {
+        return;
+    }
+    let year = today.year();
+    for (guild_id, config) in &birthdays.guild_configs {
+        if !config.year_in_review || config.year_reviewed == Some(year) {
+            continue;
+        }
+        let Some(channel) = birthdays.server_channels.get(guild_id) else {
+            continue;
+        };
+        // Claimed before posting, so it is posted at most once even if it fails
+        let claimed = storage
+            .update(|birthdays| {
+                let config = birthdays.guild_configs.entry(*guild_id).or_default();
+                let claimed = config.year_reviewed != Some(year);
+                config.year_reviewed = Some(year);
+                claimed
+            })
+            .await;
+        match claimed {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(error) => {
+                error!(guild = %guild_id, %error, "Failed to claim the year in review");
+                continue;
+            }
+        }
+        let Some(review) = review(birthdays, *guild_id, year) else {
+            continue;
+        };
+        if let Err(error) = channel.send_message(http, quiet_message(review)).await {
+            warn!(guild = %guild_id, %error, "Failed to post the year in review");
+        }
+    }
+}
+
+/// Posts a review of the year in the announcement channel at the end of December
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_year_in_review(
+    ctx: Context<'_>,
+    #[description = "Whether to post a year in review"] state: Toggle,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let mut birthdays = ctx.data().storage.read_owned().await;
+    let enabled = matches!(state, Toggle::On);
+    birthdays
+        .guild_configs
+        .entry(guild_id)
+        .or_default()
+        .year_in_review = enabled;
+    let state = if enabled { "on" } else { "off" };
+    audit(
+        &mut birthdays,
+        guild_id,
+        ctx.author().id,
+        format!("turned {} the year in review", state),
+    );
+    ctx.data().storage.write(&birthdays).await?;
+
+    let message = if enabled {
+        "📆🎈 A review of the year's birthdays is posted between December 29th and 31st! Use `preview_year_in_review` to see it now."
+    } else {
+        "📆 The year in review is no longer posted!"
+    };
+    ctx.say(message).await?;
+    Ok(())
+}
+
+/// Shows the year in review as it would be posted now
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn preview_year_in_review(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let birthdays = ctx.data().storage.read().await;
+    let year = chrono::Utc::now().year();
+    match review(&birthdays, guild_id, year) {
+        Some(review) => {
+            ctx.send(
+                poise::CreateReply::default()
+                    .content(review)
+                    .ephemeral(true)
+                    .allowed_mentions(serenity::CreateAllowedMentions::new()),
+            )
+            .await?;
+        }
+        None => {
+            ctx.say(format!(
+                "☹️🎈 No birthdays were celebrated in {} yet!",
+                year
+            ))
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::UserId;
+
+    use super::*;
+    use crate::{wishes::Wish, Announcement};
+
+    fn entry(user_id: u64, month: u32, day: u32) -> BirthdayEntry {
+        BirthdayEntry {
+            date: NaiveDate::from_ymd_opt(1995, month, day).unwrap(),
+            ..BirthdayEntry::example(user_id, 1)
+        }
+    }
+
+    #[test]
+    fn review_sums_up_the_announced_birthdays() {
+        let entries = vec![
+            entry(1, 3, 2),
+            entry(2, 1, 9),
+            entry(3, 3, 20),
+            entry(4, 1, 5),
+            BirthdayEntry {
+                visibility: Visibility::Private,
+                ..entry(5, 1, 1)
+            },
+            BirthdayEntry {
+                missing_since: Some(chrono::Utc::now()),
+                ..entry(6, 1, 2)
+            },
+        ];
+        let announced = [1, 2, 3]
+            .map(|user_id| Announcement {
+                guild_id: GuildId::new(1),
+                user_id: UserId::new(user_id),
+                year: 2024,
+            })
+            .into();
+        let birthdays = BirthdayList {
+            entries,
+            announced,
+            wishes: vec![Wish {
+                guild_id: GuildId::new(1),
+                from: UserId::new(2),
+                to: UserId::new(1),
+                date: NaiveDate::from_ymd_opt(2024, 3, 2).unwrap(),
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            review(&birthdays, GuildId::new(1), 2024).unwrap(),
+            "📆🎈 **2024 in review**\n\
+            - 🎂 Birthdays celebrated: 3\n\
+            - 📈 Busiest month: March with 2\n\
+            - 🥇 First celebrant: user 2, 🏁 last celebrant: user 3\n\
+            - 💌 Wishes sent: 1\n\
+            - ⏭️ Coming up in January: user 4, user 2"
+        );
+        assert_eq!(review(&birthdays, GuildId::new(1), 2023), None);
+        assert!(is_due(NaiveDate::from_ymd_opt(2024, 12, 29).unwrap()));
+        assert!(!is_due(NaiveDate::from_ymd_opt(2024, 12, 28).unwrap()));
+    }
+}